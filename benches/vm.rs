@@ -0,0 +1,107 @@
+//! 虚拟机基准测试
+//!
+//! 使用 `cargo bench --bench vm` 运行（release 构建；调试构建的虚拟机会逐条打印指令）。
+//! 可以在命令行中给出基准名，只运行名字包含它的基准。
+
+use std::time::{Duration, Instant};
+use Zero_compiler::compiler::Compiler;
+use Zero_compiler::lexer::{Lexer, TokenPreprocessor};
+use Zero_compiler::parser::Parser;
+use Zero_compiler::type_checker::TypeChecker;
+use Zero_compiler::bytecode::Value;
+use Zero_compiler::vm::VM;
+
+/// 一个基准：源码、执行后检查结果的全局变量和它的期望值
+struct Bench {
+    name: &'static str,
+    source: &'static str,
+    global: &'static str,
+    expected: Value,
+    limit: Option<Duration>,  // 耗时上限，用来发现退化为平方复杂度的实现
+}
+
+/// 热循环中读取全局变量
+fn global_loop() -> Bench {
+    Bench {
+        name: "global_loop",
+        source: "let limit = 1000000;
+                 var total = 0;
+                 for i in 0..(limit) {
+                     total = total + limit;
+                 }",
+        global: "total",
+        expected: Value::Integer(1000000 * 1000000),
+        limit: None,
+    }
+}
+
+/// 在循环中逐个写入大数组：元素赋值原地修改数组，总耗时与数组长度成线性关系
+fn array_fill() -> Bench {
+    Bench {
+        name: "array_fill",
+        source: "fn build(n: int) -> [int] {
+                     var xs = [0];
+                     while (xs.length < n) { xs = xs + xs; }
+                     for i in 0..(n) { xs[i] = i * 2; }
+                     return xs;
+                 }
+                 var global = build(200000);
+                 for i in 0..200000 { global[i] += 1; }
+                 let last = global[199999];",
+        global: "last",
+        expected: Value::Integer(199999 * 2 + 1),
+        limit: Some(Duration::from_secs(5)),
+    }
+}
+
+/// 用字符串构建器在循环中拼接长字符串：append 原地追加，总耗时与结果长度成线性关系
+/// （同样的循环改用 `s = s + "x"` 时每次都要复制整个字符串）
+fn string_builder() -> Bench {
+    // 每行 "line " + 数字 + 换行
+    let expected: usize = (0..200000).map(|i: usize| 6 + i.to_string().len()).sum();
+    Bench {
+        name: "string_builder",
+        source: "fn build(n: int) -> string {
+                     var sb = string_builder();
+                     for i in 0..(n) { sb.append(\"line \"); sb.append(i); sb.append(\"\\n\"); }
+                     return sb.build();
+                 }
+                 let text: string = build(200000);
+                 let length = text.length;",
+        global: "length",
+        expected: Value::Integer(expected as i64),
+        limit: Some(Duration::from_secs(5)),
+    }
+}
+
+/// 完整地走一遍 词法 -> 语法 -> 类型检查 -> 编译，再执行并计时
+fn run(bench: &Bench) {
+    let mut lexer = Lexer::new(bench.source.to_string());
+    let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+    let program = Parser::new(tokens).parse().unwrap();
+    let mut type_checker = TypeChecker::new();
+    type_checker.check(&program).unwrap();
+    let mut compiler = Compiler::new();
+    compiler.set_imported_symbols(type_checker.get_imported_symbols());
+    let chunk = compiler.compile(program).unwrap();
+
+    let start = Instant::now();
+    let mut vm = VM::new();
+    vm.execute(chunk).unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(vm.get_global(bench.global), Some(&bench.expected), "{}", bench.name);
+    if let Some(limit) = bench.limit {
+        assert!(elapsed < limit, "{} took {:?}", bench.name, elapsed);
+    }
+    println!("{:<16} {:>12} instructions in {:?}", bench.name, vm.instruction_count(), elapsed);
+}
+
+fn main() {
+    // cargo bench 会传入 --bench 等参数，第一个不以 '-' 开头的参数作为过滤条件
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let benches = [global_loop(), array_fill(), string_builder()];
+    for bench in benches.iter().filter(|b| filter.as_deref().is_none_or(|f| b.name.contains(f))) {
+        run(bench);
+    }
+}
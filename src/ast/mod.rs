@@ -1,7 +1,11 @@
+pub mod stats;
+
 use crate::lexer::token::Token;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 // 类型系统定义
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Type {
     Int,
     Float,
@@ -11,37 +15,41 @@ pub enum Type {
     Void,
     Null,
     Array(Box<Type>),  // 数组类型
+    FixedArray(Box<Type>, usize),  // 定长数组类型 [T; N]，长度只在类型检查阶段约束
+    Tuple(Vec<Type>),  // 元组类型
     Function(FunctionType),
     Struct(StructType),  // 结构体类型
     Named(String),  // 类型别名引用
+    StringBuilder,  // 内置的可变字符串构建器（运行时就是字符串，append 原地追加）
     Unknown,  // 用于类型推导
 }
 
 // 结构体字段定义
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StructField {
     pub name: String,
     pub field_type: Type,
 }
 
 // 结构体类型定义
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StructType {
     pub name: String,
     pub fields: Vec<StructField>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FunctionType {
     pub params: Vec<Type>,
     pub return_type: Box<Type>,
 }
 
 // 函数参数定义
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Parameter {
     pub name: String,
     pub type_annotation: Option<Type>,
+    pub span: Span,  // 参数名在源码中的位置
 }
 
 impl Type {
@@ -59,6 +67,16 @@ impl Type {
             (Type::Unknown, _) | (_, Type::Unknown) => true,
             // 数组类型需要元素类型兼容
             (Type::Array(a), Type::Array(b)) => a.is_compatible_with(b),
+            // 定长数组之间还要求长度相同
+            (Type::FixedArray(a, n), Type::FixedArray(b, m)) => n == m && a.is_compatible_with(b),
+            // 定长数组与普通数组互相兼容，长度无法静态确定时不做约束
+            (Type::FixedArray(a, _), Type::Array(b)) | (Type::Array(a), Type::FixedArray(b, _)) => {
+                a.is_compatible_with(b)
+            }
+            // 元组类型需要长度相同且逐个元素兼容
+            (Type::Tuple(a), Type::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.is_compatible_with(y))
+            }
             // 结构体类型需要名称和字段匹配
             (Type::Struct(a), Type::Struct(b)) => a == b,
             _ => false,
@@ -67,13 +85,119 @@ impl Type {
     
     pub fn get_element_type(&self) -> Option<&Type> {
         match self {
-            Type::Array(element_type) => Some(element_type),
+            Type::Array(element_type) | Type::FixedArray(element_type, _) => Some(element_type),
             _ => None,
         }
     }
+
+    /// impl 块的类型名对应的类型：基本类型关键字（`impl int`）对应基本类型，其他为用户定义的类型
+    pub fn from_impl_name(type_name: &str) -> Type {
+        match type_name {
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            "char" => Type::Char,
+            _ => Type::Named(type_name.to_string()),
+        }
+    }
+
+    /// 方法调用的接收者类型对应的 impl 类型名（与 `from_impl_name` 相反），不支持方法的类型返回 None
+    pub fn impl_name(&self) -> Option<String> {
+        match self {
+            Type::Struct(struct_type) => Some(struct_type.name.clone()),
+            Type::Named(name) => Some(name.clone()),
+            Type::Int | Type::Float | Type::String | Type::Bool | Type::Char => Some(self.to_string()),
+            _ => None,
+        }
+    }
+
+    /// 把方法签名中的 `Self` 替换为 impl 块实现的类型
+    pub fn replace_self(&self, type_name: &str) -> Type {
+        match self {
+            Type::Named(name) if name == "Self" => Type::from_impl_name(type_name),
+            Type::Array(element_type) => Type::Array(Box::new(element_type.replace_self(type_name))),
+            Type::FixedArray(element_type, length) => {
+                Type::FixedArray(Box::new(element_type.replace_self(type_name)), *length)
+            }
+            Type::Tuple(element_types) => {
+                Type::Tuple(element_types.iter().map(|t| t.replace_self(type_name)).collect())
+            }
+            Type::Function(func_type) => Type::Function(FunctionType {
+                params: func_type.params.iter().map(|t| t.replace_self(type_name)).collect(),
+                return_type: Box::new(func_type.return_type.replace_self(type_name)),
+            }),
+            other => other.clone(),
+        }
+    }
+}
+
+// 以源码语法的形式显示类型，如 [int]、(int, string)、fn(int) -> bool
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::Void => write!(f, "void"),
+            Type::Null => write!(f, "null"),
+            Type::Array(element_type) => write!(f, "[{}]", element_type),
+            Type::FixedArray(element_type, length) => write!(f, "[{}; {}]", element_type, length),
+            Type::Tuple(element_types) => {
+                write!(f, "(")?;
+                for (i, t) in element_types.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                write!(f, ")")
+            }
+            Type::Function(func_type) => {
+                write!(f, "fn(")?;
+                for (i, t) in func_type.params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                write!(f, ") -> {}", func_type.return_type)
+            }
+            Type::Struct(struct_type) => write!(f, "{}", struct_type.name),
+            Type::Named(name) => write!(f, "{}", name),
+            Type::StringBuilder => write!(f, "StringBuilder"),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// 表达式在源码中的位置（行、列均从 1 开始，0 表示未知）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Span { line, column }
+    }
+
+    /// 是否带有有效位置
+    pub fn is_known(&self) -> bool {
+        self.line > 0
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Expr {
     // 字面量
     Integer(i64),
@@ -81,11 +205,13 @@ pub enum Expr {
     String(String),
     Boolean(bool),
     Char(char),      // 字符字面量
-    Identifier(String),
+    Null,
+    Identifier(String, Span),
 
     // 路径表达式 (module::item 或 module::submodule::item)
     Path {
         segments: Vec<String>,  // ["math", "geometry", "area"]
+        span: Span,
     },
 
     // 数组字面量
@@ -93,10 +219,17 @@ pub enum Expr {
         elements: Vec<Expr>,
     },
     
+    // 元组字面量 (a, b, ...)
+    Tuple {
+        elements: Vec<Expr>,
+    },
+
     // 结构体字面量
     StructLiteral {
         struct_name: String,
         fields: Vec<(String, Expr)>,  // (字段名, 字段值)
+        base: Option<Box<Expr>>,      // 更新语法 `..base`：未列出的字段从 base 复制
+        span: Span,
     },
     
     // 二元运算
@@ -124,30 +257,68 @@ pub enum Expr {
         index: Box<Expr>,
     },
     
-    // 索引赋值
+    // 重复元素的数组 ([value; count])，count 在运行时求值
+    ArrayFill {
+        value: Box<Expr>,
+        count: Box<Expr>,
+    },
+
+    // 元组索引访问 (tuple.0)
+    TupleIndex {
+        object: Box<Expr>,
+        index: usize,
+    },
+
+    // 范围索引 (object[start..end])，取数组的子数组或字符串的子串
+    Slice {
+        object: Box<Expr>,
+        start: Box<Expr>,
+        end: Box<Expr>,
+    },
+
+    // 索引赋值；来自 `arr[i] op= y` 时 compound 记录运算符，value 是展开后的 `arr[i] op y`
     IndexAssign {
         object: Box<Expr>,
         index: Box<Expr>,
         value: Box<Expr>,
+        compound: Option<BinaryOp>,
     },
     
     // 赋值
     Assign {
         name: String,
         value: Box<Expr>,
+        span: Span,
     },
     
     // 字段访问 (object.field)
     FieldAccess {
         object: Box<Expr>,
         field: String,
+        span: Span,
     },
 
-    // 字段赋值
+    // 可选字段访问 (object?.field)，object 为 null 时结果为 null
+    OptionalFieldAccess {
+        object: Box<Expr>,
+        field: String,
+        span: Span,
+    },
+
+    // 字段赋值；来自 `obj.field op= y` 时 compound 记录运算符，value 是展开后的 `obj.field op y`
     FieldAssign {
         object: Box<Expr>,
         field: String,
         value: Box<Expr>,
+        compound: Option<BinaryOp>,
+        span: Span,
+    },
+
+    // 路径赋值 (module::var = value)，修改其他模块中的公开可变变量
+    PathAssign {
+        segments: Vec<String>,
+        value: Box<Expr>,
+        span: Span,
     },
 
     // 方法调用 (object.method(args))
@@ -155,10 +326,49 @@ pub enum Expr {
         object: Box<Expr>,
         method: String,
         arguments: Vec<Expr>,
+        span: Span,
+    },
+
+    // if 表达式 (if cond { ...; a } else { ...; b })，必须有 else 分支
+    If {
+        condition: Box<Expr>,
+        then_branch: ValueBlock,
+        else_branch: ValueBlock,
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// 带值的语句块：先执行 statements，最后一个不带分号的表达式 value 作为块的值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValueBlock {
+    pub statements: Vec<Stmt>,
+    pub value: Box<Expr>,
+}
+
+impl ValueBlock {
+    /// 分支中不支持的语句种类（用于错误信息）
+    ///
+    /// 局部变量槽位按声明顺序分配，不计算栈上的临时值，所以分支里的语句
+    /// 不能声明变量或函数；跳出分支的 break/continue 也不会清理这些临时值。
+    pub fn unsupported_statement(&self) -> Option<&'static str> {
+        fn find(statements: &[Stmt], in_loop: bool) -> Option<&'static str> {
+            statements.iter().find_map(|stmt| match stmt {
+                Stmt::VarDeclaration { .. } | Stmt::For { .. } | Stmt::FnDeclaration { .. } => {
+                    Some("variable declarations")
+                }
+                Stmt::Break | Stmt::Continue if !in_loop => Some("`break` and `continue`"),
+                Stmt::If { then_branch, else_branch, .. } => find(then_branch, in_loop)
+                    .or_else(|| else_branch.as_deref().and_then(|b| find(b, in_loop))),
+                Stmt::While { body, else_branch, .. } => find(body, true)
+                    .or_else(|| else_branch.as_deref().and_then(|b| find(b, in_loop))),
+                Stmt::Block { statements } => find(statements, in_loop),
+                _ => None,
+            })
+        }
+        find(&self.statements, false)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BinaryOp {
     // 算术运算符
     Add,
@@ -166,6 +376,7 @@ pub enum BinaryOp {
     Multiply,
     Divide,
     Modulo,
+    Power,
     
     // 比较运算符
     Equal,
@@ -180,20 +391,21 @@ pub enum BinaryOp {
     Or,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum UnaryOp {
     Not,
     Negate,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Stmt {
     // 表达式语句
     Expression(Expr),
     
     // 变量声明
     VarDeclaration {
-        name: String,
+        visibility: Visibility,  // 只对模块顶层的变量有意义
+        pattern: Pattern,
         mutable: bool,
         type_annotation: Option<Type>,
         initializer: Option<Expr>,
@@ -213,6 +425,7 @@ pub enum Stmt {
         visibility: Visibility,  // 新增：可见性
         name: String,
         fields: Vec<StructField>,
+        field_spans: Vec<Span>,  // 字段名在源码中的位置，与 fields 一一对应（字段类型不记录位置）
     },
     
     // 类型别名声明
@@ -234,18 +447,20 @@ pub enum Stmt {
         else_branch: Option<Vec<Stmt>>,
     },
     
-    // while 循环
+    // while 循环（else 分支只在循环没有被 break 时执行）
     While {
         condition: Expr,
         body: Vec<Stmt>,
+        else_branch: Option<Vec<Stmt>>,
     },
     
-    // for 循环
+    // for 循环（else 分支只在循环没有被 break 时执行）
     For {
         variable: String,
         start: Expr,
         end: Expr,
         body: Vec<Stmt>,
+        else_branch: Option<Vec<Stmt>>,
     },
     
     // 打印语句
@@ -290,8 +505,33 @@ pub enum Stmt {
     },
 }
 
+/// 变量绑定模式（用于 let/var 声明）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    Identifier(String),      // let x = ...
+    Tuple(Vec<Pattern>),     // let (a, (b, c)) = ...
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Identifier(name) => write!(f, "{}", name),
+            Pattern::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
 /// 导入项类型
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum UseItems {
     All,                              // use math::*
     Single(String),                   // use math::add
@@ -300,14 +540,14 @@ pub enum UseItems {
 }
 
 /// 可见性修饰符
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Visibility {
     Public,    // pub
     Private,   // 默认（无修饰符）
 }
 
 /// 方法声明（与函数类似，但有隐式的 self 参数）
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MethodDeclaration {
     pub name: String,
     pub parameters: Vec<Parameter>,  // 不包含 self
@@ -315,7 +555,7 @@ pub struct MethodDeclaration {
     pub body: Vec<Stmt>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub statements: Vec<Stmt>,
 }
@@ -357,13 +597,47 @@ impl Expr {
     }
     
     pub fn identifier(name: String) -> Self {
-        Expr::Identifier(name)
+        Expr::Identifier(name, Span::default())
     }
     
     pub fn array(elements: Vec<Expr>) -> Self {
         Expr::Array { elements }
     }
     
+    pub fn array_fill(value: Expr, count: Expr) -> Self {
+        Expr::ArrayFill {
+            value: Box::new(value),
+            count: Box::new(count),
+        }
+    }
+
+    pub fn if_expr(condition: Expr, then_branch: ValueBlock, else_branch: ValueBlock) -> Self {
+        Expr::If {
+            condition: Box::new(condition),
+            then_branch,
+            else_branch,
+        }
+    }
+
+    pub fn tuple(elements: Vec<Expr>) -> Self {
+        Expr::Tuple { elements }
+    }
+
+    pub fn slice(object: Expr, start: Expr, end: Expr) -> Self {
+        Expr::Slice {
+            object: Box::new(object),
+            start: Box::new(start),
+            end: Box::new(end),
+        }
+    }
+
+    pub fn tuple_index(object: Expr, index: usize) -> Self {
+        Expr::TupleIndex {
+            object: Box::new(object),
+            index,
+        }
+    }
+
     pub fn binary(left: Expr, operator: BinaryOp, right: Expr) -> Self {
         Expr::Binary {
             left: Box::new(left),
@@ -398,6 +672,7 @@ impl Expr {
             object: Box::new(object),
             index: Box::new(index),
             value: Box::new(value),
+            compound: None,
         }
     }
     
@@ -405,13 +680,16 @@ impl Expr {
         Expr::Assign {
             name,
             value: Box::new(value),
+            span: Span::default(),
         }
     }
     
-    pub fn struct_literal(struct_name: String, fields: Vec<(String, Expr)>) -> Self {
+    pub fn struct_literal(struct_name: String, fields: Vec<(String, Expr)>, base: Option<Expr>) -> Self {
         Expr::StructLiteral {
             struct_name,
             fields,
+            base: base.map(Box::new),
+            span: Span::default(),
         }
     }
     
@@ -419,14 +697,40 @@ impl Expr {
         Expr::FieldAccess {
             object: Box::new(object),
             field,
+            span: Span::default(),
         }
     }
     
+    pub fn optional_field_access(object: Expr, field: String) -> Self {
+        Expr::OptionalFieldAccess {
+            object: Box::new(object),
+            field,
+            span: Span::default(),
+        }
+    }
+
     pub fn field_assign(object: Expr, field: String, value: Expr) -> Self {
         Expr::FieldAssign {
             object: Box::new(object),
             field,
             value: Box::new(value),
+            compound: None,
+            span: Span::default(),
+        }
+    }
+
+    pub fn path(segments: Vec<String>) -> Self {
+        Expr::Path {
+            segments,
+            span: Span::default(),
+        }
+    }
+
+    pub fn path_assign(segments: Vec<String>, value: Expr) -> Self {
+        Expr::PathAssign {
+            segments,
+            value: Box::new(value),
+            span: Span::default(),
         }
     }
 
@@ -435,6 +739,32 @@ impl Expr {
             object: Box::new(object),
             method,
             arguments,
+            span: Span::default(),
+        }
+    }
+
+    /// 设置节点的源码位置（仅对携带位置信息的节点生效）
+    pub fn at(mut self, position: Span) -> Self {
+        match &mut self {
+            Expr::Identifier(_, span)
+            | Expr::StructLiteral { span, .. }
+            | Expr::Assign { span, .. }
+            | Expr::FieldAccess { span, .. }
+            | Expr::OptionalFieldAccess { span, .. }
+            | Expr::FieldAssign { span, .. }
+            | Expr::Path { span, .. }
+            | Expr::PathAssign { span, .. }
+            | Expr::MethodCall { span, .. } => *span = position,
+            _ => {}
+        }
+        self
+    }
+
+    /// 标记索引赋值或字段赋值来自复合赋值 `op=`（value 已经是展开后的 `target op y`）
+    pub fn compound(mut self, operator: BinaryOp) -> Self {
+        if let Expr::IndexAssign { compound, .. } | Expr::FieldAssign { compound, .. } = &mut self {
+            *compound = Some(operator);
         }
+        self
     }
 }
\ No newline at end of file
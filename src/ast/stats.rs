@@ -0,0 +1,231 @@
+// AST 统计（--ast-stats）：各种语句和表达式节点的数量、函数个数、表达式的最大嵌套深度
+
+use super::{Expr, Program, Stmt, ValueBlock};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// 程序的 AST 统计结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AstStats {
+    pub statements: BTreeMap<&'static str, usize>,   // 语句种类 -> 数量
+    pub expressions: BTreeMap<&'static str, usize>,  // 表达式种类 -> 数量
+    pub total_statements: usize,                     // 所有语句（包括嵌套在函数、代码块、模块中的）
+    pub functions: usize,                            // 函数声明和 impl 块中的方法
+    pub max_expression_depth: usize,                 // 表达式树的最大深度，单个字面量为 1
+}
+
+impl AstStats {
+    /// 遍历整个程序统计节点
+    pub fn of(program: &Program) -> Self {
+        let mut stats = AstStats::default();
+        stats.visit_stmts(&program.statements);
+        stats
+    }
+
+    fn visit_stmts(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.total_statements += 1;
+        *self.statements.entry(Self::stmt_name(stmt)).or_insert(0) += 1;
+
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print { value: expr } => self.visit_root(expr),
+            Stmt::VarDeclaration { initializer, .. } => {
+                if let Some(expr) = initializer {
+                    self.visit_root(expr);
+                }
+            }
+            Stmt::Return { value } => {
+                if let Some(expr) = value {
+                    self.visit_root(expr);
+                }
+            }
+            Stmt::FnDeclaration { body, .. } => {
+                self.functions += 1;
+                self.visit_stmts(body);
+            }
+            Stmt::If { condition, then_branch, else_branch }
+            | Stmt::While { condition, body: then_branch, else_branch } => {
+                self.visit_root(condition);
+                self.visit_stmts(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.visit_stmts(else_branch);
+                }
+            }
+            Stmt::For { start, end, body, else_branch, .. } => {
+                self.visit_root(start);
+                self.visit_root(end);
+                self.visit_stmts(body);
+                if let Some(else_branch) = else_branch {
+                    self.visit_stmts(else_branch);
+                }
+            }
+            Stmt::Block { statements } | Stmt::ModuleDeclaration { statements, .. } => self.visit_stmts(statements),
+            Stmt::ImplBlock { methods, .. } => {
+                for method in methods {
+                    self.functions += 1;
+                    self.visit_stmts(&method.body);
+                }
+            }
+            Stmt::StructDeclaration { .. } | Stmt::TypeAlias { .. } | Stmt::Break | Stmt::Continue
+            | Stmt::UseStatement { .. } | Stmt::ModuleReference { .. } => {}
+        }
+    }
+
+    /// 语句中直接出现的表达式（嵌套深度从这里开始计算）
+    fn visit_root(&mut self, expr: &Expr) {
+        let depth = self.visit_expr(expr);
+        self.max_expression_depth = self.max_expression_depth.max(depth);
+    }
+
+    /// 统计表达式及其子表达式，返回表达式树的深度
+    fn visit_expr(&mut self, expr: &Expr) -> usize {
+        *self.expressions.entry(Self::expr_name(expr)).or_insert(0) += 1;
+
+        let children: Vec<&Expr> = match expr {
+            Expr::Integer(_) | Expr::Float(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Char(_)
+            | Expr::Null | Expr::Identifier(..) | Expr::Path { .. } => Vec::new(),
+            Expr::Array { elements } | Expr::Tuple { elements } => elements.iter().collect(),
+            Expr::StructLiteral { fields, base, .. } => {
+                fields.iter().map(|(_, value)| value).chain(base.as_deref()).collect()
+            }
+            Expr::Binary { left, right, .. } => vec![left, right],
+            Expr::Unary { operand, .. } => vec![operand],
+            Expr::Call { callee, arguments } => std::iter::once(callee.as_ref()).chain(arguments).collect(),
+            Expr::MethodCall { object, arguments, .. } => std::iter::once(object.as_ref()).chain(arguments).collect(),
+            Expr::Index { object, index } => vec![object, index],
+            Expr::ArrayFill { value, count } => vec![value, count],
+            Expr::Slice { object, start, end } => vec![object, start, end],
+            Expr::IndexAssign { object, index, value, .. } => vec![object, index, value],
+            Expr::TupleIndex { object, .. } | Expr::FieldAccess { object, .. }
+            | Expr::OptionalFieldAccess { object, .. } => vec![object],
+            Expr::FieldAssign { object, value, .. } => vec![object, value],
+            Expr::Assign { value, .. } | Expr::PathAssign { value, .. } => vec![value],
+            Expr::If { condition, then_branch, else_branch } => {
+                // 分支中的语句单独计算深度，分支的值算作 if 的子表达式
+                self.visit_value_block(then_branch);
+                self.visit_value_block(else_branch);
+                vec![condition, &then_branch.value, &else_branch.value]
+            }
+        };
+
+        let deepest = children.into_iter().map(|child| self.visit_expr(child)).max().unwrap_or(0);
+        deepest + 1
+    }
+
+    fn visit_value_block(&mut self, block: &ValueBlock) {
+        self.visit_stmts(&block.statements);
+    }
+
+    fn stmt_name(stmt: &Stmt) -> &'static str {
+        match stmt {
+            Stmt::Expression(_) => "Expression",
+            Stmt::VarDeclaration { .. } => "VarDeclaration",
+            Stmt::FnDeclaration { .. } => "FnDeclaration",
+            Stmt::StructDeclaration { .. } => "StructDeclaration",
+            Stmt::TypeAlias { .. } => "TypeAlias",
+            Stmt::Return { .. } => "Return",
+            Stmt::If { .. } => "If",
+            Stmt::While { .. } => "While",
+            Stmt::For { .. } => "For",
+            Stmt::Print { .. } => "Print",
+            Stmt::Block { .. } => "Block",
+            Stmt::Break => "Break",
+            Stmt::Continue => "Continue",
+            Stmt::ImplBlock { .. } => "ImplBlock",
+            Stmt::ModuleDeclaration { .. } => "ModuleDeclaration",
+            Stmt::UseStatement { .. } => "UseStatement",
+            Stmt::ModuleReference { .. } => "ModuleReference",
+        }
+    }
+
+    fn expr_name(expr: &Expr) -> &'static str {
+        match expr {
+            Expr::Integer(_) => "Integer",
+            Expr::Float(_) => "Float",
+            Expr::String(_) => "String",
+            Expr::Boolean(_) => "Boolean",
+            Expr::Char(_) => "Char",
+            Expr::Null => "Null",
+            Expr::Identifier(..) => "Identifier",
+            Expr::Path { .. } => "Path",
+            Expr::Array { .. } => "Array",
+            Expr::Tuple { .. } => "Tuple",
+            Expr::StructLiteral { .. } => "StructLiteral",
+            Expr::Binary { .. } => "Binary",
+            Expr::Unary { .. } => "Unary",
+            Expr::Call { .. } => "Call",
+            Expr::Index { .. } => "Index",
+            Expr::ArrayFill { .. } => "ArrayFill",
+            Expr::TupleIndex { .. } => "TupleIndex",
+            Expr::Slice { .. } => "Slice",
+            Expr::IndexAssign { .. } => "IndexAssign",
+            Expr::Assign { .. } => "Assign",
+            Expr::FieldAccess { .. } => "FieldAccess",
+            Expr::OptionalFieldAccess { .. } => "OptionalFieldAccess",
+            Expr::FieldAssign { .. } => "FieldAssign",
+            Expr::PathAssign { .. } => "PathAssign",
+            Expr::MethodCall { .. } => "MethodCall",
+            Expr::If { .. } => "If",
+        }
+    }
+}
+
+impl fmt::Display for AstStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "statements: {}", self.total_statements)?;
+        writeln!(f, "expressions: {}", self.expressions.values().sum::<usize>())?;
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "max expression depth: {}", self.max_expression_depth)?;
+        writeln!(f, "Stmt:")?;
+        for (name, count) in &self.statements {
+            writeln!(f, "  {:<20} {}", name, count)?;
+        }
+        writeln!(f, "Expr:")?;
+        for (name, count) in &self.expressions {
+            writeln!(f, "  {:<20} {}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_ast_stats() {
+        let source = "
+            fn square(x: int) -> int { return x * x; }
+            fn sum_to(n: int) -> int {
+                var total = 0;
+                for i in 0..n { total = total + square(i); }
+                return total;
+            }
+            print(sum_to(3) + 1);
+        ";
+        let tokens = Lexer::new(source.to_string()).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let stats = AstStats::of(&program);
+
+        assert_eq!(stats.functions, 2);
+        assert_eq!(stats.total_statements, 8);
+        let statements: Vec<(&str, usize)> = stats.statements.iter().map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(statements, vec![
+            ("Expression", 1), ("FnDeclaration", 2), ("For", 1), ("Print", 1), ("Return", 2), ("VarDeclaration", 1),
+        ]);
+        assert_eq!(stats.expressions["Call"], 2);
+        assert_eq!(stats.expressions["Binary"], 3);
+        assert_eq!(stats.expressions["Identifier"], 8);
+        assert_eq!(stats.expressions["Integer"], 4);
+        assert_eq!(stats.expressions["Assign"], 1);
+        // total = total + square(i)：Assign -> Binary -> Call -> Identifier
+        assert_eq!(stats.max_expression_depth, 4);
+    }
+}
@@ -0,0 +1,139 @@
+//! Zero内置函数表
+//!
+//! 类型检查器和编译器都从这张表查找内置函数：参数类型和返回类型用于类型检查，
+//! `emit` 给出调用时生成的指令。新增内置函数只需要在表中加一项，并在虚拟机中实现对应的指令。
+//! 用户定义的同名函数或变量优先于内置函数，不在表中的名字按普通函数调用解析。
+
+use crate::ast::Type;
+use crate::bytecode::OpCode;
+
+/// 内置函数的返回类型
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuiltinReturn {
+    /// 固定的类型，`Type::Void` 表示没有返回值（编译后留下 null 作为表达式的值）
+    Fixed(Type),
+    /// 与第一个参数的类型相同（例如 clone）
+    SameAsArgument,
+}
+
+/// 内置函数的描述
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub params: &'static [Type],          // 参数类型，Unknown 接受任意类型，Float 也接受 int
+    pub variadic: bool,                   // 固定参数之后是否还可以有任意个任意类型的参数
+    pub returns: BuiltinReturn,
+    pub emit: fn(usize) -> Vec<OpCode>,   // 参数已经按顺序压栈，根据参数个数生成调用的指令
+}
+
+impl Builtin {
+    /// 参数个数是否符合要求
+    pub fn accepts_count(&self, count: usize) -> bool {
+        if self.variadic {
+            count >= self.params.len()
+        } else {
+            count == self.params.len()
+        }
+    }
+
+    /// 第 index 个参数（从 0 开始）期望的类型，可变参数部分为 Unknown
+    pub fn param_type(&self, index: usize) -> Type {
+        self.params.get(index).cloned().unwrap_or(Type::Unknown)
+    }
+}
+
+/// 所有内置函数
+/// - `round(number, int) -> float`：四舍五入到指定的小数位数
+/// - `format(string, ...) -> string`：填充 `{}` / `{:.N}` 占位符
+/// - `pretty_print(value)`：多行缩进打印，没有返回值
+/// - `clone(value)`：深拷贝，结果与参数类型相同
+/// - `input() -> string`：读取一行输入（不含换行），输入结束时为空字符串
+/// - `write(value)` / `eprint(value)`：打印到输出流但不换行 / 打印一行到错误流，没有返回值
+/// - `string_builder() -> StringBuilder`：空的字符串构建器，用 `append` 追加、`build` 取出字符串
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "round",
+        params: &[Type::Float, Type::Int],
+        variadic: false,
+        returns: BuiltinReturn::Fixed(Type::Float),
+        emit: |_| vec![OpCode::Round],
+    },
+    Builtin {
+        name: "format",
+        params: &[Type::String],
+        variadic: true,
+        returns: BuiltinReturn::Fixed(Type::String),
+        emit: |count| vec![OpCode::Format(count.saturating_sub(1))],
+    },
+    Builtin {
+        name: "pretty_print",
+        params: &[Type::Unknown],
+        variadic: false,
+        returns: BuiltinReturn::Fixed(Type::Void),
+        emit: |_| vec![OpCode::PrettyFormat, OpCode::Print, OpCode::LoadNull],
+    },
+    Builtin {
+        name: "clone",
+        params: &[Type::Unknown],
+        variadic: false,
+        returns: BuiltinReturn::SameAsArgument,
+        emit: |_| vec![OpCode::DeepClone],
+    },
+    Builtin {
+        name: "input",
+        params: &[],
+        variadic: false,
+        returns: BuiltinReturn::Fixed(Type::String),
+        emit: |_| vec![OpCode::Input],
+    },
+    Builtin {
+        name: "write",
+        params: &[Type::Unknown],
+        variadic: false,
+        returns: BuiltinReturn::Fixed(Type::Void),
+        emit: |_| vec![OpCode::Write, OpCode::LoadNull],
+    },
+    Builtin {
+        name: "eprint",
+        params: &[Type::Unknown],
+        variadic: false,
+        returns: BuiltinReturn::Fixed(Type::Void),
+        emit: |_| vec![OpCode::PrintError, OpCode::LoadNull],
+    },
+    Builtin {
+        name: "string_builder",
+        params: &[],
+        variadic: false,
+        returns: BuiltinReturn::Fixed(Type::StringBuilder),
+        emit: |_| vec![OpCode::NewStringBuilder],
+    },
+];
+
+/// 按名称查找内置函数
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_builtins() {
+        let round = lookup("round").unwrap();
+        assert!(round.accepts_count(2) && !round.accepts_count(1));
+        assert_eq!((round.emit)(2), vec![OpCode::Round]);
+
+        let format = lookup("format").unwrap();
+        assert!(format.accepts_count(1) && format.accepts_count(3) && !format.accepts_count(0));
+        assert_eq!(format.param_type(0), Type::String);
+        assert_eq!(format.param_type(2), Type::Unknown);
+        assert_eq!((format.emit)(3), vec![OpCode::Format(2)]);
+
+        // 名字不能重复，否则后面的项永远查不到
+        for (i, builtin) in BUILTINS.iter().enumerate() {
+            assert!(BUILTINS[..i].iter().all(|b| b.name != builtin.name), "duplicate builtin {}", builtin.name);
+        }
+        assert!(lookup("len").is_none());
+    }
+}
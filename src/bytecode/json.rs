@@ -0,0 +1,205 @@
+// 字节码的 JSON 导出（--dump-bytecode），供工具和用户查看编译结果
+//
+// 输出格式：
+// {
+//   "code": [{"op": "LoadConst", "operand": 0, "line": 1}, ...],
+//   "constants": [{"type": "int", "value": 42}, {"type": "function", ..., "chunk": {...}}],
+//   "global_names": ["x", ...]
+// }
+// 函数常量递归包含自己的 chunk。
+
+use super::{Chunk, OpCode, Value};
+
+/// 将 chunk 导出为带缩进的 JSON
+pub fn chunk_to_json(chunk: &Chunk) -> String {
+    let mut out = String::new();
+    write_chunk(chunk, 0, &mut out);
+    out
+}
+
+/// 将字符串编码为 JSON 字符串字面量
+pub fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn write_chunk(chunk: &Chunk, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent + 1);
+    out.push_str("{\n");
+
+    out.push_str(&format!("{}\"code\": ", pad));
+    let instructions: Vec<String> = chunk.code.iter().enumerate()
+        .map(|(offset, op)| instruction_json(op, offset, chunk.lines.get(offset).copied().unwrap_or(0)))
+        .collect();
+    write_list(&instructions, indent + 1, out);
+    out.push_str(",\n");
+
+    out.push_str(&format!("{}\"constants\": ", pad));
+    let constants: Vec<String> = chunk.constants.iter()
+        .map(|value| value_json(value, indent + 2))
+        .collect();
+    write_list(&constants, indent + 1, out);
+    out.push_str(",\n");
+
+    let names: Vec<String> = chunk.global_names.iter().map(|n| json_string(n)).collect();
+    out.push_str(&format!("{}\"global_names\": [{}]\n", pad, names.join(", ")));
+
+    out.push_str(&"  ".repeat(indent));
+    out.push('}');
+}
+
+/// 每个元素占一行的数组
+fn write_list(items: &[String], indent: usize, out: &mut String) {
+    if items.is_empty() {
+        out.push_str("[]");
+        return;
+    }
+    let pad = "  ".repeat(indent + 1);
+    out.push_str("[\n");
+    for (i, item) in items.iter().enumerate() {
+        out.push_str(&pad);
+        out.push_str(item);
+        if i + 1 < items.len() {
+            out.push(',');
+        }
+        out.push('\n');
+    }
+    out.push_str(&"  ".repeat(indent));
+    out.push(']');
+}
+
+fn instruction_json(op: &OpCode, offset: usize, line: usize) -> String {
+    // 操作码名称取自变体名（去掉操作数部分）
+    let debug = format!("{:?}", op);
+    let name = debug.split('(').next().unwrap_or(&debug);
+
+    let operand = match op {
+        OpCode::LoadConst(n) | OpCode::LoadLocal(n) | OpCode::StoreLocal(n)
+        | OpCode::LoadGlobal(n) | OpCode::StoreGlobal(n) | OpCode::LoadUpvalue(n)
+        | OpCode::ArraySetLocal(n) | OpCode::ArraySetGlobal(n) | OpCode::StrAppendLocal(n) | OpCode::StrAppendGlobal(n)
+        | OpCode::Call(n) | OpCode::Closure(n) | OpCode::NewArray(n) | OpCode::NewTuple(n)
+        | OpCode::NewStruct(n) | OpCode::FieldGet(n) | OpCode::FieldSet(n) | OpCode::Format(n) => {
+            format!(", \"operand\": {}", n)
+        }
+        // 跳转指令额外给出绝对目标地址
+        OpCode::Jump(jump) | OpCode::JumpIfFalse(jump) | OpCode::JumpIfTrue(jump) | OpCode::Loop(jump) => {
+            format!(", \"operand\": {}, \"target\": {}", jump, Chunk::jump_target(offset, *jump))
+        }
+        _ => String::new(),
+    };
+
+    format!("{{\"op\": {}{}, \"line\": {}}}", json_string(name), operand, line)
+}
+
+/// 常量值；函数常量包含嵌套的 chunk，需要知道当前缩进
+fn value_json(value: &Value, indent: usize) -> String {
+    match value {
+        Value::Integer(i) => format!("{{\"type\": \"int\", \"value\": {}}}", i),
+        // JSON 数字不能表示 NaN 和无穷大，这些值以字符串输出
+        Value::Float(f) if f.is_finite() => format!("{{\"type\": \"float\", \"value\": {:?}}}", f),
+        Value::Float(f) => format!("{{\"type\": \"float\", \"value\": {}}}", json_string(&f.to_string())),
+        Value::String(s) => format!("{{\"type\": \"string\", \"value\": {}}}", json_string(s)),
+        Value::Boolean(b) => format!("{{\"type\": \"bool\", \"value\": {}}}", b),
+        Value::Char(c) => format!("{{\"type\": \"char\", \"value\": {}}}", json_string(&c.to_string())),
+        Value::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(|e| value_json(e, indent)).collect();
+            format!("{{\"type\": \"array\", \"elements\": [{}]}}", elements.join(", "))
+        }
+        Value::Tuple(elements) => {
+            let elements: Vec<String> = elements.iter().map(|e| value_json(e, indent)).collect();
+            format!("{{\"type\": \"tuple\", \"elements\": [{}]}}", elements.join(", "))
+        }
+        Value::Struct(s) => {
+            let fields: Vec<String> = s.fields.iter().map(|f| value_json(f, indent)).collect();
+            format!(
+                "{{\"type\": \"struct\", \"name\": {}, \"fields\": [{}]}}",
+                json_string(&s.struct_name),
+                fields.join(", ")
+            )
+        }
+        Value::Function(function) => {
+            let mut chunk = String::new();
+            write_chunk(&function.chunk, indent, &mut chunk);
+            format!(
+                "{{\"type\": \"function\", \"name\": {}, \"arity\": {}, \"locals_count\": {}, \"chunk\": {}}}",
+                json_string(&function.name),
+                function.arity,
+                function.locals_count,
+                chunk
+            )
+        }
+        Value::Null => "{\"type\": \"null\"}".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use crate::compiler::Compiler;
+    use crate::lexer::{Lexer, TokenPreprocessor};
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_chunk_json_golden() {
+        let source = "fn add(a, b) { return a + b; }\nprint(add(2, \"x\\n\"));";
+        let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        let chunk = Compiler::new().compile(program).unwrap();
+
+        let expected = r#"{
+  "code": [
+    {"op": "LoadConst", "operand": 0, "line": 0},
+    {"op": "StoreGlobal", "operand": 0, "line": 0},
+    {"op": "Pop", "line": 0},
+    {"op": "LoadGlobal", "operand": 0, "line": 0},
+    {"op": "LoadConst", "operand": 1, "line": 0},
+    {"op": "LoadConst", "operand": 2, "line": 0},
+    {"op": "Call", "operand": 2, "line": 0},
+    {"op": "Print", "line": 0},
+    {"op": "Halt", "line": 0}
+  ],
+  "constants": [
+    {"type": "function", "name": "add", "arity": 2, "locals_count": 2, "chunk": {
+      "code": [
+        {"op": "LoadLocal", "operand": 0, "line": 0},
+        {"op": "LoadLocal", "operand": 1, "line": 0},
+        {"op": "Add", "line": 0},
+        {"op": "Return", "line": 0},
+        {"op": "LoadNull", "line": 0},
+        {"op": "Return", "line": 0}
+      ],
+      "constants": [],
+      "global_names": []
+    }},
+    {"type": "int", "value": 2},
+    {"type": "string", "value": "x\n"}
+  ],
+  "global_names": ["add"]
+}"#;
+        assert_eq!(chunk_to_json(&chunk), expected);
+    }
+
+    #[test]
+    fn test_value_json() {
+        assert_eq!(value_json(&Value::Float(1.0), 0), r#"{"type": "float", "value": 1.0}"#);
+        assert_eq!(value_json(&Value::Float(f64::NAN), 0), r#"{"type": "float", "value": "NaN"}"#);
+        assert_eq!(value_json(&Value::Char('"'), 0), r#"{"type": "char", "value": "\""}"#);
+        assert_eq!(
+            value_json(&Value::Array(Rc::new(vec![Value::Boolean(true), Value::Null])), 0),
+            r#"{"type": "array", "elements": [{"type": "bool", "value": true}, {"type": "null"}]}"#
+        );
+    }
+}
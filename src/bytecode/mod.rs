@@ -1,17 +1,28 @@
+pub mod json;
+pub mod verifier;
 pub mod serializer;
 
+use std::rc::Rc;
+
+use crate::formatter::escape_literal;
+
 /// Zero语言的字节码指令集
 #[derive(Debug, Clone, PartialEq)]
 pub enum OpCode {
     // 常量加载
     LoadConst(usize),      // 加载常量池中的值
     LoadNull,              // 加载null值
+    LoadTrue,              // 加载 true（立即数，不占用常量池）
+    LoadFalse,             // 加载 false
+    LoadZero,              // 加载整数 0
+    LoadOne,               // 加载整数 1
     
     // 变量操作
     LoadLocal(usize),      // 加载局部变量
     StoreLocal(usize),     // 存储局部变量
     LoadGlobal(usize),     // 加载全局变量
     StoreGlobal(usize),    // 存储全局变量
+    LoadUpvalue(usize),    // 加载闭包捕获的变量
     
     // 算术运算
     Add,                   // 加法
@@ -19,7 +30,23 @@ pub enum OpCode {
     Multiply,              // 乘法
     Divide,                // 除法
     Modulo,                // 取模
+    Power,                 // 乘方 (int ** int -> int，含 float 时 -> float)
+    Round,                 // 四舍五入到指定小数位数 (number, digits -> float)
     Negate,                // 取负
+
+    // 字符串操作
+    Concat,                // 字符串拼接 (string, string -> string)
+    ToString,              // 转换为字符串 (value -> string)
+    StrLen,                // 获取字符串长度（字符数） (string -> length)
+    Format(usize),         // 按格式字符串填充参数（参数：占位符参数的数量） (format, v1, ..., vn -> string)
+    PrettyFormat,          // 多行缩进格式化嵌套的数组和结构体 (value -> string)
+    StrSplit,              // 按分隔符拆分字符串，分隔符为空时拆成单个字符 (string, sep -> [string])
+    StrJoin,               // 用分隔符连接字符串数组 ([string], sep -> string)
+    StrReplace,            // 替换所有出现的子串 (string, from, to -> string)
+    StrContains,           // 是否包含子串 (string, sub -> bool)
+    NewStringBuilder,      // 创建空的字符串构建器 (-> string)
+    StrAppendLocal(usize), // 在局部变量的字符串末尾原地追加（参数：槽位） (value -> null)
+    StrAppendGlobal(usize),// 在全局变量的字符串末尾原地追加（参数：槽位） (value -> null)
     
     // 比较运算
     Equal,                 // 相等
@@ -35,20 +62,28 @@ pub enum OpCode {
     Or,                    // 逻辑或
     
     // 控制流
-    Jump(usize),           // 无条件跳转
-    JumpIfFalse(usize),    // 条件跳转（假）
-    JumpIfTrue(usize),     // 条件跳转（真）
-    Loop(usize),           // 循环跳转
+    // 跳转偏移量是相对于跳转指令的下一条指令的有符号偏移
+    Jump(isize),           // 无条件跳转
+    JumpIfFalse(isize),    // 条件跳转（假）
+    JumpIfTrue(isize),     // 条件跳转（真）
+    Loop(isize),           // 循环跳转（向后，偏移为负）
     
     // 函数相关
     Call(usize),           // 函数调用（参数数量）
     Return,                // 返回
+    Closure(usize),        // 创建闭包（参数：捕获的变量数量） (function, v1, ..., vn -> closure)
     
     // 数组操作
     NewArray(usize),       // 创建新数组（参数：元素数量）
     ArrayGet,              // 获取数组元素 (array, index -> value)
     ArraySet,              // 设置数组元素 (array, index, value -> value)
     ArrayLen,              // 获取数组长度 (array -> length)
+    ArrayConcat,           // 数组拼接 (array, array -> array)
+    ArrayFill,             // 创建 count 个相同元素的数组 (value, count -> array)
+    NewTuple(usize),       // 创建元组（参数：元素数量）；元素用 ArrayGet 按下标读取
+    ArraySetLocal(usize),  // 直接设置局部变量中的数组元素（参数：槽位） (index, value -> array)
+    ArraySetGlobal(usize), // 直接设置全局变量中的数组元素（参数：槽位） (index, value -> array)
+    Slice,                 // 范围索引 (array/string, start, end -> array/string)
     
     // 结构体操作
     NewStruct(usize),      // 创建新结构体（参数：字段数量）
@@ -58,12 +93,237 @@ pub enum OpCode {
     // 栈操作
     Pop,                   // 弹出栈顶
     Dup,                   // 复制栈顶
+    Swap,                  // 交换栈顶两个值
+    DeepClone,             // 深拷贝栈顶的值，数组不再与原值共享 (value -> value)
     
     // 其他
     Print,                 // 打印
+    Write,                 // 打印但不换行
+    PrintError,            // 打印到标准错误
+    Input,                 // 读取一行输入 (-> string)
     Halt,                  // 停止执行
 }
 
+/// 指令的操作数（序列化时按类型选择无符号或有符号 LEB128 编码）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    None,
+    Unsigned(usize),  // 常量、槽位、字段下标和参数个数等
+    Signed(isize),    // 跳转偏移
+}
+
+impl OpCode {
+    /// 序列化使用的固定编号，与枚举中的声明顺序无关。
+    /// 编号一旦写入 .zbc 文件就不能修改或复用，新指令使用未占用的编号
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            OpCode::LoadConst(_) => 0x00,
+            OpCode::LoadNull => 0x01,
+            OpCode::LoadLocal(_) => 0x02,
+            OpCode::StoreLocal(_) => 0x03,
+            OpCode::LoadGlobal(_) => 0x04,
+            OpCode::StoreGlobal(_) => 0x05,
+            OpCode::LoadUpvalue(_) => 0x06,
+            OpCode::LoadTrue => 0x07,
+            OpCode::LoadFalse => 0x08,
+            OpCode::LoadZero => 0x09,
+            OpCode::LoadOne => 0x0A,
+            OpCode::Add => 0x10,
+            OpCode::Subtract => 0x11,
+            OpCode::Multiply => 0x12,
+            OpCode::Divide => 0x13,
+            OpCode::Modulo => 0x14,
+            OpCode::Negate => 0x15,
+            OpCode::Power => 0x16,
+            OpCode::Round => 0x17,
+            OpCode::Equal => 0x20,
+            OpCode::NotEqual => 0x21,
+            OpCode::Greater => 0x22,
+            OpCode::GreaterEqual => 0x23,
+            OpCode::Less => 0x24,
+            OpCode::LessEqual => 0x25,
+            OpCode::Not => 0x30,
+            OpCode::And => 0x31,
+            OpCode::Or => 0x32,
+            OpCode::Jump(_) => 0x40,
+            OpCode::JumpIfFalse(_) => 0x41,
+            OpCode::JumpIfTrue(_) => 0x42,
+            OpCode::Loop(_) => 0x43,
+            OpCode::Call(_) => 0x50,
+            OpCode::Return => 0x51,
+            OpCode::Closure(_) => 0x52,
+            OpCode::NewArray(_) => 0x60,
+            OpCode::ArrayGet => 0x61,
+            OpCode::ArraySet => 0x62,
+            OpCode::ArrayLen => 0x63,
+            OpCode::NewStruct(_) => 0x64,
+            OpCode::FieldGet(_) => 0x65,
+            OpCode::FieldSet(_) => 0x66,
+            OpCode::ArrayConcat => 0x67,
+            OpCode::Slice => 0x68,
+            OpCode::ArraySetLocal(_) => 0x69,
+            OpCode::ArraySetGlobal(_) => 0x6A,
+            OpCode::ArrayFill => 0x6B,
+            OpCode::NewTuple(_) => 0x6C,
+            OpCode::Pop => 0x70,
+            OpCode::Dup => 0x71,
+            OpCode::Swap => 0x72,
+            OpCode::DeepClone => 0x73,
+            OpCode::Concat => 0x80,
+            OpCode::ToString => 0x81,
+            OpCode::StrLen => 0x82,
+            OpCode::Format(_) => 0x83,
+            OpCode::PrettyFormat => 0x84,
+            OpCode::StrSplit => 0x85,
+            OpCode::StrJoin => 0x86,
+            OpCode::StrReplace => 0x87,
+            OpCode::StrContains => 0x88,
+            OpCode::NewStringBuilder => 0x89,
+            OpCode::StrAppendLocal(_) => 0x8A,
+            OpCode::StrAppendGlobal(_) => 0x8B,
+            OpCode::Print => 0xF0,
+            OpCode::Write => 0xF1,
+            OpCode::PrintError => 0xF2,
+            OpCode::Input => 0xF3,
+            OpCode::Halt => 0xFF,
+        }
+    }
+
+    /// `to_u8` 的逆映射；带操作数的指令返回操作数为 0 的形式，再由 `with_operand` 填入
+    pub fn from_u8(code: u8) -> Option<OpCode> {
+        Some(match code {
+            0x00 => OpCode::LoadConst(0),
+            0x01 => OpCode::LoadNull,
+            0x02 => OpCode::LoadLocal(0),
+            0x03 => OpCode::StoreLocal(0),
+            0x04 => OpCode::LoadGlobal(0),
+            0x05 => OpCode::StoreGlobal(0),
+            0x06 => OpCode::LoadUpvalue(0),
+            0x07 => OpCode::LoadTrue,
+            0x08 => OpCode::LoadFalse,
+            0x09 => OpCode::LoadZero,
+            0x0A => OpCode::LoadOne,
+            0x10 => OpCode::Add,
+            0x11 => OpCode::Subtract,
+            0x12 => OpCode::Multiply,
+            0x13 => OpCode::Divide,
+            0x14 => OpCode::Modulo,
+            0x15 => OpCode::Negate,
+            0x16 => OpCode::Power,
+            0x17 => OpCode::Round,
+            0x20 => OpCode::Equal,
+            0x21 => OpCode::NotEqual,
+            0x22 => OpCode::Greater,
+            0x23 => OpCode::GreaterEqual,
+            0x24 => OpCode::Less,
+            0x25 => OpCode::LessEqual,
+            0x30 => OpCode::Not,
+            0x31 => OpCode::And,
+            0x32 => OpCode::Or,
+            0x40 => OpCode::Jump(0),
+            0x41 => OpCode::JumpIfFalse(0),
+            0x42 => OpCode::JumpIfTrue(0),
+            0x43 => OpCode::Loop(0),
+            0x50 => OpCode::Call(0),
+            0x51 => OpCode::Return,
+            0x52 => OpCode::Closure(0),
+            0x60 => OpCode::NewArray(0),
+            0x61 => OpCode::ArrayGet,
+            0x62 => OpCode::ArraySet,
+            0x63 => OpCode::ArrayLen,
+            0x64 => OpCode::NewStruct(0),
+            0x65 => OpCode::FieldGet(0),
+            0x66 => OpCode::FieldSet(0),
+            0x67 => OpCode::ArrayConcat,
+            0x68 => OpCode::Slice,
+            0x69 => OpCode::ArraySetLocal(0),
+            0x6A => OpCode::ArraySetGlobal(0),
+            0x6B => OpCode::ArrayFill,
+            0x6C => OpCode::NewTuple(0),
+            0x70 => OpCode::Pop,
+            0x71 => OpCode::Dup,
+            0x72 => OpCode::Swap,
+            0x73 => OpCode::DeepClone,
+            0x80 => OpCode::Concat,
+            0x81 => OpCode::ToString,
+            0x82 => OpCode::StrLen,
+            0x83 => OpCode::Format(0),
+            0x84 => OpCode::PrettyFormat,
+            0x85 => OpCode::StrSplit,
+            0x86 => OpCode::StrJoin,
+            0x87 => OpCode::StrReplace,
+            0x88 => OpCode::StrContains,
+            0x89 => OpCode::NewStringBuilder,
+            0x8A => OpCode::StrAppendLocal(0),
+            0x8B => OpCode::StrAppendGlobal(0),
+            0xF0 => OpCode::Print,
+            0xF1 => OpCode::Write,
+            0xF2 => OpCode::PrintError,
+            0xF3 => OpCode::Input,
+            0xFF => OpCode::Halt,
+            _ => return None,
+        })
+    }
+
+    /// 指令携带的操作数
+    pub fn operand(&self) -> Operand {
+        match self {
+            OpCode::LoadConst(n)
+            | OpCode::LoadLocal(n)
+            | OpCode::StoreLocal(n)
+            | OpCode::LoadGlobal(n)
+            | OpCode::StoreGlobal(n)
+            | OpCode::LoadUpvalue(n)
+            | OpCode::Call(n)
+            | OpCode::Closure(n)
+            | OpCode::NewArray(n)
+            | OpCode::NewTuple(n)
+            | OpCode::NewStruct(n)
+            | OpCode::FieldGet(n)
+            | OpCode::FieldSet(n)
+            | OpCode::ArraySetLocal(n)
+            | OpCode::ArraySetGlobal(n)
+            | OpCode::StrAppendLocal(n)
+            | OpCode::StrAppendGlobal(n)
+            | OpCode::Format(n) => Operand::Unsigned(*n),
+            OpCode::Jump(offset)
+            | OpCode::JumpIfFalse(offset)
+            | OpCode::JumpIfTrue(offset)
+            | OpCode::Loop(offset) => Operand::Signed(*offset),
+            _ => Operand::None,
+        }
+    }
+
+    /// 替换指令的操作数；操作数的种类与指令不符时原样返回
+    pub fn with_operand(self, operand: Operand) -> OpCode {
+        match (self, operand) {
+            (OpCode::LoadConst(_), Operand::Unsigned(n)) => OpCode::LoadConst(n),
+            (OpCode::LoadLocal(_), Operand::Unsigned(n)) => OpCode::LoadLocal(n),
+            (OpCode::StoreLocal(_), Operand::Unsigned(n)) => OpCode::StoreLocal(n),
+            (OpCode::LoadGlobal(_), Operand::Unsigned(n)) => OpCode::LoadGlobal(n),
+            (OpCode::StoreGlobal(_), Operand::Unsigned(n)) => OpCode::StoreGlobal(n),
+            (OpCode::LoadUpvalue(_), Operand::Unsigned(n)) => OpCode::LoadUpvalue(n),
+            (OpCode::Call(_), Operand::Unsigned(n)) => OpCode::Call(n),
+            (OpCode::Closure(_), Operand::Unsigned(n)) => OpCode::Closure(n),
+            (OpCode::NewArray(_), Operand::Unsigned(n)) => OpCode::NewArray(n),
+            (OpCode::NewTuple(_), Operand::Unsigned(n)) => OpCode::NewTuple(n),
+            (OpCode::NewStruct(_), Operand::Unsigned(n)) => OpCode::NewStruct(n),
+            (OpCode::FieldGet(_), Operand::Unsigned(n)) => OpCode::FieldGet(n),
+            (OpCode::FieldSet(_), Operand::Unsigned(n)) => OpCode::FieldSet(n),
+            (OpCode::ArraySetLocal(_), Operand::Unsigned(n)) => OpCode::ArraySetLocal(n),
+            (OpCode::ArraySetGlobal(_), Operand::Unsigned(n)) => OpCode::ArraySetGlobal(n),
+            (OpCode::StrAppendLocal(_), Operand::Unsigned(n)) => OpCode::StrAppendLocal(n),
+            (OpCode::StrAppendGlobal(_), Operand::Unsigned(n)) => OpCode::StrAppendGlobal(n),
+            (OpCode::Format(_), Operand::Unsigned(n)) => OpCode::Format(n),
+            (OpCode::Jump(_), Operand::Signed(offset)) => OpCode::Jump(offset),
+            (OpCode::JumpIfFalse(_), Operand::Signed(offset)) => OpCode::JumpIfFalse(offset),
+            (OpCode::JumpIfTrue(_), Operand::Signed(offset)) => OpCode::JumpIfTrue(offset),
+            (OpCode::Loop(_), Operand::Signed(offset)) => OpCode::Loop(offset),
+            (op, _) => op,
+        }
+    }
+}
+
 /// 常量值类型
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
@@ -72,7 +332,8 @@ pub enum Value {
     String(String),
     Boolean(bool),
     Char(char),            // 字符值
-    Array(Vec<Value>),     // 数组值
+    Array(Rc<Vec<Value>>), // 数组值（写时复制：共享的数组在修改时才复制）
+    Tuple(Rc<Vec<Value>>), // 元组值（元素不可修改）
     Struct(StructValue),   // 结构体值
     Function(Function),
     Null,
@@ -86,8 +347,35 @@ pub struct StructValue {
 }
 
 impl Value {
+    /// 常量去重使用的相等：浮点数按位比较，0.0 与 -0.0 不同，同一位模式的 NaN 相同；
+    /// 数组、元组、结构体和函数常量递归比较，避免嵌套的浮点数被 `PartialEq` 合并
+    pub fn identical(&self, other: &Value) -> bool {
+        let all_identical = |a: &[Value], b: &[Value]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.identical(y))
+        };
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::Array(a), Value::Array(b)) | (Value::Tuple(a), Value::Tuple(b)) => all_identical(a, b),
+            (Value::Struct(a), Value::Struct(b)) => {
+                a.struct_name == b.struct_name && all_identical(&a.fields, &b.fields)
+            }
+            (Value::Function(a), Value::Function(b)) => {
+                a.name == b.name
+                    && a.arity == b.arity
+                    && a.locals_count == b.locals_count
+                    && a.chunk.code == b.chunk.code
+                    && a.chunk.lines == b.chunk.lines
+                    && a.chunk.global_names == b.chunk.global_names
+                    && all_identical(&a.chunk.constants, &b.chunk.constants)
+                    && all_identical(&a.upvalues, &b.upvalues)
+            }
+            (a, b) => a == b,
+        }
+    }
+
     pub fn to_string(&self) -> String {
         match self {
+            // 数值用 Rust 的 Display 格式，与区域设置无关；须与旧解释器的 Value::to_string 保持一致
             Value::Integer(i) => i.to_string(),
             Value::Float(f) => f.to_string(),
             Value::String(s) => s.clone(),
@@ -97,10 +385,97 @@ impl Value {
                 let elements: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
                 format!("[{}]", elements.join(", "))
             }
+            Value::Tuple(elements) => Self::format_tuple(elements, Value::to_string),
             Value::Struct(s) => {
                 format!("{} {{ {} fields }}", s.struct_name, s.fields.len())
             }
-            Value::Function(_) => "<function>".to_string(),
+            Value::Function(function) => format!("<fn {}/{}>", function.name, function.arity),
+            Value::Null => "null".to_string(),
+        }
+    }
+
+    /// 调试格式（供交互式回显使用）：字符串和字符带引号并转义，
+    /// 集合按元素递归使用调试格式，以便与其他值区分；`print` 仍使用 `to_string`
+    pub fn debug_format(&self) -> String {
+        match self {
+            Value::String(s) => format!("\"{}\"", escape_literal(s, '"')),
+            Value::Char(c) => format!("'{}'", escape_literal(&c.to_string(), '\'')),
+            Value::Array(arr) => {
+                let elements: Vec<String> = arr.iter().map(|v| v.debug_format()).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Tuple(elements) => Self::format_tuple(elements, Value::debug_format),
+            Value::Struct(s) if s.fields.is_empty() => format!("{} {{}}", s.struct_name),
+            Value::Struct(s) => {
+                let fields: Vec<String> = s.fields.iter().map(|v| v.debug_format()).collect();
+                format!("{} {{ {} }}", s.struct_name, fields.join(", "))
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// 元组写成 `(a, b)`，单元素元组带尾随逗号 `(a,)`，与字面量的写法一致
+    fn format_tuple(elements: &[Value], format: fn(&Value) -> String) -> String {
+        let elements: Vec<String> = elements.iter().map(format).collect();
+        match elements.as_slice() {
+            [single] => format!("({},)", single),
+            _ => format!("({})", elements.join(", ")),
+        }
+    }
+
+    /// 深拷贝：嵌套的数组都重新分配，结果与原值不共享任何数组
+    pub fn deep_clone(&self) -> Value {
+        match self {
+            Value::Array(arr) => Value::Array(Rc::new(arr.iter().map(Value::deep_clone).collect())),
+            Value::Tuple(elements) => Value::Tuple(Rc::new(elements.iter().map(Value::deep_clone).collect())),
+            Value::Struct(s) => Value::Struct(StructValue {
+                struct_name: s.struct_name.clone(),
+                fields: s.fields.iter().map(Value::deep_clone).collect(),
+            }),
+            other => other.clone(),
+        }
+    }
+
+    /// 多行缩进格式（pretty_print 使用）：非空的数组和结构体每个元素占一行，
+    /// 按嵌套层级缩进 4 个空格；其余值与 debug_format 相同
+    pub fn pretty_format(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        let (open, close, items) = match self {
+            Value::Array(arr) if !arr.is_empty() => ("[".to_string(), "]", arr.as_ref()),
+            Value::Struct(s) if !s.fields.is_empty() => (format!("{} {{", s.struct_name), "}", &s.fields),
+            other => {
+                out.push_str(&other.debug_format());
+                return;
+            }
+        };
+        out.push_str(&open);
+        out.push('\n');
+        for item in items.iter() {
+            out.push_str(&"    ".repeat(depth + 1));
+            item.write_pretty(out, depth + 1);
+            out.push_str(",\n");
+        }
+        out.push_str(&"    ".repeat(depth));
+        out.push_str(close);
+    }
+
+    /// 值的类型名（用于运行时错误信息），结构体使用结构体名
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Integer(_) => "int".to_string(),
+            Value::Float(_) => "float".to_string(),
+            Value::String(_) => "string".to_string(),
+            Value::Boolean(_) => "bool".to_string(),
+            Value::Char(_) => "char".to_string(),
+            Value::Array(_) => "array".to_string(),
+            Value::Tuple(_) => "tuple".to_string(),
+            Value::Struct(s) => s.struct_name.clone(),
+            Value::Function(_) => "function".to_string(),
             Value::Null => "null".to_string(),
         }
     }
@@ -139,9 +514,10 @@ impl Value {
         }
     }
     
+    /// 可变访问数组；数组被共享时先复制一份
     pub fn as_array_mut(&mut self) -> Option<&mut Vec<Value>> {
         match self {
-            Value::Array(arr) => Some(arr),
+            Value::Array(arr) => Some(Rc::make_mut(arr)),
             _ => None,
         }
     }
@@ -168,6 +544,7 @@ pub struct Function {
     pub arity: usize,          // 参数数量
     pub chunk: Chunk,           // 函数字节码
     pub locals_count: usize,    // 局部变量数量
+    pub upvalues: Vec<Value>,   // 闭包捕获的变量值（运行时由 Closure 指令填充）
 }
 
 /// 字节码块
@@ -176,6 +553,7 @@ pub struct Chunk {
     pub code: Vec<OpCode>,      // 指令序列
     pub constants: Vec<Value>,  // 常量池
     pub lines: Vec<usize>,      // 行号信息（用于错误报告）
+    pub global_names: Vec<String>,  // 全局变量槽位对应的名称（仅顶层 chunk 使用）
 }
 
 impl Chunk {
@@ -184,6 +562,7 @@ impl Chunk {
             code: Vec::new(),
             constants: Vec::new(),
             lines: Vec::new(),
+            global_names: Vec::new(),
         }
     }
 
@@ -199,6 +578,79 @@ impl Chunk {
         self.constants.len() - 1
     }
 
+    /// 链接另一个单独编译的 chunk：把它的代码追加到本 chunk 之后，返回追加部分的起始地址
+    ///
+    /// - 本 chunk 末尾的 `Halt` 被去掉，执行完本 chunk 后接着执行 other 的代码
+    /// - 跳转使用相对偏移，追加后不需要重写
+    /// - 常量池合并时去掉重复的常量，`LoadConst` 重定位到合并后的下标
+    /// - 全局变量按名称合并，`LoadGlobal`/`StoreGlobal`（包括函数常量中的）重定位到合并后的槽位
+    pub fn merge(&mut self, other: Chunk) -> usize {
+        if self.code.last() == Some(&OpCode::Halt) {
+            self.code.pop();
+            self.lines.pop();
+        }
+
+        let global_map: Vec<usize> = other.global_names.iter()
+            .map(|name| match self.global_names.iter().position(|n| n == name) {
+                Some(slot) => slot,
+                None => {
+                    self.global_names.push(name.clone());
+                    self.global_names.len() - 1
+                }
+            })
+            .collect();
+
+        let constant_map: Vec<usize> = other.constants.into_iter()
+            .map(|mut constant| {
+                if let Value::Function(function) = &mut constant {
+                    function.chunk.relocate_globals(&global_map);
+                }
+                self.intern_constant(constant)
+            })
+            .collect();
+
+        let start = self.code.len();
+        for (op, line) in other.code.into_iter().zip(other.lines) {
+            let op = match op {
+                OpCode::LoadConst(idx) => OpCode::LoadConst(constant_map[idx]),
+                OpCode::LoadGlobal(slot) => OpCode::LoadGlobal(global_map[slot]),
+                OpCode::StoreGlobal(slot) => OpCode::StoreGlobal(global_map[slot]),
+                OpCode::ArraySetGlobal(slot) => OpCode::ArraySetGlobal(global_map[slot]),
+                OpCode::StrAppendGlobal(slot) => OpCode::StrAppendGlobal(global_map[slot]),
+                op => op,
+            };
+            self.write(op, line);
+        }
+        start
+    }
+
+    /// 添加常量，常量池中已有相同的值时复用它的下标
+    /// （按 `Value::identical` 比较，0.0 和 -0.0 不会被合并）
+    fn intern_constant(&mut self, value: Value) -> usize {
+        match self.constants.iter().position(|existing| existing.identical(&value)) {
+            Some(idx) => idx,
+            None => self.add_constant(value),
+        }
+    }
+
+    /// 按 global_map（旧槽位 -> 新槽位）重写函数 chunk 中的全局变量槽位，递归处理嵌套的函数常量
+    fn relocate_globals(&mut self, global_map: &[usize]) {
+        for op in &mut self.code {
+            match op {
+                OpCode::LoadGlobal(slot) | OpCode::StoreGlobal(slot) | OpCode::ArraySetGlobal(slot)
+                | OpCode::StrAppendGlobal(slot) => {
+                    *slot = global_map[*slot]
+                }
+                _ => {}
+            }
+        }
+        for constant in &mut self.constants {
+            if let Value::Function(function) = constant {
+                function.chunk.relocate_globals(global_map);
+            }
+        }
+    }
+
     /// 获取指令数量
     pub fn len(&self) -> usize {
         self.code.len()
@@ -212,6 +664,19 @@ impl Chunk {
         }
     }
 
+    /// 全局变量槽位的显示名称（函数 chunk 没有名称表时为空）
+    fn global_label(&self, slot: usize) -> String {
+        match self.global_names.get(slot) {
+            Some(name) => format!("'{}'", name),
+            None => String::new(),
+        }
+    }
+
+    /// 计算位于 offset 处的跳转指令的绝对目标地址
+    pub fn jump_target(offset: usize, jump: isize) -> usize {
+        (offset as isize + 1 + jump) as usize
+    }
+
     pub fn disassemble_instruction(&self, offset: usize, op: &OpCode) {
         print!("{:04} ", offset);
         
@@ -227,14 +692,20 @@ impl Chunk {
             }
             OpCode::LoadLocal(idx) => println!("LoadLocal {}", idx),
             OpCode::StoreLocal(idx) => println!("StoreLocal {}", idx),
-            OpCode::LoadGlobal(idx) => println!("LoadGlobal {}", idx),
-            OpCode::StoreGlobal(idx) => println!("StoreGlobal {}", idx),
-            OpCode::Jump(offset) => println!("Jump -> {}", offset),
-            OpCode::JumpIfFalse(offset) => println!("JumpIfFalse -> {}", offset),
-            OpCode::JumpIfTrue(offset) => println!("JumpIfTrue -> {}", offset),
-            OpCode::Loop(offset) => println!("Loop -> {}", offset),
+            OpCode::LoadGlobal(slot) => println!("LoadGlobal {} {}", slot, self.global_label(*slot)),
+            OpCode::StoreGlobal(slot) => println!("StoreGlobal {} {}", slot, self.global_label(*slot)),
+            OpCode::ArraySetGlobal(slot) => println!("ArraySetGlobal {} {}", slot, self.global_label(*slot)),
+            OpCode::StrAppendGlobal(slot) => println!("StrAppendGlobal {} {}", slot, self.global_label(*slot)),
+            OpCode::Jump(jump) => println!("Jump {:+} -> {}", jump, Self::jump_target(offset, *jump)),
+            OpCode::JumpIfFalse(jump) => println!("JumpIfFalse {:+} -> {}", jump, Self::jump_target(offset, *jump)),
+            OpCode::JumpIfTrue(jump) => println!("JumpIfTrue {:+} -> {}", jump, Self::jump_target(offset, *jump)),
+            OpCode::Loop(jump) => println!("Loop {:+} -> {}", jump, Self::jump_target(offset, *jump)),
+            OpCode::LoadUpvalue(idx) => println!("LoadUpvalue {}", idx),
             OpCode::Call(arity) => println!("Call({})", arity),
+            OpCode::Closure(count) => println!("Closure({})", count),
             OpCode::NewArray(size) => println!("NewArray({})", size),
+            OpCode::NewTuple(size) => println!("NewTuple({})", size),
+            OpCode::Format(count) => println!("Format({})", count),
             OpCode::NewStruct(field_count) => println!("NewStruct({})", field_count),
             OpCode::FieldGet(idx) => println!("FieldGet({})", idx),
             OpCode::FieldSet(idx) => println!("FieldSet({})", idx),
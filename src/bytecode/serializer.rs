@@ -1,10 +1,11 @@
-use super::{Chunk, OpCode, Value, Function};
+use super::{Chunk, OpCode, Operand, Value, Function};
+use std::rc::Rc;
 use std::io::{Write, Read, Result as IoResult, Error, ErrorKind};
 
 /// Zero字节码文件魔数 "ZERO"
 const MAGIC: [u8; 4] = [0x5A, 0x45, 0x52, 0x4F];
 const VERSION_MAJOR: u16 = 0;
-const VERSION_MINOR: u16 = 1;
+const VERSION_MINOR: u16 = 4;  // 0.4: 全局变量按槽位访问，文件末尾附带槽位名称表
 
 /// 字节码序列化器
 pub struct BytecodeSerializer;
@@ -34,6 +35,14 @@ impl BytecodeSerializer {
             writer.write_all(&(*line as u32).to_le_bytes())?;
         }
 
+        // 写入全局变量名称表
+        writer.write_all(&(chunk.global_names.len() as u32).to_le_bytes())?;
+        for name in &chunk.global_names {
+            let bytes = name.as_bytes();
+            writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+            writer.write_all(bytes)?;
+        }
+
         Ok(())
     }
 
@@ -68,7 +77,14 @@ impl BytecodeSerializer {
             Value::Array(arr) => {
                 writer.write_all(&[0x05])?;
                 writer.write_all(&(arr.len() as u32).to_le_bytes())?;
-                for elem in arr {
+                for elem in arr.iter() {
+                    Self::write_value(elem, writer)?;
+                }
+            }
+            Value::Tuple(elements) => {
+                writer.write_all(&[0x0A])?;
+                writer.write_all(&(elements.len() as u32).to_le_bytes())?;
+                for elem in elements.iter() {
                     Self::write_value(elem, writer)?;
                 }
             }
@@ -103,6 +119,7 @@ impl BytecodeSerializer {
         // 写入参数数量和局部变量数量
         writer.write_all(&(func.arity as u32).to_le_bytes())?;
         writer.write_all(&(func.locals_count as u32).to_le_bytes())?;
+        // upvalues 只在运行时由 Closure 指令填充，常量池中的函数不包含捕获值
 
         // 递归写入函数的Chunk
         writer.write_all(&(func.chunk.constants.len() as u32).to_le_bytes())?;
@@ -123,91 +140,44 @@ impl BytecodeSerializer {
         Ok(())
     }
 
-    /// 写入OpCode
-    fn write_opcode<W: Write>(opcode: &OpCode, writer: &mut W) -> IoResult<()> {
-        match opcode {
-            OpCode::LoadConst(idx) => {
-                writer.write_all(&[0x00])?;
-                writer.write_all(&(*idx as u32).to_le_bytes())?;
-            }
-            OpCode::LoadNull => writer.write_all(&[0x01])?,
-            OpCode::LoadLocal(slot) => {
-                writer.write_all(&[0x02])?;
-                writer.write_all(&(*slot as u32).to_le_bytes())?;
-            }
-            OpCode::StoreLocal(slot) => {
-                writer.write_all(&[0x03])?;
-                writer.write_all(&(*slot as u32).to_le_bytes())?;
-            }
-            OpCode::LoadGlobal(idx) => {
-                writer.write_all(&[0x04])?;
-                writer.write_all(&(*idx as u32).to_le_bytes())?;
-            }
-            OpCode::StoreGlobal(idx) => {
-                writer.write_all(&[0x05])?;
-                writer.write_all(&(*idx as u32).to_le_bytes())?;
-            }
-            OpCode::Add => writer.write_all(&[0x10])?,
-            OpCode::Subtract => writer.write_all(&[0x11])?,
-            OpCode::Multiply => writer.write_all(&[0x12])?,
-            OpCode::Divide => writer.write_all(&[0x13])?,
-            OpCode::Modulo => writer.write_all(&[0x14])?,
-            OpCode::Negate => writer.write_all(&[0x15])?,
-            OpCode::Equal => writer.write_all(&[0x20])?,
-            OpCode::NotEqual => writer.write_all(&[0x21])?,
-            OpCode::Greater => writer.write_all(&[0x22])?,
-            OpCode::GreaterEqual => writer.write_all(&[0x23])?,
-            OpCode::Less => writer.write_all(&[0x24])?,
-            OpCode::LessEqual => writer.write_all(&[0x25])?,
-            OpCode::Not => writer.write_all(&[0x30])?,
-            OpCode::And => writer.write_all(&[0x31])?,
-            OpCode::Or => writer.write_all(&[0x32])?,
-            OpCode::Jump(offset) => {
-                writer.write_all(&[0x40])?;
-                writer.write_all(&(*offset as u32).to_le_bytes())?;
-            }
-            OpCode::JumpIfFalse(offset) => {
-                writer.write_all(&[0x41])?;
-                writer.write_all(&(*offset as u32).to_le_bytes())?;
-            }
-            OpCode::JumpIfTrue(offset) => {
-                writer.write_all(&[0x42])?;
-                writer.write_all(&(*offset as u32).to_le_bytes())?;
-            }
-            OpCode::Loop(offset) => {
-                writer.write_all(&[0x43])?;
-                writer.write_all(&(*offset as u32).to_le_bytes())?;
-            }
-            OpCode::Call(argc) => {
-                writer.write_all(&[0x50])?;
-                writer.write_all(&(*argc as u32).to_le_bytes())?;
+    /// 写入指令操作数（无符号 LEB128 变长编码，小于 128 的值只占 1 字节）
+    fn write_operand<W: Write>(value: usize, writer: &mut W) -> IoResult<()> {
+        let mut value = value as u64;
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                writer.write_all(&[byte])?;
+                return Ok(());
             }
-            OpCode::Return => writer.write_all(&[0x51])?,
-            OpCode::NewArray(size) => {
-                writer.write_all(&[0x60])?;
-                writer.write_all(&(*size as u32).to_le_bytes())?;
-            }
-            OpCode::ArrayGet => writer.write_all(&[0x61])?,
-            OpCode::ArraySet => writer.write_all(&[0x62])?,
-            OpCode::ArrayLen => writer.write_all(&[0x63])?,
-            OpCode::NewStruct(field_count) => {
-                writer.write_all(&[0x64])?;
-                writer.write_all(&(*field_count as u32).to_le_bytes())?;
-            }
-            OpCode::FieldGet(idx) => {
-                writer.write_all(&[0x65])?;
-                writer.write_all(&(*idx as u32).to_le_bytes())?;
-            }
-            OpCode::FieldSet(idx) => {
-                writer.write_all(&[0x66])?;
-                writer.write_all(&(*idx as u32).to_le_bytes())?;
+            writer.write_all(&[byte | 0x80])?;
+        }
+    }
+
+    /// 写入有符号操作数（有符号 LEB128 编码，用于相对跳转偏移）
+    fn write_signed_operand<W: Write>(value: isize, writer: &mut W) -> IoResult<()> {
+        let mut value = value as i64;
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            // 剩余位全部等于符号位时结束
+            let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+            if done {
+                writer.write_all(&[byte])?;
+                return Ok(());
             }
-            OpCode::Pop => writer.write_all(&[0x70])?,
-            OpCode::Dup => writer.write_all(&[0x71])?,
-            OpCode::Print => writer.write_all(&[0xF0])?,
-            OpCode::Halt => writer.write_all(&[0xFF])?,
+            writer.write_all(&[byte | 0x80])?;
+        }
+    }
+
+    /// 写入OpCode：固定编号加上可选的操作数
+    fn write_opcode<W: Write>(opcode: &OpCode, writer: &mut W) -> IoResult<()> {
+        writer.write_all(&[opcode.to_u8()])?;
+        match opcode.operand() {
+            Operand::None => Ok(()),
+            Operand::Unsigned(value) => Self::write_operand(value, writer),
+            Operand::Signed(offset) => Self::write_signed_operand(offset, writer),
         }
-        Ok(())
     }
 }
 
@@ -232,7 +202,8 @@ impl BytecodeDeserializer {
         let ver_major = u16::from_le_bytes(version_major);
         let ver_minor = u16::from_le_bytes(version_minor);
 
-        if ver_major != VERSION_MAJOR {
+        // 0.x 阶段次版本号之间也不兼容（0.1 的操作数为定长 u32）
+        if ver_major != VERSION_MAJOR || ver_minor != VERSION_MINOR {
             return Err(Error::new(
                 ErrorKind::InvalidData,
                 format!("Unsupported version {}.{}", ver_major, ver_minor),
@@ -261,10 +232,23 @@ impl BytecodeDeserializer {
             lines.push(Self::read_u32(reader)? as usize);
         }
 
+        // 读取全局变量名称表
+        let globals_count = Self::read_u32(reader)?;
+        let mut global_names = Vec::with_capacity(globals_count as usize);
+        for _ in 0..globals_count {
+            let len = Self::read_u32(reader)? as usize;
+            let mut bytes = vec![0u8; len];
+            reader.read_exact(&mut bytes)?;
+            global_names.push(
+                String::from_utf8(bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            );
+        }
+
         Ok(Chunk {
             code,
             constants,
             lines,
+            global_names,
         })
     }
 
@@ -313,7 +297,15 @@ impl BytecodeDeserializer {
                 for _ in 0..len {
                     arr.push(Self::read_value(reader)?);
                 }
-                Ok(Value::Array(arr))
+                Ok(Value::Array(Rc::new(arr)))
+            }
+            0x0A => {
+                let len = Self::read_u32(reader)? as usize;
+                let mut elements = Vec::with_capacity(len);
+                for _ in 0..len {
+                    elements.push(Self::read_value(reader)?);
+                }
+                Ok(Value::Tuple(Rc::new(elements)))
             }
             0x06 => Ok(Value::Function(Self::read_function(reader)?)),
             0x07 => Ok(Value::Null),
@@ -380,59 +372,67 @@ impl BytecodeDeserializer {
                 code,
                 constants,
                 lines,
+                global_names: Vec::new(),
             },
             locals_count,
+            upvalues: Vec::new(),
         })
     }
 
     /// 读取OpCode
     fn read_opcode<R: Read>(reader: &mut R) -> IoResult<OpCode> {
-        let mut opcode = [0u8; 1];
-        reader.read_exact(&mut opcode)?;
-
-        match opcode[0] {
-            0x00 => Ok(OpCode::LoadConst(Self::read_u32(reader)? as usize)),
-            0x01 => Ok(OpCode::LoadNull),
-            0x02 => Ok(OpCode::LoadLocal(Self::read_u32(reader)? as usize)),
-            0x03 => Ok(OpCode::StoreLocal(Self::read_u32(reader)? as usize)),
-            0x04 => Ok(OpCode::LoadGlobal(Self::read_u32(reader)? as usize)),
-            0x05 => Ok(OpCode::StoreGlobal(Self::read_u32(reader)? as usize)),
-            0x10 => Ok(OpCode::Add),
-            0x11 => Ok(OpCode::Subtract),
-            0x12 => Ok(OpCode::Multiply),
-            0x13 => Ok(OpCode::Divide),
-            0x14 => Ok(OpCode::Modulo),
-            0x15 => Ok(OpCode::Negate),
-            0x20 => Ok(OpCode::Equal),
-            0x21 => Ok(OpCode::NotEqual),
-            0x22 => Ok(OpCode::Greater),
-            0x23 => Ok(OpCode::GreaterEqual),
-            0x24 => Ok(OpCode::Less),
-            0x25 => Ok(OpCode::LessEqual),
-            0x30 => Ok(OpCode::Not),
-            0x31 => Ok(OpCode::And),
-            0x32 => Ok(OpCode::Or),
-            0x40 => Ok(OpCode::Jump(Self::read_u32(reader)? as usize)),
-            0x41 => Ok(OpCode::JumpIfFalse(Self::read_u32(reader)? as usize)),
-            0x42 => Ok(OpCode::JumpIfTrue(Self::read_u32(reader)? as usize)),
-            0x43 => Ok(OpCode::Loop(Self::read_u32(reader)? as usize)),
-            0x50 => Ok(OpCode::Call(Self::read_u32(reader)? as usize)),
-            0x51 => Ok(OpCode::Return),
-            0x60 => Ok(OpCode::NewArray(Self::read_u32(reader)? as usize)),
-            0x61 => Ok(OpCode::ArrayGet),
-            0x62 => Ok(OpCode::ArraySet),
-            0x63 => Ok(OpCode::ArrayLen),
-            0x64 => Ok(OpCode::NewStruct(Self::read_u32(reader)? as usize)),
-            0x65 => Ok(OpCode::FieldGet(Self::read_u32(reader)? as usize)),
-            0x66 => Ok(OpCode::FieldSet(Self::read_u32(reader)? as usize)),
-            0x70 => Ok(OpCode::Pop),
-            0x71 => Ok(OpCode::Dup),
-            0xF0 => Ok(OpCode::Print),
-            0xFF => Ok(OpCode::Halt),
-            _ => Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Unknown opcode: 0x{:02X}", opcode[0]),
-            )),
+        let mut code = [0u8; 1];
+        reader.read_exact(&mut code)?;
+
+        let opcode = OpCode::from_u8(code[0]).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("Unknown opcode: 0x{:02X}", code[0]))
+        })?;
+        Ok(match opcode.operand() {
+            Operand::None => opcode,
+            Operand::Unsigned(_) => opcode.with_operand(Operand::Unsigned(Self::read_operand(reader)?)),
+            Operand::Signed(_) => opcode.with_operand(Operand::Signed(Self::read_signed_operand(reader)?)),
+        })
+    }
+
+    /// 读取指令操作数（无符号 LEB128 变长编码）
+    fn read_operand<R: Read>(reader: &mut R) -> IoResult<usize> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte)?;
+            if shift >= 64 || (shift == 63 && byte[0] & 0x7E != 0) {
+                return Err(Error::new(ErrorKind::InvalidData, "Operand overflows 64 bits"));
+            }
+            result |= ((byte[0] & 0x7F) as u64) << shift;
+            if byte[0] & 0x80 == 0 {
+                return usize::try_from(result)
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "Operand too large"));
+            }
+            shift += 7;
+        }
+    }
+
+    /// 读取有符号操作数（有符号 LEB128 编码）
+    fn read_signed_operand<R: Read>(reader: &mut R) -> IoResult<isize> {
+        let mut result: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let mut byte = [0u8; 1];
+            reader.read_exact(&mut byte)?;
+            if shift >= 64 {
+                return Err(Error::new(ErrorKind::InvalidData, "Operand overflows 64 bits"));
+            }
+            result |= ((byte[0] & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                // 符号扩展
+                if shift < 64 && byte[0] & 0x40 != 0 {
+                    result |= -1i64 << shift;
+                }
+                return isize::try_from(result)
+                    .map_err(|_| Error::new(ErrorKind::InvalidData, "Operand too large"));
+            }
         }
     }
 
@@ -442,4 +442,140 @@ impl BytecodeDeserializer {
         reader.read_exact(&mut bytes)?;
         Ok(u32::from_le_bytes(bytes))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 构造一个包含各种带操作数指令的示例 chunk
+    fn sample_chunk() -> Chunk {
+        let mut chunk = Chunk::new();
+        for i in 0..200 {
+            chunk.add_constant(Value::Integer(i));
+        }
+        chunk.write(OpCode::LoadConst(3), 1);
+        chunk.write(OpCode::LoadConst(199), 1);
+        chunk.write(OpCode::StoreLocal(1), 2);
+        chunk.write(OpCode::LoadLocal(0), 2);
+        chunk.write(OpCode::JumpIfFalse(300), 3);
+        chunk.write(OpCode::Loop(-5), 3);
+        chunk.write(OpCode::Jump(-300), 3);
+        chunk.write(OpCode::Call(2), 3);
+        chunk.write(OpCode::LoadUpvalue(1), 3);
+        chunk.write(OpCode::Closure(2), 3);
+        chunk.write(OpCode::Add, 4);
+        chunk.write(OpCode::LoadTrue, 4);
+        chunk.write(OpCode::LoadOne, 4);
+        chunk.write(OpCode::ArrayConcat, 4);
+        chunk.write(OpCode::NewArray(70000), 4);
+        chunk.write(OpCode::FieldGet(usize::MAX >> 1), 4);
+        chunk.write(OpCode::StoreGlobal(1), 5);
+        chunk.write(OpCode::LoadGlobal(0), 5);
+        chunk.write(OpCode::Halt, 5);
+        chunk.global_names = vec!["count".to_string(), "名字".to_string()];
+        chunk
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let chunk = sample_chunk();
+        let mut bytes = Vec::new();
+        BytecodeSerializer::serialize(&chunk, &mut bytes).unwrap();
+
+        let decoded = BytecodeDeserializer::deserialize(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded, chunk);
+    }
+
+    #[test]
+    fn test_operands_smaller_than_fixed_width() {
+        let chunk = sample_chunk();
+        let mut bytes = Vec::new();
+        BytecodeSerializer::serialize(&chunk, &mut bytes).unwrap();
+
+        // 定长编码：文件头 16 字节 + 每个整数常量 9 字节 + 每条指令 1 字节操作码
+        // + 每个操作数 4 字节 + 每条指令 4 字节行号 + 全局变量名称表
+        let operand_count = chunk.code.iter()
+            .filter(|op| !matches!(op, OpCode::Add | OpCode::LoadTrue | OpCode::LoadOne | OpCode::ArrayConcat | OpCode::Halt))
+            .count();
+        let names_size: usize = 4 + chunk.global_names.iter().map(|n| 4 + n.len()).sum::<usize>();
+        let fixed_width = 16 + chunk.constants.len() * 9 + chunk.code.len() * 5 + operand_count * 4 + names_size;
+        assert!(bytes.len() < fixed_width);
+
+        // 小于 128 的操作数只占 1 字节
+        let mut small = Vec::new();
+        BytecodeSerializer::write_operand(127, &mut small).unwrap();
+        assert_eq!(small, vec![0x7F]);
+        let mut large = Vec::new();
+        BytecodeSerializer::write_operand(300, &mut large).unwrap();
+        assert_eq!(large, vec![0xAC, 0x02]);
+
+        // 有符号偏移
+        let mut negative = Vec::new();
+        BytecodeSerializer::write_signed_operand(-5, &mut negative).unwrap();
+        assert_eq!(negative, vec![0x7B]);
+    }
+
+    /// 指令集中的每一种指令（带操作数的指令使用非零操作数）
+    fn every_opcode() -> Vec<OpCode> {
+        vec![
+            OpCode::LoadConst(1), OpCode::LoadNull, OpCode::LoadLocal(75), OpCode::StoreLocal(112),
+            OpCode::LoadGlobal(149), OpCode::StoreGlobal(186), OpCode::LoadUpvalue(223), OpCode::LoadTrue,
+            OpCode::LoadFalse, OpCode::LoadZero, OpCode::LoadOne, OpCode::Add, OpCode::Subtract,
+            OpCode::Multiply, OpCode::Divide, OpCode::Modulo, OpCode::Negate, OpCode::Power, OpCode::Round,
+            OpCode::Equal, OpCode::NotEqual, OpCode::Greater, OpCode::GreaterEqual, OpCode::Less,
+            OpCode::LessEqual, OpCode::Not, OpCode::And, OpCode::Or, OpCode::Jump(-31),
+            OpCode::JumpIfFalse(-32), OpCode::JumpIfTrue(-33), OpCode::Loop(-34), OpCode::Call(1185),
+            OpCode::Return, OpCode::Closure(1259), OpCode::NewArray(1296), OpCode::ArrayGet, OpCode::ArraySet,
+            OpCode::ArrayLen, OpCode::NewStruct(1444), OpCode::FieldGet(1481), OpCode::FieldSet(1518),
+            OpCode::ArrayConcat, OpCode::Slice, OpCode::ArraySetLocal(1629), OpCode::ArraySetGlobal(1666),
+            OpCode::ArrayFill, OpCode::NewTuple(1703), OpCode::Pop, OpCode::Dup, OpCode::Swap, OpCode::DeepClone, OpCode::Concat,
+            OpCode::ToString, OpCode::StrLen, OpCode::Format(1999), OpCode::PrettyFormat,
+            OpCode::StrSplit, OpCode::StrJoin, OpCode::StrReplace, OpCode::StrContains,
+            OpCode::NewStringBuilder, OpCode::StrAppendLocal(2131), OpCode::StrAppendGlobal(2168), OpCode::Print,
+            OpCode::Write, OpCode::PrintError, OpCode::Input, OpCode::Halt,
+        ]
+    }
+
+    #[test]
+    fn test_opcode_numbering_is_bijective() {
+        let opcodes = every_opcode();
+        let mut seen = std::collections::HashSet::new();
+        for op in &opcodes {
+            assert!(seen.insert(op.to_u8()), "duplicate opcode number 0x{:02X}", op.to_u8());
+            let decoded = OpCode::from_u8(op.to_u8()).unwrap().with_operand(op.operand());
+            assert_eq!(&decoded, op);
+        }
+
+        // 每个可解码的编号都对应列表中的一种指令，且编号与解码结果一致
+        let decodable: Vec<u8> = (0..=255u8).filter(|code| OpCode::from_u8(*code).is_some()).collect();
+        assert_eq!(decodable.len(), opcodes.len());
+        for code in decodable {
+            assert_eq!(OpCode::from_u8(code).unwrap().to_u8(), code);
+        }
+    }
+
+    #[test]
+    fn test_every_opcode_round_trips() {
+        let mut chunk = Chunk::new();
+        for op in every_opcode() {
+            chunk.write(op, 1);
+        }
+        let mut bytes = Vec::new();
+        BytecodeSerializer::serialize(&chunk, &mut bytes).unwrap();
+        assert_eq!(BytecodeDeserializer::deserialize(&mut bytes.as_slice()).unwrap(), chunk);
+
+        // 编号是固定的，不随枚举的声明顺序变化
+        assert_eq!(OpCode::Print.to_u8(), 0xF0);
+        assert_eq!(OpCode::Jump(0).to_u8(), 0x40);
+    }
+
+    #[test]
+    fn test_rejects_old_version() {
+        let mut bytes = Vec::new();
+        BytecodeSerializer::serialize(&sample_chunk(), &mut bytes).unwrap();
+        bytes[6] = 3;  // 次版本号改为 0.3（按名称访问全局变量的格式）
+
+        assert!(BytecodeDeserializer::deserialize(&mut bytes.as_slice()).is_err());
+    }
+}
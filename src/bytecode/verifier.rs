@@ -0,0 +1,137 @@
+// 字节码校验：在执行前检查 chunk 的结构是否合法
+//
+// 反序列化得到的或外部生成的 chunk 可能包含越界的跳转目标、常量下标或全局变量槽位，
+// 校验通过后虚拟机可以假定这些操作数都指向有效的位置。
+// 局部变量槽位取决于运行时的栈深度，由虚拟机在执行时检查。
+
+use super::{Chunk, OpCode, Value};
+use std::fmt;
+
+/// 字节码校验错误（offset 为出错指令在所在 chunk 中的下标）
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError {
+    JumpOutOfBounds { offset: usize, target: isize },
+    ConstantOutOfRange { offset: usize, index: usize },
+    GlobalOutOfRange { offset: usize, slot: usize },
+    MissingTerminator,  // 指令序列为空或没有以 Halt/Return 结尾
+    InFunction { name: String, error: Box<VerifyError> },  // 嵌套函数中的错误
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::JumpOutOfBounds { offset, target } => {
+                write!(f, "jump at {} targets {}, which is outside the chunk", offset, target)
+            }
+            VerifyError::ConstantOutOfRange { offset, index } => {
+                write!(f, "instruction at {} uses constant {}, which is not in the constant pool", offset, index)
+            }
+            VerifyError::GlobalOutOfRange { offset, slot } => {
+                write!(f, "instruction at {} uses global slot {}, which has no name", offset, slot)
+            }
+            VerifyError::MissingTerminator => write!(f, "chunk does not end with Halt or Return"),
+            VerifyError::InFunction { name, error } => write!(f, "in function '{}': {}", name, error),
+        }
+    }
+}
+
+/// 校验顶层 chunk 以及常量池中所有函数的 chunk
+pub fn verify_chunk(chunk: &Chunk) -> Result<(), VerifyError> {
+    // 函数 chunk 没有自己的名称表，与顶层共享全局变量槽位
+    verify_with_globals(chunk, chunk.global_names.len())
+}
+
+fn verify_with_globals(chunk: &Chunk, global_count: usize) -> Result<(), VerifyError> {
+    if !matches!(chunk.code.last(), Some(OpCode::Halt | OpCode::Return)) {
+        return Err(VerifyError::MissingTerminator);
+    }
+
+    for (offset, op) in chunk.code.iter().enumerate() {
+        match op {
+            OpCode::Jump(jump) | OpCode::JumpIfFalse(jump) | OpCode::JumpIfTrue(jump) | OpCode::Loop(jump) => {
+                let target = offset as isize + 1 + jump;
+                if target < 0 || target as usize >= chunk.code.len() {
+                    return Err(VerifyError::JumpOutOfBounds { offset, target });
+                }
+            }
+            OpCode::LoadConst(index) if *index >= chunk.constants.len() => {
+                return Err(VerifyError::ConstantOutOfRange { offset, index: *index });
+            }
+            OpCode::LoadGlobal(slot) | OpCode::StoreGlobal(slot) | OpCode::ArraySetGlobal(slot) | OpCode::StrAppendGlobal(slot)
+                if *slot >= global_count =>
+            {
+                return Err(VerifyError::GlobalOutOfRange { offset, slot: *slot });
+            }
+            _ => {}
+        }
+    }
+
+    for constant in &chunk.constants {
+        if let Value::Function(function) = constant {
+            verify_with_globals(&function.chunk, global_count).map_err(|error| VerifyError::InFunction {
+                name: function.name.clone(),
+                error: Box::new(error),
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::Function;
+
+    fn chunk_of(code: Vec<OpCode>, constants: Vec<Value>) -> Chunk {
+        let mut chunk = Chunk::new();
+        for op in code {
+            chunk.write(op, 1);
+        }
+        chunk.constants = constants;
+        chunk.global_names = vec!["x".to_string()];
+        chunk
+    }
+
+    #[test]
+    fn test_accepts_valid_chunk() {
+        let chunk = chunk_of(
+            vec![OpCode::LoadConst(0), OpCode::StoreGlobal(0), OpCode::JumpIfFalse(1), OpCode::Pop, OpCode::Loop(-4), OpCode::Halt],
+            vec![Value::Integer(1)],
+        );
+        assert_eq!(verify_chunk(&chunk), Ok(()));
+    }
+
+    #[test]
+    fn test_rejects_out_of_range_jump() {
+        let chunk = chunk_of(vec![OpCode::Jump(5), OpCode::Halt], vec![]);
+        assert_eq!(verify_chunk(&chunk), Err(VerifyError::JumpOutOfBounds { offset: 0, target: 6 }));
+
+        let chunk = chunk_of(vec![OpCode::Loop(-3), OpCode::Halt], vec![]);
+        assert_eq!(verify_chunk(&chunk), Err(VerifyError::JumpOutOfBounds { offset: 0, target: -2 }));
+    }
+
+    #[test]
+    fn test_rejects_invalid_indices() {
+        let chunk = chunk_of(vec![OpCode::LoadConst(1), OpCode::Halt], vec![Value::Null]);
+        assert_eq!(verify_chunk(&chunk), Err(VerifyError::ConstantOutOfRange { offset: 0, index: 1 }));
+
+        let chunk = chunk_of(vec![OpCode::LoadNull, OpCode::StoreGlobal(1), OpCode::Halt], vec![]);
+        assert_eq!(verify_chunk(&chunk), Err(VerifyError::GlobalOutOfRange { offset: 1, slot: 1 }));
+    }
+
+    #[test]
+    fn test_rejects_missing_terminator_and_nested_errors() {
+        assert_eq!(verify_chunk(&chunk_of(vec![], vec![])), Err(VerifyError::MissingTerminator));
+        assert_eq!(verify_chunk(&chunk_of(vec![OpCode::LoadNull], vec![])), Err(VerifyError::MissingTerminator));
+
+        // 函数 chunk 中的错误带上函数名
+        let mut body = Chunk::new();
+        body.write(OpCode::LoadConst(0), 1);
+        body.write(OpCode::Return, 1);
+        let function = Function { name: "f".to_string(), arity: 0, chunk: body, locals_count: 0, upvalues: Vec::new() };
+        let chunk = chunk_of(vec![OpCode::LoadConst(0), OpCode::Halt], vec![Value::Function(function)]);
+        let err = verify_chunk(&chunk).unwrap_err();
+        assert_eq!(err.to_string(), "in function 'f': instruction at 0 uses constant 0, which is not in the constant pool");
+    }
+}
@@ -1,16 +1,110 @@
-use crate::ast::{Expr, Program, Stmt, BinaryOp, UnaryOp, Parameter, Type, StructType, MethodDeclaration};
+use crate::ast::{Expr, Program, Stmt, BinaryOp, UnaryOp, Parameter, Type, StructType, MethodDeclaration, Pattern, Span, ValueBlock, UseItems, Visibility};
+use crate::builtins::BuiltinReturn;
 use crate::bytecode::{Chunk, OpCode, Value, Function};
-use std::collections::HashMap;
+use crate::error::{CompilerError, SourceLocation};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 /// 编译错误
+///
+/// 带 `Span` 的变体记录出错表达式在源码中的位置，用于 `ErrorDisplayer` 显示源码片段。
 #[derive(Debug)]
 pub enum CompileError {
-    UndefinedVariable(String),
+    UndefinedVariable(String, Span),
     TooManyConstants,
     TooManyLocals,
     InvalidBreakContinue,
-    UndefinedStruct(String),
-    UndefinedField(String, String), // (struct_name, field_name)
+    UndefinedStruct(String, Span),
+    UndefinedField {
+        struct_name: String,
+        field: String,
+        available: Vec<String>, // 结构体的全部字段名，用于提示
+        span: Span,
+    },
+    AssignToCaptured(String, Span), // 嵌套函数按值捕获外层变量，不能对其赋值
+    MainWithTopLevelStatements,     // 定义了 main() 入口，顶层却还有可执行语句
+    MainWithParameters,             // main() 入口不能带参数
+    StatementInIfExpression(&'static str), // if 表达式分支中的值留在栈上，分支内不能声明局部变量或跳出循环
+}
+
+impl CompileError {
+    /// 出错位置（未知时为 None）
+    pub fn span(&self) -> Option<Span> {
+        let span = match self {
+            CompileError::UndefinedVariable(_, span)
+            | CompileError::UndefinedStruct(_, span)
+            | CompileError::UndefinedField { span, .. }
+            | CompileError::AssignToCaptured(_, span) => *span,
+            _ => return None,
+        };
+        span.is_known().then_some(span)
+    }
+
+    /// 修复建议
+    pub fn suggestion(&self) -> Option<String> {
+        match self {
+            CompileError::UndefinedField { struct_name, available, .. } if available.is_empty() => {
+                Some(format!("struct `{}` has no fields", struct_name))
+            }
+            CompileError::UndefinedField { struct_name, available, .. } => Some(format!(
+                "valid fields of `{}` are: {}",
+                struct_name,
+                available.iter().map(|f| format!("`{}`", f)).collect::<Vec<_>>().join(", ")
+            )),
+            CompileError::AssignToCaptured(name, _) => Some(format!(
+                "captured variables are copied into the closure; return the new value of `{}` instead",
+                name
+            )),
+            CompileError::MainWithTopLevelStatements => {
+                Some("move the top-level statements into `main()`".to_string())
+            }
+            CompileError::StatementInIfExpression(_) => {
+                Some("move the statement before the `if` expression, or use an `if` statement".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// 转换为统一的错误结构，交给 `ErrorDisplayer` 格式化
+    pub fn to_compiler_error(&self) -> CompilerError {
+        let (line, column, length) = match (self.span(), self) {
+            (Some(span), CompileError::UndefinedField { field, .. }) => (span.line, span.column, field.chars().count()),
+            (Some(span), CompileError::UndefinedVariable(name, _))
+            | (Some(span), CompileError::UndefinedStruct(name, _))
+            | (Some(span), CompileError::AssignToCaptured(name, _)) => (span.line, span.column, name.chars().count()),
+            _ => (0, 0, 1),
+        };
+        CompilerError::compile_error(
+            self.to_string(),
+            self.suggestion(),
+            SourceLocation::new(line, column, 0, length.max(1)),
+        )
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::UndefinedVariable(name, _) => write!(f, "undefined variable `{}`", name),
+            CompileError::TooManyConstants => write!(f, "too many constants in one chunk"),
+            CompileError::TooManyLocals => write!(f, "too many local variables in one function"),
+            CompileError::InvalidBreakContinue => write!(f, "`break` or `continue` outside of a loop"),
+            CompileError::UndefinedStruct(name, _) => write!(f, "undefined struct `{}`", name),
+            CompileError::UndefinedField { struct_name, field, .. } => {
+                write!(f, "struct `{}` has no field `{}`", struct_name, field)
+            }
+            CompileError::AssignToCaptured(name, _) => {
+                write!(f, "cannot assign to `{}`, which is captured from an enclosing function", name)
+            }
+            CompileError::MainWithTopLevelStatements => {
+                write!(f, "`main()` is defined but the program also has top-level statements")
+            }
+            CompileError::MainWithParameters => write!(f, "`main()` must not take parameters"),
+            CompileError::StatementInIfExpression(kind) => {
+                write!(f, "{} are not allowed inside an `if` expression branch", kind)
+            }
+        }
+    }
 }
 
 type CompileResult<T> = Result<T, CompileError>;
@@ -35,12 +129,40 @@ struct StructDef {
     fields: Vec<StructFieldInfo>,  // 字段信息列表（按顺序）
 }
 
+impl StructDef {
+    fn undefined_field(&self, struct_name: &str, field: &str, span: Span) -> CompileError {
+        CompileError::UndefinedField {
+            struct_name: struct_name.to_string(),
+            field: field.to_string(),
+            available: self.fields.iter().map(|f| f.name.clone()).collect(),
+            span,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct StructFieldInfo {
     name: String,
     field_type: Type,
 }
 
+/// 内联函数体表达式最多的节点数（大致对应函数体的指令数）
+const INLINE_MAX_NODES: usize = 16;
+
+/// 可以在调用处展开的小函数：函数体只有一条 `return 表达式;`，表达式只读取参数
+#[derive(Debug, Clone)]
+struct InlineFunction {
+    parameters: Vec<(String, Type)>,  // (参数名, 标注的类型)
+    body: Expr,
+}
+
+/// 函数的参数类型和返回类型（没有标注的为 Unknown），实参和返回值按它们编译
+#[derive(Debug, Clone)]
+struct FunctionSignature {
+    parameters: Vec<Type>,
+    return_type: Type,
+}
+
 /// 局部变量的类型信息
 #[derive(Debug, Clone)]
 struct LocalTypeInfo {
@@ -55,11 +177,26 @@ pub struct Compiler {
     scope_depth: usize,
     loop_starts: Vec<usize>,      // 循环开始位置栈
     loop_breaks: Vec<Vec<usize>>,  // 循环break跳转位置栈
+    loop_locals: Vec<usize>,       // 循环开始时的局部变量数（break/continue 需要弹出之后声明的局部变量）
     structs: HashMap<String, StructDef>, // 结构体定义
     local_types: Vec<LocalTypeInfo>, // 局部变量类型信息
     global_types: HashMap<String, Type>, // 全局变量类型信息
     methods: HashMap<String, HashMap<String, Function>>,  // type_name -> (method_name -> function)
+    method_signatures: HashMap<String, HashMap<String, FunctionSignature>>,  // type_name -> (method_name -> 签名)
+    function_signatures: HashMap<String, FunctionSignature>,  // 只声明了一次的函数名 -> 签名
+    return_type: Type,             // 当前函数声明的返回类型（顶层代码为 Unknown）
     imported_symbols: HashMap<String, String>,  // 导入符号映射: 别名 -> 原始名
+    type_aliases: HashMap<String, (Visibility, Type)>,  // 类型别名的完整名（`模块路径::别名`，顶层为别名）-> (可见性, 目标类型)
+    global_slots: HashMap<String, usize>,  // 全局变量名 -> 槽位（编译时分配，运行时按下标访问）
+    module_path: Vec<String>,      // 当前所在的模块路径
+    module_variables: HashMap<String, Visibility>,  // 模块顶层变量的全局名（`模块路径::变量名`）-> 可见性
+    enclosing_names: Vec<String>,  // 外层函数中可见的局部变量名（嵌套函数可以捕获）
+    upvalues: Vec<String>,         // 当前函数捕获的外层变量（下标即 LoadUpvalue 的操作数）
+    declared_functions: HashSet<String>,  // 程序中声明的函数名（与内置函数同名时用户函数优先）
+    hoist_loop_invariants: bool,   // 是否把 while 循环中不变的全局变量读取外提到循环之前
+    strength_reduction: bool,      // 是否对算术表达式做代数化简和常量折叠
+    inline_small_functions: bool,  // 是否在调用处展开小函数
+    inline_functions: HashMap<String, InlineFunction>,  // 可以内联的顶层函数
 }
 
 impl Compiler {
@@ -70,11 +207,26 @@ impl Compiler {
             scope_depth: 0,
             loop_starts: Vec::new(),
             loop_breaks: Vec::new(),
+            loop_locals: Vec::new(),
             structs: HashMap::new(),
             local_types: Vec::new(),
             global_types: HashMap::new(),
             methods: HashMap::new(),
+            method_signatures: HashMap::new(),
+            function_signatures: HashMap::new(),
+            return_type: Type::Unknown,
             imported_symbols: HashMap::new(),
+            type_aliases: HashMap::new(),
+            global_slots: HashMap::new(),
+            module_path: Vec::new(),
+            module_variables: HashMap::new(),
+            enclosing_names: Vec::new(),
+            upvalues: Vec::new(),
+            declared_functions: HashSet::new(),
+            hoist_loop_invariants: false,
+            strength_reduction: false,
+            inline_small_functions: false,
+            inline_functions: HashMap::new(),
         }
     }
 
@@ -83,27 +235,436 @@ impl Compiler {
         self.imported_symbols = imported_symbols;
     }
 
+    /// 启用循环不变量外提（默认关闭）
+    pub fn set_hoist_loop_invariants(&mut self, enabled: bool) {
+        self.hoist_loop_invariants = enabled;
+    }
+
+    /// 启用算术化简（默认关闭）：折叠数值字面量的取负，
+    /// 并化简 `x * 1`、`x * 2`、`x * 0`、`x + 0`、`x - 0`
+    pub fn set_strength_reduction(&mut self, enabled: bool) {
+        self.strength_reduction = enabled;
+    }
+
+    /// 启用小函数内联（默认关闭）：函数体只有一条只读取参数的 `return`、参数都标注了类型的顶层函数，
+    /// 在实参没有副作用且类型与参数一致时直接展开到调用处，省去调用开销
+    pub fn set_inline_small_functions(&mut self, enabled: bool) {
+        self.inline_small_functions = enabled;
+    }
+
     /// 编译程序
     pub fn compile(&mut self, program: Program) -> CompileResult<Chunk> {
+        let has_main = Self::check_entry_point(&program.statements)?;
+        Self::collect_declared_functions(&program.statements, &mut self.declared_functions);
+        Self::collect_module_variables(&program.statements, &mut Vec::new(), &mut self.module_variables);
+        Self::collect_type_aliases(&program.statements, &mut Vec::new(), &mut self.type_aliases);
+        self.collect_function_signatures(&program.statements);
+        self.collect_imported_module_variables(&program.statements);
+        if self.inline_small_functions {
+            self.collect_inline_functions(&program.statements);
+        }
+
         for stmt in program.statements {
             self.compile_statement(stmt)?;
         }
-        
+
+        // 所有声明完成后调用入口函数
+        if has_main {
+            let slot = self.global_slot("main")?;
+            self.emit(OpCode::LoadGlobal(slot), 0);
+            self.emit(OpCode::Call(0), 0);
+            self.emit(OpCode::Pop, 0);
+        }
+
         // 添加Halt指令
         self.emit(OpCode::Halt, 0);
-        
+
+        // 记录全局变量槽位对应的名称（用于运行时错误信息和宿主访问）
+        let mut global_names = vec![String::new(); self.global_slots.len()];
+        for (name, slot) in &self.global_slots {
+            global_names[*slot] = name.clone();
+        }
+        self.chunk.global_names = global_names;
+
         Ok(self.chunk.clone())
     }
 
+    /// 收集顶层和模块中声明的函数名（函数可以在声明之前被其他函数调用）
+    fn collect_declared_functions(statements: &[Stmt], names: &mut HashSet<String>) {
+        for stmt in statements {
+            match stmt {
+                Stmt::FnDeclaration { name, .. } => {
+                    names.insert(name.clone());
+                }
+                Stmt::ModuleDeclaration { statements, .. } => Self::collect_declared_functions(statements, names),
+                _ => {}
+            }
+        }
+    }
+
+    /// 顶层和模块中（递归）的函数声明；模块函数与顶层函数共享全局命名空间
+    fn function_declarations<'a>(statements: &'a [Stmt], declarations: &mut Vec<&'a Stmt>) {
+        for stmt in statements {
+            match stmt {
+                Stmt::FnDeclaration { .. } => declarations.push(stmt),
+                Stmt::ModuleDeclaration { statements, .. } => Self::function_declarations(statements, declarations),
+                _ => {}
+            }
+        }
+    }
+
+    /// 全局命名空间中每个名字的声明次数：所有函数声明和顶层变量（模块变量的全局名带模块路径，不会冲突）
+    fn global_declaration_counts(statements: &[Stmt]) -> HashMap<&str, usize> {
+        let mut functions = Vec::new();
+        Self::function_declarations(statements, &mut functions);
+        let variables = statements.iter().filter(|stmt| matches!(stmt, Stmt::VarDeclaration { .. }));
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for stmt in functions.into_iter().chain(variables) {
+            if let Stmt::FnDeclaration { name, .. }
+            | Stmt::VarDeclaration { pattern: Pattern::Identifier(name), .. } = stmt
+            {
+                *counts.entry(name.as_str()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// 收集函数签名；同名的声明不止一个时无法确定调用的是哪一个，不记录
+    fn collect_function_signatures(&mut self, statements: &[Stmt]) {
+        let counts = Self::global_declaration_counts(statements);
+        let mut functions = Vec::new();
+        Self::function_declarations(statements, &mut functions);
+        for stmt in functions {
+            let Stmt::FnDeclaration { name, parameters, return_type, .. } = stmt else { continue };
+            if counts[name.as_str()] == 1 {
+                let signature = FunctionSignature {
+                    parameters: parameters.iter().map(|p| p.type_annotation.clone().unwrap_or(Type::Unknown)).collect(),
+                    return_type: return_type.clone().unwrap_or(Type::Unknown),
+                };
+                self.function_signatures.insert(name.clone(), signature);
+            }
+        }
+    }
+
+    /// 被调用函数的签名：调用的是局部变量或捕获的变量时没有签名
+    fn callee_signature(&self, callee: &Expr) -> Option<&FunctionSignature> {
+        let global = match callee {
+            Expr::Identifier(name, _) => {
+                if self.resolve_local(name).is_some() || self.enclosing_names.iter().any(|n| n == name) {
+                    return None;
+                }
+                self.global_name(name)
+            }
+            Expr::Path { segments, .. } => Self::path_global_name(&self.module_variables, segments)?,
+            _ => return None,
+        };
+        self.function_signatures.get(&global)
+    }
+
+    /// 方法的签名（impl 块中声明的方法）
+    fn method_signature(&self, object: &Expr, method: &str) -> Option<&FunctionSignature> {
+        let type_name = self.infer_expression_type(object).impl_name()?;
+        self.method_signatures.get(&type_name)?.get(method)
+    }
+
+    /// 按参数类型编译实参（期望 float 的整数字面量编译为浮点数）
+    fn compile_arguments(&mut self, arguments: Vec<Expr>, parameters: &[Type]) -> CompileResult<()> {
+        for (i, arg) in arguments.into_iter().enumerate() {
+            match parameters.get(i) {
+                Some(parameter_type) => self.compile_expression_as(arg, parameter_type)?,
+                None => self.compile_expression(arg)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// 收集模块顶层声明的变量。不同模块（以及模块外）可以有同名变量，
+    /// 因此它们的全局名带上模块路径，例如 `config::level`
+    fn collect_module_variables(statements: &[Stmt], path: &mut Vec<String>, variables: &mut HashMap<String, Visibility>) {
+        fn pattern_names(pattern: &Pattern, names: &mut Vec<String>) {
+            match pattern {
+                Pattern::Identifier(name) => names.push(name.clone()),
+                Pattern::Tuple(elements) => elements.iter().for_each(|p| pattern_names(p, names)),
+            }
+        }
+
+        for stmt in statements {
+            match stmt {
+                Stmt::VarDeclaration { visibility, pattern, .. } if !path.is_empty() => {
+                    let mut names = Vec::new();
+                    pattern_names(pattern, &mut names);
+                    for name in names.into_iter().filter(|name| name != "_") {
+                        variables.insert(format!("{}::{}", path.join("::"), name), visibility.clone());
+                    }
+                }
+                Stmt::ModuleDeclaration { name, statements, .. } => {
+                    path.push(name.clone());
+                    Self::collect_module_variables(statements, path, variables);
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 收集顶层和模块中声明的类型别名。不同模块可以声明同名的别名，
+    /// 因此和模块变量一样以带模块路径的名字为键，例如 `geo::Meters`
+    fn collect_type_aliases(statements: &[Stmt], path: &mut Vec<String>, aliases: &mut HashMap<String, (Visibility, Type)>) {
+        for stmt in statements {
+            match stmt {
+                Stmt::TypeAlias { visibility, name, target_type } => {
+                    aliases.insert(Self::qualify(path, name), (visibility.clone(), target_type.clone()));
+                }
+                Stmt::ModuleDeclaration { name, statements, .. } => {
+                    path.push(name.clone());
+                    Self::collect_type_aliases(statements, path, aliases);
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// 模块路径下的完整名字（顶层不加前缀）
+    fn qualify(path: &[String], name: &str) -> String {
+        if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", path.join("::"), name)
+        }
+    }
+
+    /// 用 use 导入的模块变量和类型别名：别名映射到带模块路径的名字（函数等其他符号仍按原始名）
+    fn collect_imported_module_variables(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            match stmt {
+                Stmt::UseStatement { path, items } => {
+                    let prefix = path.join("::");
+                    let imports: Vec<(String, String)> = match items {
+                        UseItems::Single(name) => vec![(name.clone(), name.clone())],
+                        UseItems::Multiple(names) => names.iter().map(|n| (n.clone(), n.clone())).collect(),
+                        UseItems::Renamed(original, alias) => vec![(alias.clone(), original.clone())],
+                        UseItems::All => self.module_variables.iter()
+                            .chain(self.type_aliases.iter().map(|(alias, (visibility, _))| (alias, visibility)))
+                            .filter(|(_, visibility)| **visibility == Visibility::Public)
+                            .filter_map(|(global, _)| global.strip_prefix(&prefix)?.strip_prefix("::"))
+                            .filter(|name| !name.contains("::"))
+                            .map(|name| (name.to_string(), name.to_string()))
+                            .collect(),
+                    };
+                    for (alias, original) in imports {
+                        let global = format!("{}::{}", prefix, original);
+                        if self.module_variables.contains_key(&global) || self.type_aliases.contains_key(&global) {
+                            self.imported_symbols.insert(alias, global);
+                        }
+                    }
+                }
+                Stmt::ModuleDeclaration { statements, .. } => self.collect_imported_module_variables(statements),
+                _ => {}
+            }
+        }
+    }
+
+    /// 找出可以内联的顶层函数；同名的声明（包括模块中的函数）不止一个时不内联
+    fn collect_inline_functions(&mut self, statements: &[Stmt]) {
+        let declarations = Self::global_declaration_counts(statements);
+
+        for stmt in statements {
+            let Stmt::FnDeclaration { name, parameters, body, .. } = stmt else { continue };
+            let [Stmt::Return { value: Some(body) }] = body.as_slice() else { continue };
+            if declarations[name.as_str()] > 1 {
+                continue;
+            }
+            let Some(parameters) = parameters.iter()
+                .map(|p| p.type_annotation.clone().map(|t| (p.name.clone(), t)))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+
+            // 每个参数至少使用一次，内联后实参中的运行时错误不会被跳过
+            let names: Vec<&str> = parameters.iter().map(|(n, _)| n.as_str()).collect();
+            let mut used = HashSet::new();
+            let small = Self::inline_body_size(body, &names, &mut used).is_some_and(|n| n <= INLINE_MAX_NODES);
+            if small && used.len() == names.len() {
+                self.inline_functions.insert(name.clone(), InlineFunction { parameters, body: body.clone() });
+            }
+        }
+    }
+
+    /// 可内联函数体的节点数；含有调用、赋值或参数以外的变量时返回 None
+    /// （因此内联的函数不会递归，也没有捕获的变量）
+    fn inline_body_size<'a>(expr: &'a Expr, parameters: &[&str], used: &mut HashSet<&'a str>) -> Option<usize> {
+        let children = match expr {
+            Expr::Integer(_) | Expr::Float(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Char(_) | Expr::Null => 0,
+            Expr::Identifier(name, _) if parameters.contains(&name.as_str()) => {
+                used.insert(name.as_str());
+                0
+            }
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right } => {
+                Self::inline_body_size(left, parameters, used)? + Self::inline_body_size(right, parameters, used)?
+            }
+            Expr::Unary { operand: inner, .. }
+            | Expr::FieldAccess { object: inner, .. }
+            | Expr::TupleIndex { object: inner, .. } => Self::inline_body_size(inner, parameters, used)?,
+            _ => return None,
+        };
+        Some(children + 1)
+    }
+
+    /// 可以在内联时重复求值的实参：字面量、变量读取和不会出错的运算
+    fn is_pure_argument(expr: &Expr) -> bool {
+        match expr {
+            Expr::Integer(_) | Expr::Float(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Char(_) | Expr::Null
+            | Expr::Identifier(..) => true,
+            Expr::Unary { operand: inner, .. }
+            | Expr::FieldAccess { object: inner, .. }
+            | Expr::TupleIndex { object: inner, .. } => Self::is_pure_argument(inner),
+            Expr::Binary { left, operator, right } => {
+                !matches!(operator, BinaryOp::Divide | BinaryOp::Modulo)
+                    && Self::is_pure_argument(left)
+                    && Self::is_pure_argument(right)
+            }
+            _ => false,
+        }
+    }
+
+    /// 调用可以内联时，返回用实参替换参数后的函数体
+    fn inline_call(&self, callee: &Expr, arguments: &[Expr]) -> Option<Expr> {
+        let Expr::Identifier(name, _) = callee else { return None };
+        // 局部变量、可捕获的变量和导入的别名遮蔽同名函数
+        if self.resolve_local(name).is_some()
+            || self.enclosing_names.iter().any(|n| n == name)
+            || self.imported_symbols.contains_key(name)
+        {
+            return None;
+        }
+        let function = self.inline_functions.get(name)?;
+        if function.parameters.len() != arguments.len() {
+            return None;
+        }
+
+        // 实参类型必须与参数一致，函数体中的字段访问等才能按同样的类型编译
+        let mut bindings = HashMap::new();
+        for ((parameter, parameter_type), argument) in function.parameters.iter().zip(arguments) {
+            let argument_type = self.infer_expression_type(argument);
+            if !Self::is_pure_argument(argument)
+                || argument_type == Type::Unknown
+                || argument_type != self.resolve_named_type(parameter_type)
+            {
+                return None;
+            }
+            bindings.insert(parameter.as_str(), argument);
+        }
+        Some(Self::substitute_parameters(&function.body, &bindings))
+    }
+
+    fn substitute_parameters(expr: &Expr, bindings: &HashMap<&str, &Expr>) -> Expr {
+        let substitute = |e: &Expr| Box::new(Self::substitute_parameters(e, bindings));
+        match expr {
+            Expr::Identifier(name, _) => bindings.get(name.as_str()).map_or_else(|| expr.clone(), |arg| (*arg).clone()),
+            Expr::Binary { left, operator, right } => Expr::Binary {
+                left: substitute(left),
+                operator: operator.clone(),
+                right: substitute(right),
+            },
+            Expr::Index { object, index } => Expr::Index { object: substitute(object), index: substitute(index) },
+            Expr::Unary { operator, operand } => Expr::Unary { operator: operator.clone(), operand: substitute(operand) },
+            Expr::FieldAccess { object, field, span } => Expr::FieldAccess {
+                object: substitute(object),
+                field: field.clone(),
+                span: *span,
+            },
+            Expr::TupleIndex { object, index } => Expr::TupleIndex { object: substitute(object), index: *index },
+            _ => expr.clone(),
+        }
+    }
+
+    /// 调用的名称是否为内置函数：局部变量、可捕获的变量、全局变量和声明的函数都优先于内置函数
+    fn is_builtin_call(&self, name: &str) -> bool {
+        crate::builtins::lookup(name).is_some()
+            && self.resolve_local(name).is_none()
+            && !self.enclosing_names.iter().any(|n| n == name)
+            && !self.global_slots.contains_key(&self.global_name(name))
+            && !self.declared_functions.contains(name)
+            && !self.imported_symbols.contains_key(name)
+    }
+
+    /// 方法调用是否为所有值都有的内置 `to_string()`：impl 块中为该类型自定义的 to_string 优先
+    fn is_builtin_to_string(&self, object: &Expr, method: &str, arguments: &[Expr]) -> bool {
+        method == "to_string"
+            && arguments.is_empty()
+            && !self.infer_expression_type(object).impl_name()
+                .and_then(|type_name| self.methods.get(&type_name))
+                .is_some_and(|methods| methods.contains_key(method))
+    }
+
+    /// 字符串和数组的内置方法对应的指令（impl 块中的同名方法优先）
+    fn builtin_method(&self, object: &Expr, method: &str, arguments: &[Expr]) -> Option<OpCode> {
+        let obj_type = self.resolve_named_type(&self.infer_expression_type(object));
+        let user_defined = obj_type.impl_name()
+            .and_then(|type_name| self.methods.get(&type_name))
+            .is_some_and(|methods| methods.contains_key(method));
+        if user_defined {
+            return None;
+        }
+        match (&obj_type, method, arguments.len()) {
+            (Type::String, "split", 1) => Some(OpCode::StrSplit),
+            (Type::String, "replace", 2) => Some(OpCode::StrReplace),
+            (Type::String, "contains", 1) => Some(OpCode::StrContains),
+            (Type::Array(_) | Type::FixedArray(..), "join", 1) => Some(OpCode::StrJoin),
+            // 构建器在运行时就是字符串，build 复制一份当前内容
+            (Type::StringBuilder, "build", 0) => Some(OpCode::ToString),
+            _ => None,
+        }
+    }
+
+    /// 方法调用是否为对变量中的字符串构建器的 append（编译为原地追加，见 StrAppendLocal/StrAppendGlobal）
+    fn is_builder_append(&self, object: &Expr, method: &str, arguments: &[Expr]) -> bool {
+        method == "append"
+            && arguments.len() == 1
+            && matches!(object, Expr::Identifier(..))
+            && self.resolve_named_type(&self.infer_expression_type(object)) == Type::StringBuilder
+    }
+
+    /// 检查顶层是否定义了 `fn main()` 入口
+    /// 有入口时顶层只允许声明（函数、结构体、类型别名、impl、模块、全局变量），
+    /// 否则按顺序执行顶层语句
+    fn check_entry_point(statements: &[Stmt]) -> CompileResult<bool> {
+        let main = statements.iter().find_map(|stmt| match stmt {
+            Stmt::FnDeclaration { name, parameters, .. } if name == "main" => Some(parameters),
+            _ => None,
+        });
+        let parameters = match main {
+            Some(parameters) => parameters,
+            None => return Ok(false),
+        };
+
+        if !parameters.is_empty() {
+            return Err(CompileError::MainWithParameters);
+        }
+
+        let has_statements = statements.iter().any(|stmt| matches!(stmt,
+            Stmt::Expression(_) | Stmt::Return { .. } | Stmt::If { .. } | Stmt::While { .. }
+            | Stmt::For { .. } | Stmt::Print { .. } | Stmt::Block { .. } | Stmt::Break | Stmt::Continue
+        ));
+        if has_statements {
+            return Err(CompileError::MainWithTopLevelStatements);
+        }
+
+        Ok(true)
+    }
+
     /// 编译语句
     fn compile_statement(&mut self, stmt: Stmt) -> CompileResult<()> {
         match stmt {
             Stmt::Expression(expr) => {
+                // 每个表达式都在栈上留下一个值（赋值的存储指令也保留被存储的值），语句结束时统一弹出
                 self.compile_expression(expr)?;
                 self.emit(OpCode::Pop, 0);
             }
 
-            Stmt::StructDeclaration { visibility: _, name, fields } => {
+            Stmt::StructDeclaration { name, fields, .. } => {
                 // 注册结构体定义（包含完整的字段类型信息）
                 let field_infos: Vec<StructFieldInfo> = fields.iter().map(|f| {
                     StructFieldInfo {
@@ -115,11 +676,28 @@ impl Compiler {
                 // 结构体声明在运行时不需要操作
             }
 
-            Stmt::TypeAlias { visibility: _, name: _, target_type: _ } => {
-                // 类型别名在编译时处理，运行时不需要操作
+            Stmt::TypeAlias { visibility, name, target_type } => {
+                // 记录类型别名，供编译时类型传播解析（运行时不需要操作）；
+                // 顶层和模块中的别名已在 collect_type_aliases 中记录，这里补上函数体中声明的别名
+                self.type_aliases.insert(Self::qualify(&self.module_path, &name), (visibility, target_type));
             }
 
             Stmt::ImplBlock { type_name, methods } => {
+                // 先记录方法的签名，方法调用的结果类型由此推断（链式调用需要）
+                let signatures = methods.iter()
+                    .map(|method| {
+                        let signature = FunctionSignature {
+                            parameters: method.parameters.iter()
+                                .map(|p| p.type_annotation.as_ref().map_or(Type::Unknown, |t| t.replace_self(&type_name)))
+                                .collect(),
+                            return_type: method.return_type.as_ref()
+                                .map_or(Type::Unknown, |t| t.replace_self(&type_name)),
+                        };
+                        (method.name.clone(), signature)
+                    })
+                    .collect();
+                self.method_signatures.insert(type_name.clone(), signatures);
+
                 // 编译每个方法并存储到方法表中
                 let mut method_map = HashMap::new();
 
@@ -127,14 +705,21 @@ impl Compiler {
                     // 创建包含 self 参数的参数列表
                     let mut params_with_self = vec![Parameter {
                         name: "self".to_string(),
-                        type_annotation: Some(Type::Named(type_name.clone())),
+                        type_annotation: Some(Type::from_impl_name(&type_name)),
+                        span: Span::default(),
                     }];
-                    params_with_self.extend(method.parameters.clone());
+                    params_with_self.extend(method.parameters.iter().map(|p| Parameter {
+                        name: p.name.clone(),
+                        type_annotation: p.type_annotation.as_ref().map(|t| t.replace_self(&type_name)),
+                        span: p.span,
+                    }));
 
                     // 编译方法体（作为函数）
-                    let function = self.compile_function(
+                    let return_type = method.return_type.as_ref().map_or(Type::Unknown, |t| t.replace_self(&type_name));
+                    let (function, _) = self.compile_function(
                         format!("{}.{}", type_name, method.name),
                         &params_with_self,
+                        return_type,
                         method.body.clone()
                     )?;
 
@@ -147,11 +732,13 @@ impl Compiler {
                 // Impl块在运行时不需要额外操作
             }
 
-            Stmt::ModuleDeclaration { name: _, statements, is_public: _ } => {
-                // 编译模块内的所有语句
+            Stmt::ModuleDeclaration { name, statements, is_public: _ } => {
+                // 编译模块内的所有语句（模块顶层的变量以带模块路径的名字存为全局变量）
+                self.module_path.push(name);
                 for stmt in statements {
                     self.compile_statement(stmt)?;
                 }
+                self.module_path.pop();
                 // 模块声明本身在运行时不需要额外操作
             }
 
@@ -165,9 +752,9 @@ impl Compiler {
                 // 编译时不需要生成字节码
             }
 
-            Stmt::VarDeclaration { name, mutable, type_annotation, initializer } => {
+            Stmt::VarDeclaration { visibility: _, pattern, mutable, type_annotation, initializer } => {
                 // 推断变量类型
-                let var_type = if let Some(annotated) = type_annotation {
+                let var_type = if let Some(ref annotated) = type_annotation {
                     annotated.clone()
                 } else if let Some(ref init) = initializer {
                     self.infer_expression_type(init)
@@ -175,37 +762,31 @@ impl Compiler {
                     Type::Null
                 };
 
-                if let Some(init) = initializer {
-                    self.compile_expression(init)?;
-                } else {
-                    self.emit(OpCode::LoadNull, 0);
+                match (initializer, &type_annotation) {
+                    (Some(init), Some(annotated)) => self.compile_expression_as(init, annotated)?,
+                    (Some(init), None) => self.compile_expression(init)?,
+                    (None, _) => self.emit(OpCode::LoadNull, 0),
                 }
 
-                if self.scope_depth == 0 {
-                    // 全局变量
-                    let idx = self.identifier_constant(&name)?;
-                    self.emit(OpCode::StoreGlobal(idx), 0);
-                    self.emit(OpCode::Pop, 0);
-                    // 记录全局变量类型
-                    self.global_types.insert(name.clone(), var_type);
-                } else {
-                    // 局部变量
-                    self.add_local(name.clone(), mutable)?;
-                    // 记录局部变量类型
-                    self.local_types.push(LocalTypeInfo {
-                        name: name.clone(),
-                        var_type,
-                    });
-                }
+                self.bind_pattern(pattern, var_type, mutable)?;
             }
 
-            Stmt::FnDeclaration { visibility: _, name, parameters, return_type: _, body } => {
-                let function = self.compile_function(name.clone(), &parameters, body)?;
+            Stmt::FnDeclaration { visibility: _, name, parameters, return_type, body } => {
+                let return_type = return_type.unwrap_or(Type::Unknown);
+                let (function, captures) = self.compile_function(name.clone(), &parameters, return_type, body)?;
                 let idx = self.chunk.add_constant(Value::Function(function));
                 self.emit(OpCode::LoadConst(idx), 0);
 
+                // 嵌套函数引用了外层变量：按顺序压入捕获值，生成闭包
+                if !captures.is_empty() {
+                    for captured in &captures {
+                        self.compile_expression(Expr::identifier(captured.clone()))?;
+                    }
+                    self.emit(OpCode::Closure(captures.len()), 0);
+                }
+
                 if self.scope_depth == 0 {
-                    let name_idx = self.identifier_constant(&name)?;
+                    let name_idx = self.global_slot(&name)?;
                     self.emit(OpCode::StoreGlobal(name_idx), 0);
                     self.emit(OpCode::Pop, 0);
                 } else {
@@ -215,7 +796,8 @@ impl Compiler {
 
             Stmt::Return { value } => {
                 if let Some(expr) = value {
-                    self.compile_expression(expr)?;
+                    let return_type = self.return_type.clone();
+                    self.compile_expression_as(expr, &return_type)?;
                 } else {
                     self.emit(OpCode::LoadNull, 0);
                 }
@@ -249,10 +831,30 @@ impl Compiler {
                 self.patch_jump(else_jump);
             }
 
-            Stmt::While { condition, body } => {
+            Stmt::While { condition, body, else_branch } => {
+                // 循环不变量外提：循环前把不变的全局变量读入同名局部变量，
+                // 循环内的读取随之解析为 LoadLocal
+                let hoisted = if self.hoist_loop_invariants {
+                    self.invariant_globals(&condition, &body)
+                } else {
+                    Vec::new()
+                };
+                if !hoisted.is_empty() {
+                    self.begin_scope();
+                    for (name, actual_name) in hoisted.iter().cloned() {
+                        let idx = self.global_slot(&actual_name)?;
+                        self.emit(OpCode::LoadGlobal(idx), 0);
+                        self.add_local(name.clone(), false)?;
+                        if let Some(var_type) = self.global_types.get(&actual_name).cloned() {
+                            self.local_types.push(LocalTypeInfo { name, var_type });
+                        }
+                    }
+                }
+
                 let loop_start = self.chunk.len();
                 self.loop_starts.push(loop_start);
                 self.loop_breaks.push(Vec::new());
+                self.loop_locals.push(self.locals.len());
                 
                 self.compile_expression(condition)?;
                 let exit_jump = self.emit_jump(OpCode::JumpIfFalse(0));
@@ -264,9 +866,12 @@ impl Compiler {
                 }
                 self.end_scope();
                 
-                self.emit(OpCode::Loop(loop_start), 0);
+                self.emit_loop(loop_start);
                 self.patch_jump(exit_jump);
                 self.emit(OpCode::Pop, 0);
+
+                // else 分支只在条件不满足正常退出时执行，break 跳过它
+                self.compile_loop_else(else_branch)?;
                 
                 // 修补所有break跳转
                 if let Some(breaks) = self.loop_breaks.pop() {
@@ -275,26 +880,55 @@ impl Compiler {
                     }
                 }
                 self.loop_starts.pop();
+                self.loop_locals.pop();
+
+                if !hoisted.is_empty() {
+                    self.end_scope();
+                }
             }
 
-            Stmt::For { variable, start, end, body } => {
+            Stmt::For { variable, start, end, body, else_branch } => {
                 self.begin_scope();
                 
-                // 初始化循环变量
+                // 初始化循环变量（整数或字符，字符按码点递增）
+                let var_type = match self.infer_expression_type(&start) {
+                    Type::Char => Type::Char,
+                    _ => Type::Int,
+                };
+                // `for _ in ...` 仍需要计数器，使用内部名字（与 __end__ 相同的约定），`_` 本身不可引用
+                let variable = if variable == "_" { "__counter__".to_string() } else { variable };
                 self.compile_expression(start)?;
+                let var_slot = self.locals.len();
                 self.add_local(variable.clone(), true)?;
+                self.local_types.push(LocalTypeInfo {
+                    name: variable.clone(),
+                    var_type,
+                });
                 
                 // 计算结束值
                 self.compile_expression(end)?;
                 let end_local = self.locals.len();
                 self.add_local("__end__".to_string(), false)?;
                 
+
+                // 递增放在循环开头，continue 跳回 loop_start 时先递增再检查条件；
+                // 第一次进入循环时跳过递增
+                let first_jump = self.emit_jump(OpCode::Jump(0));
                 let loop_start = self.chunk.len();
                 self.loop_starts.push(loop_start);
                 self.loop_breaks.push(Vec::new());
+                self.loop_locals.push(self.locals.len());
+
+                // 递增: i = i + 1（字符变量由虚拟机按码点递增）
+                // 递增前已经检查过 i < end，所以 i + 1 <= end，结束值为 i64::MAX 时也不会溢出
+                self.emit(OpCode::LoadLocal(var_slot), 0);
+                self.emit_integer(1);
+                self.emit(OpCode::Add, 0);
+                self.emit(OpCode::StoreLocal(var_slot), 0);
+                self.emit(OpCode::Pop, 0);
+                self.patch_jump(first_jump);
                 
                 // 条件检查: i < end
-                let var_slot = self.resolve_local(&variable)?;
                 self.emit(OpCode::LoadLocal(var_slot), 0);
                 self.emit(OpCode::LoadLocal(end_local), 0);
                 self.emit(OpCode::Less, 0);
@@ -302,22 +936,18 @@ impl Compiler {
                 let exit_jump = self.emit_jump(OpCode::JumpIfFalse(0));
                 self.emit(OpCode::Pop, 0);
                 
-                // 循环体
+                // 循环体（单独的作用域，每次迭代结束时弹出体内声明的局部变量）
+                self.begin_scope();
                 for stmt in body {
                     self.compile_statement(stmt)?;
                 }
+                self.end_scope();
                 
-                // 递增: i = i + 1
-                self.emit(OpCode::LoadLocal(var_slot), 0);
-                let one_idx = self.chunk.add_constant(Value::Integer(1));
-                self.emit(OpCode::LoadConst(one_idx), 0);
-                self.emit(OpCode::Add, 0);
-                self.emit(OpCode::StoreLocal(var_slot), 0);
-                self.emit(OpCode::Pop, 0);
-                
-                self.emit(OpCode::Loop(loop_start), 0);
+                self.emit_loop(loop_start);
                 self.patch_jump(exit_jump);
                 self.emit(OpCode::Pop, 0);
+
+                self.compile_loop_else(else_branch)?;
                 
                 // 修补break跳转
                 if let Some(breaks) = self.loop_breaks.pop() {
@@ -326,6 +956,7 @@ impl Compiler {
                     }
                 }
                 self.loop_starts.pop();
+                self.loop_locals.pop();
                 
                 self.end_scope();
             }
@@ -347,6 +978,7 @@ impl Compiler {
                 if self.loop_breaks.is_empty() {
                     return Err(CompileError::InvalidBreakContinue);
                 }
+                self.emit_loop_scope_cleanup();
                 let break_jump = self.emit_jump(OpCode::Jump(0));
                 if let Some(breaks) = self.loop_breaks.last_mut() {
                     breaks.push(break_jump);
@@ -358,7 +990,8 @@ impl Compiler {
                     return Err(CompileError::InvalidBreakContinue);
                 }
                 let loop_start = *self.loop_starts.last().unwrap();
-                self.emit(OpCode::Loop(loop_start), 0);
+                self.emit_loop_scope_cleanup();
+                self.emit_loop(loop_start);
             }
         }
 
@@ -367,11 +1000,35 @@ impl Compiler {
 
     /// 编译表达式
     fn compile_expression(&mut self, expr: Expr) -> CompileResult<()> {
+        let expr = if self.strength_reduction { self.simplify_arithmetic(expr) } else { expr };
         match expr {
-            Expr::StructLiteral { struct_name, fields } => {
+            Expr::StructLiteral { struct_name, fields, base: Some(base), span } => {
+                let struct_def = self.structs.get(&struct_name).cloned()
+                    .ok_or_else(|| CompileError::UndefinedStruct(struct_name.clone(), span))?;
+
+                // 更新语法：复制 base，再逐个覆盖列出的字段
+                self.compile_expression(*base)?;
+                for (field_name, value) in fields {
+                    let field_index = struct_def.fields.iter()
+                        .position(|f| f.name == field_name)
+                        .ok_or_else(|| struct_def.undefined_field(&struct_name, &field_name, span))?;
+                    let field_type = struct_def.fields[field_index].field_type.clone();
+                    self.compile_expression_as(value, &field_type)?;
+                    self.emit(OpCode::FieldSet(field_index), 0);
+                }
+            }
+
+            Expr::StructLiteral { struct_name, fields, base: None, span } => {
                 // 获取结构体定义
                 let struct_def = self.structs.get(&struct_name).cloned()
-                    .ok_or_else(|| CompileError::UndefinedStruct(struct_name.clone()))?;
+                    .ok_or_else(|| CompileError::UndefinedStruct(struct_name.clone(), span))?;
+
+                // 字面量中不能出现结构体没有的字段
+                if let Some((unknown, _)) = fields.iter()
+                    .find(|(name, _)| !struct_def.fields.iter().any(|f| &f.name == name))
+                {
+                    return Err(struct_def.undefined_field(&struct_name, unknown, span));
+                }
 
                 // 按照结构体定义的字段顺序编译字段值
                 for defined_field in &struct_def.fields {
@@ -379,12 +1036,9 @@ impl Compiler {
                     let field_value = fields.iter()
                         .find(|(name, _)| name == &defined_field.name)
                         .map(|(_, value)| value)
-                        .ok_or_else(|| CompileError::UndefinedField(
-                            struct_name.clone(),
-                            defined_field.name.clone()
-                        ))?;
+                        .ok_or_else(|| struct_def.undefined_field(&struct_name, &defined_field.name, span))?;
 
-                    self.compile_expression(field_value.clone())?;
+                    self.compile_expression_as(field_value.clone(), &defined_field.field_type)?;
                 }
 
                 // 推送结构体名称到栈
@@ -395,29 +1049,35 @@ impl Compiler {
                 self.emit(OpCode::NewStruct(struct_def.fields.len()), 0);
             }
 
-            Expr::FieldAccess { object, field } => {
+            Expr::FieldAccess { object, field, span } => {
                 // 编译对象表达式
                 self.compile_expression(*object.clone())?;
 
                 // 推断对象类型并获取字段索引
                 let obj_type = self.infer_expression_type(&object);
+                self.emit_field_get(&obj_type, &field, span)?;
+            }
 
-                let field_index = match obj_type {
-                    Type::Struct(struct_type) => {
-                        // 从结构体类型中查找字段索引
-                        self.get_field_index(&struct_type, &field)
-                            .unwrap_or(0) // 如果找不到，使用 0 作为回退
-                    }
-                    _ => 0, // 非结构体类型，使用 0
-                };
+            Expr::OptionalFieldAccess { object, field, span } => {
+                let obj_type = self.infer_expression_type(&object);
+                self.compile_expression(*object)?;
 
-                // 使用实际的字段索引
-                self.emit(OpCode::FieldGet(field_index), 0);
+                // 对象为 null 时直接把它作为结果，否则读取字段
+                self.emit(OpCode::Dup, 0);
+                self.emit(OpCode::LoadNull, 0);
+                self.emit(OpCode::Equal, 0);
+                let null_jump = self.emit_jump(OpCode::JumpIfTrue(0));
+                self.emit(OpCode::Pop, 0);
+                self.emit_field_get(&obj_type, &field, span)?;
+                let end_jump = self.emit_jump(OpCode::Jump(0));
+                self.patch_jump(null_jump);
+                self.emit(OpCode::Pop, 0);
+                self.patch_jump(end_jump);
             }
 
-            Expr::FieldAssign { object, field, value } => {
+            Expr::FieldAssign { object, field, value, compound, span } => {
                 // 编译字段赋值
-                let var_name = if let Expr::Identifier(name) = object.as_ref() {
+                let var_name = if let Expr::Identifier(name, _) = object.as_ref() {
                     Some(name.clone())
                 } else {
                     None
@@ -425,38 +1085,38 @@ impl Compiler {
 
                 // 推断对象类型并获取字段索引
                 let obj_type = self.infer_expression_type(&object);
-
-                let field_index = match obj_type {
-                    Type::Struct(struct_type) => {
-                        // 从结构体类型中查找字段索引
-                        self.get_field_index(&struct_type, &field)
-                            .unwrap_or(0) // 如果找不到，使用 0 作为回退
-                    }
-                    _ => 0, // 非结构体类型，使用 0
-                };
-
-                // 编译对象和值
-                self.compile_expression(*object)?;
-                self.compile_expression(*value)?;
+                let field_index = self.resolve_field_index(&obj_type, &field, span)?;
+
+                let target = Expr::FieldAccess { object: object.clone(), field: field.clone(), span };
+                let field_type = self.infer_expression_type(&target);
+                if let Some((operator, rhs)) = Self::split_compound(compound, &value) {
+                    // 复合赋值：对象只求值一次，复制后读出旧字段值
+                    self.compile_expression(*object)?;
+                    self.emit(OpCode::Dup, 0);
+                    self.emit(OpCode::FieldGet(field_index), 0);
+                    self.compile_binary_rhs(field_type, operator, rhs)?;
+                } else {
+                    // 编译对象和值（值按字段类型编译）
+                    self.compile_expression(*object)?;
+                    self.compile_expression_as(*value, &field_type)?;
+                }
 
                 // 使用实际的字段索引
                 self.emit(OpCode::FieldSet(field_index), 0);
 
                 // 如果object是标识符，将修改后的结构体存回
                 if let Some(name) = var_name {
-                    if let Ok(slot) = self.resolve_local(&name) {
+                    if let Some(slot) = self.resolve_local(&name) {
                         self.emit(OpCode::StoreLocal(slot), 0);
                     } else {
-                        let idx = self.identifier_constant(&name)?;
+                        self.check_not_captured(&name, span)?;
+                        let idx = self.global_slot(&self.global_name(&name))?;
                         self.emit(OpCode::StoreGlobal(idx), 0);
                     }
                 }
             }
 
-            Expr::Integer(n) => {
-                let idx = self.chunk.add_constant(Value::Integer(n));
-                self.emit(OpCode::LoadConst(idx), 0);
-            }
+            Expr::Integer(n) => self.emit_integer(n),
 
             Expr::Float(f) => {
                 let idx = self.chunk.add_constant(Value::Float(f));
@@ -469,83 +1129,69 @@ impl Compiler {
             }
 
             Expr::Boolean(b) => {
-                let idx = self.chunk.add_constant(Value::Boolean(b));
-                self.emit(OpCode::LoadConst(idx), 0);
+                self.emit(if b { OpCode::LoadTrue } else { OpCode::LoadFalse }, 0);
             }
 
+            Expr::Null => self.emit(OpCode::LoadNull, 0),
+
             Expr::Char(c) => {
                 let idx = self.chunk.add_constant(Value::Char(c));
                 self.emit(OpCode::LoadConst(idx), 0);
             }
 
-            Expr::Identifier(name) => {
-                if let Ok(slot) = self.resolve_local(&name) {
+            Expr::Identifier(name, _) => {
+                if let Some(slot) = self.resolve_local(&name) {
                     self.emit(OpCode::LoadLocal(slot), 0);
+                } else if let Some(idx) = self.resolve_upvalue(&name) {
+                    self.emit(OpCode::LoadUpvalue(idx), 0);
                 } else {
-                    // 检查是否是导入的符号，如果是，使用原始名而不是别名
-                    let actual_name = self.imported_symbols.get(&name)
-                        .map(|s| s.clone())
-                        .unwrap_or_else(|| name.clone());
-                    let idx = self.identifier_constant(&actual_name)?;
+                    // 导入的别名和模块变量换成对应的全局名
+                    let idx = self.global_slot(&self.global_name(&name))?;
                     self.emit(OpCode::LoadGlobal(idx), 0);
                 }
             }
 
-            Expr::Path { segments } => {
+            Expr::Path { segments, span } => {
                 // 路径表达式: module::item
-                // 将路径转换为全局变量名
-                // 例如: math::add -> 全局变量 "add"（因为在模块内已定义）
-                //
-                // 注意：由于我们当前的编译策略是将所有模块函数编译到全局作用域，
-                // 我们只需要访问最后一个段（实际的符号名）
-                if segments.is_empty() {
-                    return Err(CompileError::UndefinedVariable("empty path".to_string()));
-                }
-
-                let item_name = &segments[segments.len() - 1];
-                let idx = self.identifier_constant(item_name)?;
+                // 模块变量的全局名就是完整路径；模块函数编译在全局作用域，按最后一个段（实际的符号名）访问
+                let global = Self::path_global_name(&self.module_variables, &segments)
+                    .ok_or_else(|| CompileError::UndefinedVariable("empty path".to_string(), span))?;
+                let idx = self.global_slot(&global)?;
                 self.emit(OpCode::LoadGlobal(idx), 0);
             }
 
             Expr::Binary { left, operator, right } => {
                 // 短路求值优化
                 match operator {
-                    BinaryOp::And => {
-                        self.compile_expression(*left)?;
-                        let jump = self.emit_jump(OpCode::JumpIfFalse(0));
-                        self.emit(OpCode::Pop, 0);
-                        self.compile_expression(*right)?;
-                        self.patch_jump(jump);
-                        return Ok(());
-                    }
-                    BinaryOp::Or => {
+                    BinaryOp::And | BinaryOp::Or => {
+                        // 操作数不一定是 bool（例如无类型注解的参数）时，结果需要转换为 bool，
+                        // 与解释器保持一致
+                        let needs_bool = self.infer_expression_type(&left) != Type::Bool
+                            || self.infer_expression_type(&right) != Type::Bool;
+
                         self.compile_expression(*left)?;
-                        let jump = self.emit_jump(OpCode::JumpIfTrue(0));
+                        let jump = if operator == BinaryOp::And {
+                            self.emit_jump(OpCode::JumpIfFalse(0))
+                        } else {
+                            self.emit_jump(OpCode::JumpIfTrue(0))
+                        };
                         self.emit(OpCode::Pop, 0);
                         self.compile_expression(*right)?;
                         self.patch_jump(jump);
+
+                        if needs_bool {
+                            // !!x：按真值转换为 bool
+                            self.emit(OpCode::Not, 0);
+                            self.emit(OpCode::Not, 0);
+                        }
                         return Ok(());
                     }
                     _ => {}
                 }
 
+                let left_type = self.infer_expression_type(&left);
                 self.compile_expression(*left)?;
-                self.compile_expression(*right)?;
-
-                match operator {
-                    BinaryOp::Add => self.emit(OpCode::Add, 0),
-                    BinaryOp::Subtract => self.emit(OpCode::Subtract, 0),
-                    BinaryOp::Multiply => self.emit(OpCode::Multiply, 0),
-                    BinaryOp::Divide => self.emit(OpCode::Divide, 0),
-                    BinaryOp::Modulo => self.emit(OpCode::Modulo, 0),
-                    BinaryOp::Equal => self.emit(OpCode::Equal, 0),
-                    BinaryOp::NotEqual => self.emit(OpCode::NotEqual, 0),
-                    BinaryOp::Greater => self.emit(OpCode::Greater, 0),
-                    BinaryOp::GreaterEqual => self.emit(OpCode::GreaterEqual, 0),
-                    BinaryOp::Less => self.emit(OpCode::Less, 0),
-                    BinaryOp::LessEqual => self.emit(OpCode::LessEqual, 0),
-                    BinaryOp::And | BinaryOp::Or => unreachable!(), // 已处理
-                };
+                self.compile_binary_rhs(left_type, operator, *right)?;
             }
 
             Expr::Unary { operator, operand } => {
@@ -556,38 +1202,97 @@ impl Compiler {
                 };
             }
 
-            Expr::Assign { name, value } => {
-                self.compile_expression(*value)?;
+            Expr::Assign { name, value, span } => {
+                let target_type = self.infer_expression_type(&Expr::identifier(name.clone()));
+                self.compile_expression_as(*value, &target_type)?;
                 
-                if let Ok(slot) = self.resolve_local(&name) {
+                if let Some(slot) = self.resolve_local(&name) {
                     self.emit(OpCode::StoreLocal(slot), 0);
                 } else {
-                    let idx = self.identifier_constant(&name)?;
+                    self.check_not_captured(&name, span)?;
+                    let idx = self.global_slot(&self.global_name(&name))?;
                     self.emit(OpCode::StoreGlobal(idx), 0);
                 }
             }
 
-            Expr::Call { callee, arguments } => {
-                self.compile_expression(*callee)?;
+            Expr::PathAssign { segments, value, span } => {
+                // 与路径读取一样，存储到模块变量带模块路径的全局名
+                let global = Self::path_global_name(&self.module_variables, &segments)
+                    .ok_or_else(|| CompileError::UndefinedVariable("empty path".to_string(), span))?;
+                let target_type = self.infer_expression_type(&Expr::Path { segments, span });
+                self.compile_expression_as(*value, &target_type)?;
+                let idx = self.global_slot(&global)?;
+                self.emit(OpCode::StoreGlobal(idx), 0);
+            }
 
-                for arg in arguments.iter() {
-                    self.compile_expression(arg.clone())?;
+            Expr::Call { callee, arguments } if matches!(callee.as_ref(), Expr::Identifier(name, _) if self.is_builtin_call(name)) => {
+                let argument_count = arguments.len();
+                for arg in arguments {
+                    self.compile_expression(arg)?;
+                }
+                let builtin = match callee.as_ref() {
+                    Expr::Identifier(name, _) => crate::builtins::lookup(name),
+                    _ => None,
+                };
+                // 守卫已经确认是内置函数；没有返回值的内置函数会在指令中留下 null 作为表达式的值
+                if let Some(builtin) = builtin {
+                    for op in (builtin.emit)(argument_count) {
+                        self.emit(op, 0);
+                    }
                 }
-
-                self.emit(OpCode::Call(arguments.len()), 0);
             }
 
-            Expr::MethodCall { object, method, arguments } => {
-                // 推断对象类型以确定方法所属的类型
-                let obj_type = self.infer_expression_type(&object);
+            Expr::Call { callee, arguments } => {
+                if let Some(inlined) = self.inline_call(&callee, &arguments) {
+                    return self.compile_expression(inlined);
+                }
 
-                let type_name = match obj_type {
-                    Type::Struct(struct_type) => struct_type.name.clone(),
-                    Type::Named(name) => name.clone(),
-                    _ => {
-                        return Err(CompileError::UndefinedVariable(
-                            format!("Cannot call method on type {:?}", obj_type)
-                        ));
+                let parameters = self.callee_signature(&callee).map(|s| s.parameters.clone()).unwrap_or_default();
+                let argument_count = arguments.len();
+                self.compile_expression(*callee)?;
+                self.compile_arguments(arguments, &parameters)?;
+                self.emit(OpCode::Call(argument_count), 0);
+            }
+
+            Expr::MethodCall { object, method, arguments, .. } if self.is_builtin_to_string(&object, &method, &arguments) => {
+                self.compile_expression(*object)?;
+                self.emit(OpCode::ToString, 0);
+            }
+
+            Expr::MethodCall { object, method, mut arguments, .. } if self.is_builder_append(&object, &method, &arguments) => {
+                // 直接追加到变量中的字符串，不把构建器复制到栈上；表达式的值为 null
+                if let Expr::Identifier(name, span) = *object {
+                    let append_op = if let Some(slot) = self.resolve_local(&name) {
+                        OpCode::StrAppendLocal(slot)
+                    } else {
+                        self.check_not_captured(&name, span)?;
+                        OpCode::StrAppendGlobal(self.global_slot(&self.global_name(&name))?)
+                    };
+                    self.compile_expression(arguments.remove(0))?;
+                    self.emit(append_op, 0);
+                }
+            }
+
+            Expr::MethodCall { object, method, arguments, span } => {
+                if let Some(op) = self.builtin_method(&object, &method, &arguments) {
+                    self.compile_expression(*object)?;
+                    for arg in arguments {
+                        self.compile_expression(arg)?;
+                    }
+                    self.emit(op, 0);
+                    return Ok(());
+                }
+
+                // 推断对象类型以确定方法所属的类型
+                let obj_type = self.infer_expression_type(&object);
+
+                let type_name = match obj_type.impl_name() {
+                    Some(name) => name,
+                    None => {
+                        return Err(CompileError::UndefinedVariable(
+                            format!("Cannot call method on type {:?}", obj_type),
+                            span,
+                        ));
                     }
                 };
 
@@ -596,7 +1301,8 @@ impl Compiler {
                     .get(&type_name)
                     .and_then(|methods| methods.get(&method))
                     .ok_or_else(|| CompileError::UndefinedVariable(
-                        format!("Method {} not found on type {}", method, type_name)
+                        format!("Method {} not found on type {}", method, type_name),
+                        span,
                     ))?
                     .clone();
 
@@ -604,16 +1310,17 @@ impl Compiler {
                 let func_idx = self.chunk.add_constant(Value::Function(function));
                 self.emit(OpCode::LoadConst(func_idx), 0);
 
+                let parameters = self.method_signature(&object, &method).map(|s| s.parameters.clone()).unwrap_or_default();
+                let argument_count = arguments.len();
+
                 // 编译 self 参数（对象）
                 self.compile_expression(*object)?;
 
                 // 编译其他参数
-                for arg in arguments.iter() {
-                    self.compile_expression(arg.clone())?;
-                }
+                self.compile_arguments(arguments, &parameters)?;
 
                 // 调用方法（参数数量 = arguments.len() + 1 for self）
-                self.emit(OpCode::Call(arguments.len() + 1), 0);
+                self.emit(OpCode::Call(argument_count + 1), 0);
             }
 
             Expr::Array { elements } => {
@@ -626,6 +1333,37 @@ impl Compiler {
                 self.emit(OpCode::NewArray(len), 0);
             }
 
+            Expr::If { condition, then_branch, else_branch } => {
+                self.compile_if_expression(*condition, then_branch, else_branch, &Type::Unknown)?;
+            }
+
+            Expr::ArrayFill { value, count } => {
+                self.compile_expression(*value)?;
+                self.compile_expression(*count)?;
+                self.emit(OpCode::ArrayFill, 0);
+            }
+
+            Expr::Tuple { elements } => {
+                let len = elements.len();
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(OpCode::NewTuple(len), 0);
+            }
+
+            Expr::Slice { object, start, end } => {
+                self.compile_expression(*object)?;
+                self.compile_expression(*start)?;
+                self.compile_expression(*end)?;
+                self.emit(OpCode::Slice, 0);
+            }
+
+            Expr::TupleIndex { object, index } => {
+                self.compile_expression(*object)?;
+                self.emit_integer(index as i64);
+                self.emit(OpCode::ArrayGet, 0);
+            }
+
             Expr::Index { object, index } => {
                 // 编译数组和索引表达式
                 self.compile_expression(*object)?;
@@ -634,57 +1372,134 @@ impl Compiler {
                 self.emit(OpCode::ArrayGet, 0);
             }
             
-            Expr::IndexAssign { object, index, value } => {
-                // 对于数组元素赋值，我们需要特殊处理来确保原数组被更新
-                // 如果object是标识符，我们需要：
-                // 1. 加载数组
-                // 2. 加载索引
-                // 3. 加载值
-                // 4. 执行ArraySet（修改数组并将新数组留在栈上）
-                // 5. 将新数组存回变量
-
-                // 先检查是否是标识符，保存名称
-                let var_name = if let Expr::Identifier(name) = object.as_ref() {
-                    Some(name.clone())
-                } else {
-                    None
-                };
+            Expr::IndexAssign { object, index, value, compound } => {
+                let target = Expr::Index { object: object.clone(), index: index.clone() };
+                let compound = Self::split_compound(compound, &value);
+                let element_type = self.infer_expression_type(&target);
+
+                // 目标是变量时用 ArraySetLocal/ArraySetGlobal 直接修改变量中的数组，
+                // 不必先把数组复制到栈上再存回；栈上只有索引和新值
+                if let Expr::Identifier(name, span) = object.as_ref() {
+                    let set_op = if let Some(slot) = self.resolve_local(name) {
+                        OpCode::ArraySetLocal(slot)
+                    } else {
+                        self.check_not_captured(name, *span)?;
+                        OpCode::ArraySetGlobal(self.global_slot(&self.global_name(name))?)
+                    };
+                    match compound {
+                        Some((operator, rhs)) => {
+                            // 复合赋值：索引只求值一次。复制索引后读取数组变量，交换后取出旧元素
+                            self.compile_expression(*index)?;
+                            self.emit(OpCode::Dup, 0);
+                            self.compile_expression(*object)?;
+                            self.emit(OpCode::Swap, 0);
+                            self.emit(OpCode::ArrayGet, 0);
+                            self.compile_binary_rhs(element_type, operator, rhs)?;
+                        }
+                        None => {
+                            self.compile_expression(*index)?;
+                            self.compile_expression_as(*value, &element_type)?;
+                        }
+                    }
+                    self.emit(set_op, 0);
+                    return Ok(());
+                }
 
-                // 编译表达式
+                // 其他目标：ArraySet 把修改后的数组留在栈上作为表达式结果
                 self.compile_expression(*object)?;
                 self.compile_expression(*index)?;
-                self.compile_expression(*value)?;
-                // ArraySet返回修改后的数组
+                self.compile_expression_as(*value, &element_type)?;
                 self.emit(OpCode::ArraySet, 0);
+            }
+        }
 
-                // 如果object是标识符，将修改后的数组存回
-                if let Some(name) = var_name {
-                    if let Ok(slot) = self.resolve_local(&name) {
-                        self.emit(OpCode::StoreLocal(slot), 0);
-                    } else {
-                        let idx = self.identifier_constant(&name)?;
-                        self.emit(OpCode::StoreGlobal(idx), 0);
-                    }
+        Ok(())
+    }
+
+    /// 左操作数已经在栈顶时，编译右操作数并执行二元运算（不含短路的 && 和 ||）
+    fn compile_binary_rhs(&mut self, left_type: Type, operator: BinaryOp, right: Expr) -> CompileResult<()> {
+        let right_type = self.infer_expression_type(&right);
+
+        if operator == BinaryOp::Add {
+            // 字符串与任意类型相加：先把非字符串一侧转换为字符串，再拼接
+            if left_type == Type::String || right_type == Type::String {
+                if left_type != Type::String {
+                    self.emit(OpCode::ToString, 0);
                 }
-                // 否则留在栈上作为表达式结果
+                self.compile_expression(right)?;
+                if right_type != Type::String {
+                    self.emit(OpCode::ToString, 0);
+                }
+                self.emit(OpCode::Concat, 0);
+                return Ok(());
+            }
+
+            // 两侧都是数组：拼接为新数组
+            if left_type.get_element_type().is_some() && right_type.get_element_type().is_some() {
+                self.compile_expression(right)?;
+                self.emit(OpCode::ArrayConcat, 0);
+                return Ok(());
             }
         }
 
+        self.compile_expression(right)?;
+
+        match operator {
+            BinaryOp::Add => self.emit(OpCode::Add, 0),
+            BinaryOp::Subtract => self.emit(OpCode::Subtract, 0),
+            BinaryOp::Multiply => self.emit(OpCode::Multiply, 0),
+            BinaryOp::Divide => self.emit(OpCode::Divide, 0),
+            BinaryOp::Modulo => self.emit(OpCode::Modulo, 0),
+            BinaryOp::Power => self.emit(OpCode::Power, 0),
+            BinaryOp::Equal => self.emit(OpCode::Equal, 0),
+            BinaryOp::NotEqual => self.emit(OpCode::NotEqual, 0),
+            BinaryOp::Greater => self.emit(OpCode::Greater, 0),
+            BinaryOp::GreaterEqual => self.emit(OpCode::GreaterEqual, 0),
+            BinaryOp::Less => self.emit(OpCode::Less, 0),
+            BinaryOp::LessEqual => self.emit(OpCode::LessEqual, 0),
+            BinaryOp::And | BinaryOp::Or => unreachable!(), // 由调用方处理
+        };
         Ok(())
     }
 
+    /// 复合赋值 `target op= rhs` 的值已由解析器展开为 `target op rhs`，取出运算符和右操作数
+    /// （只看解析器记录的运算符：手写的 `a[f()] = a[f()] + 1` 中下标要求值两次）
+    fn split_compound(compound: Option<BinaryOp>, value: &Expr) -> Option<(BinaryOp, Expr)> {
+        match (compound, value) {
+            (Some(operator), Expr::Binary { right, .. }) => Some((operator, right.as_ref().clone())),
+            _ => None,
+        }
+    }
+
     /// 编译函数
     fn compile_function(
         &mut self,
         name: String,
         parameters: &[Parameter],
+        return_type: Type,
         body: Vec<Stmt>,
-    ) -> CompileResult<Function> {
+    ) -> CompileResult<(Function, Vec<String>)> {
         let mut function_compiler = Compiler::new();
 
         // 复制结构体定义和方法定义到新编译器
         function_compiler.structs = self.structs.clone();
         function_compiler.methods = self.methods.clone();
+        function_compiler.method_signatures = self.method_signatures.clone();
+        function_compiler.function_signatures = self.function_signatures.clone();
+        function_compiler.return_type = return_type;
+        function_compiler.type_aliases = self.type_aliases.clone();
+        function_compiler.imported_symbols = self.imported_symbols.clone();
+        function_compiler.module_path = self.module_path.clone();
+        function_compiler.module_variables = self.module_variables.clone();
+        function_compiler.declared_functions = self.declared_functions.clone();
+        function_compiler.hoist_loop_invariants = self.hoist_loop_invariants;
+        function_compiler.strength_reduction = self.strength_reduction;
+        function_compiler.inline_functions = self.inline_functions.clone();
+        // 函数与顶层代码共享全局变量槽位表
+        function_compiler.global_slots = std::mem::take(&mut self.global_slots);
+        // 外层函数的局部变量（以及它自己能捕获的变量）对嵌套函数可见
+        function_compiler.enclosing_names = self.enclosing_names.clone();
+        function_compiler.enclosing_names.extend(self.locals.iter().map(|l| l.name.clone()));
 
         function_compiler.begin_scope();
 
@@ -709,12 +1524,17 @@ impl Compiler {
         function_compiler.emit(OpCode::LoadNull, 0);
         function_compiler.emit(OpCode::Return, 0);
 
-        Ok(Function {
+        // 取回函数体中新分配的全局槽位
+        self.global_slots = std::mem::take(&mut function_compiler.global_slots);
+
+        let function = Function {
             name,
             arity: parameters.len(),
             chunk: function_compiler.chunk,
             locals_count: function_compiler.locals.len(),
-        })
+            upvalues: Vec::new(),
+        };
+        Ok((function, function_compiler.upvalues))
     }
 
     // 辅助方法
@@ -722,13 +1542,71 @@ impl Compiler {
         self.chunk.write(op, line);
     }
 
+    /// 编译循环的 else 分支（位于正常退出路径上，break 的跳转目标在它之后）
+    /// else 中的 break/continue 属于外层循环，因此要先弹出当前循环再编译
+    fn compile_loop_else(&mut self, else_branch: Option<Vec<Stmt>>) -> CompileResult<()> {
+        let else_stmts = match else_branch {
+            Some(else_stmts) => else_stmts,
+            None => return Ok(()),
+        };
+
+        let breaks = self.loop_breaks.pop();
+        let loop_start = self.loop_starts.pop();
+        let loop_locals = self.loop_locals.pop();
+
+        self.begin_scope();
+        for stmt in else_stmts {
+            self.compile_statement(stmt)?;
+        }
+        self.end_scope();
+
+        if let Some(loop_start) = loop_start {
+            self.loop_starts.push(loop_start);
+        }
+        if let Some(breaks) = breaks {
+            self.loop_breaks.push(breaks);
+        }
+        if let Some(loop_locals) = loop_locals {
+            self.loop_locals.push(loop_locals);
+        }
+        Ok(())
+    }
+
+    /// break/continue 跳出循环体之前，弹出循环开始之后声明的局部变量
+    /// （只发出 Pop，不修改编译期的 locals：跳转之后的代码仍在原来的作用域中）
+    fn emit_loop_scope_cleanup(&mut self) {
+        let loop_locals = self.loop_locals.last().copied().unwrap_or(self.locals.len());
+        for _ in loop_locals..self.locals.len() {
+            self.emit(OpCode::Pop, 0);
+        }
+    }
+
+    /// 加载整数：0 和 1 使用立即数指令，不占用常量池
+    fn emit_integer(&mut self, n: i64) {
+        match n {
+            0 => self.emit(OpCode::LoadZero, 0),
+            1 => self.emit(OpCode::LoadOne, 0),
+            _ => {
+                let idx = self.chunk.add_constant(Value::Integer(n));
+                self.emit(OpCode::LoadConst(idx), 0);
+            }
+        }
+    }
+
     fn emit_jump(&mut self, op: OpCode) -> usize {
         self.emit(op, 0);
         self.chunk.len() - 1
     }
 
+    /// 发出跳回 loop_start 的 Loop 指令（相对偏移为负）
+    fn emit_loop(&mut self, loop_start: usize) {
+        let jump = loop_start as isize - (self.chunk.len() as isize + 1);
+        self.emit(OpCode::Loop(jump), 0);
+    }
+
+    /// 将 offset 处的跳转指令修补为跳到当前位置（相对于跳转指令的下一条指令）
     fn patch_jump(&mut self, offset: usize) {
-        let jump = self.chunk.len();
+        let jump = self.chunk.len() as isize - (offset as isize + 1);
         self.chunk.code[offset] = match self.chunk.code[offset] {
             OpCode::Jump(_) => OpCode::Jump(jump),
             OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(jump),
@@ -737,9 +1615,112 @@ impl Compiler {
         };
     }
 
-    fn identifier_constant(&mut self, name: &str) -> CompileResult<usize> {
-        let value = Value::String(name.to_string());
-        Ok(self.chunk.add_constant(value))
+    /// 将栈顶的值绑定到模式上
+    /// 元组解构时按下标逐个取出元素（ArrayGet 同样适用于元组值）
+    fn bind_pattern(&mut self, pattern: Pattern, var_type: Type, mutable: bool) -> CompileResult<()> {
+        match pattern {
+            // `_` 不分配变量槽，直接丢弃值
+            Pattern::Identifier(name) if name == "_" => {
+                self.emit(OpCode::Pop, 0);
+            }
+
+            Pattern::Identifier(name) => {
+                if self.scope_depth == 0 {
+                    // 全局变量（模块顶层的变量带上模块路径）
+                    let name = Self::qualify(&self.module_path, &name);
+                    let idx = self.global_slot(&name)?;
+                    self.emit(OpCode::StoreGlobal(idx), 0);
+                    self.emit(OpCode::Pop, 0);
+                    // 记录全局变量类型
+                    self.global_types.insert(name, var_type);
+                } else {
+                    // 局部变量；同一作用域中重新声明时复用原来的槽位（新值覆盖旧值）
+                    match self.resolve_local_in_current_scope(&name) {
+                        Some(slot) => {
+                            self.emit(OpCode::StoreLocal(slot), 0);
+                            self.emit(OpCode::Pop, 0);
+                            self.locals[slot].is_mutable = mutable;
+                        }
+                        None => self.add_local(name.clone(), mutable)?,
+                    }
+                    // 记录局部变量类型（重新声明时新类型覆盖旧类型）
+                    self.local_types.push(LocalTypeInfo {
+                        name,
+                        var_type,
+                    });
+                }
+            }
+
+            Pattern::Tuple(elements) => {
+                let element_types = match var_type {
+                    Type::Tuple(types) => types,
+                    _ => Vec::new(),
+                };
+
+                if self.scope_depth == 0 {
+                    // 全局作用域：复制元组取出元素，最后弹出元组本身
+                    for (i, element) in elements.into_iter().enumerate() {
+                        self.emit(OpCode::Dup, 0);
+                        self.emit_integer(i as i64);
+                        self.emit(OpCode::ArrayGet, 0);
+                        let element_type = element_types.get(i).cloned().unwrap_or(Type::Unknown);
+                        self.bind_pattern(element, element_type, mutable)?;
+                    }
+                    self.emit(OpCode::Pop, 0);
+                } else {
+                    // 局部作用域：元组占用一个隐藏的局部变量槽，元素依次占用后续槽位
+                    let tuple_slot = self.locals.len();
+                    self.add_local("__tuple__".to_string(), false)?;
+                    for (i, element) in elements.into_iter().enumerate() {
+                        self.emit(OpCode::LoadLocal(tuple_slot), 0);
+                        self.emit_integer(i as i64);
+                        self.emit(OpCode::ArrayGet, 0);
+                        let element_type = element_types.get(i).cloned().unwrap_or(Type::Unknown);
+                        self.bind_pattern(element, element_type, mutable)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 获取全局变量的槽位，首次引用时分配新槽位
+    /// （函数体可能在全局变量声明之前引用它，例如递归函数引用自身）
+    fn global_slot(&mut self, name: &str) -> CompileResult<usize> {
+        let next_slot = self.global_slots.len();
+        Ok(*self.global_slots.entry(name.to_string()).or_insert(next_slot))
+    }
+
+    /// 不是局部变量的名字对应的全局变量名：导入的别名换成原始名（导入的模块变量已映射到全局名），
+    /// 当前模块及外层模块的顶层变量换成带模块路径的名字，从内向外查找
+    fn global_name(&self, name: &str) -> String {
+        if let Some(original) = self.imported_symbols.get(name) {
+            return original.clone();
+        }
+        (1..=self.module_path.len()).rev()
+            .map(|depth| format!("{}::{}", self.module_path[..depth].join("::"), name))
+            .find(|global| self.module_variables.contains_key(global))
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// 路径对应的全局变量名：模块变量使用完整路径，其他符号（函数）使用最后一个段
+    fn path_global_name(module_variables: &HashMap<String, Visibility>, segments: &[String]) -> Option<String> {
+        let path = segments.join("::");
+        if module_variables.contains_key(&path) {
+            return Some(path);
+        }
+        segments.last().cloned()
+    }
+
+    /// 在当前作用域（不包括外层作用域）中查找局部变量的槽位
+    fn resolve_local_in_current_scope(&self, name: &str) -> Option<usize> {
+        self.locals.iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, local)| local.depth == self.scope_depth)
+            .find(|(_, local)| local.name == name)
+            .map(|(slot, _)| slot)
     }
 
     fn add_local(&mut self, name: String, is_mutable: bool) -> CompileResult<()> {
@@ -756,13 +1737,89 @@ impl Compiler {
         Ok(())
     }
 
-    fn resolve_local(&self, name: &str) -> CompileResult<usize> {
-        for (i, local) in self.locals.iter().enumerate().rev() {
-            if local.name == name {
-                return Ok(i);
-            }
+    /// 找出 while 循环中可以外提的全局变量读取，返回 (源码中的名字, 全局变量名)
+    ///
+    /// 只处理最保守的情况：循环中没有函数调用和嵌套声明（它们可能修改全局变量），
+    /// 变量是此前已在顶层声明的全局变量，且在循环中没有被赋值。
+    fn invariant_globals(&self, condition: &Expr, body: &[Stmt]) -> Vec<(String, String)> {
+        let mut usage = LoopUsage::default();
+        usage.visit_expr(condition);
+        for stmt in body {
+            usage.visit_stmt(stmt);
+        }
+        if usage.opaque {
+            return Vec::new();
         }
-        Err(CompileError::UndefinedVariable(name.to_string()))
+
+        usage.reads.into_iter()
+            .filter(|name| !usage.writes.contains(name))
+            .filter(|name| self.resolve_local(name).is_none() && !self.enclosing_names.contains(name))
+            .map(|name| {
+                let actual_name = self.global_name(&name);
+                (name, actual_name)
+            })
+            .filter(|(_, actual_name)| self.global_types.contains_key(actual_name))
+            .collect()
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local.name == name)
+    }
+
+    /// 解析外层函数的变量，返回它在当前函数捕获列表中的下标
+    /// 捕获发生在创建闭包时，按值复制
+    fn resolve_upvalue(&mut self, name: &str) -> Option<usize> {
+        if let Some(idx) = self.upvalues.iter().position(|n| n == name) {
+            return Some(idx);
+        }
+        if !self.enclosing_names.iter().any(|n| n == name) {
+            return None;
+        }
+        self.upvalues.push(name.to_string());
+        Some(self.upvalues.len() - 1)
+    }
+
+    /// 对外层变量的赋值无法写回（按值捕获），编译时报错而不是误写同名全局变量
+    fn check_not_captured(&self, name: &str, span: Span) -> CompileResult<()> {
+        if self.enclosing_names.iter().any(|n| n == name) {
+            return Err(CompileError::AssignToCaptured(name.to_string(), span));
+        }
+        Ok(())
+    }
+
+    /// 编译 if 表达式的一个分支：执行语句后把最后的值留在栈上
+    /// （分支中不能有的语句见 ValueBlock::unsupported_statement，类型检查阶段已经报告）
+    /// 编译 if 表达式：两个分支各自在栈上留下一个值（按期望类型编译），结构与 if 语句相同
+    fn compile_if_expression(
+        &mut self,
+        condition: Expr,
+        then_branch: ValueBlock,
+        else_branch: ValueBlock,
+        expected: &Type,
+    ) -> CompileResult<()> {
+        self.compile_expression(condition)?;
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop, 0);
+        self.compile_value_block(then_branch, expected)?;
+
+        let else_jump = self.emit_jump(OpCode::Jump(0));
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop, 0);
+        self.compile_value_block(else_branch, expected)?;
+
+        self.patch_jump(else_jump);
+        Ok(())
+    }
+
+    fn compile_value_block(&mut self, block: ValueBlock, expected: &Type) -> CompileResult<()> {
+        if let Some(kind) = block.unsupported_statement() {
+            return Err(CompileError::StatementInIfExpression(kind));
+        }
+        for stmt in block.statements {
+            self.compile_statement(stmt)?;
+        }
+        self.compile_expression_as(*block.value, expected)
     }
 
     fn begin_scope(&mut self) {
@@ -798,38 +1855,28 @@ impl Compiler {
             Expr::Float(_) => Type::Float,
             Expr::String(_) => Type::String,
             Expr::Boolean(_) => Type::Bool,
+            Expr::Null => Type::Null,
             Expr::Char(_) => Type::Char,
 
-            Expr::Identifier(name) => {
+            Expr::Identifier(name, _) => {
                 // 先查找局部变量类型
                 for lt in self.local_types.iter().rev() {
                     if &lt.name == name {
                         return self.resolve_named_type(&lt.var_type);
                     }
                 }
-                // 再查找全局变量类型
-                // 如果是导入的符号，使用原始名查找
-                let actual_name = self.imported_symbols.get(name)
-                    .map(|s| s.as_str())
-                    .unwrap_or(name.as_str());
-                if let Some(t) = self.global_types.get(actual_name) {
+                // 再查找全局变量类型（导入的别名和模块变量按全局名查找）
+                if let Some(t) = self.global_types.get(&self.global_name(name)) {
                     return self.resolve_named_type(t);
                 }
                 Type::Unknown
             }
 
-            Expr::Path { segments } => {
-                // 路径表达式类型推断
-                // 由于模块符号被编译为全局变量，我们使用最后一个段查找类型
-                if segments.is_empty() {
-                    return Type::Unknown;
-                }
-
-                let item_name = &segments[segments.len() - 1];
-                if let Some(t) = self.global_types.get(item_name) {
-                    return self.resolve_named_type(t);
-                }
-                Type::Unknown
+            Expr::Path { segments, .. } => {
+                // 路径表达式类型推断：按路径对应的全局变量名查找类型
+                Self::path_global_name(&self.module_variables, segments)
+                    .and_then(|global| self.global_types.get(&global))
+                    .map_or(Type::Unknown, |t| self.resolve_named_type(t))
             }
 
             Expr::Array { elements } => {
@@ -860,7 +1907,8 @@ impl Compiler {
                 }
             }
 
-            Expr::FieldAccess { object, field } => {
+            Expr::OptionalFieldAccess { object, .. } if self.infer_expression_type(object) == Type::Null => Type::Null,
+            Expr::FieldAccess { object, field, .. } | Expr::OptionalFieldAccess { object, field, .. } => {
                 let obj_type = self.infer_expression_type(object);
                 match obj_type {
                     Type::Struct(struct_type) => {
@@ -871,32 +1919,234 @@ impl Compiler {
                         }
                         Type::Unknown
                     }
+                    Type::Array(_) | Type::FixedArray(..) | Type::String if field == "length" => Type::Int,
                     _ => Type::Unknown,
                 }
             }
 
+            Expr::Tuple { elements } => {
+                Type::Tuple(elements.iter().map(|e| self.infer_expression_type(e)).collect())
+            }
+
+            Expr::TupleIndex { object, index } => {
+                match self.infer_expression_type(object) {
+                    Type::Tuple(element_types) => {
+                        element_types.get(*index).cloned().unwrap_or(Type::Unknown)
+                    }
+                    _ => Type::Unknown,
+                }
+            }
+
+            Expr::ArrayFill { value, .. } => Type::Array(Box::new(self.infer_expression_type(value))),
+
+            Expr::If { then_branch, else_branch, .. } => {
+                let then_type = self.infer_expression_type(&then_branch.value);
+                if then_type == self.infer_expression_type(&else_branch.value) {
+                    then_type
+                } else {
+                    Type::Unknown
+                }
+            }
+
             Expr::Index { object, .. } => {
                 let obj_type = self.infer_expression_type(object);
-                match obj_type {
-                    Type::Array(element_type) => *element_type,
+                obj_type.get_element_type().cloned().unwrap_or(Type::Unknown)
+            }
+
+            Expr::Slice { object, .. } => match self.infer_expression_type(object) {
+                Type::String => Type::String,
+                obj_type => obj_type.get_element_type()
+                    .map_or(Type::Unknown, |element_type| Type::Array(Box::new(element_type.clone()))),
+            },
+
+            Expr::Binary { left, operator: BinaryOp::Add, right } => {
+                let left_type = self.infer_expression_type(left);
+                let right_type = self.infer_expression_type(right);
+                match (&left_type, &right_type) {
+                    (Type::String, _) | (_, Type::String) => Type::String,
+                    (Type::Int, Type::Int) => Type::Int,
+                    (Type::Float, Type::Float) => Type::Float,
+                    // 拼接结果的长度不再固定，统一视为普通数组
+                    _ => match (left_type.get_element_type(), right_type.get_element_type()) {
+                        (Some(Type::Unknown), Some(right_elem)) => Type::Array(Box::new(right_elem.clone())),
+                        (Some(left_elem), Some(_)) => Type::Array(Box::new(left_elem.clone())),
+                        _ => Type::Unknown,
+                    },
+                }
+            }
+            Expr::Binary {
+                operator: BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessEqual
+                    | BinaryOp::Greater | BinaryOp::GreaterEqual | BinaryOp::And | BinaryOp::Or,
+                ..
+            } => Type::Bool,
+            // 同类型数值的减法和乘法结果类型不变；其余情况简化处理
+            Expr::Binary { left, operator: BinaryOp::Subtract | BinaryOp::Multiply, right } => {
+                match (self.infer_expression_type(left), self.infer_expression_type(right)) {
+                    (Type::Int, Type::Int) => Type::Int,
+                    (Type::Float, Type::Float) => Type::Float,
                     _ => Type::Unknown,
                 }
             }
-
-            Expr::Binary { .. } => Type::Unknown, // 简化处理
+            Expr::Binary { .. } => Type::Unknown,
+            Expr::Unary { operator: UnaryOp::Not, .. } => Type::Bool,
             Expr::Unary { .. } => Type::Unknown,
-            Expr::Assign { .. } => Type::Unknown,
-            Expr::Call { .. } => Type::Unknown,
-            Expr::MethodCall { .. } => Type::Unknown,
+            Expr::Assign { .. } | Expr::PathAssign { .. } => Type::Unknown,
+            Expr::Call { callee, arguments } => match callee.as_ref() {
+                Expr::Identifier(name, _) if self.is_builtin_call(name) => match crate::builtins::lookup(name).map(|b| &b.returns) {
+                    Some(BuiltinReturn::Fixed(Type::Void)) => Type::Null,
+                    Some(BuiltinReturn::Fixed(return_type)) => return_type.clone(),
+                    Some(BuiltinReturn::SameAsArgument) => {
+                        arguments.first().map_or(Type::Unknown, |arg| self.infer_expression_type(arg))
+                    }
+                    None => Type::Unknown,
+                },
+                // 用户函数按声明的返回类型推断（没有标注时为 Unknown）
+                _ => self.callee_signature(callee)
+                    .map_or(Type::Unknown, |signature| self.resolve_named_type(&signature.return_type)),
+            },
+            Expr::MethodCall { object, method, arguments, .. } if self.is_builtin_to_string(object, method, arguments) => Type::String,
+            Expr::MethodCall { object, method, arguments, .. } if self.is_builder_append(object, method, arguments) => Type::Null,
+            Expr::MethodCall { object, method, arguments, .. } => {
+                match self.builtin_method(object, method, arguments) {
+                    Some(OpCode::StrSplit) => return Type::Array(Box::new(Type::String)),
+                    Some(OpCode::StrContains) => return Type::Bool,
+                    Some(_) => return Type::String,
+                    None => {}
+                }
+                self.method_signature(object, method)
+                    .map_or(Type::Unknown, |signature| self.resolve_named_type(&signature.return_type))
+            }
             Expr::IndexAssign { .. } => Type::Unknown,
             Expr::FieldAssign { .. } => Type::Unknown,
         }
     }
 
-    /// 解析 Named 类型为实际的 Struct 类型
+    /// 算术表达式的代数化简。另一侧必须是已知为数值类型的变量读取（没有副作用），
+    /// 整数字面量参与运算时结果类型不变；浮点数的 `x * 0.0`、`x + 0` 受 NaN 和 -0.0 影响，
+    /// 因此乘 0 和加减 0 只用于整数变量
+    fn simplify_arithmetic(&self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Unary { operator: UnaryOp::Negate, operand } => match *operand {
+                Expr::Integer(n) => Expr::Integer(-n),
+                Expr::Float(f) => Expr::Float(-f),
+                operand => Expr::Unary { operator: UnaryOp::Negate, operand: Box::new(operand) },
+            },
+            Expr::Binary { left, operator, right } => {
+                let simplified = match (&operator, left.as_ref(), right.as_ref()) {
+                    (BinaryOp::Multiply, Expr::Integer(k), other) | (BinaryOp::Multiply, other, Expr::Integer(k)) => {
+                        match (*k, self.pure_numeric_type(other)) {
+                            (1, Some(_)) => Some(other.clone()),
+                            (0, Some(Type::Int)) => Some(Expr::Integer(0)),
+                            (2, Some(_)) => Some(Expr::Binary {
+                                left: Box::new(other.clone()),
+                                operator: BinaryOp::Add,
+                                right: Box::new(other.clone()),
+                            }),
+                            _ => None,
+                        }
+                    }
+                    (BinaryOp::Add, Expr::Integer(0), other)
+                    | (BinaryOp::Add | BinaryOp::Subtract, other, Expr::Integer(0))
+                        if self.pure_numeric_type(other) == Some(Type::Int) => Some(other.clone()),
+                    _ => None,
+                };
+                simplified.unwrap_or(Expr::Binary { left, operator, right })
+            }
+            expr => expr,
+        }
+    }
+
+    /// 没有副作用且类型已知为 int/float 的表达式（变量读取）的类型
+    fn pure_numeric_type(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Identifier(..) => match self.infer_expression_type(expr) {
+                numeric @ (Type::Int | Type::Float) => Some(numeric),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// 按期望类型编译表达式：期望 float 的整数字面量直接编译为浮点常量，期望类型会传入
+    /// 数组、元组的元素和 if 表达式的分支，与类型检查器按期望类型推断字面量的规则一致
+    fn compile_expression_as(&mut self, expr: Expr, expected: &Type) -> CompileResult<()> {
+        match (expr, self.resolve_named_type(expected)) {
+            (Expr::Integer(n), Type::Float) => {
+                let idx = self.chunk.add_constant(Value::Float(n as f64));
+                self.emit(OpCode::LoadConst(idx), 0);
+            }
+            (Expr::Unary { operator: UnaryOp::Negate, operand }, Type::Float) if matches!(*operand, Expr::Integer(_)) => {
+                self.compile_expression_as(*operand, &Type::Float)?;
+                self.emit(OpCode::Negate, 0);
+            }
+            (Expr::Array { elements }, Type::Array(element_type) | Type::FixedArray(element_type, _)) => {
+                let len = elements.len();
+                for element in elements {
+                    self.compile_expression_as(element, &element_type)?;
+                }
+                self.emit(OpCode::NewArray(len), 0);
+            }
+            (Expr::ArrayFill { value, count }, Type::Array(element_type) | Type::FixedArray(element_type, _)) => {
+                self.compile_expression_as(*value, &element_type)?;
+                self.compile_expression(*count)?;
+                self.emit(OpCode::ArrayFill, 0);
+            }
+            (Expr::Tuple { elements }, Type::Tuple(element_types)) if elements.len() == element_types.len() => {
+                let len = elements.len();
+                for (element, element_type) in elements.into_iter().zip(&element_types) {
+                    self.compile_expression_as(element, element_type)?;
+                }
+                self.emit(OpCode::NewTuple(len), 0);
+            }
+            (Expr::If { condition, then_branch, else_branch }, expected) => {
+                self.compile_if_expression(*condition, then_branch, else_branch, &expected)?;
+            }
+            (expr, _) => self.compile_expression(expr)?,
+        }
+        Ok(())
+    }
+
+    /// 解析 Named 类型为实际的 Struct 类型（会展开类型别名）
     fn resolve_named_type(&self, t: &Type) -> Type {
+        self.resolve_named_type_in(t, &self.module_path, &mut Vec::new())
+    }
+
+    /// 名字在模块 `scope` 中指向的类型别名的完整名：当前模块中声明的别名优先，其次是导入的别名，
+    /// 最后从内向外依次查找外层模块（最后是顶层）中声明的别名
+    fn type_alias_name(&self, name: &str, scope: &[String]) -> Option<String> {
+        let local = Self::qualify(scope, name);
+        if self.type_aliases.contains_key(&local) {
+            return Some(local);
+        }
+        if let Some(original) = self.imported_symbols.get(name) {
+            if self.type_aliases.contains_key(original) {
+                return Some(original.clone());
+            }
+        }
+        (0..scope.len()).rev()
+            .map(|depth| Self::qualify(&scope[..depth], name))
+            .find(|alias| self.type_aliases.contains_key(alias))
+    }
+
+    /// `scope` 是名字所在的模块路径（别名的目标类型在声明别名的模块中解析）；
+    /// `expanding` 是正在展开的别名；跳过类型检查时别名可能循环引用，此时保持 Named 类型
+    fn resolve_named_type_in(&self, t: &Type, scope: &[String], expanding: &mut Vec<String>) -> Type {
         match t {
             Type::Named(name) => {
+                // 展开类型别名
+                if let Some(alias) = self.type_alias_name(name, scope) {
+                    if expanding.contains(&alias) {
+                        return t.clone();
+                    }
+                    let (_, target) = &self.type_aliases[&alias];
+                    let alias_scope: Vec<String> = alias.split("::").map(String::from).collect();
+                    expanding.push(alias);
+                    return self.resolve_named_type_in(target, &alias_scope[..alias_scope.len() - 1], expanding);
+                }
+
+                // 如果是导入的符号，使用原始名查找
+                let name = self.imported_symbols.get(name).unwrap_or(name);
+
                 // 查找结构体定义
                 if let Some(struct_def) = self.structs.get(name) {
                     let fields = struct_def.fields.iter().map(|field_info| {
@@ -922,8 +2172,159 @@ impl Compiler {
     fn get_field_index(&self, struct_type: &StructType, field_name: &str) -> Option<usize> {
         struct_type.fields.iter().position(|f| f.name == field_name)
     }
+
+    /// 对象已在栈顶时读取字段（数组和字符串的 length 属性使用专门的指令）
+    fn emit_field_get(&mut self, obj_type: &Type, field: &str, span: Span) -> CompileResult<()> {
+        match obj_type {
+            Type::Array(_) | Type::FixedArray(..) if field == "length" => self.emit(OpCode::ArrayLen, 0),
+            Type::String if field == "length" => self.emit(OpCode::StrLen, 0),
+            _ => {
+                let field_index = self.resolve_field_index(obj_type, field, span)?;
+                self.emit(OpCode::FieldGet(field_index), 0);
+            }
+        }
+        Ok(())
+    }
+
+    /// 字段访问的字段索引：对象类型已知为结构体时字段必须存在，类型未知时回退到 0
+    fn resolve_field_index(&self, obj_type: &Type, field: &str, span: Span) -> CompileResult<usize> {
+        match obj_type {
+            Type::Struct(struct_type) => self.get_field_index(struct_type, field)
+                .ok_or_else(|| CompileError::UndefinedField {
+                    struct_name: struct_type.name.clone(),
+                    field: field.to_string(),
+                    available: struct_type.fields.iter().map(|f| f.name.clone()).collect(),
+                    span,
+                }),
+            _ => Ok(0),
+        }
+    }
+}
+
+/// 循环中变量的读写情况（用于循环不变量外提）
+#[derive(Default)]
+struct LoopUsage {
+    reads: Vec<String>,       // 读取的变量名（按首次出现的顺序）
+    writes: HashSet<String>,  // 被赋值（包括字段、下标赋值）的变量名
+    opaque: bool,             // 含有调用或嵌套声明，无法证明全局变量不变
 }
 
+impl LoopUsage {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print { value: expr } => self.visit_expr(expr),
+            Stmt::VarDeclaration { initializer, .. } => {
+                if let Some(init) = initializer {
+                    self.visit_expr(init);
+                }
+            }
+            Stmt::Return { value } => {
+                if let Some(value) = value {
+                    self.visit_expr(value);
+                }
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.visit_expr(condition);
+                then_branch.iter().for_each(|s| self.visit_stmt(s));
+                if let Some(else_branch) = else_branch {
+                    else_branch.iter().for_each(|s| self.visit_stmt(s));
+                }
+            }
+            Stmt::While { condition, body, else_branch } => {
+                self.visit_expr(condition);
+                body.iter().chain(else_branch.iter().flatten()).for_each(|s| self.visit_stmt(s));
+            }
+            Stmt::For { start, end, body, else_branch, .. } => {
+                self.visit_expr(start);
+                self.visit_expr(end);
+                body.iter().chain(else_branch.iter().flatten()).for_each(|s| self.visit_stmt(s));
+            }
+            Stmt::Block { statements } => statements.iter().for_each(|s| self.visit_stmt(s)),
+            Stmt::StructDeclaration { .. } | Stmt::TypeAlias { .. } | Stmt::Break | Stmt::Continue => {}
+            Stmt::FnDeclaration { .. } | Stmt::ImplBlock { .. } | Stmt::ModuleDeclaration { .. }
+            | Stmt::UseStatement { .. } | Stmt::ModuleReference { .. } => self.opaque = true,
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(name, _) => {
+                if !self.reads.contains(name) {
+                    self.reads.push(name.clone());
+                }
+            }
+            Expr::Integer(_) | Expr::Float(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Char(_)
+            | Expr::Null | Expr::Path { .. } => {}
+            Expr::Array { elements } | Expr::Tuple { elements } => {
+                elements.iter().for_each(|e| self.visit_expr(e));
+            }
+            Expr::StructLiteral { fields, base, .. } => {
+                fields.iter().for_each(|(_, e)| self.visit_expr(e));
+                if let Some(base) = base {
+                    self.visit_expr(base);
+                }
+            }
+            Expr::Binary { left, right, .. } => {
+                self.visit_expr(left);
+                self.visit_expr(right);
+            }
+            Expr::Unary { operand, .. } => self.visit_expr(operand),
+            Expr::Call { .. } | Expr::MethodCall { .. } => self.opaque = true,
+            Expr::Index { object, index } => {
+                self.visit_expr(object);
+                self.visit_expr(index);
+            }
+            Expr::Slice { object, start, end } => {
+                self.visit_expr(object);
+                self.visit_expr(start);
+                self.visit_expr(end);
+            }
+            Expr::ArrayFill { value, count } => {
+                self.visit_expr(value);
+                self.visit_expr(count);
+            }
+            Expr::If { condition, then_branch, else_branch } => {
+                self.visit_expr(condition);
+                for block in [then_branch, else_branch] {
+                    block.statements.iter().for_each(|s| self.visit_stmt(s));
+                    self.visit_expr(&block.value);
+                }
+            }
+            Expr::TupleIndex { object, .. } | Expr::FieldAccess { object, .. }
+            | Expr::OptionalFieldAccess { object, .. } => self.visit_expr(object),
+            Expr::Assign { name, value, .. } => {
+                self.writes.insert(name.clone());
+                self.visit_expr(value);
+            }
+            Expr::IndexAssign { object, index, value, .. } => {
+                self.mark_written(object);
+                self.visit_expr(object);
+                self.visit_expr(index);
+                self.visit_expr(value);
+            }
+            Expr::FieldAssign { object, value, .. } => {
+                self.mark_written(object);
+                self.visit_expr(object);
+                self.visit_expr(value);
+            }
+            // 被修改的模块变量可能在循环中通过导入的别名读取，无法按名字判断
+            Expr::PathAssign { .. } => self.opaque = true,
+        }
+    }
+
+    /// `a[i] = v`、`a.b.c = v` 修改的是最外层的变量 `a`
+    fn mark_written(&mut self, target: &Expr) {
+        match target {
+            Expr::Identifier(name, _) => {
+                self.writes.insert(name.clone());
+            }
+            Expr::Index { object, .. } | Expr::TupleIndex { object, .. } | Expr::FieldAccess { object, .. } => {
+                self.mark_written(object)
+            }
+            _ => {}
+        }
+    }
+}
 
 impl Default for Compiler {
     fn default() -> Self {
@@ -9,6 +9,7 @@
 use std::collections::HashMap;
 use std::fmt;
 use serde::Deserialize;
+use crate::lexer::Lexer;
 
 /// 错误显示模式
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -38,6 +39,46 @@ impl SourceLocation {
     }
 }
 
+/// 错误位置所在的行号（从 1 开始）和插入符（^）之前的显示宽度；源码为空时返回 None。
+///
+/// 优先按 `offset`（字符下标）定位，插入符按字符的显示宽度对齐（中日韩字符占两列）；
+/// 只记录了行列的位置（`offset` 为 0 但列号不是 1，或 `offset` 不在 `line` 行内）按行列定位。超出文件范围的位置截断到首行或末行的边界
+fn locate(source: &str, location: &SourceLocation) -> Option<(usize, usize)> {
+    let lines: Vec<&str> = source.lines().collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let width = |text: &str| text.chars().map(Lexer::char_display_width).sum::<usize>();
+    let has_offset = location.offset > 0 || location.column <= 1;
+
+    // offset 所在的行和行内的字符下标（每行之后的换行符占一个字符）
+    if has_offset {
+        let mut line_start = 0;
+        for (index, text) in lines.iter().enumerate() {
+            let line_len = text.chars().count();
+            if location.offset <= line_start + line_len || index + 1 == lines.len() {
+                let line = index + 1;
+                if location.line == 0 || location.line == line {
+                    let column = location.offset.saturating_sub(line_start).min(line_len);
+                    let prefix: String = text.chars().take(column).collect();
+                    return Some((line, width(&prefix)));
+                }
+                break;
+            }
+            line_start += line_len + 1;
+        }
+    }
+
+    // 按行列定位：列号已经是显示宽度（从 1 开始）
+    let line = location.line.clamp(1, lines.len());
+    let caret_column = if location.line > lines.len() {
+        width(lines[line - 1])
+    } else {
+        location.column.saturating_sub(1).min(width(lines[line - 1]))
+    };
+    Some((line, caret_column))
+}
+
 /// 编译器错误 - 纯数据结构
 #[derive(Debug, Clone)]
 pub struct CompilerError {
@@ -76,6 +117,7 @@ pub enum ErrorType {
     LexerInvalidCharacter,
     LexerInvalidNumber,
     LexerInvalidUnicodeEscape,
+    LexerUnterminatedComment,
     
     // 语法错误
     ParserUnexpectedToken,
@@ -102,6 +144,7 @@ impl ErrorType {
             Self::LexerInvalidCharacter => "L003",
             Self::LexerInvalidNumber => "L004",
             Self::LexerInvalidUnicodeEscape => "L005",
+            Self::LexerUnterminatedComment => "L006",
             Self::ParserUnexpectedToken => "P001",
             Self::ParserUnexpectedEOF => "P002",
             Self::ParserInvalidExpression => "P003",
@@ -120,6 +163,7 @@ impl ErrorType {
             Self::LexerInvalidCharacter => "lexer.L003",
             Self::LexerInvalidNumber => "lexer.L004",
             Self::LexerInvalidUnicodeEscape => "lexer.L005",
+            Self::LexerUnterminatedComment => "lexer.L006",
             Self::ParserUnexpectedToken => "parser.P001",
             Self::ParserUnexpectedEOF => "parser.P002",
             Self::ParserInvalidExpression => "parser.P003",
@@ -307,12 +351,24 @@ impl ErrorDisplayer {
             .join("\n\n")
     }
     
+    /// 错误标题：配置模板没有引用 `message` 参数时，把它追加在标题后面
+    fn format_title(&self, error: &CompilerError) -> String {
+        let config = self.registry.get(error.error_type.config_key());
+        let title = config.map(|c| Self::replace_params(&c.title, &error.params));
+
+        match (title, error.params.get("message")) {
+            (Some(title), Some(message)) if !title.contains(message.as_str()) => {
+                format!("{}: {}", title, message)
+            }
+            (Some(title), _) => title,
+            (None, Some(message)) => message.clone(),
+            (None, None) => "未知错误".to_string(),
+        }
+    }
+
     /// 简易模式格式化
     fn format_simple(&self, error: &CompilerError) -> String {
-        let config = self.registry.get(error.error_type.config_key());
-        let title = config
-            .map(|c| Self::replace_params(&c.title, &error.params))
-            .unwrap_or_else(|| "未知错误".to_string());
+        let title = self.format_title(error);
         
         format!(
             "错误 [{}] 在 {}:{}: {}",
@@ -327,9 +383,7 @@ impl ErrorDisplayer {
     fn format_detailed(&self, error: &CompilerError, source: Option<&str>) -> String {
         let config = self.registry.get(error.error_type.config_key());
         
-        let title = config
-            .map(|c| Self::replace_params(&c.title, &error.params))
-            .unwrap_or_else(|| "未知错误".to_string());
+        let title = self.format_title(error);
         
         let description = config
             .map(|c| Self::replace_params(&c.description, &error.params))
@@ -353,6 +407,8 @@ impl ErrorDisplayer {
                 c.suggestion.as_ref()
             }
         });
+        // 配置中没有建议时，使用错误自身携带的建议
+        let suggestion = suggestion.or_else(|| error.params.get("help"));
         
         let mut output = String::new();
         
@@ -385,20 +441,20 @@ impl ErrorDisplayer {
     /// 格式化源码片段
     fn format_source_snippet(&self, source: &str, location: &SourceLocation) -> String {
         let lines: Vec<&str> = source.lines().collect();
-        
-        if location.line == 0 || location.line > lines.len() {
-            return String::new();
-        }
+        let (line, caret_column) = match locate(source, location) {
+            Some(found) => found,
+            None => return String::new(),
+        };
         
         let mut output = String::new();
-        let line_num_width = location.line.to_string().len();
+        let line_num_width = line.to_string().len();
         
         // 显示出错行的前一行
-        if location.line > 1 {
+        if line > 1 {
             output.push_str(&format!(
                 "{:>width$} | {}\n",
-                location.line - 1,
-                lines[location.line - 2],
+                line - 1,
+                lines[line - 2],
                 width = line_num_width
             ));
         }
@@ -406,8 +462,8 @@ impl ErrorDisplayer {
         // 显示出错行
         output.push_str(&format!(
             "{:>width$} | {}\n",
-            location.line,
-            lines[location.line - 1],
+            line,
+            lines[line - 1],
             width = line_num_width
         ));
         
@@ -415,7 +471,7 @@ impl ErrorDisplayer {
         output.push_str(&format!(
             "{:>width$} | {}{}",
             "",
-            " ".repeat(location.column.saturating_sub(1)),
+            " ".repeat(caret_column),
             "\x1b[1;31m^",
             width = line_num_width
         ));
@@ -426,11 +482,11 @@ impl ErrorDisplayer {
         output.push_str("\x1b[0m\n");
         
         // 显示出错行的后一行
-        if location.line < lines.len() {
+        if line < lines.len() {
             output.push_str(&format!(
                 "{:>width$} | {}\n",
-                location.line + 1,
-                lines[location.line],
+                line + 1,
+                lines[line],
                 width = line_num_width
             ));
         }
@@ -499,6 +555,14 @@ impl CompilerError {
         .with_param("sequence", sequence)
     }
     
+    pub fn unterminated_comment(line: usize, column: usize, offset: usize) -> Self {
+        Self::new(
+            "L006",
+            SourceLocation::new(line, column, offset, 2),
+            ErrorType::LexerUnterminatedComment,
+        )
+    }
+    
     pub fn unexpected_token(expected: String, found: String, line: usize, column: usize, offset: usize, length: usize) -> Self {
         Self::new(
             "P001",
@@ -524,6 +588,16 @@ impl CompilerError {
             ErrorType::ParserInvalidExpression,
         )
     }
+
+    /// 字节码编译阶段的错误：具体内容放在 `message` 参数中，建议放在 `help` 参数中
+    pub fn compile_error(message: String, help: Option<String>, location: SourceLocation) -> Self {
+        let error = Self::new("C001", location, ErrorType::CompilerError)
+            .with_param("message", message);
+        match help {
+            Some(help) => error.with_param("help", help),
+            None => error,
+        }
+    }
 }
 
 impl fmt::Display for CompilerError {
@@ -533,4 +607,44 @@ impl fmt::Display for CompilerError {
     }
 }
 
-impl std::error::Error for CompilerError {}
\ No newline at end of file
+impl std::error::Error for CompilerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 错误位置所在的源码行，以及插入符之前的显示宽度
+    fn extract_snippet(source: &str, location: &SourceLocation) -> (String, usize) {
+        match locate(source, location) {
+            Some((line, caret_column)) => (source.lines().nth(line - 1).unwrap_or("").to_string(), caret_column),
+            None => (String::new(), 0),
+        }
+    }
+
+    #[test]
+    fn test_extract_snippet_aligns_caret() {
+        // 第二行的 `y`：offset 为字符下标
+        let source = "let x = 1;\nlet y = ;";
+        assert_eq!(extract_snippet(source, &SourceLocation::single(2, 5, 15)), ("let y = ;".to_string(), 4));
+
+        // 宽字符占两列：`名字` 之后的 `=`
+        let source = "let 名字 = \"值\";";
+        let location = SourceLocation::single(1, 10, 7);
+        assert_eq!(extract_snippet(source, &location), (source.to_string(), 9));
+        // 字符串中的宽字符之后的 `;`
+        assert_eq!(extract_snippet(source, &SourceLocation::single(1, 16, 12)).1, 15);
+    }
+
+    #[test]
+    fn test_extract_snippet_falls_back_and_clamps() {
+        let source = "fn f() {\n    return 名;\n}";
+        // 编译错误只记录行列（offset 为 0）
+        assert_eq!(extract_snippet(source, &SourceLocation::new(2, 12, 0, 1)), ("    return 名;".to_string(), 11));
+        // 超出文件末尾：截断到最后一行的行尾
+        assert_eq!(extract_snippet(source, &SourceLocation::single(9, 1, 500)), ("}".to_string(), 1));
+        assert_eq!(extract_snippet(source, &SourceLocation::single(3, 40, 500)), ("}".to_string(), 1));
+        // 列号超出行宽时截断到行尾
+        assert_eq!(extract_snippet(source, &SourceLocation::new(1, 80, 0, 1)), ("fn f() {".to_string(), 8));
+        assert_eq!(extract_snippet("", &SourceLocation::single(1, 1, 0)), (String::new(), 0));
+    }
+}
@@ -0,0 +1,747 @@
+//! Zero源码格式化器
+//!
+//! 从 AST 重新生成统一风格的源码：4 空格缩进、运算符两侧空格、左花括号不换行。
+//! 注释不在 AST 中，格式化后会丢失。`x += 1` 等复合赋值在解析时已展开为 `x = x + 1`；
+//! 索引和字段的复合赋值仍输出为 `op=`，目标中的下标表达式只求值一次。
+
+use crate::ast::{BinaryOp, Expr, MethodDeclaration, Parameter, Pattern, Program, Stmt, Type, UnaryOp, UseItems, ValueBlock, Visibility};
+
+const INDENT: &str = "    ";
+
+/// 将整个程序格式化为源码
+pub fn format_program(program: &Program) -> String {
+    let mut formatter = SourceFormatter::new();
+    formatter.format_items(&program.statements);
+    formatter.output
+}
+
+/// 将单个表达式格式化为源码（用于在诊断信息中引用出错的表达式）
+pub fn format_expression(expr: &Expr) -> String {
+    SourceFormatter::expr(expr, Precedence::Assignment)
+}
+
+/// 运算符优先级（与解析器的递归下降层级一致，由低到高）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Power,
+    Postfix,
+}
+
+impl Precedence {
+    fn of(expr: &Expr) -> Self {
+        match expr {
+            Expr::Assign { .. } | Expr::IndexAssign { .. } | Expr::FieldAssign { .. } | Expr::PathAssign { .. } => {
+                Precedence::Assignment
+            }
+            Expr::Binary { operator, .. } => Self::of_binary(operator),
+            Expr::Unary { .. } => Precedence::Unary,
+            _ => Precedence::Postfix,
+        }
+    }
+
+    fn of_binary(operator: &BinaryOp) -> Self {
+        match operator {
+            BinaryOp::Or => Precedence::Or,
+            BinaryOp::And => Precedence::And,
+            BinaryOp::Equal | BinaryOp::NotEqual => Precedence::Equality,
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                Precedence::Comparison
+            }
+            BinaryOp::Add | BinaryOp::Subtract => Precedence::Term,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => Precedence::Factor,
+            BinaryOp::Power => Precedence::Power,
+        }
+    }
+}
+
+struct SourceFormatter {
+    output: String,
+    depth: usize,
+}
+
+impl SourceFormatter {
+    fn new() -> Self {
+        SourceFormatter {
+            output: String::new(),
+            depth: 0,
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    /// 顶层或模块内的语句：函数、结构体等声明前后留一个空行
+    fn format_items(&mut self, statements: &[Stmt]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            if i > 0 && (Self::is_item(stmt) || Self::is_item(&statements[i - 1])) {
+                self.output.push('\n');
+            }
+            self.format_stmt(stmt);
+        }
+    }
+
+    fn is_item(stmt: &Stmt) -> bool {
+        matches!(
+            stmt,
+            Stmt::FnDeclaration { .. }
+                | Stmt::StructDeclaration { .. }
+                | Stmt::ImplBlock { .. }
+                | Stmt::ModuleDeclaration { .. }
+        )
+    }
+
+    fn format_block(&mut self, header: &str, body: &[Stmt]) {
+        self.line(&format!("{} {{", header));
+        self.depth += 1;
+        for stmt in body {
+            self.format_stmt(stmt);
+        }
+        self.depth -= 1;
+    }
+
+    /// 闭合语句块；有 else 分支（if 或循环）时输出 `} else { ... }`
+    fn format_else(&mut self, else_branch: &Option<Vec<Stmt>>) {
+        if let Some(else_branch) = else_branch {
+            self.format_block("} else", else_branch);
+        }
+        self.line("}");
+    }
+
+    fn format_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => self.line(&format!("{};", Self::expr(expr, Precedence::Assignment))),
+
+            Stmt::VarDeclaration { visibility, pattern, mutable, type_annotation, initializer } => {
+                let mut text = format!(
+                    "{}{} {}",
+                    Self::visibility(visibility),
+                    if *mutable { "var" } else { "let" },
+                    Self::pattern(pattern)
+                );
+                if let Some(t) = type_annotation {
+                    text.push_str(&format!(": {}", Self::type_name(t)));
+                }
+                if let Some(value) = initializer {
+                    text.push_str(&format!(" = {}", Self::expr(value, Precedence::Assignment)));
+                }
+                text.push(';');
+                self.line(&text);
+            }
+
+            Stmt::FnDeclaration { visibility, name, parameters, return_type, body } => {
+                let header = format!(
+                    "{}fn {}({}){}",
+                    Self::visibility(visibility),
+                    name,
+                    Self::parameters(None, parameters),
+                    Self::return_type(return_type)
+                );
+                self.format_block(&header, body);
+                self.line("}");
+            }
+
+            Stmt::StructDeclaration { visibility, name, fields, .. } => {
+                self.line(&format!("{}struct {} {{", Self::visibility(visibility), name));
+                self.depth += 1;
+                for field in fields {
+                    self.line(&format!("{}: {},", field.name, Self::type_name(&field.field_type)));
+                }
+                self.depth -= 1;
+                self.line("};");
+            }
+
+            Stmt::TypeAlias { visibility, name, target_type } => {
+                self.line(&format!("{}type {} = {};", Self::visibility(visibility), name, Self::type_name(target_type)));
+            }
+
+            Stmt::Return { value: Some(value) } => {
+                self.line(&format!("return {};", Self::expr(value, Precedence::Assignment)));
+            }
+            Stmt::Return { value: None } => self.line("return;"),
+
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.format_block(&format!("if {}", Self::condition(condition)), then_branch);
+                self.format_else(else_branch);
+            }
+
+            Stmt::While { condition, body, else_branch } => {
+                self.format_block(&format!("while {}", Self::condition(condition)), body);
+                self.format_else(else_branch);
+            }
+
+            Stmt::For { variable, start, end, body, else_branch } => {
+                let header = format!(
+                    "for {} in {}..{}",
+                    variable,
+                    Self::expr(start, Precedence::Assignment),
+                    Self::condition(end)
+                );
+                self.format_block(&header, body);
+                self.format_else(else_branch);
+            }
+
+            Stmt::Print { value } => self.line(&format!("print({});", Self::expr(value, Precedence::Assignment))),
+
+            Stmt::Block { statements } => {
+                self.line("{");
+                self.depth += 1;
+                for stmt in statements {
+                    self.format_stmt(stmt);
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+
+            Stmt::Break => self.line("break;"),
+            Stmt::Continue => self.line("continue;"),
+
+            Stmt::ImplBlock { type_name, methods } => {
+                self.line(&format!("impl {} {{", type_name));
+                self.depth += 1;
+                for (i, method) in methods.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push('\n');
+                    }
+                    self.format_method(method);
+                }
+                self.depth -= 1;
+                self.line("}");
+            }
+
+            Stmt::ModuleDeclaration { name, statements, is_public } => {
+                self.line(&format!("{}mod {} {{", if *is_public { "pub " } else { "" }, name));
+                self.depth += 1;
+                self.format_items(statements);
+                self.depth -= 1;
+                self.line("}");
+            }
+
+            Stmt::UseStatement { path, items } => {
+                let prefix = path.join("::");
+                let text = match items {
+                    UseItems::All => format!("use {}::*;", prefix),
+                    UseItems::Single(item) => format!("use {}::{};", prefix, item),
+                    UseItems::Multiple(names) => format!("use {}::{{{}}};", prefix, names.join(", ")),
+                    UseItems::Renamed(item, alias) => format!("use {}::{} as {};", prefix, item, alias),
+                };
+                self.line(&text);
+            }
+
+            Stmt::ModuleReference { name, is_public } => {
+                self.line(&format!("{}mod {};", if *is_public { "pub " } else { "" }, name));
+            }
+        }
+    }
+
+    fn format_method(&mut self, method: &MethodDeclaration) {
+        let header = format!(
+            "fn {}({}){}",
+            method.name,
+            Self::parameters(Some("self"), &method.parameters),
+            Self::return_type(&method.return_type)
+        );
+        self.format_block(&header, &method.body);
+        self.line("}");
+    }
+
+    fn visibility(visibility: &Visibility) -> &'static str {
+        match visibility {
+            Visibility::Public => "pub ",
+            Visibility::Private => "",
+        }
+    }
+
+    fn parameters(receiver: Option<&str>, parameters: &[Parameter]) -> String {
+        receiver
+            .map(str::to_string)
+            .into_iter()
+            .chain(parameters.iter().map(|p| match &p.type_annotation {
+                Some(t) => format!("{}: {}", p.name, Self::type_name(t)),
+                None => p.name.clone(),
+            }))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn return_type(return_type: &Option<Type>) -> String {
+        match return_type {
+            Some(t) => format!(" -> {}", Self::type_name(t)),
+            None => String::new(),
+        }
+    }
+
+    fn pattern(pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Identifier(name) => name.clone(),
+            Pattern::Tuple(elements) if elements.len() == 1 => format!("({},)", Self::pattern(&elements[0])),
+            Pattern::Tuple(elements) => {
+                format!("({})", elements.iter().map(Self::pattern).collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+
+    /// 类型注解：结构体类型只会来自匿名结构体语法，按 `struct { ... }` 输出
+    fn type_name(t: &Type) -> String {
+        match t {
+            Type::Array(element) => format!("[{}]", Self::type_name(element)),
+            Type::FixedArray(element, length) => format!("[{}; {}]", Self::type_name(element), length),
+            Type::Tuple(elements) => {
+                format!("({})", elements.iter().map(Self::type_name).collect::<Vec<_>>().join(", "))
+            }
+            Type::Struct(struct_type) => {
+                let fields = struct_type.fields.iter()
+                    .map(|f| format!("{}: {}", f.name, Self::type_name(&f.field_type)))
+                    .collect::<Vec<_>>();
+                if fields.is_empty() {
+                    "struct {}".to_string()
+                } else {
+                    format!("struct {{ {} }}", fields.join(", "))
+                }
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// `if`/`while`/`for` 后面紧跟 `{`：以标识符结尾的表达式会被解析为结构体字面量，需要加括号
+    fn condition(expr: &Expr) -> String {
+        let text = Self::expr(expr, Precedence::Assignment);
+        if Self::ends_with_identifier(expr) {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn ends_with_identifier(expr: &Expr) -> bool {
+        match expr {
+            Expr::Identifier(..) => true,
+            Expr::Binary { right, .. } => Self::ends_with_identifier(right),
+            Expr::Unary { operand, .. } => Self::ends_with_identifier(operand),
+            Expr::Assign { value, .. } | Expr::IndexAssign { value, .. } | Expr::FieldAssign { value, .. }
+            | Expr::PathAssign { value, .. } => Self::ends_with_identifier(value),
+            _ => false,
+        }
+    }
+
+    /// 格式化表达式；`min` 是所在位置允许的最低优先级，低于它时加括号
+    fn expr(expr: &Expr, min: Precedence) -> String {
+        let text = Self::expr_unparenthesized(expr);
+        if Precedence::of(expr) < min {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn expr_unparenthesized(expr: &Expr) -> String {
+        match expr {
+            Expr::Integer(n) => n.to_string(),
+            Expr::Float(f) => Self::float_literal(*f),
+            Expr::String(s) => format!("\"{}\"", escape_literal(s, '"')),
+            Expr::Boolean(b) => b.to_string(),
+            Expr::Null => "null".to_string(),
+            Expr::Char(c) => format!("'{}'", escape_literal(&c.to_string(), '\'')),
+            Expr::Identifier(name, _) => name.clone(),
+            Expr::Path { segments, .. } => segments.join("::"),
+
+            Expr::Array { elements } => format!("[{}]", Self::list(elements)),
+            Expr::Tuple { elements } if elements.len() == 1 => {
+                format!("({},)", Self::expr(&elements[0], Precedence::Assignment))
+            }
+            Expr::Tuple { elements } => format!("({})", Self::list(elements)),
+
+            Expr::StructLiteral { struct_name, fields, base, .. } => {
+                let mut parts: Vec<String> = fields.iter()
+                    .map(|(name, value)| format!("{}: {}", name, Self::expr(value, Precedence::Assignment)))
+                    .collect();
+                if let Some(base) = base {
+                    parts.push(format!("..{}", Self::expr(base, Precedence::Assignment)));
+                }
+                if parts.is_empty() {
+                    format!("{} {{}}", struct_name)
+                } else {
+                    format!("{} {{ {} }}", struct_name, parts.join(", "))
+                }
+            }
+
+            Expr::Binary { left, operator, right } => {
+                // 左结合：左操作数允许同级，右操作数必须更高一级；
+                // 乘方右结合：左操作数必须更高一级，右操作数允许一元运算
+                let precedence = Precedence::of_binary(operator);
+                let (left_min, right_min) = match precedence {
+                    Precedence::Or => (precedence, Precedence::And),
+                    Precedence::And => (precedence, Precedence::Equality),
+                    Precedence::Equality => (precedence, Precedence::Comparison),
+                    Precedence::Comparison => (precedence, Precedence::Term),
+                    Precedence::Term => (precedence, Precedence::Factor),
+                    Precedence::Power => (Precedence::Postfix, Precedence::Unary),
+                    _ => (precedence, Precedence::Unary),
+                };
+                format!(
+                    "{} {} {}",
+                    Self::expr(left, left_min),
+                    Self::binary_operator(operator),
+                    Self::expr(right, right_min)
+                )
+            }
+
+            Expr::Unary { operator, operand } => {
+                let symbol = match operator {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Negate => "-",
+                };
+                // 连续的负号需要分开，避免写成 `--x`
+                let nested_negate = *operator == UnaryOp::Negate && Self::starts_with_minus(operand);
+                if nested_negate {
+                    format!("{}({})", symbol, Self::expr(operand, Precedence::Assignment))
+                } else {
+                    format!("{}{}", symbol, Self::expr(operand, Precedence::Unary))
+                }
+            }
+
+            Expr::Call { callee, arguments } => {
+                format!("{}({})", Self::expr(callee, Precedence::Postfix), Self::list(arguments))
+            }
+            Expr::Index { object, index } => {
+                format!("{}[{}]", Self::expr(object, Precedence::Postfix), Self::expr(index, Precedence::Assignment))
+            }
+            Expr::ArrayFill { value, count } => format!(
+                "[{}; {}]",
+                Self::expr(value, Precedence::Assignment),
+                Self::expr(count, Precedence::Assignment),
+            ),
+            Expr::If { condition, then_branch, else_branch } => format!(
+                "if {} {} else {}",
+                Self::condition(condition),
+                Self::value_block(then_branch),
+                Self::value_block(else_branch),
+            ),
+            Expr::Slice { object, start, end } => format!(
+                "{}[{}..{}]",
+                Self::expr(object, Precedence::Postfix),
+                Self::expr(start, Precedence::Assignment),
+                Self::expr(end, Precedence::Assignment),
+            ),
+            Expr::TupleIndex { object, index } => {
+                format!("{}.{}", Self::postfix_object(object), index)
+            }
+            Expr::FieldAccess { object, field, .. } => {
+                format!("{}.{}", Self::postfix_object(object), field)
+            }
+            Expr::OptionalFieldAccess { object, field, .. } => {
+                format!("{}?.{}", Self::postfix_object(object), field)
+            }
+            Expr::MethodCall { object, method, arguments, .. } => {
+                format!("{}.{}({})", Self::postfix_object(object), method, Self::list(arguments))
+            }
+
+            Expr::IndexAssign { object, index, value, compound } => {
+                let (operator, value) = Self::assignment_operator(compound, value);
+                format!(
+                    "{}[{}] {} {}",
+                    Self::expr(object, Precedence::Postfix),
+                    Self::expr(index, Precedence::Assignment),
+                    operator,
+                    Self::expr(value, Precedence::Assignment)
+                )
+            }
+            Expr::Assign { name, value, .. } => {
+                format!("{} = {}", name, Self::expr(value, Precedence::Assignment))
+            }
+            Expr::FieldAssign { object, field, value, compound, .. } => {
+                let (operator, value) = Self::assignment_operator(compound, value);
+                format!(
+                    "{}.{} {} {}",
+                    Self::postfix_object(object),
+                    field,
+                    operator,
+                    Self::expr(value, Precedence::Assignment)
+                )
+            }
+            Expr::PathAssign { segments, value, .. } => {
+                format!("{} = {}", segments.join("::"), Self::expr(value, Precedence::Assignment))
+            }
+        }
+    }
+
+    /// if 表达式的分支写在一行里：`{ stmt; ... value }`
+    fn value_block(block: &ValueBlock) -> String {
+        let mut inner = SourceFormatter::new();
+        for stmt in &block.statements {
+            inner.format_stmt(stmt);
+        }
+        let mut parts: Vec<String> = inner.output.lines().map(|line| line.trim().to_string()).collect();
+        parts.push(Self::expr(&block.value, Precedence::Assignment));
+        format!("{{ {} }}", parts.join(" "))
+    }
+
+    /// `.` 左侧的对象：数字字面量后直接跟 `.` 会被当成小数点，需要加括号
+    fn postfix_object(object: &Expr) -> String {
+        match object {
+            Expr::Integer(_) | Expr::Float(_) => format!("({})", Self::expr_unparenthesized(object)),
+            _ => Self::expr(object, Precedence::Postfix),
+        }
+    }
+
+    fn starts_with_minus(expr: &Expr) -> bool {
+        match expr {
+            Expr::Unary { operator: UnaryOp::Negate, .. } => true,
+            Expr::Integer(n) => *n < 0,
+            Expr::Float(f) => f.is_sign_negative(),
+            _ => false,
+        }
+    }
+
+    fn list(elements: &[Expr]) -> String {
+        elements.iter()
+            .map(|e| Self::expr(e, Precedence::Assignment))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// 赋值运算符和右侧的值：复合赋值输出 `op=` 和展开前的右操作数
+    fn assignment_operator<'a>(compound: &Option<BinaryOp>, value: &'a Expr) -> (String, &'a Expr) {
+        match (compound, value) {
+            (Some(operator), Expr::Binary { right, .. }) => (format!("{}=", Self::binary_operator(operator)), right),
+            _ => ("=".to_string(), value),
+        }
+    }
+
+    fn binary_operator(operator: &BinaryOp) -> &'static str {
+        match operator {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Power => "**",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    /// 浮点字面量总是带小数点，避免重新解析为整数
+    fn float_literal(value: f64) -> String {
+        let text = value.to_string();
+        if text.contains('.') || !value.is_finite() {
+            text
+        } else {
+            format!("{}.0", text)
+        }
+    }
+}
+
+/// 按词法分析器支持的转义序列还原字符串/字符字面量的内容
+pub fn escape_literal(text: &str, quote: char) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' => escaped.push_str("\\\\"),
+            c if c == quote => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, TokenPreprocessor};
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    fn format_source(source: &str) -> String {
+        format_program(&parse(source))
+    }
+
+    /// AST 的调试输出，去掉源码位置（格式化会改变节点的行列）
+    fn without_spans(program: &Program) -> String {
+        let debug = format!("{:?}", program.statements);
+        let mut result = String::new();
+        let mut rest = debug.as_str();
+        while let Some(start) = rest.find("Span { ") {
+            result.push_str(&rest[..start]);
+            let end = start + rest[start..].find('}').unwrap();
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+        result
+    }
+
+    /// 格式化结果与期望一致，并且再次格式化不会改变
+    fn assert_formats_to(source: &str, expected: &str) {
+        let formatted = format_source(source);
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted), formatted, "formatting is not idempotent");
+        assert_eq!(without_spans(&parse(&formatted)), without_spans(&parse(source)), "formatting changed the AST");
+    }
+
+    #[test]
+    fn test_format_functions_and_control_flow() {
+        assert_formats_to(
+            "fn fib(n:int)->int{if n<2{return n;}else{return fib(n-1)+fib(n-2);}}
+             var total=0;for i in 0..(10){total=total+fib(i);}
+             while (total>100) {total=total/2;}
+             print(total);",
+            "fn fib(n: int) -> int {
+    if n < 2 {
+        return n;
+    } else {
+        return fib(n - 1) + fib(n - 2);
+    }
+}
+
+var total = 0;
+for i in 0..10 {
+    total = total + fib(i);
+}
+while total > 100 {
+    total = total / 2;
+}
+print(total);
+",
+        );
+    }
+
+    #[test]
+    fn test_format_loop_else() {
+        assert_formats_to(
+            "for i in 0..(3){if i==2{break;}}else{print(0);} while (false) {} else {print(1);}",
+            "for i in 0..3 {
+    if i == 2 {
+        break;
+    }
+} else {
+    print(0);
+}
+while false {
+} else {
+    print(1);
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_format_structs_and_modules() {
+        assert_formats_to(
+            "pub struct Point{x:int,y:int};
+             impl Point{fn len(self)->int{return self.x*self.x+self.y*self.y;} fn moved(self,dx:int)->Point{return Point{x:self.x+dx,..self};}}
+             mod geo{pub fn origin()->Point{return Point{x:0,y:0};}}
+             use geo::{origin};
+             type Pair=(int,[string]);
+             let p=geo::origin();p.x=3;",
+            "pub struct Point {
+    x: int,
+    y: int,
+};
+
+impl Point {
+    fn len(self) -> int {
+        return self.x * self.x + self.y * self.y;
+    }
+
+    fn moved(self, dx: int) -> Point {
+        return Point { x: self.x + dx, ..self };
+    }
+}
+
+mod geo {
+    pub fn origin() -> Point {
+        return Point { x: 0, y: 0 };
+    }
+}
+
+use geo::{origin};
+type Pair = (int, [string]);
+let p = geo::origin();
+p.x = 3;
+",
+        );
+    }
+
+    #[test]
+    fn test_format_expressions() {
+        assert_formats_to(
+            "let a=(1+2)*3-(4-5);let b=!(x&&y)||z;let c=-(-1);let d=[1,2.0,\"q\\\"\\n\",'\\''];
+             let (e,(f,g))=(1,(2,3));let h=(1,);let i=t.0.1;let j=arr[i+1][0];var k=x=y=2;
+             let l=(2**3)**2+2**(3**2)*(-2)**2**-1;let m=s[i+1..n];let o=p?.x;let q=null;
+             xs[f()]+=1;p.x*=2+3;xs[0]=xs[0]+1;",
+            "let a = (1 + 2) * 3 - (4 - 5);
+let b = !(x && y) || z;
+let c = -(-1);
+let d = [1, 2.0, \"q\\\"\\n\", '\\''];
+let (e, (f, g)) = (1, (2, 3));
+let h = (1,);
+let i = t.0.1;
+let j = arr[i + 1][0];
+var k = x = y = 2;
+let l = (2 ** 3) ** 2 + 2 ** 3 ** 2 * (-2) ** 2 ** -1;
+let m = s[i + 1..n];
+let o = p?.x;
+let q = null;
+xs[f()] += 1;
+p.x *= 2 + 3;
+xs[0] = xs[0] + 1;
+",
+        );
+    }
+
+    #[test]
+    fn test_format_if_expression() {
+        assert_formats_to(
+            "let m=if a>b{a}else{b};let n=1+if c{print(1);if d{print(2);}2}else{if d{3}else{4}};",
+            "let m = if (a > b) { a } else { b };
+let n = 1 + if (c) { print(1); if (d) { print(2); } 2 } else { if (d) { 3 } else { 4 } };
+",
+        );
+    }
+
+    #[test]
+    fn test_condition_ending_in_identifier_is_parenthesized() {
+        // `while !done {}` 会把 `done {}` 解析为结构体字面量
+        assert_formats_to(
+            "if (a && b) { print(1); } while (!done) { done = true; }",
+            "if (a && b) {
+    print(1);
+}
+while (!done) {
+    done = true;
+}
+",
+        );
+    }
+}
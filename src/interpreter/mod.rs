@@ -1,5 +1,6 @@
-use crate::ast::{BinaryOp, Expr, Program, Stmt, UnaryOp, Parameter};
+use crate::ast::{BinaryOp, Expr, Program, Stmt, UnaryOp, Parameter, Pattern};
 use std::collections::HashMap;
+use std::cmp::Ordering;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
@@ -8,7 +9,9 @@ pub enum Value {
     String(String),
     Boolean(bool),
     Char(char),
+    Tuple(Vec<Value>),
     Function {
+        name: String,
         parameters: Vec<Parameter>,
         body: Vec<Stmt>,
     },
@@ -18,12 +21,21 @@ pub enum Value {
 impl Value {
     pub fn to_string(&self) -> String {
         match self {
+            // 数值格式与虚拟机的 Value::to_string 相同
             Value::Integer(i) => i.to_string(),
             Value::Float(f) => f.to_string(),
             Value::String(s) => s.clone(),
             Value::Boolean(b) => b.to_string(),
             Value::Char(c) => c.to_string(),
-            Value::Function { .. } => "<function>".to_string(),
+            // 元组格式与虚拟机相同：(a, b)，单元素元组为 (a,)
+            Value::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(|v| v.to_string()).collect();
+                match elements.as_slice() {
+                    [single] => format!("({},)", single),
+                    _ => format!("({})", elements.join(", ")),
+                }
+            }
+            Value::Function { name, parameters, .. } => format!("<fn {}/{}>", name, parameters.len()),
             Value::Null => "null".to_string(),
         }
     }
@@ -34,7 +46,6 @@ impl Value {
             Value::Null => false,
             Value::Integer(0) => false,
             Value::Float(f) if *f == 0.0 => false,
-            Value::Char('\0') => false,
             _ => true,
         }
     }
@@ -116,9 +127,24 @@ impl Interpreter {
         Ok(())
     }
 
+    /// 按名称读取全局变量
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.environment.scopes.first()?.get(name).cloned()
+    }
+
+    /// 循环正常结束（没有 break）时执行 else 分支
+    fn execute_loop_else(&mut self, else_branch: &Option<Vec<Stmt>>) -> RuntimeResult<()> {
+        if let Some(else_stmts) = else_branch {
+            for stmt in else_stmts {
+                self.execute_statement(stmt)?;
+            }
+        }
+        Ok(())
+    }
+
     fn execute_statement(&mut self, stmt: &Stmt) -> RuntimeResult<Value> {
         match stmt {
-            Stmt::StructDeclaration { visibility: _, name: _, fields: _ } => {
+            Stmt::StructDeclaration { .. } => {
                 // 结构体声明在解释器中不需要运行时操作
                 // 结构体信息由类型检查器管理
                 Ok(Value::Null)
@@ -132,11 +158,19 @@ impl Interpreter {
             Stmt::Expression(expr) => self.evaluate_expression(expr),
 
             Stmt::VarDeclaration {
-                name,
+                visibility: _,
+                pattern,
                 mutable: _,
                 type_annotation: _,
                 initializer,
             } => {
+                let name = match pattern {
+                    Pattern::Identifier(name) => name,
+                    Pattern::Tuple(_) => {
+                        // Tuple destructuring is only supported by the bytecode compiler
+                        return Err(RuntimeError::InvalidOperation("Tuple destructuring not supported in legacy interpreter".to_string()));
+                    }
+                };
                 let value = if let Some(init) = initializer {
                     self.evaluate_expression(init)?
                 } else {
@@ -154,6 +188,7 @@ impl Interpreter {
                 body,
             } => {
                 let func = Value::Function {
+                    name: name.clone(),
                     parameters: parameters.clone(),
                     body: body.clone(),
                 };
@@ -189,7 +224,8 @@ impl Interpreter {
                 Ok(Value::Null)
             }
 
-            Stmt::While { condition, body } => {
+            Stmt::While { condition, body, else_branch } => {
+                let mut broke = false;
                 while self.evaluate_expression(condition)?.is_truthy() {
                     let mut should_break = false;
                     for stmt in body {
@@ -206,9 +242,13 @@ impl Interpreter {
                         }
                     }
                     if should_break {
+                        broke = true;
                         break;
                     }
                 }
+                if !broke {
+                    self.execute_loop_else(else_branch)?;
+                }
                 Ok(Value::Null)
             }
 
@@ -217,20 +257,29 @@ impl Interpreter {
                 start,
                 end,
                 body,
+                else_branch,
             } => {
                 let start_val = self.evaluate_expression(start)?;
                 let end_val = self.evaluate_expression(end)?;
 
-                if let (Value::Integer(start_i), Value::Integer(end_i)) = (start_val, end_val) {
+                // 整数范围或字符范围（字符按码点递增）
+                let values: Option<Box<dyn Iterator<Item = Value>>> = match (start_val, end_val) {
+                    (Value::Integer(a), Value::Integer(b)) => Some(Box::new((a..b).map(Value::Integer))),
+                    (Value::Char(a), Value::Char(b)) => Some(Box::new((a..b).map(Value::Char))),
+                    _ => None,
+                };
+
+                if let Some(values) = values {
                     self.environment.push_scope();
 
-                    'outer: for i in start_i..end_i {
-                        self.environment
-                            .define(variable.clone(), Value::Integer(i));
+                    let mut broke = false;
+                    'outer: for value in values {
+                        self.environment.define(variable.clone(), value);
 
                         for stmt in body {
                             match self.execute_statement(stmt) {
                                 Err(RuntimeError::BreakSignal) => {
+                                    broke = true;
                                     break 'outer;
                                 }
                                 Err(RuntimeError::ContinueSignal) => {
@@ -246,10 +295,13 @@ impl Interpreter {
                     }
 
                     self.environment.pop_scope();
+                    if !broke {
+                        self.execute_loop_else(else_branch)?;
+                    }
                     Ok(Value::Null)
                 } else {
                     Err(RuntimeError::TypeMismatch(
-                        "For loop requires integer range".to_string(),
+                        "For loop requires integer or char range".to_string(),
                     ))
                 }
             }
@@ -309,18 +361,34 @@ impl Interpreter {
 
     fn evaluate_expression(&mut self, expr: &Expr) -> RuntimeResult<Value> {
         match expr {
-            Expr::StructLiteral { struct_name: _, fields: _ } => {
+            Expr::StructLiteral { struct_name: _, fields: _, base: _, span: _ } => {
                 // TODO: 实现结构体字面量的解释执行
                 // 暂时返回占位值
                 Ok(Value::Null)
             }
 
-            Expr::FieldAccess { object: _, field: _ } => {
+            Expr::FieldAccess { object, field, span: _ } => {
+                // 字符串的 length 属性
+                if field == "length" {
+                    if let Value::String(s) = self.evaluate_expression(object)? {
+                        return Ok(Value::Integer(s.chars().count() as i64));
+                    }
+                }
                 // TODO: 实现字段访问的解释执行
                 Ok(Value::Null)
             }
 
-            Expr::FieldAssign { object: _, field: _, value } => {
+            Expr::OptionalFieldAccess { object, .. } => {
+                // 字段访问尚未实现，只处理对象为 null 的情况
+                match self.evaluate_expression(object)? {
+                    Value::Null => Ok(Value::Null),
+                    _ => Err(RuntimeError::InvalidOperation(
+                        "Optional field access not supported in legacy interpreter".to_string(),
+                    )),
+                }
+            }
+
+            Expr::FieldAssign { object: _, field: _, value, compound: _, span: _ } => {
                 // TODO: 实现字段赋值的解释执行
                 self.evaluate_expression(value)
             }
@@ -331,14 +399,33 @@ impl Interpreter {
                 Err(RuntimeError::InvalidOperation("Method calls not supported in legacy interpreter".to_string()))
             }
 
+            Expr::Tuple { elements } => {
+                let elements = elements.iter()
+                    .map(|element| self.evaluate_expression(element))
+                    .collect::<RuntimeResult<Vec<Value>>>()?;
+                Ok(Value::Tuple(elements))
+            }
+
+            Expr::TupleIndex { object, index } => {
+                match self.evaluate_expression(object)? {
+                    Value::Tuple(elements) => elements.get(*index).cloned().ok_or_else(|| {
+                        RuntimeError::InvalidOperation(format!(
+                            "Tuple index {} out of bounds (length: {})", index, elements.len()
+                        ))
+                    }),
+                    other => Err(RuntimeError::TypeMismatch(format!("Cannot index {} as a tuple", other.to_string()))),
+                }
+            }
+
             Expr::Integer(i) => Ok(Value::Integer(*i)),
             Expr::Float(f) => Ok(Value::Float(*f)),
             Expr::String(s) => Ok(Value::String(s.clone())),
             Expr::Boolean(b) => Ok(Value::Boolean(*b)),
             Expr::Char(c) => Ok(Value::Char(*c)),
-            Expr::Identifier(name) => self.environment.get(name),
+            Expr::Null => Ok(Value::Null),
+            Expr::Identifier(name, _) => self.environment.get(name),
 
-            Expr::Path { segments } => {
+            Expr::Path { segments, .. } => {
                 // 路径表达式在旧解释器中不支持模块系统
                 // 我们简单地使用最后一个段作为变量名
                 if segments.is_empty() {
@@ -358,18 +445,53 @@ impl Interpreter {
 
             Expr::Call { callee, arguments } => self.evaluate_call(callee, arguments),
 
-            Expr::Assign { name, value } => {
+            Expr::Assign { name, value, .. } => {
                 let val = self.evaluate_expression(value)?;
                 self.environment.set(name, val.clone())?;
                 Ok(val)
             }
 
+            Expr::PathAssign { segments, value, .. } => {
+                // 与路径读取一致，只使用最后一个段作为变量名
+                let item_name = segments.last()
+                    .ok_or_else(|| RuntimeError::UndefinedVariable("empty path".to_string()))?;
+                let val = self.evaluate_expression(value)?;
+                self.environment.set(item_name, val.clone())?;
+                Ok(val)
+            }
+
             Expr::Array { elements } => {
                 // 数组字面量 - 暂时返回占位值
                 // TODO: 实现完整的数组支持
                 Ok(Value::String(format!("Array[{}]", elements.len())))
             }
 
+            Expr::If { .. } => {
+                // if 表达式只由字节码编译器支持
+                Err(RuntimeError::InvalidOperation(
+                    "If expression not supported in legacy interpreter".to_string(),
+                ))
+            }
+
+            Expr::ArrayFill { value, count } => {
+                // 与数组字面量相同返回占位值；value 和 count 照常求值，count 的检查与虚拟机一致
+                self.evaluate_expression(value)?;
+                match self.evaluate_expression(count)? {
+                    Value::Integer(n) if n >= 0 => Ok(Value::String(format!("Array[{}]", n))),
+                    Value::Integer(n) => Err(RuntimeError::InvalidOperation(
+                        format!("Array fill count must not be negative (got {})", n)
+                    )),
+                    _ => Err(RuntimeError::TypeMismatch("Array fill count must be an integer".to_string())),
+                }
+            }
+
+            Expr::Slice { .. } => {
+                // 范围索引只由字节码编译器支持
+                Err(RuntimeError::InvalidOperation(
+                    "Slicing not supported in legacy interpreter".to_string(),
+                ))
+            }
+
             Expr::Index { object, index } => {
                 // 数组索引 - 暂时返回占位值
                 // TODO: 实现完整的数组索引支持
@@ -378,7 +500,7 @@ impl Interpreter {
                 ))
             }
             
-            Expr::IndexAssign { object, index, value } => {
+            Expr::IndexAssign { object, index, value, .. } => {
                 // 数组索引赋值 - 暂时返回占位值
                 // TODO: 实现完整的数组索引赋值支持
                 let val = self.evaluate_expression(value)?;
@@ -394,6 +516,14 @@ impl Interpreter {
         right: &Expr,
     ) -> RuntimeResult<Value> {
         let left_val = self.evaluate_expression(left)?;
+
+        // 逻辑运算短路求值：左侧已能决定结果时不再计算右侧（与字节码虚拟机一致）
+        match operator {
+            BinaryOp::And if !left_val.is_truthy() => return Ok(Value::Boolean(false)),
+            BinaryOp::Or if left_val.is_truthy() => return Ok(Value::Boolean(true)),
+            _ => {}
+        }
+
         let right_val = self.evaluate_expression(right)?;
 
         match operator {
@@ -403,6 +533,8 @@ impl Interpreter {
                 (Value::Integer(l), Value::Float(r)) => Ok(Value::Float(l as f64 + r)),
                 (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l + r as f64)),
                 (Value::String(l), Value::String(r)) => Ok(Value::String(l + &r)),
+                (Value::String(l), r) => Ok(Value::String(l + &r.to_string())),
+                (l, Value::String(r)) => Ok(Value::String(l.to_string() + &r)),
                 _ => Err(RuntimeError::TypeMismatch("Invalid addition".to_string())),
             },
 
@@ -469,32 +601,33 @@ impl Interpreter {
                 _ => Err(RuntimeError::TypeMismatch("Invalid modulo".to_string())),
             },
 
-            BinaryOp::Equal => Ok(Value::Boolean(self.values_equal(&left_val, &right_val))),
-            BinaryOp::NotEqual => Ok(Value::Boolean(!self.values_equal(&left_val, &right_val))),
-
-            BinaryOp::Less => match (left_val, right_val) {
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l < r)),
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l < r)),
-                _ => Err(RuntimeError::TypeMismatch("Invalid comparison".to_string())),
-            },
-
-            BinaryOp::LessEqual => match (left_val, right_val) {
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l <= r)),
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l <= r)),
-                _ => Err(RuntimeError::TypeMismatch("Invalid comparison".to_string())),
+            BinaryOp::Power => match (left_val, right_val) {
+                (Value::Integer(l), Value::Integer(r)) => {
+                    if r < 0 {
+                        return Err(RuntimeError::InvalidOperation(format!(
+                            "Negative integer exponent: {} ** {} (use a float base, e.g. {}.0 ** {})",
+                            l, r, l, r
+                        )));
+                    }
+                    u32::try_from(r)
+                        .ok()
+                        .and_then(|r| l.checked_pow(r))
+                        .map(Value::Integer)
+                        .ok_or_else(|| RuntimeError::InvalidOperation(format!("Integer overflow: {} ** {}", l, r)))
+                }
+                (Value::Float(l), Value::Float(r)) => Ok(Value::Float(l.powf(r))),
+                (Value::Integer(l), Value::Float(r)) => Ok(Value::Float((l as f64).powf(r))),
+                (Value::Float(l), Value::Integer(r)) => Ok(Value::Float(l.powf(r as f64))),
+                _ => Err(RuntimeError::TypeMismatch("Invalid power".to_string())),
             },
 
-            BinaryOp::Greater => match (left_val, right_val) {
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l > r)),
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l > r)),
-                _ => Err(RuntimeError::TypeMismatch("Invalid comparison".to_string())),
-            },
+            BinaryOp::Equal => Ok(Value::Boolean(self.values_equal(&left_val, &right_val))),
+            BinaryOp::NotEqual => Ok(Value::Boolean(!self.values_equal(&left_val, &right_val))),
 
-            BinaryOp::GreaterEqual => match (left_val, right_val) {
-                (Value::Integer(l), Value::Integer(r)) => Ok(Value::Boolean(l >= r)),
-                (Value::Float(l), Value::Float(r)) => Ok(Value::Boolean(l >= r)),
-                _ => Err(RuntimeError::TypeMismatch("Invalid comparison".to_string())),
-            },
+            BinaryOp::Less => self.compare_values(&left_val, &right_val, |o| o == Ordering::Less),
+            BinaryOp::LessEqual => self.compare_values(&left_val, &right_val, |o| o != Ordering::Greater),
+            BinaryOp::Greater => self.compare_values(&left_val, &right_val, |o| o == Ordering::Greater),
+            BinaryOp::GreaterEqual => self.compare_values(&left_val, &right_val, |o| o != Ordering::Less),
 
             BinaryOp::And => Ok(Value::Boolean(left_val.is_truthy() && right_val.is_truthy())),
             BinaryOp::Or => Ok(Value::Boolean(left_val.is_truthy() || right_val.is_truthy())),
@@ -517,7 +650,7 @@ impl Interpreter {
     fn evaluate_call(&mut self, callee: &Expr, arguments: &[Expr]) -> RuntimeResult<Value> {
         let func = self.evaluate_expression(callee)?;
 
-        if let Value::Function { parameters, body } = func {
+        if let Value::Function { parameters, body, .. } = func {
             if parameters.len() != arguments.len() {
                 return Err(RuntimeError::TypeMismatch(format!(
                     "Expected {} arguments, got {}",
@@ -555,10 +688,28 @@ impl Interpreter {
         Ok(Value::Null)
     }
 
+    /// 比较大小：整数之间按整数比较，整数与浮点数混合时整数提升为浮点数（与算术运算一致）
+    /// 与 NaN 比较时结果总是 false
+    fn compare_values<F>(&self, left: &Value, right: &Value, test: F) -> RuntimeResult<Value>
+    where
+        F: FnOnce(Ordering) -> bool,
+    {
+        let ordering = match (left, right) {
+            (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+            (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+            (Value::Integer(l), Value::Float(r)) => (*l as f64).partial_cmp(r),
+            (Value::Float(l), Value::Integer(r)) => l.partial_cmp(&(*r as f64)),
+            (Value::Char(l), Value::Char(r)) => Some(l.cmp(r)),
+            _ => return Err(RuntimeError::TypeMismatch("Invalid comparison".to_string())),
+        };
+        Ok(Value::Boolean(ordering.is_some_and(test)))
+    }
+
     fn values_equal(&self, left: &Value, right: &Value) -> bool {
         match (left, right) {
             (Value::Integer(l), Value::Integer(r)) => l == r,
             (Value::Float(l), Value::Float(r)) => l == r,
+            (Value::Integer(l), Value::Float(r)) | (Value::Float(r), Value::Integer(l)) => *l as f64 == *r,
             (Value::String(l), Value::String(r)) => l == r,
             (Value::Boolean(l), Value::Boolean(r)) => l == r,
             (Value::Null, Value::Null) => true,
@@ -571,4 +722,191 @@ impl Default for Interpreter {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, TokenPreprocessor};
+    use crate::parser::Parser;
+    use crate::type_checker::TypeChecker;
+
+    /// 分别用解释器和字节码虚拟机执行程序，返回两边全局变量的字符串形式
+    fn run_both(source: &str, globals: &[&str]) -> (Vec<String>, Vec<String>) {
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        TypeChecker::new().check(&program).unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program.clone()).unwrap();
+        let interpreted = globals.iter()
+            .map(|name| interpreter.get_global(name).map(|v| v.to_string()).unwrap_or_default())
+            .collect();
+
+        let chunk = crate::compiler::Compiler::new().compile(program).unwrap();
+        let mut vm = crate::vm::VM::new();
+        vm.execute(chunk).unwrap();
+        let compiled = globals.iter()
+            .map(|name| vm.get_global(name).map(|v| v.to_string()).unwrap_or_default())
+            .collect();
+
+        (interpreted, compiled)
+    }
+
+    #[test]
+    fn test_function_value_display_matches_vm() {
+        // 函数值显示名称和参数个数
+        let (interpreted, compiled) = run_both(
+            "fn add(a: int, b: int) -> int { return a + b; } fn tick() {} let f = add; let g = tick;",
+            &["f", "g"],
+        );
+        assert_eq!(interpreted, vec!["<fn add/2>", "<fn tick/0>"]);
+        assert_eq!(compiled, interpreted);
+    }
+
+    #[test]
+    fn test_boolean_logic_matches_vm() {
+        let programs: &[(&str, &[&str])] = &[
+            // 基本的优先级与组合
+            ("let r = true && false || true; let s = !(true && false) && !false;", &["r", "s"]),
+            // 非 bool 操作数（无类型注解的参数）：结果统一为 bool
+            ("fn both(a, b) { return a && b; } let r = both(1, 2); let s = both(0, 2); let t = both(2, 0);", &["r", "s", "t"]),
+            ("fn either(a, b) { return a || b; } let r = either(0, \"x\"); let s = either(3, 0); let t = either(0, 0.0);", &["r", "s", "t"]),
+            // 短路：右侧的副作用不会发生
+            ("var calls = 0;
+              fn bump() -> bool { calls = calls + 1; return true; }
+              let r = false && bump();
+              let s = true || bump();
+              let t = true && bump();", &["r", "s", "t", "calls"]),
+            // 比较运算与逻辑运算混合
+            ("let x = 5; let r = x > 3 && x < 10 || x == 0; let s = x > 3 && (x < 4 || x == 5);", &["r", "s"]),
+            // null 与字符串的真值
+            ("fn truthy(v) { return v || false; } var nothing; let r = truthy(nothing); let s = truthy(\"\"); let t = truthy(\"a\");", &["r", "s", "t"]),
+            // 字符（包括 '\0'）总是为真
+            ("fn truthy(v) { return v && true; } let r = truthy('a'); let s = truthy('\\0');", &["r", "s"]),
+            // 逻辑运算的结果用作条件
+            ("fn pick(a, b) { if (a && b) { return 1; } return 2; } let r = pick(1, 0); let s = pick(1, 1);", &["r", "s"]),
+        ];
+
+        for (source, globals) in programs {
+            let (interpreted, compiled) = run_both(source, globals);
+            assert_eq!(interpreted, compiled, "backends disagree on: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_array_fill_in_interpreter() {
+        // 解释器中的数组是占位值：[v; n] 与 n 个元素的数组字面量相同，value 的副作用照常发生
+        let source = "var calls = 0; fn tick() -> int { calls = calls + 1; return 0; }
+                      let n = 3; let a = [tick(); n]; let b = [0, 0, 0];";
+        let mut lexer = Lexer::new(source.to_string());
+        let program = Parser::new(TokenPreprocessor::preprocess(lexer.tokenize().unwrap())).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program).unwrap();
+        assert_eq!(interpreter.get_global("a").map(|v| v.to_string()), interpreter.get_global("b").map(|v| v.to_string()));
+        assert_eq!(interpreter.get_global("calls").map(|v| v.to_string()), Some("1".to_string()));
+
+        let mut lexer = Lexer::new("let n = 0 - 1; let a = [0; n];".to_string());
+        let program = Parser::new(TokenPreprocessor::preprocess(lexer.tokenize().unwrap())).parse().unwrap();
+        assert!(matches!(Interpreter::new().interpret(program), Err(RuntimeError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_tuple_display_matches_vm() {
+        let source = "let pair = (1, \"two\"); let nested = ((1.5, 'c'), true); let single = (7,);
+                      let second = nested.0.1;";
+        let (interpreted, compiled) = run_both(source, &["pair", "nested", "single", "second"]);
+        assert_eq!(interpreted, vec!["(1, two)".to_string(), "((1.5, c), true)".to_string(), "(7,)".to_string(), "c".to_string()]);
+        assert_eq!(interpreted, compiled);
+    }
+
+    #[test]
+    fn test_range_at_i64_max_matches_vm() {
+        // 结束值为 i64::MAX 时循环正常结束，计数器不会溢出回绕
+        let source = "var n = 0; var last = 0;
+                      for i in (9223372036854775807 - 2)..9223372036854775807 { n = n + 1; last = i; }
+                      var empty = 0;
+                      for i in 9223372036854775807..9223372036854775807 { empty = empty + 1; }";
+        let (interpreted, compiled) = run_both(source, &["n", "last", "empty"]);
+        assert_eq!(interpreted, vec!["2".to_string(), "9223372036854775806".to_string(), "0".to_string()]);
+        assert_eq!(interpreted, compiled);
+    }
+
+    #[test]
+    fn test_char_range_matches_vm() {
+        let source = "var s = \"\"; for c in 'a'..'f' { s = s + c; } var n = 0; for c in 'f'..'a' { n = n + 1; }";
+        let (interpreted, compiled) = run_both(source, &["s", "n"]);
+        assert_eq!(interpreted, vec!["abcde".to_string(), "0".to_string()]);
+        assert_eq!(interpreted, compiled);
+    }
+
+    #[test]
+    fn test_loop_else_matches_vm() {
+        // 找到时 break 跳过 else，找不到时执行 else
+        let source = "
+            fn first_divisor(n: int) -> int {
+                var found = 0;
+                for d in 2..(n) {
+                    if n % d == 0 { found = d; break; }
+                } else {
+                    found = -1;
+                }
+                return found;
+            }
+            let a = first_divisor(15);
+            let b = first_divisor(13);
+
+            var i = 0;
+            var w = \"none\";
+            while i < 10 {
+                if i * i == 49 { w = \"found\"; break; }
+                i = i + 1;
+            } else {
+                w = \"missing\";
+            }
+            var j = 0;
+            var v = \"none\";
+            while j < 3 { j = j + 1; } else { v = \"missing\"; }
+
+            var outer = 0;
+            while outer < 5 {
+                outer = outer + 1;
+                for k in 0..2 { } else { if outer == 3 { break; } }
+            }
+        ";
+        let (interpreted, compiled) = run_both(source, &["a", "b", "w", "v", "outer"]);
+        assert_eq!(interpreted, vec!["3", "-1", "found", "missing", "3"]);
+        assert_eq!(interpreted, compiled);
+    }
+
+    #[test]
+    fn test_mixed_numeric_comparison_matches_vm() {
+        let source = "
+            let lt = 1 < 2.5; let le = 2 <= 2.0; let gt = 3.5 > 3; let ge = 2.0 >= 3;
+            let eq = 1 == 1.0; let ne = 1.5 != 1;
+            let big = 9007199254740993 > 9007199254740992;
+            let same = 9007199254740993 == 9007199254740992;
+        ";
+        let globals = ["lt", "le", "gt", "ge", "eq", "ne", "big", "same"];
+        let (interpreted, compiled) = run_both(source, &globals);
+        // 纯整数比较不经过浮点数，超过 2^53 的整数也能区分
+        assert_eq!(interpreted, vec!["true", "true", "true", "false", "true", "true", "true", "false"]);
+        assert_eq!(interpreted, compiled);
+    }
+
+    #[test]
+    fn test_number_formatting_matches_vm() {
+        let source = "
+            let million = 1000000; let small = 0.0001; let large = 1e20; let negative_zero = -0.0;
+            let max = 9223372036854775807; let tiny = 1e-7; let whole = 2.0; let third = 1.0 / 3.0;
+        ";
+        let globals = ["million", "small", "large", "negative_zero", "max", "tiny", "whole", "third"];
+        let (interpreted, compiled) = run_both(source, &globals);
+        assert_eq!(interpreted, vec![
+            "1000000", "0.0001", "100000000000000000000", "-0",
+            "9223372036854775807", "0.0000001", "2", "0.3333333333333333",
+        ]);
+        assert_eq!(interpreted, compiled);
+    }
+}
@@ -47,7 +47,7 @@ impl Lexer {
     }
 
     /// 计算字符的显示宽度（用于正确的列位置计算）
-    fn char_display_width(ch: char) -> usize {
+    pub(crate) fn char_display_width(ch: char) -> usize {
         // 简化版本：大多数字符宽度为1，某些CJK字符为2
         if ch.is_ascii() {
             1
@@ -100,6 +100,34 @@ impl Lexer {
         }
     }
 
+    /// 跳过块注释 /* ... */，块注释可以嵌套；文件在注释闭合前结束时报错
+    fn skip_block_comment(&mut self) -> LexerResult<()> {
+        let start_pos = self.current_position();
+        self.advance(); // 跳过 '/'
+        self.advance(); // 跳过 '*'
+
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.current_char, self.peek(1)) {
+                (Some('/'), Some('*')) => {
+                    self.advance();
+                    self.advance();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.advance();
+                    self.advance();
+                    depth -= 1;
+                }
+                (Some(_), _) => self.advance(),
+                (None, _) => {
+                    return Err(LexerError::unterminated_comment(start_pos.line, start_pos.column, start_pos.offset));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// 读取数字（支持多种进制和科学计数法）
     fn read_number(&mut self) -> LexerResult<Token> {
         let start_pos = self.current_position();
@@ -306,7 +334,14 @@ impl Lexer {
             
             if ch == '\\' {
                 self.advance();
-                value.push_str(&self.read_escape_sequence()?);
+                if matches!(self.current_char, Some('\n') | Some('\r')) {
+                    // 行尾的反斜杠表示续行：跳过换行符和下一行的前导空白
+                    while let Some(' ' | '\t' | '\n' | '\r') = self.current_char {
+                        self.advance();
+                    }
+                } else {
+                    value.push_str(&self.read_escape_sequence()?);
+                }
             } else if ch == '\n' {
                 // 支持多行字符串
                 value.push(ch);
@@ -469,9 +504,11 @@ impl Lexer {
         if use_braces {
             self.advance(); // 跳过 '{'
             
+            let mut closed = false;
             while let Some(ch) = self.current_char {
                 if ch == '}' {
                     self.advance();
+                    closed = true;
                     break;
                 } else if ch.is_ascii_hexdigit() && hex.len() < 6 {
                     hex.push(ch);
@@ -480,6 +517,10 @@ impl Lexer {
                     return Err(LexerError::invalid_unicode_escape(format!("\\u{{{}}}", hex), line, column, self.position));
                 }
             }
+            // 文件在 '}' 之前结束
+            if !closed {
+                return Err(LexerError::invalid_unicode_escape(format!("\\u{{{}", hex), line, column, self.position));
+            }
         } else {
             // 固定4位十六进制
             for _ in 0..4 {
@@ -528,6 +569,11 @@ impl Lexer {
                 continue;
             }
 
+            if self.current_char == Some('/') && self.peek(1) == Some('*') {
+                self.skip_block_comment()?;
+                continue;
+            }
+
             break;
         }
 
@@ -588,6 +634,9 @@ impl Lexer {
                         if self.current_char == Some('=') {
                             self.advance();
                             Token::new(TokenType::StarEqual, "*=".to_string(), start_pos, self.current_position())
+                        } else if self.current_char == Some('*') {
+                            self.advance();
+                            Token::new(TokenType::StarStar, "**".to_string(), start_pos, self.current_position())
                         } else {
                             Token::new(TokenType::Star, "*".to_string(), start_pos, self.current_position())
                         }
@@ -714,6 +763,11 @@ impl Lexer {
                             Token::new(TokenType::Dot, ".".to_string(), start_pos, self.current_position())
                         }
                     }
+                    '?' if self.peek(1) == Some('.') => {
+                        self.advance();
+                        self.advance();
+                        Token::new(TokenType::QuestionDot, "?.".to_string(), start_pos, self.current_position())
+                    }
                     _ => {
                         self.advance();
                         Token::new(TokenType::Unknown, ch.to_string(), start_pos, self.current_position())
@@ -776,6 +830,73 @@ mod tests {
         assert_eq!(tokens[0].value, "hello\nworld\t");
     }
 
+    #[test]
+    fn test_comments_between_tokens() {
+        let kinds = |source: &str| -> Vec<(TokenType, String)> {
+            Lexer::new(source.to_string()).tokenize().unwrap()
+                .into_iter()
+                .map(|token| (token.token_type, token.value))
+                .collect()
+        };
+
+        // 注释出现在表达式和语句中的任意位置都不影响 token 序列
+        let expected = kinds("let x = a + b;\nprint(x);");
+        assert_eq!(kinds("let x = a /* c */ + b;\nprint(x);"), expected);
+        assert_eq!(kinds("let /* 1 */ x /* 2 */ = /**/ a + /* 多行\n注释 */ b; // 行尾\nprint(x); //"), expected);
+        assert_eq!(kinds("let x = a + // 换行前\n b;\n/* /* 嵌套 */ 注释 */print(/* */x);"), expected);
+        assert_eq!(kinds("let x = a/**/+/**/b;\nprint(x)/* 末尾 */;"), expected);
+
+        // 除号和乘号不受影响
+        assert_eq!(kinds("a / b * c")[1].0, TokenType::Slash);
+        assert_eq!(kinds("a * /* x */ b").len(), 4);
+
+        // 块注释之后的 token 位置正确
+        let tokens = Lexer::new("/* a\nb */ x".to_string()).tokenize().unwrap();
+        assert_eq!((tokens[0].start_pos.line, tokens[0].start_pos.column), (2, 6));
+
+        // 未闭合的块注释
+        let err = Lexer::new("let x = 1; /* a /* b */".to_string()).tokenize().unwrap_err();
+        assert_eq!(err.code, "L006");
+        assert_eq!((err.location.line, err.location.column), (1, 12));
+    }
+
+    #[test]
+    fn test_char_literal_escapes() {
+        let char_value = |source: &str| {
+            let tokens = Lexer::new(source.to_string()).tokenize()?;
+            assert_eq!(tokens[0].token_type, TokenType::Char);
+            Ok::<_, LexerError>(tokens[0].value.clone())
+        };
+
+        // \xHH 按码点解释：'\xFF' 是 U+00FF
+        assert_eq!(char_value(r"'\xFF'").unwrap(), "\u{FF}");
+        assert_eq!(char_value(r"'\x41'").unwrap(), "A");
+        // BMP 之外的字符是一个 char
+        assert_eq!(char_value(r"'\u{1F600}'").unwrap(), "😀");
+        assert_eq!(char_value(r"'\u{1F600}'").unwrap().chars().count(), 1);
+        assert_eq!(char_value(r"'\u00e9'").unwrap(), "é");
+        assert_eq!(char_value("'😀'").unwrap(), "😀");
+
+        // 代理项、超出 Unicode 范围、缺少 '}' 的转义都被拒绝
+        assert!(char_value(r"'\u{D800}'").is_err());
+        assert!(char_value(r"'\uDFFF'").is_err());
+        assert!(char_value(r"'\u{110000}'").is_err());
+        assert!(char_value(r"'\u{41").is_err());
+        assert!(char_value(r"'\xG1'").is_err());
+    }
+
+    #[test]
+    fn test_string_line_continuation() {
+        let mut lexer = Lexer::new("\"long \\\n        text\" \"first\nsecond\"".to_string());
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token_type, TokenType::String);
+        assert_eq!(tokens[0].value, "long text");
+        // 没有反斜杠的换行仍然保留
+        assert_eq!(tokens[1].value, "first\nsecond");
+        assert_eq!(tokens[1].start_pos.line, 2);
+    }
+
     #[test]
     fn test_hex_numbers() {
         let mut lexer = Lexer::new("0xFF 0x10".to_string());
@@ -816,6 +937,30 @@ mod tests {
         assert_eq!(tokens[4].token_type, TokenType::PercentEqual);
     }
 
+    #[test]
+    fn test_power_operator() {
+        let mut lexer = Lexer::new("2 ** 3 * 4 **= ".to_string());
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[1].token_type, TokenType::StarStar);
+        assert_eq!(tokens[1].value, "**");
+        assert_eq!(tokens[3].token_type, TokenType::Star);
+        assert_eq!(tokens[5].token_type, TokenType::StarStar);
+        assert_eq!(tokens[6].token_type, TokenType::Equal);
+    }
+
+    #[test]
+    fn test_optional_field_access() {
+        let tokens = Lexer::new("a?.b ? .c".to_string()).tokenize().unwrap();
+
+        assert_eq!(tokens[1].token_type, TokenType::QuestionDot);
+        assert_eq!(tokens[1].value, "?.");
+        assert_eq!(tokens[2].token_type, TokenType::Identifier);
+        // 中间有空白时不是 ?.
+        assert_eq!(tokens[3].token_type, TokenType::Unknown);
+        assert_eq!(tokens[4].token_type, TokenType::Dot);
+    }
+
     #[test]
     fn test_raw_string() {
         let mut lexer = Lexer::new(r#"r"hello\nworld""#.to_string());
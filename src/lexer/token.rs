@@ -68,6 +68,7 @@ pub enum TokenType {
     Plus,       // +
     Minus,      // -
     Star,       // *
+    StarStar,   // **
     Slash,      // /
     Percent,    // %
     
@@ -104,6 +105,7 @@ pub enum TokenType {
     Colon,          // :
     Dot,            // .
     DotDot,         // ..
+    QuestionDot,    // ?.
     Arrow,          // ->
     DoubleColon,    // ::
 
@@ -1,10 +1,15 @@
 // Zero编译器库接口
 pub mod ast;
+pub mod builtins;
 pub mod bytecode;
 pub mod compiler;
 pub mod error;
+pub mod formatter;
 pub mod interpreter;
 pub mod lexer;
 pub mod parser;
 pub mod type_checker;
-pub mod vm;
\ No newline at end of file
+pub mod vm;
+
+#[cfg(test)]
+mod test_support;
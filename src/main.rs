@@ -1,12 +1,16 @@
 mod lexer;
 mod parser;
 mod ast;
+mod builtins;
 mod bytecode;
 mod compiler;
 mod vm;
 mod type_checker;
 mod error;
+mod formatter;
 mod module_loader;
+#[cfg(test)]
+mod test_support;
 
 // 保留旧的解释器用于对比
 mod interpreter;
@@ -15,29 +19,68 @@ use lexer::Lexer;
 use parser::Parser;
 use compiler::Compiler;
 use vm::VM;
-use type_checker::TypeChecker;
+use type_checker::{TypeChecker, SymbolKind, Level, Diagnostic, DEFAULT_MAX_ERRORS, DEFAULT_MAX_MODULE_DEPTH};
+use bytecode::json::{chunk_to_json, json_string};
 use bytecode::serializer::{BytecodeSerializer, BytecodeDeserializer};
 use error::{ErrorMode, ErrorDisplayer};
 use module_loader::ModuleLoader;
-use ast::{Program, Stmt};
+use ast::{Program, Stmt, Type, Visibility};
+use lexer::token::{Token, TokenType, Position};
 use std::env;
 use std::fs;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::process;
 use std::path::PathBuf;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // 额外的模块搜索路径：--module-path <dir>（可重复）和 ZERO_PATH 环境变量
+    let module_paths = match take_module_paths(&mut args) {
+        Ok(paths) => paths,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let max_errors = match take_count_option(&mut args, "--max-errors", DEFAULT_MAX_ERRORS) {
+        Ok(max_errors) => max_errors,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let max_module_depth = match take_count_option(&mut args, "--max-module-depth", DEFAULT_MAX_MODULE_DEPTH) {
+        Ok(depth) => depth,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
 
     if args.len() < 2 {
         eprintln!("Usage: {} <source_file.zero> [--dtl]", args[0]);
         eprintln!("       {} --old <source_file.zero> [--dtl]  (use old interpreter)", args[0]);
         eprintln!("       {} --compile <source_file.zero> <output.zbc> [--dtl]  (compile to bytecode)", args[0]);
-        eprintln!("       {} --run <bytecode_file.zbc>  (run bytecode file)", args[0]);
+        eprintln!("       {} --run <bytecode_file.zbc> [more.zbc ...]  (link and run bytecode files)", args[0]);
+        eprintln!("       {} --symbols <source_file.zero>  (dump declared symbols as JSON)", args[0]);
+        eprintln!("       {} --fmt <source_file.zero>  (print formatted source)", args[0]);
+        eprintln!("       {} --ast-stats <source_file.zero>  (print AST node counts and expression depth)", args[0]);
+        eprintln!("       {} --dump-bytecode <source_file.zero|bytecode_file.zbc>  (dump compiled bytecode as JSON)", args[0]);
         eprintln!("");
         eprintln!("Options:");
-        eprintln!("  --dtl    显示详细的错误信息（包含源码片段和修复建议）");
+        eprintln!("  --dtl                 显示详细的错误信息（包含源码片段和修复建议）");
+        eprintln!("  --opt                 启用编译优化（把 while 循环中不变的全局变量读取外提到循环之前，化简简单的算术表达式）");
+        eprintln!("  --warn-shadow         let 以不同类型遮蔽外层作用域的同名变量时给出警告");
+        eprintln!("  --strict              把所有警告当作错误，有警告时以非零状态退出");
+        eprintln!("  --no-typecheck        跳过类型检查直接编译，类型错误留到运行时报告（用于试验）");
+        eprintln!("  --max-errors <n>      最多输出 n 条诊断信息，其余只给出数量（默认 {}）", DEFAULT_MAX_ERRORS);
+        eprintln!("  --max-module-depth <n> 模块最多嵌套 n 层，超过时报错（默认 {}）", DEFAULT_MAX_MODULE_DEPTH);
+        eprintln!("  --module-path <dir>   添加模块搜索路径（可重复；也可用 ZERO_PATH 环境变量，以 ';' 或 ':' 分隔）");
         process::exit(1);
     }
 
@@ -47,6 +90,15 @@ fn main() {
     } else {
         ErrorMode::Simple
     };
+    let options = CompileOptions {
+        error_mode,
+        optimize: args.contains(&"--opt".to_string()),
+        warn_shadow: args.contains(&"--warn-shadow".to_string()),
+        strict: args.contains(&"--strict".to_string()),
+        no_typecheck: args.contains(&"--no-typecheck".to_string()),
+        max_errors,
+        max_module_depth,
+    };
 
     match args[1].as_str() {
         "--old" => {
@@ -64,22 +116,98 @@ fn main() {
                 process::exit(1);
             }
             let source = read_source_file(&args[2]);
-            compile_to_bytecode(&source, &args[3], error_mode);
+            compile_to_bytecode(&source, &args[3], &options);
         }
         "--run" => {
             if args.len() < 3 {
-                eprintln!("Usage: {} --run <bytecode_file.zbc>", args[0]);
+                eprintln!("Usage: {} --run <bytecode_file.zbc> [more.zbc ...]", args[0]);
+                process::exit(1);
+            }
+            run_bytecode_files(&args[2..]);
+        }
+        "--symbols" => {
+            if args.len() < 3 {
+                eprintln!("Usage: {} --symbols <source_file.zero>", args[0]);
+                process::exit(1);
+            }
+            let source = read_source_file(&args[2]);
+            match symbols_json(&source, &args[2], &module_paths) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        "--fmt" => {
+            if args.len() < 3 {
+                eprintln!("Usage: {} --fmt <source_file.zero>", args[0]);
                 process::exit(1);
             }
-            run_bytecode_file(&args[2]);
+            let source = read_source_file(&args[2]);
+            format_source(&source, error_mode);
+        }
+        "--ast-stats" => {
+            if args.len() < 3 {
+                eprintln!("Usage: {} --ast-stats <source_file.zero>", args[0]);
+                process::exit(1);
+            }
+            let source = read_source_file(&args[2]);
+            print_ast_stats(&source, error_mode);
+        }
+        "--dump-bytecode" => {
+            if args.len() < 3 {
+                eprintln!("Usage: {} --dump-bytecode <source_file.zero|bytecode_file.zbc>", args[0]);
+                process::exit(1);
+            }
+            // .zbc 文件直接读取，其他文件按源码编译
+            let chunk = if args[2].ends_with(".zbc") {
+                load_bytecode_file(&args[2])
+            } else {
+                let source = read_source_file(&args[2]);
+                compile_program(&source, &args[2], &module_paths, &options)
+            };
+            println!("{}", chunk_to_json(&chunk));
         }
         _ => {
             let filename = &args[1];
             let source = read_source_file(filename);
             println!("Using bytecode compiler + VM...");
-            run(&source, filename, error_mode);
+            run(&source, filename, &module_paths, &options);
+        }
+    }
+}
+
+/// 从命令行参数中取出所有 `--module-path <dir>`，再追加 ZERO_PATH 中的路径
+fn take_module_paths(args: &mut Vec<String>) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    while let Some(i) = args.iter().position(|arg| arg == "--module-path") {
+        if i + 1 >= args.len() {
+            return Err("--module-path requires a directory argument".to_string());
         }
+        paths.push(PathBuf::from(args.remove(i + 1)));
+        args.remove(i);
     }
+
+    if let Ok(value) = env::var("ZERO_PATH") {
+        paths.extend(ModuleLoader::parse_search_paths(&value));
+    }
+
+    Ok(paths)
+}
+
+/// 从命令行参数中取出 `<flag> <n>`（如 `--max-errors 5`），n 必须是正整数，没有时使用默认值
+fn take_count_option(args: &mut Vec<String>, flag: &str, default: usize) -> Result<usize, String> {
+    let Some(i) = args.iter().position(|arg| arg == flag) else {
+        return Ok(default);
+    };
+    let value = args.get(i + 1).cloned().ok_or(format!("{} requires a number argument", flag))?;
+    let count = match value.parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => return Err(format!("{} expects a positive integer, got '{}'", flag, value)),
+    };
+    args.drain(i..=i + 1);
+    Ok(count)
 }
 
 fn read_source_file(filename: &str) -> String {
@@ -92,8 +220,45 @@ fn read_source_file(filename: &str) -> String {
     }
 }
 
+/// 解析源代码并输出格式化后的源码
+fn format_source(source: &str, error_mode: ErrorMode) {
+    let program = parse_or_exit(source, error_mode);
+    print!("{}", formatter::format_program(&program));
+}
+
+/// 解析源代码并输出 AST 统计
+fn print_ast_stats(source: &str, error_mode: ErrorMode) {
+    let program = parse_or_exit(source, error_mode);
+    print!("{}", ast::stats::AstStats::of(&program));
+}
+
+/// 词法和语法分析，出错时输出错误并退出
+fn parse_or_exit(source: &str, error_mode: ErrorMode) -> Program {
+    let mut lexer = Lexer::new(source.to_string());
+    let tokens = match lexer.tokenize() {
+        Ok(t) => t,
+        Err(err) => {
+            let displayer = ErrorDisplayer::new(error_mode);
+            eprintln!("{}", displayer.format_error(&err, Some(source)));
+            process::exit(1);
+        }
+    };
+
+    let tokens = lexer::TokenPreprocessor::preprocess(tokens);
+
+    let mut parser = Parser::new(tokens);
+    match parser.parse() {
+        Ok(prog) => prog,
+        Err(err) => {
+            eprintln!("Parse error: {:?}", err);
+            process::exit(1);
+        }
+    }
+}
+
 /// 编译源代码到字节码文件
-fn compile_to_bytecode(source: &str, output_file: &str, error_mode: ErrorMode) {
+fn compile_to_bytecode(source: &str, output_file: &str, options: &CompileOptions) {
+    let error_mode = options.error_mode;
     println!("Compiling {} to {}...", "source", output_file);
 
     // 词法分析
@@ -120,23 +285,20 @@ fn compile_to_bytecode(source: &str, output_file: &str, error_mode: ErrorMode) {
         }
     };
 
-    // 类型检查
-    let mut type_checker = TypeChecker::new();
-    if let Err(err) = type_checker.check(&program) {
-        eprintln!("Type error: {:?}", err);
-        process::exit(1);
-    }
-
-    // 获取导入符号映射
-    let imported_symbols = type_checker.get_imported_symbols();
+    // 类型检查，同时得到导入符号映射
+    let imported_symbols = type_check(&program, options);
 
     // 编译为字节码
     let mut compiler = Compiler::new();
     compiler.set_imported_symbols(imported_symbols);
+    compiler.set_hoist_loop_invariants(options.optimize);
+    compiler.set_strength_reduction(options.optimize);
+    compiler.set_inline_small_functions(options.optimize);
     let chunk = match compiler.compile(program) {
         Ok(chunk) => chunk,
         Err(err) => {
-            eprintln!("Compile error: {:?}", err);
+            let displayer = ErrorDisplayer::new(error_mode);
+            eprintln!("{}", displayer.format_error(&err.to_compiler_error(), Some(source)));
             process::exit(1);
         }
     };
@@ -159,10 +321,47 @@ fn compile_to_bytecode(source: &str, output_file: &str, error_mode: ErrorMode) {
     println!("Successfully compiled to {}", output_file);
 }
 
-/// 从字节码文件运行
-fn run_bytecode_file(filename: &str) {
-    println!("Loading bytecode from {}...", filename);
+/// 从字节码文件运行（给出多个文件时先用 `Chunk::merge` 链接成一个 chunk）
+fn run_bytecode_files(filenames: &[String]) {
+    let mut linked: Option<bytecode::Chunk> = None;
+    for filename in filenames {
+        println!("Loading bytecode from {}...", filename);
+        let chunk = load_bytecode_file(filename);
+
+        // 执行前校验跳转目标和操作数，避免损坏的文件导致虚拟机越界（链接时也依赖下标合法）
+        if let Err(err) = bytecode::verifier::verify_chunk(&chunk) {
+            eprintln!("Invalid bytecode in {}: {}", filename, err);
+            process::exit(1);
+        }
+
+        // 多个文件按命令行顺序链接：全局变量按名称合并，常量池去重
+        match linked.as_mut() {
+            Some(linked) => {
+                linked.merge(chunk);
+            }
+            None => linked = Some(chunk),
+        }
+    }
+    let chunk = linked.expect("at least one bytecode file");
+
+    println!("Running bytecode...");
     
+    // 调试：打印反汇编代码
+    if env::var("ZERO_DEBUG").is_ok() {
+        chunk.disassemble("loaded");
+    }
+
+    // VM执行（调试模式下检查顶层代码执行完后值栈是否平衡）
+    let mut vm = VM::new();
+    vm.set_strict_stack(env::var("ZERO_DEBUG").is_ok());
+    if let Err(err) = vm.execute(chunk) {
+        eprintln!("Runtime error: {:?}", err);
+        process::exit(1);
+    }
+}
+
+/// 读取字节码文件
+fn load_bytecode_file(filename: &str) -> bytecode::Chunk {
     let file = match File::open(filename) {
         Ok(f) => f,
         Err(err) => {
@@ -172,39 +371,144 @@ fn run_bytecode_file(filename: &str) {
     };
 
     let mut reader = BufReader::new(file);
-    let chunk = match BytecodeDeserializer::deserialize(&mut reader) {
+    match BytecodeDeserializer::deserialize(&mut reader) {
         Ok(c) => c,
         Err(err) => {
             eprintln!("Error deserializing bytecode: {}", err);
             process::exit(1);
         }
-    };
+    }
+}
 
-    println!("Running bytecode...");
-    
-    // 调试：打印反汇编代码
-    if env::var("ZERO_DEBUG").is_ok() {
-        chunk.disassemble("loaded");
+/// 输出文件中声明的所有顶层和模块级符号（JSON 格式，用于编辑器集成）
+fn symbols_json(source: &str, source_file: &str, module_paths: &[PathBuf]) -> Result<String, String> {
+    let mut lexer = Lexer::new(source.to_string());
+    let tokens = lexer.tokenize().map_err(|err| format!("Lexer error: {:?}", err))?;
+    let tokens = lexer::TokenPreprocessor::preprocess(tokens);
+    let positions = declaration_positions(&tokens);
+
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse().map_err(|err| format!("Parse error: {:?}", err))?;
+    let program = resolve_module_references(program, source_file, module_paths, DEFAULT_MAX_MODULE_DEPTH)
+        .map_err(|err| format!("Module resolution error: {}", err))?;
+
+    let mut type_checker = TypeChecker::new();
+    type_checker.check(&program).map_err(|err| format!("Type error: {}", err))?;
+
+    let entries: Vec<String> = type_checker.symbols().iter().map(|symbol| {
+        let mut fields = vec![
+            format!("\"name\": {}", json_string(&symbol.name)),
+            format!("\"kind\": {}", json_string(match symbol.kind {
+                SymbolKind::Variable => "variable",
+                SymbolKind::Function => "function",
+                SymbolKind::Struct => "struct",
+                SymbolKind::TypeAlias => "type_alias",
+            })),
+            format!("\"visibility\": {}", json_string(match symbol.visibility {
+                Visibility::Public => "public",
+                Visibility::Private => "private",
+            })),
+            format!("\"module_path\": [{}]", symbol.module_path.iter()
+                .map(|segment| json_string(segment))
+                .collect::<Vec<_>>()
+                .join(", ")),
+        ];
+
+        match (&symbol.kind, &symbol.symbol_type) {
+            (SymbolKind::Function, Type::Function(func_type)) => {
+                fields.push(format!("\"params\": [{}]", func_type.params.iter()
+                    .map(|t| json_string(&t.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(", ")));
+                fields.push(format!("\"return_type\": {}", json_string(&func_type.return_type.to_string())));
+            }
+            (SymbolKind::TypeAlias, target) => {
+                fields.push(format!("\"target\": {}", json_string(&target.to_string())));
+            }
+            _ => {}
+        }
+
+        // 从其他文件加载的模块没有位置信息
+        let position = positions.get(&(symbol.module_path.clone(), symbol.name.clone()))
+            .map(|pos| format!("{{\"line\": {}, \"column\": {}}}", pos.line, pos.column))
+            .unwrap_or_else(|| "null".to_string());
+        fields.push(format!("\"position\": {}", position));
+
+        format!("  {{{}}}", fields.join(", "))
+    }).collect();
+
+    if entries.is_empty() {
+        Ok("[]".to_string())
+    } else {
+        Ok(format!("[\n{}\n]", entries.join(",\n")))
     }
+}
 
-    // VM执行
-    let mut vm = VM::new();
-    if let Err(err) = vm.execute(chunk) {
-        eprintln!("Runtime error: {:?}", err);
-        process::exit(1);
+/// 扫描 token 流，记录每个声明（fn/struct/type）名称的位置
+/// 键为 (模块路径, 名称)；函数体和 impl 块内部的声明会被跳过
+fn declaration_positions(tokens: &[Token]) -> HashMap<(Vec<String>, String), Position> {
+    let mut positions = HashMap::new();
+    let mut module_path: Vec<String> = Vec::new();
+    let mut braces: Vec<bool> = Vec::new();  // 每个 '{' 是否开启了一个模块
+    let mut body_depth = 0;  // 非模块花括号的嵌套深度
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.token_type {
+            TokenType::LeftBrace => {
+                let opens_module = i >= 2
+                    && tokens[i - 2].token_type == TokenType::Mod
+                    && tokens[i - 1].token_type == TokenType::Identifier;
+                if opens_module {
+                    module_path.push(tokens[i - 1].value.clone());
+                } else {
+                    body_depth += 1;
+                }
+                braces.push(opens_module);
+            }
+            TokenType::RightBrace => match braces.pop() {
+                Some(true) => {
+                    module_path.pop();
+                }
+                Some(false) => body_depth -= 1,
+                None => {}
+            },
+            TokenType::Fn | TokenType::Struct | TokenType::Type if body_depth == 0 => {
+                if let Some(name) = tokens.get(i + 1).filter(|t| t.token_type == TokenType::Identifier) {
+                    positions.entry((module_path.clone(), name.value.clone()))
+                        .or_insert_with(|| name.start_pos.clone());
+                }
+            }
+            _ => {}
+        }
     }
+
+    positions
 }
 
 /// 解析程序中的模块引用，将 ModuleReference 转换为 ModuleDeclaration
-fn resolve_module_references(program: Program, source_file_path: &str) -> Result<Program, String> {
+fn resolve_module_references(
+    program: Program,
+    source_file_path: &str,
+    module_paths: &[PathBuf],
+    max_depth: usize,
+) -> Result<Program, String> {
     let mut loader = ModuleLoader::new();
+    loader.set_max_depth(max_depth);
 
-    // 添加搜索路径：源文件所在目录和当前工作目录
+    // 添加搜索路径：源文件所在目录、额外的搜索路径和当前工作目录
     if let Some(parent) = PathBuf::from(source_file_path).parent() {
         loader.add_search_path(parent);
     }
+    for path in module_paths {
+        loader.add_search_path(path);
+    }
     loader.add_search_path(".");
 
+    // 设置 ZERO_CACHE_DIR 时缓存模块的解析结果
+    if let Ok(cache_dir) = env::var("ZERO_CACHE_DIR") {
+        loader.set_cache_dir(cache_dir);
+    }
+
     let mut resolved_statements = Vec::new();
 
     for stmt in program.statements {
@@ -231,13 +535,106 @@ fn resolve_module_references(program: Program, source_file_path: &str) -> Result
         }
     }
 
+    // 调试模式下报告模块缓存的命中情况
+    if env::var("ZERO_DEBUG").is_ok() && loader.loaded_count() > 0 {
+        eprintln!(
+            "Module cache: {} of {} loaded modules served from cache",
+            loader.cache_hits(),
+            loader.loaded_count()
+        );
+    }
+
     Ok(Program {
         statements: resolved_statements,
     })
 }
 
 /// 新的字节码编译器 + VM执行
-fn run(source: &str, source_file: &str, error_mode: ErrorMode) {
+fn run(source: &str, source_file: &str, module_paths: &[PathBuf], options: &CompileOptions) {
+    let chunk = compile_program(source, source_file, module_paths, options);
+
+    // 调试：打印反汇编代码
+    if env::var("ZERO_DEBUG").is_ok() {
+        chunk.disassemble("main");
+    }
+
+    // VM执行（调试模式下检查顶层代码执行完后值栈是否平衡）
+    let mut vm = VM::new();
+    vm.set_strict_stack(env::var("ZERO_DEBUG").is_ok());
+    if let Err(err) = vm.execute(chunk) {
+        eprintln!("Runtime error: {:?}", err);
+        process::exit(1);
+    }
+}
+
+/// 命令行上影响编译过程的选项
+#[derive(Debug, Clone, Copy)]
+struct CompileOptions {
+    error_mode: ErrorMode,
+    optimize: bool,     // --opt
+    warn_shadow: bool,  // --warn-shadow
+    strict: bool,       // --strict：警告视为错误
+    no_typecheck: bool, // --no-typecheck：跳过类型检查，只依赖运行时检查
+    max_errors: usize,  // --max-errors N：最多输出的诊断条数
+    max_module_depth: usize, // --max-module-depth N：模块最多嵌套的层数
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            error_mode: ErrorMode::Simple,
+            optimize: false,
+            warn_shadow: false,
+            strict: false,
+            no_typecheck: false,
+            max_errors: DEFAULT_MAX_ERRORS,
+            max_module_depth: DEFAULT_MAX_MODULE_DEPTH,
+        }
+    }
+}
+
+/// 打印类型检查收集到的诊断信息，返回其中是否有错误级别的诊断
+fn report_diagnostics(type_checker: &TypeChecker, strict: bool) -> bool {
+    let diagnostics = type_checker.diagnostics(strict);
+    for line in render_diagnostics(&diagnostics, type_checker.dropped_diagnostics()) {
+        eprintln!("{}", line);
+    }
+    diagnostics.iter().any(|diagnostic| diagnostic.level == Level::Error)
+}
+
+/// 诊断信息的输出行：有未收集的诊断时，最后一行给出省略的数量
+fn render_diagnostics(diagnostics: &[Diagnostic], dropped: usize) -> Vec<String> {
+    let mut lines: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+    if dropped > 0 {
+        lines.push(format!("... and {} more", dropped));
+    }
+    lines
+}
+
+/// 类型检查程序并返回导入符号映射，出错时打印错误并退出；
+/// 使用 --no-typecheck 时跳过检查，只从 use 语句收集导入符号
+fn type_check(program: &Program, options: &CompileOptions) -> HashMap<String, String> {
+    if options.no_typecheck {
+        return TypeChecker::collect_imported_symbols(program);
+    }
+
+    let mut type_checker = TypeChecker::new();
+    type_checker.set_warn_shadow(options.warn_shadow);
+    type_checker.set_max_module_depth(options.max_module_depth);
+    type_checker.set_max_errors(options.max_errors);
+    if let Err(err) = type_checker.check(program) {
+        eprintln!("Type error: {}", err);
+        process::exit(1);
+    }
+    if report_diagnostics(&type_checker, options.strict) {
+        process::exit(1);
+    }
+    type_checker.get_imported_symbols()
+}
+
+/// 编译源码（包括加载模块和类型检查），出错时打印错误并退出
+fn compile_program(source: &str, source_file: &str, module_paths: &[PathBuf], options: &CompileOptions) -> bytecode::Chunk {
+    let error_mode = options.error_mode;
     // 词法分析
     let mut lexer = Lexer::new(source.to_string());
     let tokens = match lexer.tokenize() {
@@ -263,7 +660,7 @@ fn run(source: &str, source_file: &str, error_mode: ErrorMode) {
     };
 
     // 解析模块引用（将 mod name; 转换为实际加载的模块）
-    program = match resolve_module_references(program, source_file) {
+    program = match resolve_module_references(program, source_file, module_paths, options.max_module_depth) {
         Ok(prog) => prog,
         Err(err) => {
             eprintln!("Module resolution error: {}", err);
@@ -271,37 +668,22 @@ fn run(source: &str, source_file: &str, error_mode: ErrorMode) {
         }
     };
 
-    // 类型检查
-    let mut type_checker = TypeChecker::new();
-    if let Err(err) = type_checker.check(&program) {
-        eprintln!("Type error: {:?}", err);
-        process::exit(1);
-    }
-
-    // 获取导入符号映射
-    let imported_symbols = type_checker.get_imported_symbols();
+    // 类型检查，同时得到导入符号映射
+    let imported_symbols = type_check(&program, options);
 
     // 编译为字节码
     let mut compiler = Compiler::new();
     compiler.set_imported_symbols(imported_symbols);
-    let chunk = match compiler.compile(program) {
+    compiler.set_hoist_loop_invariants(options.optimize);
+    compiler.set_strength_reduction(options.optimize);
+    compiler.set_inline_small_functions(options.optimize);
+    match compiler.compile(program) {
         Ok(chunk) => chunk,
         Err(err) => {
-            eprintln!("Compile error: {:?}", err);
+            let displayer = ErrorDisplayer::new(error_mode);
+            eprintln!("{}", displayer.format_error(&err.to_compiler_error(), Some(source)));
             process::exit(1);
         }
-    };
-
-    // 调试：打印反汇编代码
-    if env::var("ZERO_DEBUG").is_ok() {
-        chunk.disassemble("main");
-    }
-
-    // VM执行
-    let mut vm = VM::new();
-    if let Err(err) = vm.execute(chunk) {
-        eprintln!("Runtime error: {:?}", err);
-        process::exit(1);
     }
 }
 
@@ -342,6 +724,7 @@ fn run_old(source: &str, error_mode: ErrorMode) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_support::run_and_capture;
 
     #[test]
     fn test_simple_program() {
@@ -350,7 +733,7 @@ mod tests {
             let y = 20;
             print(x + y);
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        run(source, "test.zero", &[], &CompileOptions::default());
     }
 
     #[test]
@@ -363,7 +746,7 @@ mod tests {
             let result = add(5, 3);
             print(result);
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        run(source, "test.zero", &[], &CompileOptions::default());
     }
 
     #[test]
@@ -375,12 +758,166 @@ mod tests {
         "#;
 
         println!("\n=== Bytecode VM ===");
-        run(source, "test.zero", ErrorMode::Simple);
+        run(source, "test.zero", &[], &CompileOptions::default());
 
         println!("\n=== Old Interpreter ===");
         run_old(source, ErrorMode::Simple);
     }
 
+    #[test]
+    fn test_strict_promotes_warnings_to_errors() {
+        let tokens = lexer::TokenPreprocessor::preprocess(
+            Lexer::new("let x = 1; fn f() { let x = \"one\"; print(x); } f(); print(x);".to_string()).tokenize().unwrap(),
+        );
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut type_checker = TypeChecker::new();
+        type_checker.set_warn_shadow(true);
+        type_checker.check(&program).unwrap();
+
+        // 只有警告的程序正常通过，严格模式下失败
+        assert_eq!(type_checker.diagnostics(false).len(), 1);
+        assert!(!report_diagnostics(&type_checker, false));
+        assert!(report_diagnostics(&type_checker, true));
+        assert_eq!(
+            type_checker.diagnostics(true)[0].to_string(),
+            "Error: 'x' shadows an outer variable of type int with type string",
+        );
+    }
+
+    #[test]
+    fn test_no_typecheck_skips_type_errors() {
+        // 类型检查拒绝混合类型的数组和类型不符的注解，但程序本身可以运行
+        let source = "mod util { pub fn twice(x: int) -> int { return x * 2; } pub fn half(x: int) -> int { return x / 2; } }\n\
+                      use util::twice as double;\nuse util::*;\n\
+                      let items = [1, \"two\"];\nlet n: int = \"three\";\n\
+                      print(items[1]); print(n); print(double(21)); print(half(8));\n";
+        let tokens = lexer::TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        assert!(TypeChecker::new().check(&program).is_err());
+
+        // 导入符号映射仍然从 use 语句中得到
+        let imported = TypeChecker::collect_imported_symbols(&program);
+        assert_eq!(imported.get("double").map(String::as_str), Some("twice"));
+        assert_eq!(imported.get("half").map(String::as_str), Some("half"));
+
+        let options = CompileOptions { no_typecheck: true, ..CompileOptions::default() };
+        let chunk = compile_program(source, "test.zero", &[], &options);
+        let mut vm = VM::new();
+        vm.set_print_sink(vm::PrintSink::Buffer(String::new()));
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.take_output(), "two\nthree\n42\n4\n");
+    }
+
+    #[test]
+    fn test_max_errors_caps_diagnostics() {
+        // 150 个未使用的变量，每个产生一条警告
+        let source: String = (0..150).map(|i| format!("let v{} = {};\n", i, i)).collect();
+        let tokens = lexer::TokenPreprocessor::preprocess(Lexer::new(source).tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut type_checker = TypeChecker::new();
+        type_checker.check(&program).unwrap();
+        let diagnostics = type_checker.diagnostics(true);
+        assert_eq!(diagnostics.len(), DEFAULT_MAX_ERRORS);
+        assert_eq!(type_checker.dropped_diagnostics(), 50);
+
+        let lines = render_diagnostics(&diagnostics, type_checker.dropped_diagnostics());
+        assert_eq!(lines.len(), DEFAULT_MAX_ERRORS + 1);
+        assert_eq!(lines[0], diagnostics[0].to_string());
+        assert_eq!(lines.last().unwrap(), "... and 50 more");
+
+        let mut type_checker = TypeChecker::new();
+        type_checker.set_max_errors(150);
+        type_checker.check(&program).unwrap();
+        assert_eq!(type_checker.dropped_diagnostics(), 0);
+        assert_eq!(render_diagnostics(&type_checker.diagnostics(true), 0).len(), 150);
+
+        let mut type_checker = TypeChecker::new();
+        type_checker.set_max_errors(3);
+        type_checker.check(&program).unwrap();
+        assert_eq!(type_checker.warnings().len(), 3);
+        let lines = render_diagnostics(&type_checker.diagnostics(true), type_checker.dropped_diagnostics());
+        assert_eq!(lines.last().unwrap(), "... and 147 more");
+
+        let mut args: Vec<String> = ["zero", "--max-errors", "5", "main.zero"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(take_count_option(&mut args, "--max-errors", DEFAULT_MAX_ERRORS), Ok(5));
+        assert_eq!(args, vec!["zero".to_string(), "main.zero".to_string()]);
+        assert_eq!(take_count_option(&mut args, "--max-errors", DEFAULT_MAX_ERRORS), Ok(DEFAULT_MAX_ERRORS));
+        for bad in [&["zero", "--max-errors"][..], &["zero", "--max-errors", "0"], &["zero", "--max-errors", "x"]] {
+            let mut args: Vec<String> = bad.iter().map(|s| s.to_string()).collect();
+            assert!(take_count_option(&mut args, "--max-errors", DEFAULT_MAX_ERRORS).is_err());
+        }
+    }
+
+    #[test]
+    fn test_symbols_json() {
+        let source = "pub fn add(a: int, b: int) -> int {\n    return a + b;\n}\nstruct Point { x: int, y: int };\n";
+        let json = symbols_json(source, "test.zero", &[]).unwrap();
+
+        assert_eq!(json, concat!(
+            "[\n",
+            "  {\"name\": \"Point\", \"kind\": \"struct\", \"visibility\": \"private\", \"module_path\": [], \"position\": {\"line\": 4, \"column\": 8}},\n",
+            "  {\"name\": \"add\", \"kind\": \"function\", \"visibility\": \"public\", \"module_path\": [], \"params\": [\"int\", \"int\"], \"return_type\": \"int\", \"position\": {\"line\": 1, \"column\": 8}}\n",
+            "]",
+        ));
+    }
+
+    #[test]
+    fn test_module_found_via_extra_search_path() {
+        let lib_dir = env::temp_dir().join(format!("zero_module_path_{}", process::id()));
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::write(lib_dir.join("extra_math.zero"), "pub fn triple(x: int) -> int { return x * 3; }").unwrap();
+
+        let parse = |source: &str| {
+            let tokens = lexer::TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+            Parser::new(tokens).parse().unwrap()
+        };
+        let source = "mod extra_math;";
+
+        // 源文件目录和当前目录中都没有这个模块
+        assert!(resolve_module_references(parse(source), "nowhere/main.zero", &[], DEFAULT_MAX_MODULE_DEPTH).is_err());
+
+        let program = resolve_module_references(parse(source), "nowhere/main.zero", std::slice::from_ref(&lib_dir), DEFAULT_MAX_MODULE_DEPTH).unwrap();
+        assert!(matches!(
+            &program.statements[0],
+            Stmt::ModuleDeclaration { name, statements, .. } if name == "extra_math" && statements.len() == 1
+        ));
+
+        let mut args = vec!["zero".to_string(), "--module-path".to_string(), "lib".to_string(), "main.zero".to_string()];
+        let paths = take_module_paths(&mut args).unwrap();
+        assert!(paths.starts_with(&[PathBuf::from("lib")]));
+        assert_eq!(args, vec!["zero".to_string(), "main.zero".to_string()]);
+
+        fs::remove_dir_all(&lib_dir).unwrap();
+    }
+
+    #[test]
+    fn test_directory_module_reexports_children() {
+        // shapes/mod.zero 通过 pub mod 引入两个子模块，分别以 circle.zero 和 square/mod.zero 的形式存放
+        let root = env::temp_dir().join(format!("zero_dir_module_{}", process::id()));
+        let shapes = root.join("shapes");
+        fs::create_dir_all(shapes.join("square")).unwrap();
+        fs::write(shapes.join("mod.zero"), "pub mod circle;\npub mod square;\npub fn unit() -> int { return 1; }\n").unwrap();
+        fs::write(shapes.join("circle.zero"), "pub fn diameter(r: int) -> int { return r * 2; }\n").unwrap();
+        fs::write(shapes.join("square").join("mod.zero"), "pub fn perimeter(side: int) -> int { return side * 4; }\n").unwrap();
+        let main_file = root.join("main.zero");
+
+        let source = "mod shapes;\nuse shapes::circle::diameter;\nuse shapes::square::*;\nuse shapes::unit;\n\
+                      print(diameter(5));\nprint(perimeter(3));\nprint(unit());\n";
+        let chunk = compile_program(source, main_file.to_str().unwrap(), &[], &CompileOptions::default());
+        let mut vm = VM::new();
+        vm.set_print_sink(vm::PrintSink::Buffer(String::new()));
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.take_output(), "10\n12\n1\n");
+
+        // 子模块只在父模块的目录中查找
+        fs::write(shapes.join("mod.zero"), "pub mod missing;\n").unwrap();
+        let tokens = lexer::TokenPreprocessor::preprocess(Lexer::new("mod shapes;".to_string()).tokenize().unwrap());
+        let result = resolve_module_references(Parser::new(tokens).parse().unwrap(), main_file.to_str().unwrap(), &[], DEFAULT_MAX_MODULE_DEPTH);
+        assert!(matches!(result, Err(ref message) if message.contains("shapes::missing")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_control_flow() {
         let source = r#"
@@ -389,13 +926,13 @@ mod tests {
                 print(x);
             }
 
-            let i = 0;
+            var i = 0;
             while i < 3 {
                 print(i);
                 i = i + 1;
             }
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        assert_eq!(run_and_capture(source), "15\n0\n1\n2\n");
     }
 
     #[test]
@@ -415,7 +952,7 @@ mod tests {
             print(multiply(6, 7));
             print(factorial(5));
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        assert_eq!(run_and_capture(source), "42\n120\n");
     }
 
     #[test]
@@ -430,7 +967,7 @@ mod tests {
             print(s);
             print(b);
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        assert_eq!(run_and_capture(source), "42\n3.14\nhello\ntrue\n");
     }
 
     #[test]
@@ -443,7 +980,7 @@ mod tests {
             let result = add(10, 20);
             print(result);
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        assert_eq!(run_and_capture(source), "30\n");
     }
 
     #[test]
@@ -457,7 +994,7 @@ mod tests {
             let result = multiply(x, 10);
             print(result);
         "#;
-        run(source, "test.zero", ErrorMode::Simple);
+        assert_eq!(run_and_capture(source), "50\n");
     }
 
 }
@@ -1,15 +1,20 @@
-use crate::ast::Program;
+use crate::ast::{Program, Stmt};
 use crate::lexer::Lexer;
 use crate::parser::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 /// 模块加载错误
 #[derive(Debug)]
 pub enum LoadError {
     ModuleNotFound(String),
     CircularDependency(String),
+    NestingTooDeep(String),
     IoError(std::io::Error),
     LexerError(String),
     ParseError(String),
@@ -35,6 +40,16 @@ impl From<crate::parser::ParseError> for LoadError {
 
 pub type LoadResult<T> = Result<T, LoadError>;
 
+/// 磁盘缓存条目：源文件的路径、修改时间和大小，以及解析得到的 AST
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    source_path: String,
+    modified_secs: u64,
+    modified_nanos: u32,
+    size: u64,
+    program: Program,
+}
+
 /// 模块加载器
 ///
 /// 负责从文件系统加载模块文件，解析为 AST，并检测循环依赖
@@ -50,6 +65,15 @@ pub struct ModuleLoader {
 
     /// 所有已访问过的模块（用于避免重复加载）
     visited: HashSet<String>,
+
+    /// 解析结果的磁盘缓存目录（None 表示不使用缓存）
+    cache_dir: Option<PathBuf>,
+
+    /// 从磁盘缓存中直接取得的模块数量
+    cache_hits: usize,
+
+    /// 允许的最大子模块文件嵌套层数
+    max_depth: usize,
 }
 
 impl ModuleLoader {
@@ -60,6 +84,9 @@ impl ModuleLoader {
             loaded_modules: HashMap::new(),
             loading_stack: Vec::new(),
             visited: HashSet::new(),
+            cache_dir: None,
+            cache_hits: 0,
+            max_depth: crate::type_checker::DEFAULT_MAX_MODULE_DEPTH,
         }
     }
 
@@ -68,33 +95,134 @@ impl ModuleLoader {
         self.search_paths.push(path.as_ref().to_path_buf());
     }
 
+    /// 设置磁盘缓存目录
+    ///
+    /// 模块解析后的 AST 会以源文件路径为键写入该目录，
+    /// 下次加载时如果源文件的修改时间和大小都没有变化，就跳过词法和语法分析
+    pub fn set_cache_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.cache_dir = Some(dir.as_ref().to_path_buf());
+    }
+
+    /// 设置允许的最大子模块嵌套层数（顶层加载的模块为第 1 层），超过时返回 NestingTooDeep
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// 解析搜索路径列表（如 ZERO_PATH 环境变量）
+    ///
+    /// 以 `;` 分隔；非 Windows 平台也接受 `:`（Windows 上 `:` 是盘符的一部分）。空项会被忽略
+    pub fn parse_search_paths(value: &str) -> Vec<PathBuf> {
+        value
+            .split(|c| c == ';' || (c == ':' && !cfg!(windows)))
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| PathBuf::from(entry.trim()))
+            .collect()
+    }
+
     /// 加载模块
     ///
     /// 查找规则:
     /// 1. mod math; → math.zero
     /// 2. mod math; → math/mod.zero
     /// 3. 在所有 search_paths 中查找
+    ///
+    /// 模块文件中的 `mod child;`（包括 `pub mod child;`）会递归加载并展开为内联模块声明，
+    /// 子模块只在父模块的目录中查找：math/mod.zero 和 math.zero 的子模块都位于 math/ 下
     pub fn load_module(&mut self, name: &str) -> LoadResult<Program> {
+        self.load_qualified(name, name, None)
+    }
+
+    /// 加载模块；key 是从顶层开始的完整路径（如 shapes::circle），用于缓存和循环检测，
+    /// parent_dir 为 None 时在搜索路径中查找，否则只在父模块的目录中查找
+    fn load_qualified(&mut self, key: &str, name: &str, parent_dir: Option<&Path>) -> LoadResult<Program> {
         // 检查是否已经加载过
-        if let Some(program) = self.loaded_modules.get(name) {
+        if let Some(program) = self.loaded_modules.get(key) {
             return Ok(program.clone());
         }
 
         // 检测循环依赖
-        if self.loading_stack.contains(&name.to_string()) {
-            let cycle = self.build_cycle_message(name);
+        if self.loading_stack.contains(&key.to_string()) {
+            let cycle = self.build_cycle_message(key);
             return Err(LoadError::CircularDependency(cycle));
         }
 
+        // 限制嵌套层数，避免病态的目录结构导致过深的递归
+        if self.loading_stack.len() >= self.max_depth {
+            return Err(LoadError::NestingTooDeep(format!(
+                "Module '{}' exceeds the maximum nesting depth of {}", key, self.max_depth
+            )));
+        }
+
         // 标记为正在加载
-        self.loading_stack.push(name.to_string());
-        self.visited.insert(name.to_string());
+        self.loading_stack.push(key.to_string());
+        self.visited.insert(key.to_string());
 
         // 查找模块文件
-        let file_path = self.find_module_file(name)?;
+        let file_path = match parent_dir {
+            Some(dir) => Self::find_in_dir(dir, name).ok_or_else(|| LoadError::ModuleNotFound(format!(
+                "Module '{}' not found in {}", key, dir.display()
+            )))?,
+            None => self.find_module_file(name)?,
+        };
+
+        // 源文件没有变化时直接使用磁盘缓存
+        let program = match self.read_cache(&file_path) {
+            Some(program) => {
+                self.cache_hits += 1;
+                program
+            }
+            None => {
+                let program = Self::parse_file(&file_path)?;
+                self.write_cache(&file_path, &program);
+                program
+            }
+        };
+
+        // 展开子模块引用
+        let program = self.expand_submodules(program, key, &Self::submodule_dir(&file_path, name))?;
+
+        // 缓存模块
+        self.loaded_modules.insert(key.to_string(), program.clone());
+
+        // 从加载栈中移除
+        self.loading_stack.pop();
+
+        Ok(program)
+    }
+
+    /// 把模块文件中的 `mod child;` 替换为加载得到的内联模块声明
+    fn expand_submodules(&mut self, program: Program, key: &str, dir: &Path) -> LoadResult<Program> {
+        let mut statements = Vec::with_capacity(program.statements.len());
+        for stmt in program.statements {
+            match stmt {
+                Stmt::ModuleReference { name, is_public } => {
+                    let child = self.load_qualified(&format!("{}::{}", key, name), &name, Some(dir))?;
+                    statements.push(Stmt::ModuleDeclaration {
+                        name,
+                        statements: child.statements,
+                        is_public,
+                    });
+                }
+                other => statements.push(other),
+            }
+        }
+        Ok(Program { statements })
+    }
+
+    /// 子模块所在的目录：dir/mod.zero → dir/，name.zero → name/
+    fn submodule_dir(file_path: &Path, name: &str) -> PathBuf {
+        let parent = file_path.parent().unwrap_or(Path::new("."));
+        if file_path.file_name().is_some_and(|f| f == "mod.zero") {
+            parent.to_path_buf()
+        } else {
+            parent.join(name)
+        }
+    }
 
+    /// 读取并解析模块文件
+    fn parse_file(file_path: &Path) -> LoadResult<Program> {
         // 读取源码
-        let source = fs::read_to_string(&file_path)?;
+        let source = fs::read_to_string(file_path)?;
 
         // 词法分析
         let mut lexer = Lexer::new(source);
@@ -105,15 +233,59 @@ impl ModuleLoader {
 
         // 语法分析
         let mut parser = Parser::new(tokens);
-        let program = parser.parse()?;
+        Ok(parser.parse()?)
+    }
 
-        // 缓存模块
-        self.loaded_modules.insert(name.to_string(), program.clone());
+    /// 源文件的修改时间（秒、纳秒）和大小
+    fn fingerprint(file_path: &Path) -> Option<(u64, u32, u64)> {
+        let metadata = fs::metadata(file_path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some((modified.as_secs(), modified.subsec_nanos(), metadata.len()))
+    }
 
-        // 从加载栈中移除
-        self.loading_stack.pop();
+    /// 源文件对应的缓存文件路径：<cache_dir>/<文件名>-<路径哈希>.toml
+    fn cache_path(&self, file_path: &Path) -> Option<PathBuf> {
+        let cache_dir = self.cache_dir.as_ref()?;
+        let mut hasher = DefaultHasher::new();
+        file_path.hash(&mut hasher);
+        let stem = file_path.file_stem()?.to_string_lossy();
+        Some(cache_dir.join(format!("{}-{:016x}.toml", stem, hasher.finish())))
+    }
 
-        Ok(program)
+    /// 读取缓存；缓存不存在、已损坏或源文件已修改时返回 None
+    fn read_cache(&self, file_path: &Path) -> Option<Program> {
+        let text = fs::read_to_string(self.cache_path(file_path)?).ok()?;
+        let entry: CacheEntry = toml::from_str(&text).ok()?;
+        let (secs, nanos, size) = Self::fingerprint(file_path)?;
+
+        let fresh = entry.source_path == file_path.to_string_lossy()
+            && entry.modified_secs == secs
+            && entry.modified_nanos == nanos
+            && entry.size == size;
+        if fresh {
+            Some(entry.program)
+        } else {
+            None
+        }
+    }
+
+    /// 写入缓存（缓存只是加速手段，写入失败时忽略）
+    fn write_cache(&self, file_path: &Path, program: &Program) {
+        let (cache_path, (secs, nanos, size)) = match (self.cache_path(file_path), Self::fingerprint(file_path)) {
+            (Some(cache_path), Some(fingerprint)) => (cache_path, fingerprint),
+            _ => return,
+        };
+        let entry = CacheEntry {
+            source_path: file_path.to_string_lossy().into_owned(),
+            modified_secs: secs,
+            modified_nanos: nanos,
+            size,
+            program: program.clone(),
+        };
+        if let Ok(text) = toml::to_string(&entry) {
+            let _ = fs::create_dir_all(cache_path.parent().unwrap_or(Path::new(".")));
+            let _ = fs::write(cache_path, text);
+        }
     }
 
     /// 查找模块文件
@@ -123,16 +295,7 @@ impl ModuleLoader {
     /// 2. <search_path>/<name>/mod.zero
     fn find_module_file(&self, name: &str) -> LoadResult<PathBuf> {
         for search_path in &self.search_paths {
-            // 尝试 name.zero
-            let mut path = search_path.join(name);
-            path.set_extension("zero");
-            if path.exists() && path.is_file() {
-                return Ok(path);
-            }
-
-            // 尝试 name/mod.zero
-            let path = search_path.join(name).join("mod.zero");
-            if path.exists() && path.is_file() {
+            if let Some(path) = Self::find_in_dir(search_path, name) {
                 return Ok(path);
             }
         }
@@ -143,6 +306,22 @@ impl ModuleLoader {
         )))
     }
 
+    /// 在一个目录中查找模块文件：先 <dir>/<name>.zero，再 <dir>/<name>/mod.zero
+    fn find_in_dir(dir: &Path, name: &str) -> Option<PathBuf> {
+        let mut path = dir.join(name);
+        path.set_extension("zero");
+        if path.is_file() {
+            return Some(path);
+        }
+
+        let path = dir.join(name).join("mod.zero");
+        if path.is_file() {
+            return Some(path);
+        }
+
+        None
+    }
+
     /// 构建循环依赖错误消息
     fn build_cycle_message(&self, current_module: &str) -> String {
         let mut cycle = Vec::new();
@@ -167,6 +346,11 @@ impl ModuleLoader {
     pub fn loaded_count(&self) -> usize {
         self.loaded_modules.len()
     }
+
+    /// 获取从磁盘缓存中取得的模块数量
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
 }
 
 #[cfg(test)]
@@ -185,4 +369,87 @@ mod tests {
         loader.add_search_path("./test");
         assert_eq!(loader.search_paths.len(), 1);
     }
+
+    #[test]
+    fn test_parse_search_paths() {
+        let paths = ModuleLoader::parse_search_paths("lib;vendor/zero;;");
+        assert_eq!(paths, vec![PathBuf::from("lib"), PathBuf::from("vendor/zero")]);
+
+        #[cfg(not(windows))]
+        assert_eq!(
+            ModuleLoader::parse_search_paths("/opt/zero/lib:./lib"),
+            vec![PathBuf::from("/opt/zero/lib"), PathBuf::from("./lib")]
+        );
+    }
+
+    #[test]
+    fn test_cache_invalidated_by_modification() {
+        let root = std::env::temp_dir().join(format!("zero_module_cache_{}", std::process::id()));
+        let src_dir = root.join("src");
+        let cache_dir = root.join("cache");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::write(src_dir.join("changed.zero"), "pub fn f() -> int { return 1; }").unwrap();
+        fs::write(src_dir.join("unchanged.zero"), "pub fn g() -> int { return 2; }").unwrap();
+
+        let load_all = || {
+            let mut loader = ModuleLoader::new();
+            loader.add_search_path(&src_dir);
+            loader.set_cache_dir(&cache_dir);
+            let changed = loader.load_module("changed").unwrap();
+            let unchanged = loader.load_module("unchanged").unwrap();
+            (loader.cache_hits(), changed, unchanged)
+        };
+
+        // 第一次加载：没有缓存
+        let (hits, first_changed, first_unchanged) = load_all();
+        assert_eq!(hits, 0);
+
+        // 第二次加载：两个模块都来自缓存，且与重新解析的结果一致
+        let (hits, changed, unchanged) = load_all();
+        assert_eq!(hits, 2);
+        assert_eq!(changed.statements, first_changed.statements);
+        assert_eq!(unchanged.statements, first_unchanged.statements);
+
+        // 修改一个模块（并把修改时间推后，避免文件系统时间精度的影响）
+        let path = src_dir.join("changed.zero");
+        fs::write(&path, "pub fn f() -> int { return 100; }").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+
+        let (hits, changed, _) = load_all();
+        assert_eq!(hits, 1);
+        assert_ne!(changed.statements, first_changed.statements);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_nesting_depth_limit() {
+        // level0.zero -> level0/level1.zero -> level0/level1/level2.zero -> ...
+        let root = std::env::temp_dir().join(format!("zero_module_depth_{}", std::process::id()));
+        let mut dir = root.clone();
+        for i in 0..4 {
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("level{}.zero", i)), format!("pub mod level{};\npub let x = {};", i + 1, i)).unwrap();
+            dir = dir.join(format!("level{}", i));
+        }
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("level4.zero"), "pub let x = 4;").unwrap();
+
+        let load = |max_depth: usize| {
+            let mut loader = ModuleLoader::new();
+            loader.add_search_path(&root);
+            loader.set_max_depth(max_depth);
+            loader.load_module("level0")
+        };
+
+        assert!(load(5).is_ok());
+        let result = load(4);
+        assert!(
+            matches!(result, Err(LoadError::NestingTooDeep(ref message)) if message.contains("level0::level1::level2::level3::level4")),
+            "{:?}", result.err()
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -1,4 +1,4 @@
-use crate::ast::{BinaryOp, Expr, Program, Stmt, UnaryOp, Type, Parameter, MethodDeclaration, UseItems, Visibility};
+use crate::ast::{BinaryOp, Expr, Program, Stmt, UnaryOp, Type, Parameter, MethodDeclaration, UseItems, Visibility, Pattern, Span, ValueBlock};
 use crate::lexer::token::{Token, TokenType, Position};
 
 pub struct Parser {
@@ -14,6 +14,8 @@ pub enum ParseError {
     },
     UnexpectedEOF,
     InvalidExpression,
+    InvalidNumber(String),  // 无法解析或超出范围的数字字面量
+    InvalidArrayLength(String),  // 定长数组的长度不是非负的常量表达式
 }
 
 type ParseResult<T> = Result<T, ParseError>;
@@ -73,18 +75,25 @@ impl Parser {
         }
     }
 
+    /// token 起始位置对应的 AST 位置
+    fn span_of(token: &Token) -> Span {
+        Span::new(token.start_pos.line, token.start_pos.column)
+    }
+
     pub fn parse(&mut self) -> ParseResult<Program> {
         let mut program = Program::new();
 
         while !self.check(TokenType::EOF) {
-            let stmt = self.declaration()?;
-            program.add_statement(stmt);
+            for stmt in self.declaration()? {
+                program.add_statement(stmt);
+            }
         }
 
         Ok(program)
     }
 
-    fn declaration(&mut self) -> ParseResult<Stmt> {
+    /// 解析一条声明或语句；`let a = 1, b = 2;` 展开为多条声明，因此返回列表
+    fn declaration(&mut self) -> ParseResult<Vec<Stmt>> {
         // 检查可见性修饰符
         let visibility = if self.match_token(&[TokenType::Pub]) {
             Visibility::Public
@@ -93,8 +102,10 @@ impl Parser {
         };
 
         if self.match_token(&[TokenType::Let, TokenType::Var]) {
-            self.var_declaration()
-        } else if self.match_token(&[TokenType::Fn]) {
+            return self.var_declaration(visibility);
+        }
+
+        let stmt = if self.match_token(&[TokenType::Fn]) {
             self.fn_declaration(visibility)
         } else if self.match_token(&[TokenType::Struct]) {
             self.struct_declaration(visibility)
@@ -110,43 +121,76 @@ impl Parser {
             // 如果有 pub 但没有后续声明，报错
             if visibility == Visibility::Public {
                 return Err(ParseError::UnexpectedToken {
-                    expected: "fn, struct, type, or mod after 'pub'".to_string(),
+                    expected: "fn, struct, type, mod, let, or var after 'pub'".to_string(),
                     found: self.current_token().token_type.clone(),
                 });
             }
             self.statement()
-        }
+        }?;
+        Ok(vec![stmt])
     }
 
-    fn var_declaration(&mut self) -> ParseResult<Stmt> {
+    /// 解析 let/var 声明：逗号分隔的多个绑定 `name [: type] [= expr]` 依次生成独立的 VarDeclaration，
+    /// 后面的初始化表达式可以引用前面的绑定
+    fn var_declaration(&mut self, visibility: Visibility) -> ParseResult<Vec<Stmt>> {
         let is_mutable = self.tokens.get(self.current.saturating_sub(1))
             .map(|t| t.token_type == TokenType::Var)
             .unwrap_or(false);
 
-        let name_token = self.consume(TokenType::Identifier, "Expected variable name")?;
-        let name = name_token.value.clone();
+        let mut declarations = Vec::new();
+        loop {
+            let pattern = self.parse_pattern()?;
 
-        // 解析可选的类型注解
-        let type_annotation = if self.match_token(&[TokenType::Colon]) {
-            Some(self.parse_type()?)
-        } else {
-            None
-        };
+            // 解析可选的类型注解
+            let type_annotation = if self.match_token(&[TokenType::Colon]) {
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
 
-        let initializer = if self.match_token(&[TokenType::Equal]) {
-            Some(self.expression()?)
-        } else {
-            None
-        };
+            let initializer = if self.match_token(&[TokenType::Equal]) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+
+            declarations.push(Stmt::VarDeclaration {
+                visibility: visibility.clone(),
+                pattern,
+                mutable: is_mutable,
+                type_annotation,
+                initializer,
+            });
+
+            if !self.match_token(&[TokenType::Comma]) {
+                break;
+            }
+        }
 
         self.consume(TokenType::Semicolon, "Expected ';' after variable declaration")?;
 
-        Ok(Stmt::VarDeclaration {
-            name,
-            mutable: is_mutable,
-            type_annotation,
-            initializer,
-        })
+        Ok(declarations)
+    }
+
+    /// 解析绑定模式：标识符或（可嵌套的）元组模式 (a, (b, c))
+    fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        if self.match_token(&[TokenType::LeftParen]) {
+            let mut elements = Vec::new();
+
+            while !self.check(TokenType::RightParen) && !self.check(TokenType::EOF) {
+                elements.push(self.parse_pattern()?);
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            self.consume(TokenType::RightParen, "Expected ')' after tuple pattern")?;
+            return Ok(Pattern::Tuple(elements));
+        }
+
+        let name_token = self.consume(TokenType::Identifier, "Expected variable name")?;
+        Ok(Pattern::Identifier(name_token.value.clone()))
     }
 
     fn fn_declaration(&mut self, visibility: Visibility) -> ParseResult<Stmt> {
@@ -170,6 +214,7 @@ impl Parser {
                 parameters.push(Parameter {
                     name: param_name.value.clone(),
                     type_annotation,
+                    span: Self::span_of(&param_name),
                 });
 
                 if !self.match_token(&[TokenType::Comma]) {
@@ -191,7 +236,7 @@ impl Parser {
 
         let mut body = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            body.push(self.declaration()?);
+            body.extend(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after function body")?;
@@ -212,10 +257,12 @@ impl Parser {
         self.consume(TokenType::LeftBrace, "Expected '{' after struct name")?;
 
         let mut fields = Vec::new();
+        let mut field_spans = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
             let field_name_token = self.consume(TokenType::Identifier, "Expected field name")?;
             let field_name = field_name_token.value.clone();
+            field_spans.push(Self::span_of(&field_name_token));
 
             self.consume(TokenType::Colon, "Expected ':' after field name")?;
 
@@ -237,7 +284,7 @@ impl Parser {
         self.consume(TokenType::RightBrace, "Expected '}' after struct fields")?;
         self.consume(TokenType::Semicolon, "Expected ';' after struct declaration")?;
 
-        Ok(Stmt::StructDeclaration { visibility, name, fields })
+        Ok(Stmt::StructDeclaration { visibility, name, fields, field_spans })
     }
     
     fn type_alias_declaration(&mut self, visibility: Visibility) -> ParseResult<Stmt> {
@@ -290,9 +337,13 @@ impl Parser {
     }
 
     fn impl_block(&mut self) -> ParseResult<Stmt> {
-        // impl TypeName { ... }
-        let type_token = self.consume(TokenType::Identifier, "Expected type name after 'impl'")?;
-        let type_name = type_token.value.clone();
+        // impl TypeName { ... } 或 impl int { ... }（基本类型按类型关键字命名）
+        let type_name = match self.current_token().token_type {
+            TokenType::Int | TokenType::Float64 | TokenType::String64 | TokenType::Bool | TokenType::Char => {
+                self.parse_type()?.to_string()
+            }
+            _ => self.consume(TokenType::Identifier, "Expected type name after 'impl'")?.value.clone(),
+        };
 
         self.consume(TokenType::LeftBrace, "Expected '{' after type name")?;
 
@@ -329,6 +380,7 @@ impl Parser {
                             parameters.push(Parameter {
                                 name: param_name.value.clone(),
                                 type_annotation,
+                                span: Self::span_of(&param_name),
                             });
 
                             if !self.match_token(&[TokenType::Comma]) {
@@ -357,7 +409,7 @@ impl Parser {
 
             let mut body = Vec::new();
             while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-                body.push(self.declaration()?);
+                body.extend(self.declaration()?);
             }
 
             self.consume(TokenType::RightBrace, "Expected '}' after method body")?;
@@ -393,7 +445,7 @@ impl Parser {
 
         let mut statements = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            statements.push(self.declaration()?);
+            statements.extend(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after module body")?;
@@ -472,14 +524,65 @@ impl Parser {
         })
     }
 
+    /// 在编译期求值定长数组的长度（只支持整数字面量和算术运算）
+    fn eval_array_length(expr: &Expr) -> ParseResult<usize> {
+        let value = Self::eval_const_int(expr)
+            .ok_or_else(|| ParseError::InvalidArrayLength("length must be a constant integer expression".to_string()))?;
+        usize::try_from(value)
+            .map_err(|_| ParseError::InvalidArrayLength(format!("length must not be negative, found {}", value)))
+    }
+
+    fn eval_const_int(expr: &Expr) -> Option<i64> {
+        match expr {
+            Expr::Integer(value) => Some(*value),
+            Expr::Unary { operator: UnaryOp::Negate, operand } => Self::eval_const_int(operand)?.checked_neg(),
+            Expr::Binary { left, operator, right } => {
+                let l = Self::eval_const_int(left)?;
+                let r = Self::eval_const_int(right)?;
+                match operator {
+                    BinaryOp::Add => l.checked_add(r),
+                    BinaryOp::Subtract => l.checked_sub(r),
+                    BinaryOp::Multiply => l.checked_mul(r),
+                    BinaryOp::Divide => l.checked_div(r),
+                    BinaryOp::Modulo => l.checked_rem(r),
+                    BinaryOp::Power => l.checked_pow(u32::try_from(r).ok()?),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     fn parse_type(&mut self) -> ParseResult<Type> {
-        // 检查数组类型 [element_type]
+        // 检查数组类型 [element_type] 或定长数组类型 [element_type; length]
         if self.check(TokenType::LeftBracket) {
             self.advance(); // 消费 '['
             let element_type = self.parse_type()?;
+            if self.match_token(&[TokenType::Semicolon]) {
+                let length_expr = self.expression()?;
+                let length = Self::eval_array_length(&length_expr)?;
+                self.consume(TokenType::RightBracket, "Expected ']' after array length")?;
+                return Ok(Type::FixedArray(Box::new(element_type), length));
+            }
             self.consume(TokenType::RightBracket, "Expected ']' after array element type")?;
             return Ok(Type::Array(Box::new(element_type)));
         }
+
+        // 检查元组类型 (T1, T2, ...)
+        if self.match_token(&[TokenType::LeftParen]) {
+            let mut element_types = Vec::new();
+
+            while !self.check(TokenType::RightParen) && !self.check(TokenType::EOF) {
+                element_types.push(self.parse_type()?);
+
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            self.consume(TokenType::RightParen, "Expected ')' after tuple element types")?;
+            return Ok(Type::Tuple(element_types));
+        }
         
         // 检查匿名结构体类型
         if self.match_token(&[TokenType::Struct]) {
@@ -545,8 +648,13 @@ impl Parser {
                 self.advance();
                 Ok(Type::Char)
             }
+            TokenType::Identifier if token.value == "StringBuilder" => {
+                self.advance();
+                Ok(Type::StringBuilder)
+            }
             TokenType::Identifier => {
-                // 用户定义的类型（结构体名或类型别名）
+                // 用户定义的类型（结构体名或类型别名）；
+                // 方法签名中的 `Self` 同样按名称解析，由类型检查器和编译器替换为 impl 的类型
                 let type_name = token.value.clone();
                 self.advance();
                 Ok(Type::Named(type_name))
@@ -609,7 +717,7 @@ impl Parser {
 
         let mut then_branch = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            then_branch.push(self.declaration()?);
+            then_branch.extend(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after then branch")?;
@@ -619,7 +727,7 @@ impl Parser {
 
             let mut else_stmts = Vec::new();
             while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-                else_stmts.push(self.declaration()?);
+                else_stmts.extend(self.declaration()?);
             }
 
             self.consume(TokenType::RightBrace, "Expected '}' after else branch")?;
@@ -642,12 +750,29 @@ impl Parser {
 
         let mut body = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            body.push(self.declaration()?);
+            body.extend(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after while body")?;
+        let else_branch = self.loop_else_branch()?;
+
+        Ok(Stmt::While { condition, body, else_branch })
+    }
+
+    /// 循环后可选的 else 分支：`while cond { ... } else { ... }`
+    fn loop_else_branch(&mut self) -> ParseResult<Option<Vec<Stmt>>> {
+        if !self.match_token(&[TokenType::Else]) {
+            return Ok(None);
+        }
+        self.consume(TokenType::LeftBrace, "Expected '{' after else")?;
+
+        let mut else_stmts = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
+            else_stmts.extend(self.declaration()?);
+        }
 
-        Ok(Stmt::While { condition, body })
+        self.consume(TokenType::RightBrace, "Expected '}' after loop else branch")?;
+        Ok(Some(else_stmts))
     }
 
     fn for_statement(&mut self) -> ParseResult<Stmt> {
@@ -666,16 +791,18 @@ impl Parser {
 
         let mut body = Vec::new();
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            body.push(self.declaration()?);
+            body.extend(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after for body")?;
+        let else_branch = self.loop_else_branch()?;
 
         Ok(Stmt::For {
             variable,
             start,
             end,
             body,
+            else_branch,
         })
     }
 
@@ -692,7 +819,7 @@ impl Parser {
         let mut statements = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
-            statements.push(self.declaration()?);
+            statements.extend(self.declaration()?);
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after block")?;
@@ -715,17 +842,21 @@ impl Parser {
 
         if self.match_token(&[TokenType::Equal]) {
             match expr {
-                Expr::Identifier(name) => {
+                Expr::Identifier(name, span) => {
                     let value = self.assignment()?;
-                    return Ok(Expr::assign(name, value));
+                    return Ok(Expr::assign(name, value).at(span));
                 }
                 Expr::Index { object, index } => {
                     let value = self.assignment()?;
                     return Ok(Expr::index_assign(*object, *index, value));
                 }
-                Expr::FieldAccess { object, field } => {
+                Expr::FieldAccess { object, field, span } => {
+                    let value = self.assignment()?;
+                    return Ok(Expr::field_assign(*object, field, value).at(span));
+                }
+                Expr::Path { segments, span } => {
                     let value = self.assignment()?;
-                    return Ok(Expr::field_assign(*object, field, value));
+                    return Ok(Expr::path_assign(segments, value).at(span));
                 }
                 _ => {}
             }
@@ -744,23 +875,29 @@ impl Parser {
             };
 
             match expr.clone() {
-                Expr::Identifier(name) => {
+                Expr::Identifier(name, span) => {
                     let value = self.assignment()?;
                     // x += y 转换为 x = x + y
                     let new_value = Expr::binary(expr, op, value);
-                    return Ok(Expr::assign(name, new_value));
+                    return Ok(Expr::assign(name, new_value).at(span));
                 }
                 Expr::Index { object, index } => {
                     let value = self.assignment()?;
                     // arr[i] += y 转换为 arr[i] = arr[i] + y
-                    let new_value = Expr::binary(expr, op, value);
-                    return Ok(Expr::index_assign(*object, *index, new_value));
+                    let new_value = Expr::binary(expr, op.clone(), value);
+                    return Ok(Expr::index_assign(*object, *index, new_value).compound(op));
                 }
-                Expr::FieldAccess { object, field } => {
+                Expr::FieldAccess { object, field, span } => {
                     let value = self.assignment()?;
                     // obj.field += y 转换为 obj.field = obj.field + y
+                    let new_value = Expr::binary(expr, op.clone(), value);
+                    return Ok(Expr::field_assign(*object, field, new_value).compound(op).at(span));
+                }
+                Expr::Path { segments, span } => {
+                    let value = self.assignment()?;
+                    // m::x += y 转换为 m::x = m::x + y
                     let new_value = Expr::binary(expr, op, value);
-                    return Ok(Expr::field_assign(*object, field, new_value));
+                    return Ok(Expr::path_assign(segments, new_value).at(span));
                 }
                 _ => {}
             }
@@ -884,7 +1021,20 @@ impl Parser {
             return Ok(Expr::unary(op, operand));
         }
 
-        self.call()
+        self.power()
+    }
+
+    /// 乘方：右结合，优先级高于一元运算符（`-2 ** 2` 为 `-(2 ** 2)`）
+    fn power(&mut self) -> ParseResult<Expr> {
+        let base = self.call()?;
+
+        if self.match_token(&[TokenType::StarStar]) {
+            // 指数部分允许一元运算符，例如 `2 ** -1`
+            let exponent = self.unary()?;
+            return Ok(Expr::binary(base, BinaryOp::Power, exponent));
+        }
+
+        Ok(base)
     }
 
     fn call(&mut self) -> ParseResult<Expr> {
@@ -895,9 +1045,38 @@ impl Parser {
                 expr = self.finish_call(expr)?;
             } else if self.match_token(&[TokenType::LeftBracket]) {
                 let index = self.expression()?;
+                // object[start..end] 是范围索引
+                if self.match_token(&[TokenType::DotDot]) {
+                    let end = self.expression()?;
+                    self.consume(TokenType::RightBracket, "Expected ']' after slice range")?;
+                    expr = Expr::slice(expr, index, end);
+                    continue;
+                }
                 self.consume(TokenType::RightBracket, "Expected ']' after index")?;
                 expr = Expr::index(expr, index);
             } else if self.match_token(&[TokenType::Dot]) {
+                // 元组索引 (tuple.0)
+                if self.match_token(&[TokenType::Integer]) {
+                    let index_token = self.tokens.get(self.current.saturating_sub(1)).unwrap().clone();
+                    expr = Expr::tuple_index(expr, self.parse_tuple_index(&index_token)?);
+                    continue;
+                }
+
+                // 连续的元组索引 (tuple.0.1) 会被词法分析为浮点数 "0.1"
+                if self.match_token(&[TokenType::Float]) {
+                    let index_token = self.tokens.get(self.current.saturating_sub(1)).unwrap().clone();
+                    for part in index_token.value.split('.') {
+                        let part_token = Token::new(
+                            TokenType::Integer,
+                            part.to_string(),
+                            index_token.start_pos.clone(),
+                            index_token.end_pos.clone(),
+                        );
+                        expr = Expr::tuple_index(expr, self.parse_tuple_index(&part_token)?);
+                    }
+                    continue;
+                }
+
                 // 字段访问或方法调用
                 let field_token = self.consume(TokenType::Identifier, "Expected field name after '.'")?;
                 let field = field_token.value.clone();
@@ -905,10 +1084,14 @@ impl Parser {
                 // 检查是否是方法调用 (后面跟着左括号)
                 if self.check(TokenType::LeftParen) {
                     self.advance(); // 消费 '('
-                    expr = self.finish_method_call(expr, field)?;
+                    expr = self.finish_method_call(expr, field)?.at(Self::span_of(&field_token));
                 } else {
-                    expr = Expr::field_access(expr, field);
+                    expr = Expr::field_access(expr, field).at(Self::span_of(&field_token));
                 }
+            } else if self.match_token(&[TokenType::QuestionDot]) {
+                let field_token = self.consume(TokenType::Identifier, "Expected field name after '?.'")?;
+                let field = field_token.value.clone();
+                expr = Expr::optional_field_access(expr, field).at(Self::span_of(&field_token));
             } else {
                 break;
             }
@@ -917,6 +1100,29 @@ impl Parser {
         Ok(expr)
     }
 
+    /// 解析整数字面量，支持 0x/0b/0o 进制前缀（词法分析器已去除下划线分隔符）
+    fn parse_integer_literal(literal: &str) -> ParseResult<i64> {
+        let (digits, radix) = if let Some(hex) = literal.strip_prefix("0x") {
+            (hex, 16)
+        } else if let Some(bin) = literal.strip_prefix("0b") {
+            (bin, 2)
+        } else if let Some(oct) = literal.strip_prefix("0o") {
+            (oct, 8)
+        } else {
+            (literal, 10)
+        };
+
+        i64::from_str_radix(digits, radix)
+            .map_err(|_| ParseError::InvalidNumber(literal.to_string()))
+    }
+
+    fn parse_tuple_index(&self, token: &Token) -> ParseResult<usize> {
+        token.value.parse::<usize>().map_err(|_| ParseError::UnexpectedToken {
+            expected: "tuple index".to_string(),
+            found: token.token_type.clone(),
+        })
+    }
+
     fn finish_call(&mut self, callee: Expr) -> ParseResult<Expr> {
         let mut arguments = Vec::new();
 
@@ -962,10 +1168,14 @@ impl Parser {
             return Ok(Expr::boolean(false));
         }
 
+        if self.match_token(&[TokenType::Null]) {
+            return Ok(Expr::Null);
+        }
+
         if self.match_token(&[TokenType::Integer]) {
-            let value = self.tokens.get(self.current.saturating_sub(1))
-                .unwrap().value.parse::<i64>().unwrap();
-            return Ok(Expr::integer(value));
+            let literal = self.tokens.get(self.current.saturating_sub(1))
+                .unwrap().value.clone();
+            return Ok(Expr::integer(Self::parse_integer_literal(&literal)?));
         }
 
         if self.match_token(&[TokenType::Float]) {
@@ -983,18 +1193,15 @@ impl Parser {
         if self.match_token(&[TokenType::Char]) {
             let value = self.tokens.get(self.current.saturating_sub(1))
                 .unwrap().value.clone();
-            // 解析字符字面量 (移除单引号)
-            let char_value = if value.len() >= 2 {
-                value.chars().nth(0).unwrap_or('\0')
-            } else {
-                '\0'
-            };
+            // 词法分析器已去掉单引号并处理了转义，这里取第一个字符即可
+            let char_value = value.chars().next().unwrap_or('\0');
             return Ok(Expr::Char(char_value));
         }
 
         if self.match_token(&[TokenType::Identifier]) {
-            let name = self.tokens.get(self.current.saturating_sub(1))
-                .unwrap().value.clone();
+            let name_token = self.tokens.get(self.current.saturating_sub(1)).unwrap();
+            let name = name_token.value.clone();
+            let span = Self::span_of(name_token);
 
             // 检查是否是路径表达式 module::item
             if self.check(TokenType::DoubleColon) {
@@ -1006,16 +1213,23 @@ impl Parser {
                     segments.push(segment_token.value.clone());
                 }
 
-                return Ok(Expr::Path { segments });
+                return Ok(Expr::path(segments).at(span));
             }
 
             // 检查是否是结构体字面量 StructName { field: value, ... }
-            if self.check(TokenType::LeftBrace) {
+            if self.check(TokenType::LeftBrace) && self.looks_like_struct_literal() {
                 self.advance(); // 消费 '{'
 
                 let mut fields = Vec::new();
+                let mut base = None;
 
                 while !self.check(TokenType::RightBrace) && !self.check(TokenType::EOF) {
+                    // 更新语法 `..base` 必须是最后一项
+                    if self.match_token(&[TokenType::DotDot]) {
+                        base = Some(self.expression()?);
+                        break;
+                    }
+
                     let field_name_token = self.consume(TokenType::Identifier, "Expected field name")?;
                     let field_name = field_name_token.value.clone();
 
@@ -1034,25 +1248,52 @@ impl Parser {
 
                 self.consume(TokenType::RightBrace, "Expected '}' after struct fields")?;
 
-                return Ok(Expr::struct_literal(name, fields));
+                return Ok(Expr::struct_literal(name, fields, base).at(span));
             }
 
-            return Ok(Expr::identifier(name));
+            return Ok(Expr::identifier(name).at(span));
+        }
+
+        if self.match_token(&[TokenType::If]) {
+            return self.if_expression();
         }
 
         if self.match_token(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
+
+            // 元组字面量 (a, b, ...)，允许尾随逗号，如 (a,)
+            if self.match_token(&[TokenType::Comma]) {
+                let mut elements = vec![expr];
+
+                while !self.check(TokenType::RightParen) && !self.check(TokenType::EOF) {
+                    elements.push(self.expression()?);
+
+                    if !self.match_token(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume(TokenType::RightParen, "Expected ')' after tuple elements")?;
+                return Ok(Expr::tuple(elements));
+            }
+
             self.consume(TokenType::RightParen, "Expected ')' after expression")?;
             return Ok(expr);
         }
 
-        // 数组字面量 [elem1, elem2, ...]
+        // 数组字面量 [elem1, elem2, ...] 或重复元素 [value; count]
         if self.match_token(&[TokenType::LeftBracket]) {
             let mut elements = Vec::new();
             
             if !self.check(TokenType::RightBracket) {
                 loop {
                     elements.push(self.expression()?);
+
+                    if elements.len() == 1 && self.match_token(&[TokenType::Semicolon]) {
+                        let count = self.expression()?;
+                        self.consume(TokenType::RightBracket, "Expected ']' after array fill count")?;
+                        return Ok(Expr::array_fill(elements.remove(0), count));
+                    }
                     
                     if !self.match_token(&[TokenType::Comma]) {
                         break;
@@ -1066,6 +1307,94 @@ impl Parser {
 
         Err(ParseError::InvalidExpression)
     }
+
+    /// 当前 `{` 是否开始结构体字面量：`{}`、`{ ..base }` 或 `{ field: ... }`。
+    /// 其余情况（如 `if a > b { a }`）把 `{` 留给语句块
+    fn looks_like_struct_literal(&self) -> bool {
+        match self.peek(1).token_type {
+            TokenType::RightBrace | TokenType::DotDot => true,
+            TokenType::Identifier => self.peek(2).token_type == TokenType::Colon,
+            _ => false,
+        }
+    }
+
+    /// if 表达式：两个分支都必须给出值，因此 else 不能省略
+    fn if_expression(&mut self) -> ParseResult<Expr> {
+        let condition = self.expression()?;
+        let then_branch = self.value_block("Expected '{' after if condition")?;
+        self.consume(TokenType::Else, "Expected 'else' in if expression")?;
+        let else_branch = self.value_block("Expected '{' after else")?;
+        Ok(Expr::if_expr(condition, then_branch, else_branch))
+    }
+
+    /// `{ stmt; ... value }`：最后一个不带分号的表达式是块的值
+    fn value_block(&mut self, open_message: &str) -> ParseResult<ValueBlock> {
+        self.consume(TokenType::LeftBrace, open_message)?;
+
+        let mut statements = Vec::new();
+        loop {
+            if self.check(TokenType::RightBrace) || self.check(TokenType::EOF) {
+                return Err(ParseError::UnexpectedToken {
+                    expected: "Expected value at end of if expression branch".to_string(),
+                    found: self.current_token().token_type.clone(),
+                });
+            }
+
+            // 分支里的 if 可能是值（嵌套的 if 表达式），也可能是没有 else 的 if 语句：
+            // 先按表达式解析，失败再回退成语句
+            let start = self.current;
+            if self.check(TokenType::If) {
+                if let Ok(expr) = self.expression() {
+                    if self.match_token(&[TokenType::RightBrace]) {
+                        return Ok(ValueBlock { statements, value: Box::new(expr) });
+                    }
+                    if self.match_token(&[TokenType::Semicolon]) {
+                        statements.push(Stmt::Expression(expr));
+                        continue;
+                    }
+                }
+                self.current = start;
+                statements.extend(self.declaration()?);
+                continue;
+            }
+
+            if self.starts_statement() {
+                statements.extend(self.declaration()?);
+                continue;
+            }
+
+            let expr = self.expression()?;
+            if self.match_token(&[TokenType::Semicolon]) {
+                statements.push(Stmt::Expression(expr));
+                continue;
+            }
+            self.consume(TokenType::RightBrace, "Expected '}' after if expression value")?;
+            return Ok(ValueBlock { statements, value: Box::new(expr) });
+        }
+    }
+
+    /// 当前 token 是否只能开始一条语句（不能作为值表达式）
+    fn starts_statement(&self) -> bool {
+        matches!(
+            self.current_token().token_type,
+            TokenType::Let
+                | TokenType::Var
+                | TokenType::Pub
+                | TokenType::Fn
+                | TokenType::Struct
+                | TokenType::Type
+                | TokenType::Impl
+                | TokenType::Mod
+                | TokenType::Use
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Print
+                | TokenType::LeftBrace
+        )
+    }
 }
 
 #[cfg(test)]
@@ -1102,4 +1431,244 @@ mod tests {
 
         assert_eq!(program.statements.len(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_nested_glob_use() {
+        let mut lexer = Lexer::new("use a::b::*;".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        assert_eq!(program.statements, vec![Stmt::UseStatement {
+            path: vec!["a".to_string(), "b".to_string()],
+            items: UseItems::All,
+        }]);
+    }
+
+    #[test]
+    fn test_parse_prefixed_integers() {
+        let mut lexer = Lexer::new("0xFF; 0b1010; 0o17; 1_000;".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let values: Vec<Stmt> = [255, 10, 15, 1000].iter()
+            .map(|v| Stmt::Expression(Expr::integer(*v)))
+            .collect();
+        assert_eq!(program.statements, values);
+
+        // 超出 i64 范围的字面量返回错误而不是 panic
+        for source in ["0xFFFFFFFFFFFFFFFFF;", "99999999999999999999;"] {
+            let mut lexer = Lexer::new(source.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            assert!(matches!(parser.parse(), Err(ParseError::InvalidNumber(_))));
+        }
+    }
+
+    #[test]
+    fn test_parse_fixed_array_type() {
+        let mut lexer = Lexer::new("let a: [int; 2 * (1 + 1)] = [1, 2, 3, 4];".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        match &program.statements[0] {
+            Stmt::VarDeclaration { type_annotation, .. } => {
+                assert_eq!(type_annotation, &Some(Type::FixedArray(Box::new(Type::Int), 4)));
+            }
+            other => panic!("Expected variable declaration, got {:?}", other),
+        }
+
+        // 长度必须是非负的常量表达式
+        for source in ["let a: [int; n] = [];", "let a: [int; 1 - 2] = [];"] {
+            let mut lexer = Lexer::new(source.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            assert!(matches!(parser.parse(), Err(ParseError::InvalidArrayLength(_))));
+        }
+    }
+
+    #[test]
+    fn test_parse_tuple_destructuring() {
+        let mut lexer = Lexer::new("let (a, (b, c)) = (1, (\"two\", 3.0)); print(t.0.1);".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        match &program.statements[0] {
+            Stmt::VarDeclaration { pattern, initializer: Some(Expr::Tuple { elements }), .. } => {
+                assert_eq!(
+                    *pattern,
+                    Pattern::Tuple(vec![
+                        Pattern::Identifier("a".to_string()),
+                        Pattern::Tuple(vec![
+                            Pattern::Identifier("b".to_string()),
+                            Pattern::Identifier("c".to_string()),
+                        ]),
+                    ])
+                );
+                assert_eq!(elements.len(), 2);
+            }
+            other => panic!("Expected tuple destructuring, got {:?}", other),
+        }
+
+        match &program.statements[1] {
+            Stmt::Print { value } => assert_eq!(
+                *value,
+                Expr::tuple_index(Expr::tuple_index(Expr::identifier("t".to_string()).at(Span::new(1, 44)), 0), 1)
+            ),
+            other => panic!("Expected print statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_multiple_bindings() {
+        let mut lexer = Lexer::new("fn f() { var a: int = 1, (b, c) = (2, 3), d; }".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let body = match &program.statements[..] {
+            [Stmt::FnDeclaration { body, .. }] => body,
+            other => panic!("Expected a single function, got {:?}", other),
+        };
+        assert_eq!(body.len(), 3);
+        assert!(matches!(
+            &body[0],
+            Stmt::VarDeclaration { pattern: Pattern::Identifier(name), mutable: true, type_annotation: Some(Type::Int), .. } if name == "a"
+        ));
+        assert!(matches!(&body[1], Stmt::VarDeclaration { pattern: Pattern::Tuple(elements), mutable: true, .. } if elements.len() == 2));
+        assert!(matches!(&body[2], Stmt::VarDeclaration { initializer: None, mutable: true, .. }));
+
+        // 缺少分号或多余的逗号都是错误
+        for source in ["let a = 1, b = 2", "let a = 1,;"] {
+            let tokens = Lexer::new(source.to_string()).tokenize().unwrap();
+            assert!(Parser::new(tokens).parse().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_array_fill() {
+        let tokens = Lexer::new("let a = [0; 5]; let b = [x + 1; n * 2]; let c = [1, 2];".to_string()).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let initializer = |i: usize| match &program.statements[i] {
+            Stmt::VarDeclaration { initializer: Some(expr), .. } => expr.clone(),
+            other => panic!("Expected variable declaration, got {:?}", other),
+        };
+        assert_eq!(initializer(0), Expr::array_fill(Expr::Integer(0), Expr::Integer(5)));
+        assert!(matches!(initializer(1), Expr::ArrayFill { value, count }
+            if matches!(*value, Expr::Binary { .. }) && matches!(*count, Expr::Binary { .. })));
+        assert!(matches!(initializer(2), Expr::Array { elements } if elements.len() == 2));
+
+        // 只有第一个元素之后可以出现 ';'
+        let tokens = Lexer::new("let a = [1, 2; 3];".to_string()).tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_path_assignment() {
+        let tokens = Lexer::new("mod m { pub var x = 1; } m::x = 2; m::x += 3;".to_string()).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        match &program.statements[0] {
+            Stmt::ModuleDeclaration { statements, .. } => assert!(matches!(
+                &statements[0],
+                Stmt::VarDeclaration { visibility: Visibility::Public, mutable: true, .. }
+            )),
+            other => panic!("Expected module, got {:?}", other),
+        }
+        let segments = vec!["m".to_string(), "x".to_string()];
+        assert_eq!(
+            program.statements[1],
+            Stmt::Expression(Expr::path_assign(segments.clone(), Expr::Integer(2)).at(Span::new(1, 26)))
+        );
+        let target = Span::new(1, 36);
+        assert_eq!(
+            program.statements[2],
+            Stmt::Expression(Expr::path_assign(
+                segments.clone(),
+                Expr::binary(Expr::path(segments).at(target), BinaryOp::Add, Expr::Integer(3)),
+            ).at(target))
+        );
+    }
+
+    #[test]
+    fn test_parse_if_expression() {
+        let tokens = Lexer::new("let m = if a > b { a } else { b };".to_string()).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        match &program.statements[0] {
+            Stmt::VarDeclaration { initializer: Some(Expr::If { condition, then_branch, else_branch }), .. } => {
+                assert!(matches!(condition.as_ref(), Expr::Binary { operator: BinaryOp::Greater, .. }));
+                assert!(then_branch.statements.is_empty());
+                assert_eq!(*then_branch.value, Expr::identifier("a".to_string()).at(Span::new(1, 20)));
+                assert_eq!(*else_branch.value, Expr::identifier("b".to_string()).at(Span::new(1, 31)));
+            }
+            other => panic!("Expected if expression, got {:?}", other),
+        }
+
+        // 分支中可以先有语句（包括没有 else 的 if 语句），最后的值可以是嵌套的 if 表达式
+        let source = "let v = if c { print(1); if d { print(2); } 1 } else { if d { 2 } else { 3 } };";
+        let program = Parser::new(Lexer::new(source.to_string()).tokenize().unwrap()).parse().unwrap();
+        match &program.statements[0] {
+            Stmt::VarDeclaration { initializer: Some(Expr::If { then_branch, else_branch, .. }), .. } => {
+                assert_eq!(then_branch.statements.len(), 2);
+                assert_eq!(*then_branch.value, Expr::Integer(1));
+                assert!(else_branch.statements.is_empty());
+                assert!(matches!(else_branch.value.as_ref(), Expr::If { .. }));
+            }
+            other => panic!("Expected if expression, got {:?}", other),
+        }
+
+        // 表达式位置的 if 必须有 else
+        let tokens = Lexer::new("let m = if a > b { a };".to_string()).tokenize().unwrap();
+        assert!(matches!(
+            Parser::new(tokens).parse(),
+            Err(ParseError::UnexpectedToken { expected, found: TokenType::Semicolon })
+                if expected == "Expected 'else' in if expression"
+        ));
+        // 分支必须以值结尾
+        let tokens = Lexer::new("let m = if c { 1; } else { 2 };".to_string()).tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_struct_update() {
+        let mut lexer = Lexer::new("let q = Point { x: 10, ..p };".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        match &program.statements[0] {
+            Stmt::VarDeclaration { initializer: Some(Expr::StructLiteral { struct_name, fields, base, span }), .. } => {
+                assert_eq!(struct_name, "Point");
+                assert_eq!((span.line, span.column), (1, 9));
+                assert_eq!(fields, &vec![("x".to_string(), Expr::Integer(10))]);
+                assert_eq!(base.as_deref(), Some(&Expr::identifier("p".to_string()).at(Span::new(1, 26))));
+            }
+            other => panic!("Expected struct update, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_power_precedence() {
+        let mut lexer = Lexer::new("2 ** 3 ** 2; -2 ** 2; 3 * 2 ** 2;".to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let pow = |l, r| Expr::binary(l, BinaryOp::Power, r);
+        assert_eq!(program.statements, vec![
+            // 右结合
+            Stmt::Expression(pow(Expr::integer(2), pow(Expr::integer(3), Expr::integer(2)))),
+            // 比一元负号绑定更紧
+            Stmt::Expression(Expr::unary(UnaryOp::Negate, pow(Expr::integer(2), Expr::integer(2)))),
+            // 比乘法绑定更紧
+            Stmt::Expression(Expr::binary(
+                Expr::integer(3),
+                BinaryOp::Multiply,
+                pow(Expr::integer(2), Expr::integer(2)),
+            )),
+        ]);
+    }
+}
@@ -0,0 +1,73 @@
+// 测试辅助工具：运行 Zero 程序并捕获 print 输出
+
+use crate::compiler::Compiler;
+use crate::lexer::{Lexer, TokenPreprocessor};
+use crate::parser::Parser;
+use crate::type_checker::TypeChecker;
+use crate::vm::{PrintSink, VmIo, VM};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// 经过完整的编译流水线（词法、语法、类型检查、编译）在虚拟机中运行源码，
+/// 返回 print 输出的全部文本（每次 print 一行）
+///
+/// 任何阶段出错都会 panic，并在信息中注明出错的阶段。
+pub fn run_and_capture(source: &str) -> String {
+    let tokens = Lexer::new(source.to_string()).tokenize()
+        .unwrap_or_else(|err| panic!("lexer error: {:?}", err));
+    let tokens = TokenPreprocessor::preprocess(tokens);
+    let program = Parser::new(tokens).parse()
+        .unwrap_or_else(|err| panic!("parse error: {:?}", err));
+
+    let mut type_checker = TypeChecker::new();
+    type_checker.check(&program)
+        .unwrap_or_else(|err| panic!("type error: {:?}", err));
+
+    let mut compiler = Compiler::new();
+    compiler.set_imported_symbols(type_checker.get_imported_symbols());
+    let chunk = compiler.compile(program)
+        .unwrap_or_else(|err| panic!("compile error: {}", err));
+
+    let mut vm = VM::new();
+    vm.set_strict_stack(true);
+    vm.set_print_sink(PrintSink::Buffer(String::new()));
+    vm.execute(chunk)
+        .unwrap_or_else(|err| panic!("runtime error: {:?}", err));
+    vm.take_output()
+}
+
+/// 从字符串读取输入，输出和错误写入内存；返回的两个缓冲区分别对应 output 和 error
+pub fn captured_io(input: &str) -> (VmIo, CaptureBuffer, CaptureBuffer) {
+    let output = CaptureBuffer::default();
+    let error = CaptureBuffer::default();
+    let io = VmIo::new(
+        Box::new(io::Cursor::new(input.as_bytes().to_vec())),
+        Box::new(output.clone()),
+        Box::new(error.clone()),
+    );
+    (io, output, error)
+}
+
+/// 可共享的内存写入目标：交给 VmIo 之后仍能从外部读取写入的内容
+#[derive(Debug, Clone, Default)]
+pub struct CaptureBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl CaptureBuffer {
+    /// 取出已写入的文本并清空缓冲区
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.0.borrow_mut());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
-use crate::ast::{Expr, Program, Stmt, BinaryOp, UnaryOp, Type, Parameter, FunctionType, MethodDeclaration};
+use crate::ast::{Expr, Program, Stmt, BinaryOp, UnaryOp, Type, Parameter, FunctionType, MethodDeclaration, Pattern, Span, UseItems, ValueBlock, Visibility};
+use crate::builtins::BuiltinReturn;
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::fmt;
 
 /// 类型检查错误
 #[derive(Debug)]
@@ -38,17 +41,162 @@ pub enum TypeError {
     },
     BreakOutsideLoop,
     ContinueOutsideLoop,
+    PrivateImport {
+        module: String,
+        item: String,
+    },
+    // format 的格式字符串不合法
+    InvalidFormat(String),
+    // 调用的值不是函数
+    NotCallable {
+        found_type: Type,
+    },
+    // 不返回值（void）的函数调用被当作值使用
+    VoidValue {
+        function: String,
+        location: String,
+    },
+    // first/second 为两次声明该字段的源码位置
+    DuplicateField {
+        struct_name: String,
+        field: String,
+        first: Span,
+        second: Span,
+    },
+    // first/second 为两次声明该参数的源码位置
+    DuplicateParameter {
+        function: String,
+        parameter: String,
+        first: Span,
+        second: Span,
+    },
+    // 类型别名直接或间接引用了自身（参数为别名的名字）
+    CyclicTypeAlias(String),
+    // 模块嵌套超过允许的最大层数（module 为超出限制的模块的完整路径）
+    ModuleNestingTooDeep {
+        module: String,
+        limit: usize,
+    },
+    // if 表达式的分支中出现了不支持的语句（参数为语句种类，见 ValueBlock::unsupported_statement）
+    StatementInIfExpression(&'static str),
+    // 元组下标超出元组长度
+    TupleIndexOutOfRange {
+        index: usize,
+        len: usize,
+    },
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::DuplicateField { struct_name, field, first, second } => write!(
+                f,
+                "field '{}' of struct '{}' is declared twice: first at {}, again at {}",
+                field, struct_name, first, second
+            ),
+            TypeError::DuplicateParameter { function, parameter, first, second } => write!(
+                f,
+                "parameter '{}' of function '{}' is declared twice: first at {}, again at {}",
+                parameter, function, first, second
+            ),
+            // 其他错误暂时沿用调试格式
+            other => write!(f, "{:?}", other),
+        }
+    }
 }
 
 type TypeResult<T> = Result<T, TypeError>;
 
+/// 默认允许的最大模块嵌套层数（内联模块和从文件加载的子模块一起计算）
+pub const DEFAULT_MAX_MODULE_DEPTH: usize = 64;
+
+/// 默认最多收集的诊断条数，超出的只计数
+pub const DEFAULT_MAX_ERRORS: usize = 100;
+
+/// 类型检查警告（不阻止编译）
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeWarning {
+    // `let` 遮蔽了外层作用域中类型不同的同名变量（--warn-shadow）
+    ShadowedWithDifferentType {
+        name: String,
+        outer_type: Type,
+        new_type: Type,
+    },
+    // 声明后从未读取的变量（以 `_` 开头的名称不提示）
+    UnusedVariable {
+        name: String,
+    },
+    // 声明后从未调用的函数
+    UnusedFunction {
+        name: String,
+    },
+}
+
+impl fmt::Display for TypeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeWarning::ShadowedWithDifferentType { name, outer_type, new_type } => write!(
+                f,
+                "'{}' shadows an outer variable of type {} with type {}",
+                name, outer_type, new_type
+            ),
+            TypeWarning::UnusedVariable { name } => write!(f, "unused variable '{}'", name),
+            TypeWarning::UnusedFunction { name } => write!(f, "unused function '{}'", name),
+        }
+    }
+}
+
+/// 诊断级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+/// 带级别的诊断信息。警告默认为 Warning 级别，严格模式（--strict）下升级为 Error
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub warning: TypeWarning,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.level {
+            Level::Warning => write!(f, "Warning: {}", self.warning),
+            Level::Error => write!(f, "Error: {}", self.warning),
+        }
+    }
+}
+
+/// 符号种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Struct,
+    TypeAlias,
+}
+
+/// 对外公开的符号信息（用于 --symbols 等编辑器集成工具）
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub symbol_type: Type,
+    pub visibility: crate::ast::Visibility,
+    pub module_path: Vec<String>,
+}
+
 /// 符号表条目
 #[derive(Debug, Clone)]
 struct Symbol {
+    kind: SymbolKind,
     symbol_type: Type,
     is_mutable: bool,
     visibility: crate::ast::Visibility,  // 新增：可见性
     module_path: Vec<String>,  // 新增：符号所在的模块路径
+    used: Cell<bool>,  // 是否被读取过（用于未使用警告）
 }
 
 /// 模块符号表（存储模块导出的符号）
@@ -62,6 +210,7 @@ pub struct SymbolTable {
     scopes: Vec<HashMap<String, Symbol>>,
     modules: HashMap<Vec<String>, ModuleSymbols>,  // 模块路径 -> 模块符号
     current_module_path: Vec<String>,  // 当前所在的模块路径
+    module_scope_depths: Vec<usize>,  // 进入各层模块时的作用域层数（模块自己的作用域紧随其后压入）
     imported_symbols: HashMap<String, (Vec<String>, String)>,  // 导入的符号名(别名) -> (模块路径, 原始名)
 }
 
@@ -71,6 +220,7 @@ impl SymbolTable {
             scopes: vec![HashMap::new()],
             modules: HashMap::new(),
             current_module_path: Vec::new(),
+            module_scope_depths: Vec::new(),
             imported_symbols: HashMap::new(),
         }
     }
@@ -83,26 +233,68 @@ impl SymbolTable {
         self.scopes.pop();
     }
 
-    /// 定义符号（兼容旧接口）
-    pub fn define(&mut self, name: String, symbol_type: Type, is_mutable: bool) {
-        self.define_with_visibility(name, symbol_type, is_mutable, crate::ast::Visibility::Private);
+    /// 当前作用域中声明后从未读取的变量和函数（按名称排序）。
+    /// 以 `_` 开头的名称、`self`、公共声明和导入的符号不算在内
+    pub fn unused_in_scope(&self) -> Vec<(String, SymbolKind)> {
+        let mut unused: Vec<(String, SymbolKind)> = self.scopes.last()
+            .into_iter()
+            .flat_map(|scope| scope.iter())
+            .filter(|(name, symbol)| {
+                matches!(symbol.kind, SymbolKind::Variable | SymbolKind::Function)
+                    && !symbol.used.get()
+                    && !name.starts_with('_')
+                    && name.as_str() != "self"
+                    && symbol.visibility != crate::ast::Visibility::Public
+                    && !self.imported_symbols.contains_key(name.as_str())
+            })
+            .map(|(name, symbol)| (name.clone(), symbol.kind))
+            .collect();
+        unused.sort();
+        unused
+    }
+
+    /// 定义符号（兼容旧接口，普通变量不注册为模块成员）
+    /// 如果新符号遮蔽了外层作用域中的同名变量，返回被遮蔽变量的类型
+    pub fn define(&mut self, name: String, symbol_type: Type, is_mutable: bool) -> Option<Type> {
+        let shadowed = self.scopes.iter()
+            .rev()
+            .skip(1)
+            .find_map(|scope| scope.get(&name))
+            .filter(|symbol| symbol.kind == SymbolKind::Variable)
+            .map(|symbol| symbol.symbol_type.clone());
+
+        let symbol = Symbol {
+            kind: SymbolKind::Variable,
+            symbol_type,
+            is_mutable,
+            visibility: crate::ast::Visibility::Private,
+            module_path: self.current_module_path.clone(),
+            used: Cell::new(false),
+        };
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, symbol);
+        }
+        shadowed
     }
 
     /// 定义符号（带可见性）
-    pub fn define_with_visibility(&mut self, name: String, symbol_type: Type, is_mutable: bool, visibility: crate::ast::Visibility) {
+    pub fn define_with_visibility(&mut self, name: String, kind: SymbolKind, symbol_type: Type, is_mutable: bool, visibility: crate::ast::Visibility) {
         let symbol = Symbol {
+            kind,
             symbol_type,
             is_mutable,
             visibility: visibility.clone(),
             module_path: self.current_module_path.clone(),
+            used: Cell::new(false),
         };
 
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(name.clone(), symbol.clone());
         }
 
-        // 如果是公共符号且在模块内，注册到模块符号表
-        if visibility == crate::ast::Visibility::Public && !self.current_module_path.is_empty() {
+        // 模块内的声明都注册到模块符号表（私有项也注册，以便导入时报告可见性错误）
+        if !self.current_module_path.is_empty() {
             self.register_module_symbol(name, symbol);
         }
     }
@@ -115,8 +307,15 @@ impl SymbolTable {
             .symbols.insert(name, symbol);
     }
 
-    /// 获取符号（先查找导入的符号，再查找本地符号）
+    /// 获取符号并记录它被读取过
     pub fn get(&self, name: &str) -> Option<&Symbol> {
+        let symbol = self.lookup(name)?;
+        symbol.used.set(true);
+        Some(symbol)
+    }
+
+    /// 获取符号（先查找导入的符号，再查找本地符号），不记录读取
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
         // 1. 检查是否是导入的符号
         // imported_symbols 现在存储: 别名 -> (模块路径, 原始名)
         if let Some((module_path, original_name)) = self.imported_symbols.get(name) {
@@ -137,34 +336,93 @@ impl SymbolTable {
         None
     }
 
+    /// 枚举顶层和模块级的声明（函数、结构体、类型别名），按模块路径和名称排序
+    pub fn declared_symbols(&self) -> Vec<SymbolInfo> {
+        let to_info = |name: &String, symbol: &Symbol| SymbolInfo {
+            name: name.clone(),
+            kind: symbol.kind,
+            symbol_type: symbol.symbol_type.clone(),
+            visibility: symbol.visibility.clone(),
+            module_path: symbol.module_path.clone(),
+        };
+
+        // 全局作用域中的顶层声明（排除变量和从模块导入的符号）
+        let mut result: Vec<SymbolInfo> = self.scopes.first()
+            .into_iter()
+            .flat_map(|scope| scope.iter())
+            .filter(|(_, symbol)| symbol.kind != SymbolKind::Variable && symbol.module_path.is_empty())
+            .map(|(name, symbol)| to_info(name, symbol))
+            .collect();
+
+        // 模块符号表中的声明
+        for module_symbols in self.modules.values() {
+            result.extend(module_symbols.symbols.iter().map(|(name, symbol)| to_info(name, symbol)));
+        }
+
+        result.sort_by(|a, b| (&a.module_path, &a.name).cmp(&(&b.module_path, &b.name)));
+        result
+    }
+
     /// 进入模块
     pub fn enter_module(&mut self, module_name: String) {
         self.current_module_path.push(module_name);
+        self.module_scope_depths.push(self.scopes.len());
     }
 
     /// 退出模块
     pub fn exit_module(&mut self) {
         self.current_module_path.pop();
+        self.module_scope_depths.pop();
     }
 
-    /// 导入单个符号
-    pub fn import_symbol(&mut self, module_path: Vec<String>, symbol_name: String) {
-        if let Some(module_symbols) = self.modules.get(&module_path) {
-            if let Some(symbol) = module_symbols.symbols.get(&symbol_name) {
-                // 检查可见性
-                if symbol.visibility == crate::ast::Visibility::Public {
-                    // 存储: 别名(=原始名) -> (模块路径, 原始名)
-                    self.imported_symbols.insert(symbol_name.clone(), (module_path.clone(), symbol_name.clone()));
-                    // 也添加到当前作用域
-                    if let Some(scope) = self.scopes.last_mut() {
-                        scope.insert(symbol_name, symbol.clone());
-                    }
-                }
+    /// 是否直接位于模块的顶层作用域（而不是模块内的函数或代码块中）
+    fn at_module_top_level(&self) -> bool {
+        self.module_scope_depths.last().is_some_and(|depth| self.scopes.len() == depth + 1)
+    }
+
+    /// 把模块顶层刚声明的变量登记为模块成员，之后可以通过路径访问
+    fn export_variable(&mut self, name: &str, visibility: crate::ast::Visibility) {
+        if !self.at_module_top_level() {
+            return;
+        }
+        let symbol = match self.scopes.last_mut().and_then(|scope| scope.get_mut(name)) {
+            Some(symbol) => {
+                symbol.visibility = visibility;
+                symbol.clone()
             }
+            None => return,
+        };
+        self.register_module_symbol(name.to_string(), symbol);
+    }
+
+    /// 按路径查找模块中的公开符号：`segments` 的最后一段是符号名，前面是模块路径
+    fn lookup_path(&self, segments: &[String]) -> TypeResult<&Symbol> {
+        let (item_name, module_path) = match segments.split_last() {
+            Some(split) => split,
+            None => return Err(TypeError::UndefinedVariable("empty path".to_string())),
+        };
+
+        let module_symbols = self.modules.get(module_path).ok_or_else(|| {
+            TypeError::UndefinedVariable(format!("module {} not found", module_path.join("::")))
+        })?;
+        let symbol = module_symbols.symbols.get(item_name).ok_or_else(|| {
+            TypeError::UndefinedVariable(format!("{}::{} not found", module_path.join("::"), item_name))
+        })?;
+        // 检查可见性
+        if symbol.visibility != crate::ast::Visibility::Public {
+            return Err(TypeError::UndefinedVariable(
+                format!("{}::{} is private", module_path.join("::"), item_name)
+            ));
         }
+        Ok(symbol)
     }
 
-    /// 导入模块的所有公共符号（通配符导入）
+    /// 导入单个符号
+    pub fn import_symbol(&mut self, module_path: Vec<String>, symbol_name: String) -> TypeResult<()> {
+        self.import_renamed(module_path, symbol_name.clone(), symbol_name)
+    }
+
+    /// 导入模块的所有公共符号（通配符导入，私有符号直接跳过）
     pub fn import_all(&mut self, module_path: Vec<String>) {
         if let Some(module_symbols) = self.modules.get(&module_path) {
             for (name, symbol) in &module_symbols.symbols {
@@ -180,25 +438,32 @@ impl SymbolTable {
     }
 
     /// 导入多个符号
-    pub fn import_multiple(&mut self, module_path: Vec<String>, symbol_names: Vec<String>) {
+    pub fn import_multiple(&mut self, module_path: Vec<String>, symbol_names: Vec<String>) -> TypeResult<()> {
         for symbol_name in symbol_names {
-            self.import_symbol(module_path.clone(), symbol_name);
+            self.import_symbol(module_path.clone(), symbol_name)?;
         }
+        Ok(())
     }
 
     /// 导入并重命名符号
-    pub fn import_renamed(&mut self, module_path: Vec<String>, original_name: String, alias: String) {
+    pub fn import_renamed(&mut self, module_path: Vec<String>, original_name: String, alias: String) -> TypeResult<()> {
         if let Some(module_symbols) = self.modules.get(&module_path) {
             if let Some(symbol) = module_symbols.symbols.get(&original_name) {
-                if symbol.visibility == crate::ast::Visibility::Public {
-                    // 存储: 别名 -> (模块路径, 原始名)
-                    self.imported_symbols.insert(alias.clone(), (module_path.clone(), original_name));
-                    if let Some(scope) = self.scopes.last_mut() {
-                        scope.insert(alias, symbol.clone());
-                    }
+                if symbol.visibility != crate::ast::Visibility::Public {
+                    return Err(TypeError::PrivateImport {
+                        module: module_path.join("::"),
+                        item: original_name,
+                    });
+                }
+
+                // 存储: 别名 -> (模块路径, 原始名)
+                self.imported_symbols.insert(alias.clone(), (module_path.clone(), original_name));
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(alias, symbol.clone());
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -215,6 +480,11 @@ pub struct TypeChecker {
     current_function_return_type: Option<Type>,
     loop_depth: usize,  // 追踪循环嵌套深度
     methods: HashMap<String, HashMap<String, MethodSignature>>,  // type_name -> (method_name -> signature)
+    warn_shadow: bool,  // 是否对改变类型的遮蔽给出警告
+    warnings: Vec<TypeWarning>,
+    max_errors: usize,        // 最多收集的诊断条数
+    dropped_warnings: usize,  // 达到 max_errors 后没有收集的诊断条数
+    max_module_depth: usize,  // 允许的最大模块嵌套层数
 }
 
 impl TypeChecker {
@@ -224,9 +494,56 @@ impl TypeChecker {
             current_function_return_type: None,
             loop_depth: 0,
             methods: HashMap::new(),
+            warn_shadow: false,
+            warnings: Vec::new(),
+            max_errors: DEFAULT_MAX_ERRORS,
+            dropped_warnings: 0,
+            max_module_depth: DEFAULT_MAX_MODULE_DEPTH,
+        }
+    }
+
+    /// 启用后，`let` 以不同类型遮蔽外层作用域的变量时记录警告
+    pub fn set_warn_shadow(&mut self, enabled: bool) {
+        self.warn_shadow = enabled;
+    }
+
+    /// 设置允许的最大模块嵌套层数，超过时报告 ModuleNestingTooDeep 而不是继续递归
+    pub fn set_max_module_depth(&mut self, depth: usize) {
+        self.max_module_depth = depth;
+    }
+
+    /// 设置最多收集的诊断条数，之后的诊断只计数（见 dropped_diagnostics）
+    pub fn set_max_errors(&mut self, max_errors: usize) {
+        self.max_errors = max_errors;
+    }
+
+    /// 检查过程中收集到的警告
+    pub fn warnings(&self) -> &[TypeWarning] {
+        &self.warnings
+    }
+
+    /// 达到 max_errors 后没有收集的诊断条数
+    pub fn dropped_diagnostics(&self) -> usize {
+        self.dropped_warnings
+    }
+
+    /// 记录一条警告；已经收集了 max_errors 条时只计数，避免严重出错的文件产生大量连锁诊断
+    fn push_warning(&mut self, warning: TypeWarning) {
+        if self.warnings.len() < self.max_errors {
+            self.warnings.push(warning);
+        } else {
+            self.dropped_warnings += 1;
         }
     }
 
+    /// 按级别整理的诊断信息；`strict` 为 true 时所有警告都升级为错误
+    pub fn diagnostics(&self, strict: bool) -> Vec<Diagnostic> {
+        let level = if strict { Level::Error } else { Level::Warning };
+        self.warnings.iter()
+            .map(|warning| Diagnostic { level, warning: warning.clone() })
+            .collect()
+    }
+
     /// 获取导入符号映射（别名 -> 原始名）
     /// 返回格式: HashMap<别名, 原始名>
     pub fn get_imported_symbols(&self) -> HashMap<String, String> {
@@ -238,14 +555,201 @@ impl TypeChecker {
         result
     }
 
+    /// 不做类型检查，只从 use 语句收集导入符号映射（别名 -> 原始名），
+    /// 与 check 之后 get_imported_symbols 的结果相同（--no-typecheck 使用）
+    pub fn collect_imported_symbols(program: &Program) -> HashMap<String, String> {
+        fn collect(statements: &[Stmt], program: &[Stmt], result: &mut HashMap<String, String>) {
+            for stmt in statements {
+                match stmt {
+                    Stmt::UseStatement { path, items } => match items {
+                        UseItems::Single(name) => {
+                            result.insert(name.clone(), name.clone());
+                        }
+                        UseItems::Multiple(names) => {
+                            for name in names {
+                                result.insert(name.clone(), name.clone());
+                            }
+                        }
+                        UseItems::Renamed(original, alias) => {
+                            result.insert(alias.clone(), original.clone());
+                        }
+                        UseItems::All => {
+                            for name in public_names(program, path) {
+                                result.insert(name.clone(), name);
+                            }
+                        }
+                    },
+                    Stmt::ModuleDeclaration { statements, .. } => collect(statements, program, result),
+                    _ => {}
+                }
+            }
+        }
+
+        // 按路径找到模块声明，返回其中的公共函数、结构体和类型别名
+        fn public_names(statements: &[Stmt], path: &[String]) -> Vec<String> {
+            let Some((first, rest)) = path.split_first() else {
+                return statements.iter().filter_map(|stmt| match stmt {
+                    Stmt::FnDeclaration { visibility: Visibility::Public, name, .. }
+                    | Stmt::StructDeclaration { visibility: Visibility::Public, name, .. }
+                    | Stmt::TypeAlias { visibility: Visibility::Public, name, .. } => Some(name.clone()),
+                    _ => None,
+                }).collect();
+            };
+            statements.iter().find_map(|stmt| match stmt {
+                Stmt::ModuleDeclaration { name, statements, .. } if name == first => Some(public_names(statements, rest)),
+                _ => None,
+            }).unwrap_or_default()
+        }
+
+        let mut result = HashMap::new();
+        collect(&program.statements, &program.statements, &mut result);
+        result
+    }
+
+    /// 获取程序中声明的所有顶层和模块级符号
+    pub fn symbols(&self) -> Vec<SymbolInfo> {
+        self.symbol_table.declared_symbols()
+    }
+
+    /// 带期望类型的推断（双向类型检查，期望类型来自类型注解、函数参数或返回类型）
+    ///
+    /// - 期望 float 时整数字面量（包括取负）直接推断为 float
+    /// - 空数组字面量本身推断不出元素类型，有期望的数组类型时直接采用它
+    /// - 嵌套的数组字面量逐层使用期望的元素类型，元组字面量逐个使用期望的元素类型
+    /// - if 表达式的两个分支都使用期望类型
+    fn infer_type_with_hint(&mut self, expr: &Expr, expected: &Type) -> TypeResult<Type> {
+        match (expr, self.resolve_type(expected)) {
+            (Expr::Integer(_), Type::Float) => Ok(Type::Float),
+            (Expr::Unary { operator: UnaryOp::Negate, operand }, Type::Float)
+                if matches!(operand.as_ref(), Expr::Integer(_)) => Ok(Type::Float),
+            (Expr::Array { elements }, Type::Array(element) | Type::FixedArray(element, _)) => {
+                self.infer_array_literal(elements, Some(&element))
+            }
+            (Expr::ArrayFill { value, count }, Type::Array(element) | Type::FixedArray(element, _)) => {
+                self.infer_array_fill(value, count, Some(&element))
+            }
+            (Expr::Tuple { elements }, Type::Tuple(element_types)) if elements.len() == element_types.len() => {
+                let mut inferred = Vec::new();
+                for (elem, element_type) in elements.iter().zip(&element_types) {
+                    inferred.push(self.infer_type_with_hint(elem, element_type)?);
+                }
+                Ok(Type::Tuple(inferred))
+            }
+            (Expr::If { condition, then_branch, else_branch }, expected) => {
+                self.infer_if_expression(condition, then_branch, else_branch, Some(&expected))
+            }
+            _ => self.infer_type(expr),
+        }
+    }
+
+    /// 推断数组字面量的类型（所有元素必须同类型）
+    fn infer_array_literal(&mut self, elements: &[Expr], element_hint: Option<&Type>) -> TypeResult<Type> {
+        if elements.is_empty() {
+            // 没有期望类型时空数组的元素类型未知
+            return Ok(match element_hint {
+                Some(hint) => Type::Array(Box::new(self.resolve_type(hint))),
+                None => Type::Unknown,
+            });
+        }
+
+        let first_type = self.infer_element_type(&elements[0], element_hint)?;
+        for elem in elements.iter().skip(1) {
+            let elem_type = self.infer_element_type(elem, element_hint)?;
+            // 数组要求严格的类型匹配，不允许类型自动转换
+            if first_type != elem_type && elem_type != Type::Unknown && first_type != Type::Unknown {
+                return Err(TypeError::TypeMismatch {
+                    expected: first_type,
+                    found: elem_type,
+                    location: "array literal".to_string(),
+                });
+            }
+        }
+
+        Ok(Type::Array(Box::new(first_type)))
+    }
+
+    /// 推断 [value; count] 的类型：count 必须是整数，结果是元素类型的数组
+    fn infer_array_fill(&mut self, value: &Expr, count: &Expr, element_hint: Option<&Type>) -> TypeResult<Type> {
+        let count_type = self.infer_type(count)?;
+        if count_type != Type::Int && count_type != Type::Unknown {
+            return Err(TypeError::TypeMismatch {
+                expected: Type::Int,
+                found: count_type,
+                location: "array fill count".to_string(),
+            });
+        }
+        let element_type = self.infer_element_type(value, element_hint)?;
+        let element_type = Self::ensure_value(value, element_type, "array fill value".to_string())?;
+        Ok(Type::Array(Box::new(element_type)))
+    }
+
+    fn infer_element_type(&mut self, elem: &Expr, element_hint: Option<&Type>) -> TypeResult<Type> {
+        match element_hint {
+            Some(hint) => self.infer_type_with_hint(elem, hint),
+            None => self.infer_type(elem),
+        }
+    }
+
+    /// 期望类型为定长数组时，把数组字面量的类型细化为带长度的 [T; N]
+    ///
+    /// 数组字面量本身只推断出 [T]，长度约束由这里结合期望类型补上，
+    /// 嵌套的字面量会逐层细化，之后交给 is_compatible_with 比较长度。
+    fn sized_literal_type(&self, expected: &Type, expr: &Expr, actual: Type) -> Type {
+        let actual = self.resolve_type(&actual);
+        let (expected_element, elements) = match (expected, expr) {
+            (Type::FixedArray(element, _), Expr::Array { elements }) => (element, elements),
+            // 字面量个数的 [value; N] 长度在编译时已知
+            (Type::FixedArray(element, _), Expr::ArrayFill { value, count }) => {
+                let Expr::Integer(n) = count.as_ref() else { return actual };
+                let inferred_element = match &actual {
+                    Type::Array(inner) => (**inner).clone(),
+                    _ => (**element).clone(),
+                };
+                let element_type = self.sized_literal_type(element, value, inferred_element);
+                return Type::FixedArray(Box::new(element_type), (*n).max(0) as usize);
+            }
+            _ => return actual,
+        };
+
+        let inferred_element = match &actual {
+            Type::Array(element) => (**element).clone(),
+            _ => (**expected_element).clone(),
+        };
+        let element_types: Vec<Type> = elements
+            .iter()
+            .map(|e| self.sized_literal_type(expected_element, e, inferred_element.clone()))
+            .collect();
+        // 优先报告第一个不匹配的元素，否则沿用第一个元素的类型
+        let element_type = element_types
+            .iter()
+            .find(|t| !expected_element.is_compatible_with(t))
+            .or_else(|| element_types.first())
+            .cloned()
+            .unwrap_or(inferred_element);
+
+        Type::FixedArray(Box::new(element_type), elements.len())
+    }
+
     /// 解析类型（将Named类型解析为实际类型）
     fn resolve_type(&self, t: &Type) -> Type {
+        self.resolve_type_in(t, &mut Vec::new())
+    }
+
+    /// `resolving` 是正在展开的名字：再次遇到时保持名字不展开，
+    /// 这样引用自身的结构体（`struct Node { next: [Node] }`）不会无限递归
+    fn resolve_type_in(&self, t: &Type, resolving: &mut Vec<String>) -> Type {
         match t {
             Type::Named(name) => {
+                if resolving.contains(name) {
+                    return t.clone();
+                }
                 // 查找符号表中的类型别名或结构体定义
                 if let Some(symbol) = self.symbol_table.get(name) {
                     // 递归解析，防止链式别名
-                    self.resolve_type(&symbol.symbol_type)
+                    resolving.push(name.clone());
+                    let resolved = self.resolve_type_in(&symbol.symbol_type, resolving);
+                    resolving.pop();
+                    resolved
                 } else {
                     // 如果找不到定义，保持原样（后续会报错）
                     t.clone()
@@ -253,24 +757,34 @@ impl TypeChecker {
             }
             Type::Array(element_type) => {
                 // 递归解析数组元素类型
-                Type::Array(Box::new(self.resolve_type(element_type)))
+                Type::Array(Box::new(self.resolve_type_in(element_type, resolving)))
+            }
+            Type::FixedArray(element_type, length) => {
+                Type::FixedArray(Box::new(self.resolve_type_in(element_type, resolving)), *length)
+            }
+            Type::Tuple(element_types) => {
+                // 递归解析元组元素类型
+                Type::Tuple(element_types.iter().map(|t| self.resolve_type_in(t, resolving)).collect())
             }
             Type::Function(func_type) => {
                 // 递归解析函数参数和返回类型
                 let params = func_type.params.iter()
-                    .map(|p| self.resolve_type(p))
+                    .map(|p| self.resolve_type_in(p, resolving))
                     .collect();
-                let return_type = Box::new(self.resolve_type(&func_type.return_type));
+                let return_type = Box::new(self.resolve_type_in(&func_type.return_type, resolving));
                 Type::Function(FunctionType { params, return_type })
             }
             Type::Struct(struct_type) => {
-                // 递归解析结构体字段类型
+                // 递归解析结构体字段类型；字段中对结构体自身的引用保持为名字，
+                // 这样无论从结构体名还是别名出发，展开的结果都相同
+                resolving.push(struct_type.name.clone());
                 let fields = struct_type.fields.iter()
                     .map(|f| crate::ast::StructField {
                         name: f.name.clone(),
-                        field_type: self.resolve_type(&f.field_type),
+                        field_type: self.resolve_type_in(&f.field_type, resolving),
                     })
                     .collect();
+                resolving.pop();
                 Type::Struct(crate::ast::StructType {
                     name: struct_type.name.clone(),
                     fields,
@@ -281,50 +795,406 @@ impl TypeChecker {
         }
     }
 
+    /// 类型中是否出现了指定名字的 Named 类型（结构体有自己的名字，不检查字段）
+    fn mentions_name(t: &Type, name: &str) -> bool {
+        match t {
+            Type::Named(n) => n == name,
+            Type::Array(element_type) | Type::FixedArray(element_type, _) => Self::mentions_name(element_type, name),
+            Type::Tuple(element_types) => element_types.iter().any(|t| Self::mentions_name(t, name)),
+            Type::Function(func_type) => {
+                func_type.params.iter().any(|t| Self::mentions_name(t, name))
+                    || Self::mentions_name(&func_type.return_type, name)
+            }
+            _ => false,
+        }
+    }
+
+    /// 查找列表中第一个重复的名字，返回 (名字, 首次出现的位置, 重复出现的位置)
+    fn find_duplicate<'a>(names: impl Iterator<Item = (&'a String, Span)>) -> Option<(String, Span, Span)> {
+        let mut seen: HashMap<&String, Span> = HashMap::new();
+        for (name, span) in names {
+            if let Some(&first) = seen.get(name) {
+                return Some((name.clone(), first, span));
+            }
+            seen.insert(name, span);
+        }
+        None
+    }
+
+    /// 检查函数（或方法）的参数名是否重复
+    fn check_duplicate_parameters(function: &str, parameters: &[Parameter]) -> TypeResult<()> {
+        match Self::find_duplicate(parameters.iter().map(|p| (&p.name, p.span))) {
+            Some((parameter, first, second)) => Err(TypeError::DuplicateParameter {
+                function: function.to_string(),
+                parameter,
+                first,
+                second,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// 检查循环的 else 分支：按普通语句块处理，其中的 break/continue 属于外层循环
+    fn check_loop_else(&mut self, else_branch: &Option<Vec<Stmt>>) -> TypeResult<()> {
+        if let Some(else_stmts) = else_branch {
+            self.symbol_table.push_scope();
+            for stmt in else_stmts {
+                self.check_statement(stmt)?;
+            }
+            self.pop_scope();
+        }
+        Ok(())
+    }
+
+    /// 按绑定模式定义变量（元组模式逐个元素解构）
+    fn define_pattern(&mut self, pattern: &Pattern, var_type: Type, is_mutable: bool) -> TypeResult<()> {
+        match pattern {
+            // `_` 只丢弃值，不注册为可查找的符号
+            Pattern::Identifier(name) if name == "_" => Ok(()),
+            Pattern::Identifier(name) => {
+                let shadowed = self.symbol_table.define(name.clone(), var_type.clone(), is_mutable);
+                // 同类型的遮蔽是常见写法，不提示；类型未知时无法判断是否改变
+                if let Some(outer_type) = shadowed {
+                    let differs = outer_type != Type::Unknown && var_type != Type::Unknown
+                        && self.resolve_type(&outer_type) != self.resolve_type(&var_type);
+                    if self.warn_shadow && differs {
+                        self.push_warning(TypeWarning::ShadowedWithDifferentType {
+                            name: name.clone(),
+                            outer_type,
+                            new_type: var_type,
+                        });
+                    }
+                }
+                Ok(())
+            }
+            Pattern::Tuple(elements) => {
+                let element_types = match self.resolve_type(&var_type) {
+                    Type::Tuple(types) if types.len() == elements.len() => types,
+                    Type::Unknown => vec![Type::Unknown; elements.len()],
+                    other => {
+                        return Err(TypeError::TypeMismatch {
+                            expected: Type::Tuple(vec![Type::Unknown; elements.len()]),
+                            found: other,
+                            location: format!("tuple destructuring '{}'", pattern),
+                        });
+                    }
+                };
+
+                for (element, element_type) in elements.iter().zip(element_types) {
+                    self.define_pattern(element, element_type, is_mutable)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 语句序列中是否有带值的 return（不进入嵌套的函数声明）
+    fn returns_value(body: &[Stmt]) -> bool {
+        body.iter().any(|stmt| match stmt {
+            Stmt::Return { value } => value.is_some(),
+            Stmt::If { then_branch, else_branch, .. } => {
+                Self::returns_value(then_branch) || else_branch.as_deref().is_some_and(Self::returns_value)
+            }
+            Stmt::While { body, else_branch, .. } | Stmt::For { body, else_branch, .. } => {
+                Self::returns_value(body) || else_branch.as_deref().is_some_and(Self::returns_value)
+            }
+            Stmt::Block { statements } => Self::returns_value(statements),
+            _ => false,
+        })
+    }
+
+    /// 错误位置的描述，附上出错表达式的源码（由格式化器重新生成，例如 "if condition `x + 1`"）
+    fn describe(location: &str, expr: &Expr) -> String {
+        format!("{} `{}`", location, crate::formatter::format_expression(expr))
+    }
+
+    /// 检查用作值的表达式：void 函数的调用结果不能用于 let、参数、运算等需要值的位置
+    fn ensure_value(expr: &Expr, expr_type: Type, location: impl Into<String>) -> TypeResult<Type> {
+        let function = match expr {
+            Expr::Call { callee, .. } => match callee.as_ref() {
+                Expr::Identifier(name, _) => name.clone(),
+                _ => "<function>".to_string(),
+            },
+            Expr::MethodCall { method, .. } => method.clone(),
+            _ => return Ok(expr_type),
+        };
+        if expr_type == Type::Void {
+            return Err(TypeError::VoidValue { function, location: location.into() });
+        }
+        Ok(expr_type)
+    }
+
+    /// 检查内置函数调用（见 `crate::builtins::BUILTINS`），name 不是内置函数时返回 None
+    /// `format` 的格式字符串是字面量时，在这里检查占位符数量
+    fn check_builtin_call(&mut self, name: &str, arguments: &[Expr]) -> Option<TypeResult<Type>> {
+        let builtin = crate::builtins::lookup(name)?;
+        if !builtin.accepts_count(arguments.len()) {
+            return Some(Err(TypeError::ArgumentCountMismatch {
+                expected: builtin.params.len(),
+                found: arguments.len(),
+                function: name.to_string(),
+            }));
+        }
+        let params: Vec<Type> = (0..arguments.len()).map(|i| builtin.param_type(i)).collect();
+        let result = self.check_builtin_arguments(name, arguments, &params).and_then(|_| {
+            if let ("format", Some(Expr::String(template))) = (name, arguments.first()) {
+                let pieces = crate::vm::format::parse(template).map_err(TypeError::InvalidFormat)?;
+                let placeholders = crate::vm::format::placeholder_count(&pieces);
+                if placeholders != arguments.len() - 1 {
+                    return Err(TypeError::ArgumentCountMismatch {
+                        expected: placeholders + 1,
+                        found: arguments.len(),
+                        function: name.to_string(),
+                    });
+                }
+            }
+            match &builtin.returns {
+                BuiltinReturn::Fixed(return_type) => Ok(return_type.clone()),
+                BuiltinReturn::SameAsArgument => self.infer_type(&arguments[0]),
+            }
+        });
+        Some(result)
+    }
+
+    /// 检查字符串和数组的内置方法，不是内置方法时返回 None（impl 块中的同名方法优先）
+    /// - `s.split(sep) -> [string]`：按分隔符拆分，分隔符为空字符串时拆成单个字符
+    /// - `s.replace(from, to) -> string`：替换所有出现的子串
+    /// - `s.contains(sub) -> bool`
+    /// - `arr.join(sep) -> string`：数组元素必须是字符串
+    /// - `builder.append(value)`：把值的文本追加到构建器末尾，构建器必须是 var 声明的变量，没有返回值
+    /// - `builder.build() -> string`：构建器当前的内容
+    fn check_builtin_method(&mut self, object: &Expr, obj_type: &Type, method: &str, arguments: &[Expr]) -> Option<TypeResult<Type>> {
+        let name = format!("{}.{}", obj_type, method);
+        let result = match (obj_type, method) {
+            (Type::String, "split") => self.check_builtin_arguments(&name, arguments, &[Type::String])
+                .map(|_| Type::Array(Box::new(Type::String))),
+            (Type::String, "replace") => self.check_builtin_arguments(&name, arguments, &[Type::String, Type::String])
+                .map(|_| Type::String),
+            (Type::String, "contains") => self.check_builtin_arguments(&name, arguments, &[Type::String])
+                .map(|_| Type::Bool),
+            (Type::Array(element_type) | Type::FixedArray(element_type, _), "join") => {
+                let element_type = self.resolve_type(element_type);
+                if element_type != Type::String && element_type != Type::Unknown {
+                    return Some(Err(TypeError::TypeMismatch {
+                        expected: Type::Array(Box::new(Type::String)),
+                        found: obj_type.clone(),
+                        location: "receiver of 'join'".to_string(),
+                    }));
+                }
+                self.check_builtin_arguments("join", arguments, &[Type::String]).map(|_| Type::String)
+            }
+            (Type::StringBuilder, "append") => self.check_append_receiver(object)
+                .and_then(|_| self.check_builtin_arguments(&name, arguments, &[Type::Unknown]))
+                .map(|_| Type::Void),
+            (Type::StringBuilder, "build") => self.check_builtin_arguments(&name, arguments, &[]).map(|_| Type::String),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// append 原地修改变量中的构建器，接收者必须是可变变量（不能是字段、数组元素或调用结果）
+    fn check_append_receiver(&self, object: &Expr) -> TypeResult<()> {
+        let name = match object {
+            Expr::Identifier(name, _) => name,
+            _ => {
+                return Err(TypeError::InvalidOperation {
+                    operator: format!("append to {}", crate::formatter::format_expression(object)),
+                    left_type: Type::StringBuilder,
+                    right_type: Type::Unknown,
+                })
+            }
+        };
+        match self.symbol_table.lookup(name) {
+            Some(symbol) if !symbol.is_mutable => Err(TypeError::ImmutableAssignment { variable: name.clone() }),
+            _ => Ok(()),
+        }
+    }
+
+    /// 按参数类型列表检查内置函数的参数（Unknown 表示接受任意值，Float 接受任意数字）
+    fn check_builtin_arguments(&mut self, name: &str, arguments: &[Expr], params: &[Type]) -> TypeResult<()> {
+        if arguments.len() != params.len() {
+            return Err(TypeError::ArgumentCountMismatch {
+                expected: params.len(),
+                found: arguments.len(),
+                function: name.to_string(),
+            });
+        }
+        for (i, (param_type, arg)) in params.iter().zip(arguments).enumerate() {
+            let arg_type = self.infer_type(arg)?;
+            let arg_type = Self::ensure_value(arg, arg_type, format!("argument {} of '{}'", i + 1, name))?;
+            let accepted = match param_type {
+                Type::Unknown => true,
+                Type::Float => arg_type.is_numeric(),
+                expected => &arg_type == expected,
+            };
+            if !accepted && arg_type != Type::Unknown {
+                return Err(TypeError::ArgumentTypeMismatch {
+                    expected: param_type.clone(),
+                    found: arg_type,
+                    argument: i + 1,
+                    function: name.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// impl 块是否为该类型定义了指定的方法
+    fn has_method(&self, obj_type: &Type, method: &str) -> bool {
+        obj_type.impl_name()
+            .and_then(|type_name| self.methods.get(&type_name))
+            .is_some_and(|type_methods| type_methods.contains_key(method))
+    }
+
+    /// 字段访问的结果类型
+    fn field_type(&self, obj_type: Type, field: &str, operator: &str) -> TypeResult<Type> {
+        match self.resolve_type(&obj_type) {
+            Type::Struct(struct_type) => {
+                for f in &struct_type.fields {
+                    if f.name == field {
+                        return Ok(f.field_type.clone());
+                    }
+                }
+                Err(TypeError::UndefinedVariable(format!("Field {} not found", field)))
+            }
+            // 数组和字符串只有 length 属性
+            Type::Array(_) | Type::FixedArray(..) | Type::String if field == "length" => Ok(Type::Int),
+            Type::Array(_) | Type::FixedArray(..) | Type::String => Err(TypeError::UndefinedVariable(
+                format!("property '{}' not found on {}; only 'length' is available", field, obj_type)
+            )),
+            _ => Err(TypeError::InvalidOperation {
+                operator: operator.to_string(),
+                left_type: obj_type,
+                right_type: Type::Unknown,
+            }),
+        }
+    }
+
     /// 检查程序
     pub fn check(&mut self, program: &Program) -> TypeResult<()> {
         for stmt in &program.statements {
             self.check_statement(stmt)?;
         }
+        self.warn_unused();
         Ok(())
     }
 
+    /// 为当前作用域中未使用的变量和函数记录警告
+    fn warn_unused(&mut self) {
+        for (name, kind) in self.symbol_table.unused_in_scope() {
+            self.push_warning(match kind {
+                SymbolKind::Function => TypeWarning::UnusedFunction { name },
+                _ => TypeWarning::UnusedVariable { name },
+            });
+        }
+    }
+
+    /// 推断 if 表达式的类型；有期望类型时两个分支的值都按期望类型推断
+    fn infer_if_expression(
+        &mut self,
+        condition: &Expr,
+        then_branch: &ValueBlock,
+        else_branch: &ValueBlock,
+        hint: Option<&Type>,
+    ) -> TypeResult<Type> {
+        let cond_type = self.infer_type(condition)?;
+        if cond_type != Type::Bool && cond_type != Type::Unknown {
+            return Err(TypeError::TypeMismatch {
+                expected: Type::Bool,
+                found: cond_type,
+                location: Self::describe("if condition", condition),
+            });
+        }
+
+        let then_type = self.infer_value_block(then_branch, hint)?;
+        let else_type = self.infer_value_block(else_branch, hint)?;
+        // 与数组字面量相同，两个分支必须同类型，不做数值类型提升
+        match (then_type, else_type) {
+            (Type::Unknown, other) | (other, Type::Unknown) => Ok(other),
+            (then_type, else_type) if then_type == else_type => Ok(then_type),
+            (then_type, else_type) => Err(TypeError::TypeMismatch {
+                expected: then_type,
+                found: else_type,
+                location: "if expression branches".to_string(),
+            }),
+        }
+    }
+
+    /// 检查 if 表达式的一个分支，返回分支的值类型
+    fn infer_value_block(&mut self, block: &ValueBlock, hint: Option<&Type>) -> TypeResult<Type> {
+        if let Some(kind) = block.unsupported_statement() {
+            return Err(TypeError::StatementInIfExpression(kind));
+        }
+        self.symbol_table.push_scope();
+        for stmt in &block.statements {
+            self.check_statement(stmt)?;
+        }
+        let value_type = self.infer_element_type(&block.value, hint)?;
+        let value_type = Self::ensure_value(&block.value, value_type, "if expression branch")?;
+        self.pop_scope();
+        Ok(value_type)
+    }
+
+    /// 离开作用域，先检查其中未使用的声明
+    fn pop_scope(&mut self) {
+        self.warn_unused();
+        self.symbol_table.pop_scope();
+    }
+
     /// 检查语句
     fn check_statement(&mut self, stmt: &Stmt) -> TypeResult<()> {
         match stmt {
-            Stmt::StructDeclaration { visibility, name, fields } => {
+            Stmt::StructDeclaration { visibility, name, fields, field_spans } => {
+                let names = fields.iter().map(|f| &f.name).zip(field_spans.iter().copied());
+                if let Some((field, first, second)) = Self::find_duplicate(names) {
+                    return Err(TypeError::DuplicateField { struct_name: name.clone(), field, first, second });
+                }
+
                 // 注册结构体类型
                 let struct_type = Type::Struct(crate::ast::StructType {
                     name: name.clone(),
                     fields: fields.clone(),
                 });
-                self.symbol_table.define_with_visibility(name.clone(), struct_type, false, visibility.clone());
+                self.symbol_table.define_with_visibility(name.clone(), SymbolKind::Struct, struct_type, false, visibility.clone());
                 Ok(())
             }
 
             Stmt::TypeAlias { visibility, name, target_type } => {
-                // 注册类型别名
-                self.symbol_table.define_with_visibility(name.clone(), target_type.clone(), false, visibility.clone());
+                // 在定义处解析目标类型，这样公共别名引用的模块内私有类型
+                // 在通过 use 导入到模块外之后仍然可以解析
+                let resolved_target = self.resolve_type(target_type);
+                // 解析后仍然引用自身说明别名构成了循环（例如 `type A = A;`、`type A = B; type B = A;`）
+                if Self::mentions_name(&resolved_target, name) {
+                    return Err(TypeError::CyclicTypeAlias(name.clone()));
+                }
+                // 注册类型别名（公共别名会注册为模块符号，可被 use 导入）
+                self.symbol_table.define_with_visibility(name.clone(), SymbolKind::TypeAlias, resolved_target, false, visibility.clone());
                 Ok(())
             }
 
             Stmt::ImplBlock { type_name, methods } => {
-                // 验证类型存在
-                if self.symbol_table.get(type_name).is_none() {
-                    return Err(TypeError::UndefinedVariable(format!("Type {} not found", type_name)));
-                }
+                // 验证类型存在（基本类型总是存在）
+                let self_type = match Type::from_impl_name(type_name) {
+                    Type::Named(_) => match self.symbol_table.get(type_name) {
+                        Some(symbol) => symbol.symbol_type.clone(),
+                        None => return Err(TypeError::UndefinedVariable(format!("Type {} not found", type_name))),
+                    },
+                    primitive => primitive,
+                };
 
                 // 注册所有方法
                 let mut method_map = HashMap::new();
 
                 for method in methods {
-                    // 构建方法签名（不包含 self 参数）
+                    Self::check_duplicate_parameters(&format!("{}.{}", type_name, method.name), &method.parameters)?;
+
+                    // 构建方法签名（不包含 self 参数），签名中的 Self 指实现的类型
                     let param_types: Vec<Type> = method.parameters
                         .iter()
-                        .map(|p| p.type_annotation.clone().unwrap_or(Type::Unknown))
+                        .map(|p| p.type_annotation.as_ref().map_or(Type::Unknown, |t| t.replace_self(type_name)))
                         .collect();
 
-                    let ret_type = method.return_type.clone().unwrap_or(Type::Void);
+                    let ret_type = method.return_type.as_ref().map_or(Type::Void, |t| t.replace_self(type_name));
 
                     method_map.insert(
                         method.name.clone(),
@@ -334,19 +1204,16 @@ impl TypeChecker {
                         },
                     );
 
-                    // 检查方法体
+                    // 检查方法体（保存外层的返回类型，方法体结束后恢复）
                     self.symbol_table.push_scope();
-                    self.current_function_return_type = Some(ret_type);
+                    let enclosing_return_type = self.current_function_return_type.replace(ret_type);
 
                     // 添加 self 参数到作用域
-                    if let Some(symbol) = self.symbol_table.get(type_name) {
-                        self.symbol_table.define("self".to_string(), symbol.symbol_type.clone(), false);
-                    }
+                    self.symbol_table.define("self".to_string(), self_type.clone(), false);
 
                     // 添加其他参数到作用域
-                    for param in &method.parameters {
-                        let param_type = param.type_annotation.clone().unwrap_or(Type::Unknown);
-                        self.symbol_table.define(param.name.clone(), param_type, false);
+                    for (param, param_type) in method.parameters.iter().zip(&param_types) {
+                        self.symbol_table.define(param.name.clone(), param_type.clone(), false);
                     }
 
                     // 检查方法体
@@ -354,8 +1221,8 @@ impl TypeChecker {
                         self.check_statement(stmt)?;
                     }
 
-                    self.symbol_table.pop_scope();
-                    self.current_function_return_type = None;
+                    self.pop_scope();
+                    self.current_function_return_type = enclosing_return_type;
                 }
 
                 // 注册方法到方法表
@@ -370,21 +1237,35 @@ impl TypeChecker {
             }
 
             Stmt::VarDeclaration {
-                name,
+                visibility,
+                pattern,
                 mutable,
                 type_annotation,
                 initializer,
             } => {
-                let actual_type = if let Some(init) = initializer {
-                    self.infer_type(init)?
-                } else {
-                    Type::Null
+                let actual_type = match (initializer, type_annotation) {
+                    (Some(init), Some(annotated_type)) => self.infer_type_with_hint(init, annotated_type)?,
+                    (Some(Expr::Array { elements }), None) if elements.is_empty() => {
+                        return Err(TypeError::CannotInferType(format!(
+                            "cannot infer the element type of empty array '{}'; add a type annotation such as `let {}: [int] = [];`",
+                            pattern, pattern
+                        )));
+                    }
+                    (Some(init), None) => self.infer_type(init)?,
+                    (None, _) => Type::Null,
+                };
+                let actual_type = match initializer {
+                    Some(init) => Self::ensure_value(init, actual_type, format!("variable declaration '{}'", pattern))?,
+                    None => actual_type,
                 };
 
                 let var_type = if let Some(annotated_type) = type_annotation {
                     // 解析类型注解（处理类型别名）
                     let resolved_annotated = self.resolve_type(annotated_type);
-                    let resolved_actual = self.resolve_type(&actual_type);
+                    let resolved_actual = match initializer {
+                        Some(init) => self.sized_literal_type(&resolved_annotated, init, actual_type),
+                        None => self.resolve_type(&actual_type),
+                    };
 
                     // 检查类型注解和初始化值是否匹配
                     if let Some(_init) = initializer {
@@ -392,7 +1273,7 @@ impl TypeChecker {
                             return Err(TypeError::TypeMismatch {
                                 expected: resolved_annotated.clone(),
                                 found: resolved_actual,
-                                location: format!("variable declaration '{}'", name),
+                                location: format!("variable declaration '{}'", pattern),
                             });
                         }
                     }
@@ -402,7 +1283,10 @@ impl TypeChecker {
                     actual_type
                 };
 
-                self.symbol_table.define(name.clone(), var_type, *mutable);
+                self.define_pattern(pattern, var_type, *mutable)?;
+                if let Pattern::Identifier(name) = pattern {
+                    self.symbol_table.export_variable(name, visibility.clone());
+                }
                 Ok(())
             }
 
@@ -413,13 +1297,18 @@ impl TypeChecker {
                 return_type,
                 body,
             } => {
+                Self::check_duplicate_parameters(name, parameters)?;
+
                 // 构建函数类型
                 let param_types: Vec<Type> = parameters
                     .iter()
                     .map(|p| p.type_annotation.clone().unwrap_or(Type::Unknown))
                     .collect();
 
-                let ret_type = return_type.clone().unwrap_or(Type::Unknown);
+                // 没有返回类型注解时：函数体中有带值的 return 则返回类型未知，否则为 void
+                let ret_type = return_type.clone().unwrap_or_else(|| {
+                    if Self::returns_value(body) { Type::Unknown } else { Type::Void }
+                });
 
                 let function_type = Type::Function(FunctionType {
                     params: param_types.clone(),
@@ -427,11 +1316,11 @@ impl TypeChecker {
                 });
 
                 // 注册函数（带可见性）
-                self.symbol_table.define_with_visibility(name.clone(), function_type, false, visibility.clone());
+                self.symbol_table.define_with_visibility(name.clone(), SymbolKind::Function, function_type, false, visibility.clone());
 
-                // 检查函数体
+                // 检查函数体（嵌套函数结束后要恢复外层函数的返回类型）
                 self.symbol_table.push_scope();
-                self.current_function_return_type = Some(ret_type);
+                let enclosing_return_type = self.current_function_return_type.replace(ret_type);
 
                 // 添加参数到作用域
                 for param in parameters {
@@ -444,21 +1333,24 @@ impl TypeChecker {
                     self.check_statement(stmt)?;
                 }
 
-                self.current_function_return_type = None;
-                self.symbol_table.pop_scope();
+                self.current_function_return_type = enclosing_return_type;
+                self.pop_scope();
                 Ok(())
             }
 
             Stmt::Return { value } => {
-                let return_type = if let Some(expr) = value {
-                    self.infer_type(expr)?
-                } else {
-                    Type::Void
+                let return_type = match (value, self.current_function_return_type.clone()) {
+                    (Some(expr), Some(expected_type)) => self.infer_type_with_hint(expr, &expected_type)?,
+                    (Some(expr), None) => self.infer_type(expr)?,
+                    (None, _) => Type::Void,
                 };
 
                 if let Some(expected_type) = &self.current_function_return_type {
                     let resolved_expected = self.resolve_type(expected_type);
-                    let resolved_return = self.resolve_type(&return_type);
+                    let resolved_return = match value {
+                        Some(expr) => self.sized_literal_type(&resolved_expected, expr, return_type),
+                        None => self.resolve_type(&return_type),
+                    };
 
                     if resolved_expected != Type::Unknown
                         && resolved_return != Type::Unknown
@@ -484,7 +1376,7 @@ impl TypeChecker {
                     return Err(TypeError::TypeMismatch {
                         expected: Type::Bool,
                         found: cond_type,
-                        location: "if condition".to_string(),
+                        location: Self::describe("if condition", condition),
                     });
                 }
 
@@ -492,26 +1384,26 @@ impl TypeChecker {
                 for stmt in then_branch {
                     self.check_statement(stmt)?;
                 }
-                self.symbol_table.pop_scope();
+                self.pop_scope();
 
                 if let Some(else_stmts) = else_branch {
                     self.symbol_table.push_scope();
                     for stmt in else_stmts {
                         self.check_statement(stmt)?;
                     }
-                    self.symbol_table.pop_scope();
+                    self.pop_scope();
                 }
 
                 Ok(())
             }
 
-            Stmt::While { condition, body } => {
+            Stmt::While { condition, body, else_branch } => {
                 let cond_type = self.infer_type(condition)?;
                 if cond_type != Type::Bool && cond_type != Type::Unknown {
                     return Err(TypeError::TypeMismatch {
                         expected: Type::Bool,
                         found: cond_type,
-                        location: "while condition".to_string(),
+                        location: Self::describe("while condition", condition),
                     });
                 }
 
@@ -520,10 +1412,10 @@ impl TypeChecker {
                 for stmt in body {
                     self.check_statement(stmt)?;
                 }
-                self.symbol_table.pop_scope();
+                self.pop_scope();
                 self.loop_depth -= 1;
 
-                Ok(())
+                self.check_loop_else(else_branch)
             }
 
             Stmt::For {
@@ -531,37 +1423,49 @@ impl TypeChecker {
                 start,
                 end,
                 body,
+                else_branch,
             } => {
                 let start_type = self.infer_type(start)?;
                 let end_type = self.infer_type(end)?;
 
-                if start_type != Type::Int && start_type != Type::Unknown {
+                // 范围可以是整数或字符，两端类型必须一致
+                let range_type = if start_type == Type::Char || end_type == Type::Char {
+                    Type::Char
+                } else {
+                    Type::Int
+                };
+
+                if start_type != range_type && start_type != Type::Unknown {
                     return Err(TypeError::TypeMismatch {
-                        expected: Type::Int,
+                        expected: range_type,
                         found: start_type,
-                        location: "for loop start".to_string(),
+                        location: Self::describe("for loop start", start),
                     });
                 }
 
-                if end_type != Type::Int && end_type != Type::Unknown {
+                if end_type != range_type && end_type != Type::Unknown {
                     return Err(TypeError::TypeMismatch {
-                        expected: Type::Int,
+                        expected: range_type,
                         found: end_type,
-                        location: "for loop end".to_string(),
+                        location: Self::describe("for loop end", end),
                     });
                 }
 
                 self.loop_depth += 1;
                 self.symbol_table.push_scope();
-                self.symbol_table.define(variable.clone(), Type::Int, true);
+                // `for _ in ...` 不绑定循环变量
+                if variable != "_" {
+                    self.symbol_table.define(variable.clone(), range_type, true);
+                }
 
                 for stmt in body {
                     self.check_statement(stmt)?;
                 }
 
-                self.symbol_table.pop_scope();
+                self.pop_scope();
                 self.loop_depth -= 1;
-                Ok(())
+
+                self.check_loop_else(else_branch)
             }
 
             Stmt::Break => {
@@ -588,11 +1492,17 @@ impl TypeChecker {
                 for stmt in statements {
                     self.check_statement(stmt)?;
                 }
-                self.symbol_table.pop_scope();
+                self.pop_scope();
                 Ok(())
             }
 
             Stmt::ModuleDeclaration { name, statements, is_public: _ } => {
+                if self.symbol_table.current_module_path.len() >= self.max_module_depth {
+                    let mut path = self.symbol_table.current_module_path.clone();
+                    path.push(name.clone());
+                    return Err(TypeError::ModuleNestingTooDeep { module: path.join("::"), limit: self.max_module_depth });
+                }
+
                 // 进入模块命名空间
                 self.symbol_table.enter_module(name.clone());
                 self.symbol_table.push_scope();
@@ -602,18 +1512,17 @@ impl TypeChecker {
                     self.check_statement(stmt)?;
                 }
 
+                // 模块项可以通过路径从外部访问，不检查未使用
                 self.symbol_table.pop_scope();
                 self.symbol_table.exit_module();
                 Ok(())
             }
 
             Stmt::UseStatement { path, items } => {
-                use crate::ast::UseItems;
-
                 match items {
                     UseItems::Single(name) => {
                         // 单项导入: use module::item
-                        self.symbol_table.import_symbol(path.clone(), name.clone());
+                        self.symbol_table.import_symbol(path.clone(), name.clone())?;
                     }
                     UseItems::All => {
                         // 通配符导入: use module::*
@@ -621,11 +1530,11 @@ impl TypeChecker {
                     }
                     UseItems::Multiple(names) => {
                         // 多项导入: use module::{item1, item2}
-                        self.symbol_table.import_multiple(path.clone(), names.clone());
+                        self.symbol_table.import_multiple(path.clone(), names.clone())?;
                     }
                     UseItems::Renamed(original, alias) => {
                         // 重命名导入: use module::item as alias
-                        self.symbol_table.import_renamed(path.clone(), original.clone(), alias.clone());
+                        self.symbol_table.import_renamed(path.clone(), original.clone(), alias.clone())?;
                     }
                 }
                 Ok(())
@@ -644,15 +1553,28 @@ impl TypeChecker {
     /// 推断表达式类型
     fn infer_type(&mut self, expr: &Expr) -> TypeResult<Type> {
         match expr {
-            Expr::StructLiteral { struct_name, fields } => {
+            Expr::StructLiteral { struct_name, fields, base, .. } => {
                 // 查找结构体类型
                 if let Some(symbol) = self.symbol_table.get(struct_name) {
                     let struct_type = self.resolve_type(&symbol.symbol_type);
 
                     // 验证字段
                     if let Type::Struct(ref struct_def) = struct_type {
+                        // 更新语法：base 必须是同一个结构体类型，由它提供未列出的字段
+                        if let Some(base_expr) = base {
+                            let base_type = self.infer_type(base_expr)?;
+                            let same_struct = matches!(&base_type, Type::Struct(base_def) if base_def.name == struct_def.name);
+                            if !same_struct && base_type != Type::Unknown {
+                                return Err(TypeError::TypeMismatch {
+                                    expected: struct_type.clone(),
+                                    found: base_type,
+                                    location: format!("base of struct {} update", struct_name),
+                                });
+                            }
+                        }
+
                         // 检查字段数量
-                        if fields.len() != struct_def.fields.len() {
+                        if base.is_none() && fields.len() != struct_def.fields.len() {
                             return Err(TypeError::TypeMismatch {
                                 expected: struct_type.clone(),
                                 found: Type::Unknown,
@@ -661,14 +1583,13 @@ impl TypeChecker {
                             });
                         }
 
-                        // 检查每个字段的类型
+                        // 检查每个字段的类型（字段值按声明的字段类型推断）
                         for (field_name, field_expr) in fields {
-                            let field_type = self.infer_type(field_expr)?;
-
                             // 查找字段定义
                             let field_def = struct_def.fields.iter().find(|f| &f.name == field_name);
                             if let Some(def) = field_def {
                                 let expected_type = self.resolve_type(&def.field_type);
+                                let field_type = self.infer_type_with_hint(field_expr, &expected_type)?;
                                 if !field_type.is_compatible_with(&expected_type) {
                                     return Err(TypeError::TypeMismatch {
                                         expected: expected_type,
@@ -690,26 +1611,21 @@ impl TypeChecker {
                 }
             }
 
-            Expr::FieldAccess { object, field } => {
+            Expr::FieldAccess { object, field, .. } => {
+                let obj_type = self.infer_type(object)?;
+                self.field_type(obj_type, field, "field access")
+            }
+
+            Expr::OptionalFieldAccess { object, field, .. } => {
+                // 对象为 null 时结果为 null；没有可选类型，否则就是字段本身的类型
                 let obj_type = self.infer_type(object)?;
                 match obj_type {
-                    Type::Struct(struct_type) => {
-                        for f in &struct_type.fields {
-                            if &f.name == field {
-                                return Ok(f.field_type.clone());
-                            }
-                        }
-                        Err(TypeError::UndefinedVariable(format!("Field {} not found", field)))
-                    }
-                    _ => Err(TypeError::InvalidOperation {
-                        operator: "field access".to_string(),
-                        left_type: obj_type,
-                        right_type: Type::Unknown,
-                    }),
+                    Type::Null => Ok(Type::Null),
+                    _ => self.field_type(obj_type, field, "optional field access"),
                 }
             }
 
-            Expr::FieldAssign { object, field, value } => {
+            Expr::FieldAssign { object, field, value, .. } => {
                 let obj_type = self.infer_type(object)?;
                 let val_type = self.infer_type(value)?;
                 match obj_type {
@@ -744,48 +1660,47 @@ impl TypeChecker {
             Expr::String(_) => Ok(Type::String),
             Expr::Boolean(_) => Ok(Type::Bool),
             Expr::Char(_) => Ok(Type::Char),
+            Expr::Null => Ok(Type::Null),
 
-            Expr::Identifier(name) => {
+            Expr::Identifier(name, _) => {
                 if let Some(symbol) = self.symbol_table.get(name) {
                     Ok(symbol.symbol_type.clone())
+                } else if name == "_" {
+                    Err(TypeError::UndefinedVariable("'_' discards a value and cannot be used as an expression".to_string()))
                 } else {
                     Err(TypeError::UndefinedVariable(name.clone()))
                 }
             }
 
-            Expr::Path { segments } => {
+            Expr::Path { segments, .. } => {
                 // 路径表达式: module::item 或 module::submodule::item
                 // segments = ["math", "geometry", "area"]
+                Ok(self.symbol_table.lookup_path(segments)?.symbol_type.clone())
+            }
 
-                if segments.is_empty() {
-                    return Err(TypeError::UndefinedVariable("empty path".to_string()));
-                }
+            Expr::PathAssign { segments, value, .. } => {
+                let path = segments.join("::");
+                let value_type = self.infer_type(value)?;
+                let value_type = Self::ensure_value(value, value_type, format!("assignment to variable '{}'", path))?;
 
-                // 最后一个段是实际的符号名，前面的是模块路径
-                let item_name = &segments[segments.len() - 1];
-                let module_path: Vec<String> = segments[..segments.len() - 1].to_vec();
+                let symbol = self.symbol_table.lookup_path(segments)?;
+                if symbol.kind != SymbolKind::Variable || !symbol.is_mutable {
+                    return Err(TypeError::ImmutableAssignment { variable: path });
+                }
 
-                // 查找模块中的符号
-                if let Some(module_symbols) = self.symbol_table.modules.get(&module_path) {
-                    if let Some(symbol) = module_symbols.symbols.get(item_name) {
-                        // 检查可见性
-                        if symbol.visibility == crate::ast::Visibility::Public {
-                            Ok(symbol.symbol_type.clone())
-                        } else {
-                            Err(TypeError::UndefinedVariable(
-                                format!("{}::{} is private", module_path.join("::"), item_name)
-                            ))
-                        }
-                    } else {
-                        Err(TypeError::UndefinedVariable(
-                            format!("{}::{} not found", module_path.join("::"), item_name)
-                        ))
-                    }
-                } else {
-                    Err(TypeError::UndefinedVariable(
-                        format!("module {} not found", module_path.join("::"))
-                    ))
+                let resolved_symbol = self.resolve_type(&symbol.symbol_type.clone());
+                let resolved_value = self.resolve_type(&value_type);
+                if resolved_symbol != Type::Unknown
+                    && resolved_value != Type::Unknown
+                    && !resolved_symbol.is_compatible_with(&resolved_value) {
+                    return Err(TypeError::TypeMismatch {
+                        expected: resolved_symbol,
+                        found: resolved_value,
+                        location: format!("assignment to variable '{}'", path),
+                    });
                 }
+
+                Ok(value_type)
             }
 
             Expr::Binary {
@@ -794,12 +1709,34 @@ impl TypeChecker {
                 right,
             } => {
                 let left_type = self.infer_type(left)?;
+                let left_type = Self::ensure_value(left, left_type, format!("left operand of {:?}", operator))?;
                 let right_type = self.infer_type(right)?;
+                let right_type = Self::ensure_value(right, right_type, format!("right operand of {:?}", operator))?;
 
                 match operator {
                     BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
+                        // 字符串与任意类型相加：另一侧会被转换为字符串
+                        if operator == &BinaryOp::Add
+                            && (left_type == Type::String || right_type == Type::String)
+                        {
+                            Ok(Type::String)
+                        // 数组拼接：元素类型必须相同（空数组的元素类型未知，可与任意数组拼接）
+                        // 定长数组拼接后长度不再固定，结果总是普通数组
+                        } else if let (BinaryOp::Add, Some(left_elem), Some(right_elem)) =
+                            (operator, left_type.get_element_type(), right_type.get_element_type())
+                        {
+                            match (left_elem, right_elem) {
+                                (Type::Unknown, r) => Ok(Type::Array(Box::new(r.clone()))),
+                                (l, Type::Unknown) => Ok(Type::Array(Box::new(l.clone()))),
+                                (l, r) if l == r => Ok(Type::Array(Box::new(l.clone()))),
+                                _ => Err(TypeError::InvalidOperation {
+                                    operator: format!("{:?}", operator),
+                                    left_type,
+                                    right_type,
+                                }),
+                            }
                         // 允许Unknown类型参与运算
-                        if left_type == Type::Unknown || right_type == Type::Unknown {
+                        } else if left_type == Type::Unknown || right_type == Type::Unknown {
                             Ok(Type::Unknown)
                         } else if left_type.is_numeric() && right_type.is_numeric() {
                             // 如果有一个是float，结果是float
@@ -808,11 +1745,6 @@ impl TypeChecker {
                             } else {
                                 Ok(Type::Int)
                             }
-                        } else if operator == &BinaryOp::Add
-                            && left_type == Type::String
-                            && right_type == Type::String
-                        {
-                            Ok(Type::String)
                         } else {
                             Err(TypeError::InvalidOperation {
                                 operator: format!("{:?}", operator),
@@ -836,6 +1768,22 @@ impl TypeChecker {
                         }
                     }
 
+                    BinaryOp::Power => {
+                        if left_type == Type::Unknown || right_type == Type::Unknown {
+                            Ok(Type::Unknown)
+                        } else if left_type == Type::Int && right_type == Type::Int {
+                            Ok(Type::Int)
+                        } else if left_type.is_numeric() && right_type.is_numeric() {
+                            Ok(Type::Float)
+                        } else {
+                            Err(TypeError::InvalidOperation {
+                                operator: "power".to_string(),
+                                left_type,
+                                right_type,
+                            })
+                        }
+                    }
+
                     BinaryOp::Equal
                     | BinaryOp::NotEqual
                     | BinaryOp::Less
@@ -861,6 +1809,7 @@ impl TypeChecker {
 
             Expr::Unary { operator, operand } => {
                 let operand_type = self.infer_type(operand)?;
+                let operand_type = Self::ensure_value(operand, operand_type, format!("operand of {:?}", operator))?;
 
                 match operator {
                     UnaryOp::Not => {
@@ -870,7 +1819,7 @@ impl TypeChecker {
                             Err(TypeError::TypeMismatch {
                                 expected: Type::Bool,
                                 found: operand_type,
-                                location: "unary not operator".to_string(),
+                                location: Self::describe("unary not operator", operand),
                             })
                         }
                     }
@@ -881,17 +1830,19 @@ impl TypeChecker {
                             Err(TypeError::TypeMismatch {
                                 expected: Type::Int,
                                 found: operand_type,
-                                location: "unary negate operator".to_string(),
+                                location: Self::describe("unary negate operator", operand),
                             })
                         }
                     }
                 }
             }
 
-            Expr::Assign { name, value } => {
+            Expr::Assign { name, value, .. } => {
                 let value_type = self.infer_type(value)?;
+                let value_type = Self::ensure_value(value, value_type, format!("assignment to variable '{}'", name))?;
 
-                if let Some(symbol) = self.symbol_table.get(name) {
+                // 赋值不算读取
+                if let Some(symbol) = self.symbol_table.lookup(name) {
                     // 检查可变性
                     if !symbol.is_mutable {
                         return Err(TypeError::ImmutableAssignment {
@@ -920,72 +1871,95 @@ impl TypeChecker {
             }
 
             Expr::Call { callee, arguments } => {
-                // 获取被调用函数的类型
-                if let Expr::Identifier(func_name) = callee.as_ref() {
-                    if let Some(symbol) = self.symbol_table.get(func_name) {
-                        if let Type::Function(func_type) = &symbol.symbol_type {
-                            // 检查参数数量
-                            if func_type.params.len() != arguments.len() {
-                                return Err(TypeError::ArgumentCountMismatch {
-                                    expected: func_type.params.len(),
-                                    found: arguments.len(),
-                                    function: func_name.clone(),
-                                });
+                // 推断被调用者的类型（标识符、路径、索引、字段等任意表达式）
+                let func_name = match callee.as_ref() {
+                    Expr::Identifier(name, _) => {
+                        if self.symbol_table.get(name).is_none() {
+                            // 没有用户定义的同名符号时才是内置函数
+                            if let Some(result) = self.check_builtin_call(name, arguments) {
+                                return result;
                             }
+                            return Err(TypeError::UndefinedFunction(name.clone()));
+                        }
+                        name.clone()
+                    }
+                    Expr::Path { segments, .. } => segments.join("::"),
+                    _ => "<expression>".to_string(),
+                };
+                let callee_type = self.infer_type(callee)?;
+
+                match self.resolve_type(&callee_type) {
+                    Type::Function(func_type) => {
+                        // 检查参数数量
+                        if func_type.params.len() != arguments.len() {
+                            return Err(TypeError::ArgumentCountMismatch {
+                                expected: func_type.params.len(),
+                                found: arguments.len(),
+                                function: func_name,
+                            });
+                        }
 
-                            // 克隆函数类型以避免借用冲突
-                            let params = func_type.params.clone();
-                            let return_type = *func_type.return_type.clone();
-
-                            // 检查每个参数的类型
-                            for (i, (param_type, arg)) in
-                                params.iter().zip(arguments.iter()).enumerate()
-                            {
-                                let arg_type = self.infer_type(arg)?;
-                                let resolved_param = self.resolve_type(param_type);
-                                let resolved_arg = self.resolve_type(&arg_type);
-
-                                if !resolved_param.is_compatible_with(&resolved_arg) {
-                                    return Err(TypeError::ArgumentTypeMismatch {
-                                        expected: resolved_param,
-                                        found: resolved_arg,
-                                        argument: i + 1,
-                                        function: func_name.clone(),
-                                    });
-                                }
+                        // 检查每个参数的类型
+                        for (i, (param_type, arg)) in
+                            func_type.params.iter().zip(arguments.iter()).enumerate()
+                        {
+                            let arg_type = self.infer_type_with_hint(arg, param_type)?;
+                            let arg_type = Self::ensure_value(arg, arg_type, format!("argument {} of '{}'", i + 1, func_name))?;
+                            let resolved_param = self.resolve_type(param_type);
+                            let resolved_arg = self.sized_literal_type(&resolved_param, arg, arg_type);
+
+                            if !resolved_param.is_compatible_with(&resolved_arg) {
+                                return Err(TypeError::ArgumentTypeMismatch {
+                                    expected: resolved_param,
+                                    found: resolved_arg,
+                                    argument: i + 1,
+                                    function: func_name,
+                                });
                             }
+                        }
 
-                            // 返回函数的返回类型
-                            Ok(return_type)
-                        } else {
-                            Err(TypeError::TypeMismatch {
-                                expected: Type::Function(FunctionType {
-                                    params: vec![],
-                                    return_type: Box::new(Type::Unknown),
-                                }),
-                                found: symbol.symbol_type.clone(),
-                                location: format!("function call '{}'", func_name),
-                            })
+                        // 返回函数的返回类型
+                        Ok(*func_type.return_type)
+                    }
+                    // 类型未知的值（如没有类型注解的参数）可能是函数，只检查参数本身
+                    Type::Unknown => {
+                        for (i, arg) in arguments.iter().enumerate() {
+                            let arg_type = self.infer_type(arg)?;
+                            Self::ensure_value(arg, arg_type, format!("argument {} of '{}'", i + 1, func_name))?;
                         }
-                    } else {
-                        Err(TypeError::UndefinedFunction(func_name.clone()))
+                        Ok(Type::Unknown)
                     }
-                } else {
-                    // 对于非标识符调用（如高阶函数），返回Unknown
-                    Ok(Type::Unknown)
+                    found_type => Err(TypeError::NotCallable { found_type }),
                 }
             }
 
-            Expr::MethodCall { object, method, arguments } => {
+            Expr::MethodCall { object, method, arguments, .. } => {
                 // 获取对象的类型
                 let obj_type = self.infer_type(object)?;
                 let obj_type = self.resolve_type(&obj_type);
 
-                // 根据对象类型查找方法
-                let type_name = match &obj_type {
-                    Type::Struct(struct_type) => struct_type.name.clone(),
-                    Type::Named(name) => name.clone(),
-                    _ => {
+                // 所有值都有 to_string()（impl 块中自定义的 to_string 优先）
+                if method == "to_string" && !self.has_method(&obj_type, method) {
+                    if !arguments.is_empty() {
+                        return Err(TypeError::ArgumentCountMismatch {
+                            expected: 0,
+                            found: arguments.len(),
+                            function: "to_string".to_string(),
+                        });
+                    }
+                    return Ok(Type::String);
+                }
+
+                if !self.has_method(&obj_type, method) {
+                    if let Some(result) = self.check_builtin_method(object, &obj_type, method, arguments) {
+                        return result;
+                    }
+                }
+
+                // 根据对象类型查找方法（结构体或基本类型）
+                let type_name = match obj_type.impl_name() {
+                    Some(name) => name,
+                    None => {
                         return Err(TypeError::InvalidOperation {
                             operator: "method call".to_string(),
                             left_type: obj_type,
@@ -1012,9 +1986,10 @@ impl TypeChecker {
 
                 // 检查每个参数的类型
                 for (i, (param_type, arg)) in method_sig.params.iter().zip(arguments.iter()).enumerate() {
-                    let arg_type = self.infer_type(arg)?;
+                    let arg_type = self.infer_type_with_hint(arg, param_type)?;
+                    let arg_type = Self::ensure_value(arg, arg_type, format!("argument {} of '{}.{}'", i + 1, type_name, method))?;
                     let resolved_param = self.resolve_type(param_type);
-                    let resolved_arg = self.resolve_type(&arg_type);
+                    let resolved_arg = self.sized_literal_type(&resolved_param, arg, arg_type);
 
                     if !resolved_param.is_compatible_with(&resolved_arg) && resolved_arg != Type::Unknown {
                         return Err(TypeError::ArgumentTypeMismatch {
@@ -1030,27 +2005,33 @@ impl TypeChecker {
                 Ok(method_sig.return_type.clone())
             }
 
-            Expr::Array { elements } => {
-                if elements.is_empty() {
-                    // 空数组需要类型注解，这里返回Unknown
-                    Ok(Type::Unknown)
-                } else {
-                    // 推断数组元素类型（所有元素必须同类型）
-                    let first_type = self.infer_type(&elements[0])?;
-                    
-                    for elem in elements.iter().skip(1) {
-                        let elem_type = self.infer_type(elem)?;
-                        // 数组要求严格的类型匹配，不允许类型自动转换
-                        if first_type != elem_type && elem_type != Type::Unknown && first_type != Type::Unknown {
-                            return Err(TypeError::TypeMismatch {
-                                expected: first_type,
-                                found: elem_type,
-                                location: "array literal".to_string(),
-                            });
-                        }
+            Expr::Array { elements } => self.infer_array_literal(elements, None),
+            Expr::ArrayFill { value, count } => self.infer_array_fill(value, count, None),
+
+            Expr::Tuple { elements } => {
+                // 元组允许异构元素，逐个推断
+                let mut element_types = Vec::new();
+                for elem in elements {
+                    element_types.push(self.infer_type(elem)?);
+                }
+                Ok(Type::Tuple(element_types))
+            }
+
+            Expr::TupleIndex { object, index } => {
+                let obj_type = self.infer_type(object)?;
+                match self.resolve_type(&obj_type) {
+                    Type::Tuple(element_types) => {
+                        element_types.get(*index).cloned().ok_or(TypeError::TupleIndexOutOfRange {
+                            index: *index,
+                            len: element_types.len(),
+                        })
                     }
-                    
-                    Ok(Type::Array(Box::new(first_type)))
+                    Type::Unknown => Ok(Type::Unknown),
+                    other => Err(TypeError::InvalidOperation {
+                        operator: "tuple index".to_string(),
+                        left_type: other,
+                        right_type: Type::Int,
+                    }),
                 }
             }
 
@@ -1063,7 +2044,7 @@ impl TypeChecker {
                     return Err(TypeError::TypeMismatch {
                         expected: Type::Int,
                         found: idx_type,
-                        location: "array index".to_string(),
+                        location: Self::describe("array index", index),
                     });
                 }
                 
@@ -1075,7 +2056,39 @@ impl TypeChecker {
                 }
             }
             
-            Expr::IndexAssign { object, index, value } => {
+            Expr::If { condition, then_branch, else_branch } => {
+                self.infer_if_expression(condition, then_branch, else_branch, None)
+            }
+
+            Expr::Slice { object, start, end } => {
+                let obj_type = self.infer_type(object)?;
+                for bound in [start, end] {
+                    let bound_type = self.infer_type(bound)?;
+                    if bound_type != Type::Int && bound_type != Type::Unknown {
+                        return Err(TypeError::TypeMismatch {
+                            expected: Type::Int,
+                            found: bound_type,
+                            location: "slice range".to_string(),
+                        });
+                    }
+                }
+
+                // 字符串切片得到子串，数组切片得到长度不固定的数组
+                match self.resolve_type(&obj_type) {
+                    Type::String => Ok(Type::String),
+                    Type::Unknown => Ok(Type::Unknown),
+                    resolved => match resolved.get_element_type() {
+                        Some(element_type) => Ok(Type::Array(Box::new(element_type.clone()))),
+                        None => Err(TypeError::InvalidOperation {
+                            operator: "slice".to_string(),
+                            left_type: obj_type,
+                            right_type: Type::Int,
+                        }),
+                    },
+                }
+            }
+
+            Expr::IndexAssign { object, index, value, .. } => {
                 let obj_type = self.infer_type(object)?;
                 let idx_type = self.infer_type(index)?;
                 let val_type = self.infer_type(value)?;
@@ -1085,7 +2098,7 @@ impl TypeChecker {
                     return Err(TypeError::TypeMismatch {
                         expected: Type::Int,
                         found: idx_type,
-                        location: "array index".to_string(),
+                        location: Self::describe("array index", index),
                     });
                 }
                 
@@ -1118,6 +2131,7 @@ impl Default for TypeChecker {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::Visibility;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
 
@@ -1156,4 +2170,876 @@ mod tests {
         let mut checker = TypeChecker::new();
         assert!(checker.check(&program).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_type_check_imported_type_alias() {
+        let input = "
+            mod units {
+                type Raw = int;
+                pub type Meters = Raw;
+            }
+            use units::Meters;
+            let distance: Meters = 42;
+        ";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(checker.check(&program).is_ok());
+
+        // 导入的别名仍然参与类型检查
+        let input = "mod units { pub type Meters = int; } use units::Meters; let distance: Meters = \"far\";";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(checker.check(&program).is_err());
+    }
+
+    #[test]
+    fn test_type_check_private_import() {
+        let input = "mod m { fn secret() -> int { return 1; } } use m::secret;";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        match checker.check(&program) {
+            Err(TypeError::PrivateImport { module, item }) => {
+                assert_eq!(module, "m");
+                assert_eq!(item, "secret");
+            }
+            other => panic!("Expected PrivateImport error, got {:?}", other),
+        }
+
+        // 重命名导入和多项导入同样检查可见性
+        for input in [
+            "mod m { fn secret() -> int { return 1; } } use m::secret as s;",
+            "mod m { pub fn open() {} fn secret() {} } use m::{open, secret};",
+        ] {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+
+            let mut checker = TypeChecker::new();
+            assert!(matches!(checker.check(&program), Err(TypeError::PrivateImport { .. })));
+        }
+    }
+
+    #[test]
+    fn test_type_check_nested_glob_import() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+        let modules = "mod a { pub mod b { pub fn inc(x: int) -> int { return x + 1; } pub type Count = int; fn hidden() {} } }";
+
+        // 通配符前的多段路径指向嵌套模块，导入它的所有公共项
+        assert!(check(&format!("{} use a::b::*; let n: Count = inc(1);", modules)).is_none());
+        // 私有项不会被通配符导入
+        let result = check(&format!("{} use a::b::*; hidden();", modules));
+        assert!(matches!(result, Some(TypeError::UndefinedFunction(ref name)) if name == "hidden"));
+        // 外层模块的通配符不导入嵌套模块中的项
+        assert!(check(&format!("{} use a::*; inc(1);", modules)).is_some());
+    }
+
+    #[test]
+    fn test_declared_symbols() {
+        let input = "
+            pub fn area(w: int, h: int) -> int { return w * h; }
+            struct Point { x: int, y: int };
+            mod geo { pub type Meters = float; fn helper() {} }
+            let unused = 1;
+        ";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        checker.check(&program).unwrap();
+
+        let symbols: Vec<(String, SymbolKind, Visibility, Vec<String>)> = checker.symbols()
+            .into_iter()
+            .map(|s| (s.name, s.kind, s.visibility, s.module_path))
+            .collect();
+        assert_eq!(symbols, vec![
+            ("Point".to_string(), SymbolKind::Struct, Visibility::Private, vec![]),
+            ("area".to_string(), SymbolKind::Function, Visibility::Public, vec![]),
+            ("Meters".to_string(), SymbolKind::TypeAlias, Visibility::Public, vec!["geo".to_string()]),
+            ("helper".to_string(), SymbolKind::Function, Visibility::Private, vec!["geo".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn test_type_check_tuple_destructuring() {
+        let input = "let pair: (int, (string, float)) = (1, (\"two\", 3.0)); let (a, (b, c)) = pair; let s: string = b;";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(checker.check(&program).is_ok());
+
+        // 模式元素个数与元组长度不一致
+        let input = "let (a, b) = (1, 2, 3);";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(checker.check(&program).is_err());
+
+        // 下标访问：超出长度时报告下标和元组长度
+        let input = "let pair = (1, \"two\"); let s: string = pair.1; let x = pair.2;";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(matches!(checker.check(&program), Err(TypeError::TupleIndexOutOfRange { index: 2, len: 2 })));
+    }
+
+    #[test]
+    fn test_type_check_string_concat_with_any() {
+        let input = "let a: string = \"x=\" + 5; let b: string = 5 + \"!\"; let c: int = 2 + 3;";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(checker.check(&program).is_ok());
+
+        // 非字符串之间的 + 仍然是算术运算
+        let input = "let d: string = 2 + 3;";
+        let mut lexer = Lexer::new(input.to_string());
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse().unwrap();
+
+        let mut checker = TypeChecker::new();
+        assert!(checker.check(&program).is_err());
+    }
+
+    #[test]
+    fn test_type_check_struct_update() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).is_ok()
+        };
+
+        let decls = "struct Point { x: int, y: int }; struct Size { w: int, h: int }; let p = Point { x: 1, y: 2 }; let s = Size { w: 1, h: 2 };";
+        assert!(check(&format!("{} let q = Point {{ x: 10, ..p }};", decls)));
+        // base 必须是同一个结构体类型
+        assert!(!check(&format!("{} let q = Point {{ x: 10, ..s }};", decls)));
+        // 列出的字段仍然要检查类型
+        assert!(!check(&format!("{} let q = Point {{ x: \"ten\", ..p }};", decls)));
+    }
+
+    #[test]
+    fn test_type_check_char_range() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        // 循环变量的类型为 char
+        assert!(check("var d = 'q'; for c in 'a'..'z' { d = c; }").is_none());
+        assert!(check("for c in 'a'..'z' { let n: int = c; }").is_some());
+        // 两端类型必须一致
+        let result = check("for c in 'a'..10 { }");
+        assert!(matches!(result, Some(TypeError::TypeMismatch { expected: Type::Char, found: Type::Int, .. })));
+    }
+
+    #[test]
+    fn test_type_check_array_concat() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a: [int] = [1, 2] + [3];").is_none());
+        assert!(check("let a: [int] = [] + [3];").is_none());
+        // 元素类型不同的数组不能拼接
+        let result = check("let a = [1, 2] + [\"x\"];");
+        assert!(matches!(result, Some(TypeError::InvalidOperation { .. })));
+        // 数组与非数组也不能相加
+        assert!(check("let a = [1] + 2;").is_some());
+    }
+
+    #[test]
+    fn test_type_check_fixed_array_length() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a: [int; 3] = [1, 2, 3];").is_none());
+        assert!(check("let a: [int; 2 * 2] = [1, 2, 3, 4]; let x: int = a[0];").is_none());
+        // 字面量长度与声明不符
+        let result = check("let a: [int; 3] = [1, 2];");
+        assert!(matches!(
+            result,
+            Some(TypeError::TypeMismatch { expected: Type::FixedArray(_, 3), found: Type::FixedArray(_, 2), .. })
+        ));
+        assert!(check("let a: [int; 1] = [];").is_some());
+        // 嵌套数组逐层检查长度
+        assert!(check("let m: [[int; 2]; 2] = [[1, 2], [3, 4]];").is_none());
+        assert!(check("let m: [[int; 2]; 2] = [[1, 2], [3]];").is_some());
+        // 参数和返回值同样检查
+        assert!(check("fn f(a: [int; 2]) -> int { return a[0]; } f([1, 2, 3]);").is_some());
+        assert!(check("fn f() -> [int; 2] { return [1]; }").is_some());
+    }
+
+    #[test]
+    fn test_type_check_array_fill() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a: [int] = [0; 5]; let n = 3; let b: [string] = [\"x\"; n * 2];").is_none());
+        assert!(check("let a: [float] = [0; 2]; let m: [[int; 2]; 3] = [[0; 2]; 3];").is_none());
+        // 字面量个数参与定长数组的长度检查
+        assert!(matches!(
+            check("let a: [int; 3] = [0; 4];"),
+            Some(TypeError::TypeMismatch { expected: Type::FixedArray(_, 3), found: Type::FixedArray(_, 4), .. })
+        ));
+        assert!(check("let a: [string] = [0; 5];").is_some());
+        assert!(matches!(
+            check("let a = [0; \"5\"];"),
+            Some(TypeError::TypeMismatch { expected: Type::Int, found: Type::String, .. })
+        ));
+    }
+
+    #[test]
+    fn test_type_check_if_expression() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a = 1; let b = 2; let m: int = if a > b { a } else { b };").is_none());
+        assert!(check("var n = 0; let s: string = if true { n += 1; \"x\" } else { \"y\" };").is_none());
+        // 分支中不能声明变量，跳出分支的 break/continue 也不支持（与编译器一致）
+        assert!(matches!(
+            check("let s: string = if true { let t = \"x\"; t } else { \"y\" };"),
+            Some(TypeError::StatementInIfExpression("variable declarations"))
+        ));
+        assert!(matches!(
+            check("let m = if true { fn f() {} 1 } else { 2 };"),
+            Some(TypeError::StatementInIfExpression("variable declarations"))
+        ));
+        assert!(matches!(
+            check("while true { let m = if true { break; 1 } else { 2 }; }"),
+            Some(TypeError::StatementInIfExpression("`break` and `continue`"))
+        ));
+        assert!(check("let m = if true { while true { break; } 1 } else { 2 };").is_none());
+        assert!(matches!(
+            check("let m = if true { 1 } else { \"one\" };"),
+            Some(TypeError::TypeMismatch { expected: Type::Int, found: Type::String, location })
+                if location == "if expression branches"
+        ));
+        assert!(matches!(
+            check("let m: string = if true { 1 } else { 2 };"),
+            Some(TypeError::TypeMismatch { expected: Type::String, found: Type::Int, .. })
+        ));
+        assert!(matches!(
+            check("let m = if 1 { 1 } else { 2 };"),
+            Some(TypeError::TypeMismatch { expected: Type::Bool, found: Type::Int, .. })
+        ));
+    }
+
+    #[test]
+    fn test_type_error_quotes_source() {
+        let location_of = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            match TypeChecker::new().check(&program) {
+                Err(TypeError::TypeMismatch { location, .. }) => location,
+                other => panic!("Expected TypeMismatch, got {:?}", other),
+            }
+        };
+
+        assert_eq!(location_of("let x = 1; if x + 1 { print(x); }"), "if condition `x + 1`");
+        assert_eq!(location_of("let x = 1; while x * 2 {}"), "while condition `x * 2`");
+        assert_eq!(location_of("let m = if 1 - 1 { 1 } else { 2 };"), "if condition `1 - 1`");
+        assert_eq!(location_of("for i in \"a\"..10 {}"), "for loop start `\"a\"`");
+        assert_eq!(location_of("let xs = [1, 2]; let y = xs[true || false];"), "array index `true || false`");
+        assert_eq!(location_of("let y = !(1 + 2);"), "unary not operator `1 + 2`");
+    }
+
+    #[test]
+    fn test_type_check_cyclic_type_alias() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        for (input, alias) in [
+            ("type A = A; let x: A = 1;", "A"),
+            ("type A = B; type B = A; let x: B = 1;", "B"),
+            ("type List = [List];", "List"),
+        ] {
+            assert!(
+                matches!(check(input), Some(TypeError::CyclicTypeAlias(ref name)) if name == alias),
+                "{}", input
+            );
+        }
+
+        // 通过结构体引用自身不是循环别名
+        assert!(check("struct Node { value: int, next: [Node] }; type N = Node;
+                       let leaf: N = Node { value: 2, next: [] };
+                       let n = Node { value: 1, next: [leaf] }; let v: int = n.next[0].value;").is_none());
+    }
+
+    #[test]
+    fn test_type_check_module_nesting_limit() {
+        // depth 层内联模块：mod m0 { mod m1 { ... pub let x = 1; } }
+        let nested = |depth: usize| {
+            let mut source: String = (0..depth).map(|i| format!("mod m{} {{ ", i)).collect();
+            source.push_str("pub let x = 1;");
+            source.push_str(&" }".repeat(depth));
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            Parser::new(tokens).parse().unwrap()
+        };
+        let check = |program: &Program, limit: Option<usize>| {
+            let mut type_checker = TypeChecker::new();
+            if let Some(limit) = limit {
+                type_checker.set_max_module_depth(limit);
+            }
+            type_checker.check(program).err()
+        };
+
+        assert!(check(&nested(DEFAULT_MAX_MODULE_DEPTH), None).is_none());
+        assert!(matches!(
+            check(&nested(DEFAULT_MAX_MODULE_DEPTH + 1), None),
+            Some(TypeError::ModuleNestingTooDeep { ref module, limit: DEFAULT_MAX_MODULE_DEPTH })
+                if module.ends_with(&format!("::m{}", DEFAULT_MAX_MODULE_DEPTH))
+        ));
+
+        // 限制可以调整，报告第一个超出限制的模块
+        assert!(check(&nested(3), Some(3)).is_none());
+        assert!(matches!(
+            check(&nested(4), Some(3)),
+            Some(TypeError::ModuleNestingTooDeep { ref module, limit: 3 }) if module == "m0::m1::m2::m3"
+        ));
+    }
+
+    #[test]
+    fn test_type_check_string_methods() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let parts: [string] = \"a,b\".split(\",\"); let s: string = parts.join(\"-\");").is_none());
+        assert!(check("let r: string = \"ab\".replace(\"a\", \"b\"); let c: bool = r.contains(\"b\");").is_none());
+        assert!(matches!(
+            check("let s = [1, 2].join(\",\");"),
+            Some(TypeError::TypeMismatch { location, .. }) if location == "receiver of 'join'"
+        ));
+        assert!(matches!(
+            check("let s = \"a\".split(1);"),
+            Some(TypeError::ArgumentTypeMismatch { expected: Type::String, found: Type::Int, .. })
+        ));
+        assert!(matches!(
+            check("let s = \"a\".replace(\"a\");"),
+            Some(TypeError::ArgumentCountMismatch { expected: 2, found: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_type_check_string_builder() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("var sb = string_builder(); sb.append(\"a\"); sb.append(1); let s: string = sb.build();").is_none());
+        assert!(check("fn f() -> string { var b: StringBuilder = string_builder(); b.append('c'); return b.build(); }").is_none());
+
+        // 构建器不是字符串，append 没有返回值
+        assert!(matches!(
+            check("var sb = string_builder(); let s: string = sb;"),
+            Some(TypeError::TypeMismatch { expected: Type::String, found: Type::StringBuilder, .. })
+        ));
+        assert!(matches!(
+            check("var sb = string_builder(); let v = sb.append(\"a\");"),
+            Some(TypeError::VoidValue { ref function, .. }) if function == "append"
+        ));
+
+        // append 原地修改变量：接收者必须是 var 声明的变量
+        assert!(matches!(
+            check("let sb = string_builder(); sb.append(\"a\");"),
+            Some(TypeError::ImmutableAssignment { ref variable }) if variable == "sb"
+        ));
+        assert!(matches!(
+            check("var builders = [string_builder()]; builders[0].append(\"a\");"),
+            Some(TypeError::InvalidOperation { left_type: Type::StringBuilder, .. })
+        ));
+        assert!(matches!(check("let s = \"a\".build();"), Some(TypeError::UndefinedFunction(_))));
+    }
+
+    #[test]
+    fn test_type_check_path_assignment() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+        let module = "mod config { pub var level = 1; pub let name = \"cfg\"; var hidden = 2; pub fn get() -> int { return level; } }";
+
+        assert!(check(&format!("{} config::level = 5; config::level += 1; let n: int = config::level;", module)).is_none());
+        assert!(matches!(
+            check(&format!("{} config::name = \"x\";", module)),
+            Some(TypeError::ImmutableAssignment { variable }) if variable == "config::name"
+        ));
+        assert!(matches!(
+            check(&format!("{} config::get = 1;", module)),
+            Some(TypeError::ImmutableAssignment { variable }) if variable == "config::get"
+        ));
+        assert!(matches!(
+            check(&format!("{} config::hidden = 3;", module)),
+            Some(TypeError::UndefinedVariable(message)) if message == "config::hidden is private"
+        ));
+        assert!(matches!(
+            check(&format!("{} config::level = \"high\";", module)),
+            Some(TypeError::TypeMismatch { expected: Type::Int, found: Type::String, .. })
+        ));
+        // 只有模块顶层的变量是模块成员，函数里的局部变量不是
+        assert!(matches!(
+            check("mod m { pub fn f() { var local = 1; } } m::local = 2;"),
+            Some(TypeError::UndefinedVariable(message)) if message == "m::local not found"
+        ));
+    }
+
+    #[test]
+    fn test_type_check_duplicate_names() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        // 位置指向两次声明的名字
+        let result = check("struct P {\n    x: int,\n    y: int,\n    x: float\n};");
+        assert!(matches!(
+            result,
+            Some(TypeError::DuplicateField { ref struct_name, ref field, first, second })
+                if struct_name == "P" && field == "x" && first == Span::new(2, 5) && second == Span::new(4, 5)
+        ));
+        assert_eq!(
+            result.unwrap().to_string(),
+            "field 'x' of struct 'P' is declared twice: first at 2:5, again at 4:5"
+        );
+
+        let result = check("fn f(a, b, a) {}");
+        assert!(matches!(
+            result,
+            Some(TypeError::DuplicateParameter { ref function, ref parameter, first, second })
+                if function == "f" && parameter == "a" && first == Span::new(1, 6) && second == Span::new(1, 12)
+        ));
+
+        let result = check("struct P { x: int }; impl P { fn m(self, v: int, v: int) {} }");
+        assert!(matches!(
+            result,
+            Some(TypeError::DuplicateParameter { ref function, ref parameter, first, second })
+                if function == "P.m" && parameter == "v" && first == Span::new(1, 42) && second == Span::new(1, 50)
+        ));
+        assert_eq!(
+            result.unwrap().to_string(),
+            "parameter 'v' of function 'P.m' is declared twice: first at 1:42, again at 1:50"
+        );
+
+        assert!(check("struct P { x: int, y: int }; fn f(a, b) {}").is_none());
+    }
+
+    #[test]
+    fn test_type_check_empty_array_literal() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        // 空数组的类型来自类型注解
+        assert!(check("let a: [int] = []; let x: int = a[0];").is_none());
+        assert!(check("let a: [int] = []; let s: string = a[0];").is_some());
+        assert!(check("let a: [int] = []; let b = a + [1]; let c: [string] = b;").is_some());
+        // 嵌套的空数组使用注解中的元素类型
+        assert!(check("let m: [[int]] = [[], [1]]; let r: [int] = m[0];").is_none());
+        assert!(check("fn f() -> [string] { return []; }").is_none());
+
+        // 没有注解时无法推断
+        let result = check("let a = [];");
+        assert!(matches!(result, Some(TypeError::CannotInferType(ref message)) if message.contains("'a'")));
+    }
+
+    #[test]
+    fn test_type_check_expected_type_for_literals() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let x: float = 3;").is_none());
+        assert!(check("fn half(x: float) -> float { return x / 2.0; } let y = half(3);").is_none());
+        assert!(check("fn neg() -> float { return -1; }").is_none());
+        // 整数字面量按期望类型推断后，数组元素的类型一致
+        assert!(check("let xs: [float] = [1, 2.5, -3];").is_none());
+        assert!(check("fn sum(xs: [float]) -> float { return xs[0]; } sum([1, 2.5]);").is_none());
+        // 没有期望类型时仍然要求元素类型严格一致
+        assert!(check("let xs = [1, 2.5];").is_some());
+        // 只有整数字面量会采用 float；float 不会变成 int
+        assert!(check("let xs: [int] = [1, 2.5];").is_some());
+    }
+
+    #[test]
+    fn test_type_check_length_property() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let n: int = [1, 2, 3].length; let m: int = \"abc\".length;").is_none());
+        assert!(check("let a: [int; 2] = [1, 2]; let n: int = a.length;").is_none());
+        assert!(check("let s: string = [1].length;").is_some());
+
+        // 数组上的其他属性给出明确的错误
+        let result = check("let a = [1, 2]; let n = a.size;");
+        assert!(matches!(result, Some(TypeError::UndefinedVariable(ref message))
+            if message.contains("'size'") && message.contains("'length'")));
+    }
+
+    #[test]
+    fn test_type_check_power() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a: int = 2 ** 10; let b: float = 2.0 ** 0.5; let c: float = 2 ** 0.5;").is_none());
+        assert!(check("let a = true ** 2;").is_some());
+        assert!(matches!(check("let a = \"x\" ** 2;"),
+            Some(TypeError::InvalidOperation { ref operator, .. }) if operator == "power"));
+    }
+
+    #[test]
+    fn test_warn_shadow() {
+        let warnings = |input: &str, enabled: bool| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            let mut checker = TypeChecker::new();
+            checker.set_warn_shadow(enabled);
+            checker.check(&program).unwrap();
+            // 只看遮蔽警告（示例中的变量大多未使用）
+            checker.warnings().iter()
+                .filter(|warning| matches!(warning, TypeWarning::ShadowedWithDifferentType { .. }))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        // 同类型遮蔽、同一作用域内重新声明都不提示
+        assert!(warnings("let x = 1; fn f() { let x = 2; }", true).is_empty());
+        assert!(warnings("let x = 1; let x = \"one\";", true).is_empty());
+
+        let differing = "let count = 1; fn f() { if (true) { let count = \"many\"; } }";
+        assert_eq!(warnings(differing, true), vec![TypeWarning::ShadowedWithDifferentType {
+            name: "count".to_string(),
+            outer_type: Type::Int,
+            new_type: Type::String,
+        }]);
+        // 默认关闭
+        assert!(warnings(differing, false).is_empty());
+    }
+
+    #[test]
+    fn test_warn_unused() {
+        let warnings = |input: &str| {
+            let tokens = crate::lexer::TokenPreprocessor::preprocess(Lexer::new(input.to_string()).tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            let mut checker = TypeChecker::new();
+            checker.check(&program).unwrap();
+            checker.warnings().to_vec()
+        };
+        let unused_variable = |name: &str| TypeWarning::UnusedVariable { name: name.to_string() };
+
+        assert_eq!(warnings("fn f() { let unused = 1; } f();"), vec![unused_variable("unused")]);
+        assert!(warnings("let used = 1; print(used);").is_empty());
+        // 以 `_` 开头的名称不提示
+        assert!(warnings("let _unused = 1; fn f(_x: int) {} f(1);").is_empty());
+        // 只赋值不读取仍然算未使用；被嵌套函数读取算使用
+        assert_eq!(warnings("var written = 1; written = 2;"), vec![unused_variable("written")]);
+        assert!(warnings("let base = 1; fn add(x: int) -> int { return x + base; } print(add(1));").is_empty());
+        assert_eq!(
+            warnings("fn helper() {} pub fn api() {} for i in 0..3 {}"),
+            vec![unused_variable("i"), TypeWarning::UnusedFunction { name: "helper".to_string() }],
+        );
+    }
+
+    #[test]
+    fn test_type_check_discard_binding() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        // `_` 可以在同一作用域中重复使用，且类型可以不同
+        assert!(check("let _ = 1; let _ = \"two\"; let (_, b) = (1, 2.0); for _ in 0..3 { let _ = b; }").is_none());
+
+        // `_` 不能作为值使用
+        for source in ["let _ = 1; print(_);", "for _ in 0..3 { print(_); }", "let _ = 1; let y = _ + 1;"] {
+            assert!(matches!(check(source), Some(TypeError::UndefinedVariable(ref message)) if message.contains("'_'")),
+                "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_type_check_primitive_impl() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        let methods = "impl int { fn double(self) -> Self { return self * 2; } }
+                       impl string { fn len(self) -> int { return self.length; } }";
+        assert!(check(&format!("{} let a: int = 3.double(); let b: int = \"abc\".len();", methods)).is_none());
+        // 方法只属于实现它的类型
+        assert!(matches!(check(&format!("{} let c = 1.5.double();", methods)), Some(TypeError::UndefinedFunction(_))));
+        assert!(matches!(check(&format!("{} let d: string = 3.double();", methods)), Some(TypeError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_void_call_used_as_value() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        let log = "fn log(msg: string) { print(msg); } fn id(x) { return x; } fn noop() -> void { return; }";
+        // 作为语句调用没有问题
+        assert!(check(&format!("{} log(\"a\"); noop(); let y = id(1);", log)).is_none());
+
+        let result = check(&format!("{} let x = log(\"a\");", log));
+        assert!(matches!(result, Some(TypeError::VoidValue { ref function, ref location })
+            if function == "log" && location.contains("'x'")), "{:?}", result);
+        for usage in ["let n = noop() + 1;", "id(log(\"a\"));", "let b = !noop();"] {
+            assert!(matches!(check(&format!("{} {}", log, usage)), Some(TypeError::VoidValue { .. })), "{}", usage);
+        }
+    }
+
+    #[test]
+    fn test_type_check_not_callable() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(matches!(check("let x = 5; x();"), Some(TypeError::NotCallable { found_type: Type::Int })));
+        assert!(matches!(check("struct P { x: int }; let p = P { x: 1 }; p();"),
+            Some(TypeError::NotCallable { found_type: Type::Struct(ref s) }) if s.name == "P"));
+        assert!(matches!(check("let xs = [1, 2]; xs[0](3);"), Some(TypeError::NotCallable { found_type: Type::Int })));
+
+        // 类型未知的参数仍然可以作为函数调用
+        assert!(check("fn apply(f, x) { return f(x); } fn inc(n: int) -> int { return n + 1; } let r = apply(inc, 2);").is_none());
+    }
+
+    #[test]
+    fn test_type_check_to_string() {
+        let check = |input: &str| {
+            let tokens = Lexer::new(input.to_string()).tokenize().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a: string = (42).to_string(); let b: string = [1, 2].to_string(); let c: string = (1, true).to_string();").is_none());
+        assert!(matches!(check("let n: int = true.to_string();"), Some(TypeError::TypeMismatch { expected: Type::Int, found: Type::String, .. })));
+        assert!(matches!(check("let s = (1).to_string(2);"), Some(TypeError::ArgumentCountMismatch { expected: 0, found: 1, .. })));
+        // 自定义的 to_string 按自己的签名检查
+        assert!(check("struct P { x: int }; impl P { fn to_string(self) -> int { return self.x; } } let n: int = P { x: 1 }.to_string();").is_none());
+    }
+
+    #[test]
+    fn test_type_check_optional_field_access() {
+        let check = |input: &str| {
+            let tokens = Lexer::new(input.to_string()).tokenize().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("struct P { x: int }; var p: P; let x: int = p?.x; let n = null?.x;").is_none());
+        assert!(matches!(check("struct P { x: int }; var p: P; let s: string = p?.x;"),
+            Some(TypeError::TypeMismatch { expected: Type::String, found: Type::Int, .. })));
+        assert!(matches!(check("let n = 1; let x = n?.x;"),
+            Some(TypeError::InvalidOperation { ref operator, .. }) if operator == "optional field access"));
+    }
+
+    #[test]
+    fn test_type_check_slice() {
+        let check = |input: &str| {
+            let tokens = crate::lexer::TokenPreprocessor::preprocess(Lexer::new(input.to_string()).tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let s: string = \"hello\"[1..3]; let a: [int] = [1, 2, 3][0..2];").is_none());
+        assert!(matches!(check("let n: int = \"hello\"[1..3];"), Some(TypeError::TypeMismatch { expected: Type::Int, found: Type::String, .. })));
+        assert!(matches!(check("let s = \"hello\"[1..\"3\"];"), Some(TypeError::TypeMismatch { expected: Type::Int, .. })));
+        assert!(matches!(check("let n = 5; let s = n[0..1];"), Some(TypeError::InvalidOperation { ref operator, .. }) if operator == "slice"));
+    }
+
+    #[test]
+    fn test_type_check_round_and_format() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        assert!(check("let a: float = round(3.14159, 2); let b: float = round(3, 0); let s: string = format(\"{} {:.2}\", a, b);").is_none());
+        assert!(matches!(check("let a = round(\"x\", 2);"), Some(TypeError::ArgumentTypeMismatch { argument: 1, .. })));
+        assert!(matches!(check("let a = round(1.5);"), Some(TypeError::ArgumentCountMismatch { expected: 2, found: 1, .. })));
+        // 字面量格式字符串在编译时检查
+        assert!(matches!(check("let s = format(\"{} {}\", 1);"), Some(TypeError::ArgumentCountMismatch { expected: 3, found: 2, .. })));
+        assert!(matches!(check("let s = format(\"{:x}\", 1);"), Some(TypeError::InvalidFormat(_))));
+        assert!(matches!(check("let s = format(1);"), Some(TypeError::ArgumentTypeMismatch { .. })));
+        // clone 的结果与参数类型相同
+        assert!(check("let a: [int] = clone([1]);").is_none());
+        assert!(matches!(check("let a: string = clone([1]);"), Some(TypeError::TypeMismatch { .. })));
+        assert!(matches!(check("let a = clone(1, 2);"), Some(TypeError::ArgumentCountMismatch { expected: 1, found: 2, .. })));
+    }
+
+    #[test]
+    fn test_type_check_self_type_in_methods() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+        let point = "struct P { x: int, y: int };";
+
+        assert!(check(&format!("{} impl P {{ fn clone(self) -> Self {{ return self; }} }}
+            let p = P {{ x: 1, y: 2 }}; let q: P = p.clone();", point)).is_none());
+        // 参数中的 Self
+        assert!(check(&format!("{} impl P {{ fn same_x(self, other: Self) -> bool {{ return self.x == other.x; }} }}
+            let p = P {{ x: 1, y: 2 }}; let b: bool = p.same_x(p);", point)).is_none());
+        assert!(check(&format!("{} impl P {{ fn same_x(self, other: Self) -> bool {{ return true; }} }}
+            let p = P {{ x: 1, y: 2 }}; p.same_x(1);", point)).is_some());
+
+        let result = check(&format!("{} impl P {{ fn clone(self) -> Self {{ return 1; }} }}", point));
+        assert!(matches!(result, Some(TypeError::ReturnTypeMismatch { found: Type::Int, .. })));
+    }
+
+    #[test]
+    fn test_type_check_return_in_nested_block_of_method() {
+        let check = |input: &str| {
+            let mut lexer = Lexer::new(input.to_string());
+            let tokens = lexer.tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            let program = parser.parse().unwrap();
+            TypeChecker::new().check(&program).err()
+        };
+
+        // 方法中唯一的 return 位于嵌套的 if 内
+        let result = check("struct P { x: int };
+            impl P {
+                fn sign(self) -> int {
+                    if self.x > 0 {
+                        if self.x > 10 {
+                            return \"big\";
+                        }
+                    }
+                }
+            }");
+        assert!(matches!(result, Some(TypeError::ReturnTypeMismatch { expected: Type::Int, found: Type::String, .. })));
+
+        // 嵌套函数结束后，外层方法的返回类型仍然生效
+        let result = check("struct P { x: int };
+            impl P {
+                fn get(self) -> int {
+                    fn helper() -> string { return \"h\"; }
+                    if self.x > 0 {
+                        return helper();
+                    }
+                    return 0;
+                }
+            }");
+        assert!(matches!(result, Some(TypeError::ReturnTypeMismatch { expected: Type::Int, found: Type::String, .. })));
+    }
+}
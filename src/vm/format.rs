@@ -0,0 +1,93 @@
+// format 内置函数的格式字符串
+//
+// `{}` 按 print 的格式显示参数，`{:.N}` 以 N 位小数显示数字，`{{` 和 `}}` 表示花括号本身。
+// 类型检查器用它检查字面量格式字符串的占位符数量，虚拟机用它生成结果字符串。
+
+use crate::bytecode::Value;
+
+/// 格式字符串的组成部分
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Literal(String),
+    Display,           // {}
+    Precision(usize),  // {:.N}
+}
+
+/// 解析格式字符串
+pub fn parse(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err(format!("unclosed '{{' in format string \"{}\"", format)),
+                    }
+                }
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                pieces.push(parse_spec(&spec)?);
+            }
+            '}' => return Err(format!("unmatched '}}' in format string \"{}\" (use '}}}}' for a literal brace)", format)),
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(pieces)
+}
+
+fn parse_spec(spec: &str) -> Result<Piece, String> {
+    if spec.is_empty() {
+        return Ok(Piece::Display);
+    }
+    spec.strip_prefix(":.")
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .map(Piece::Precision)
+        .ok_or_else(|| format!("unsupported format specifier '{{{}}}' (expected '{{}}' or '{{:.N}}')", spec))
+}
+
+/// 格式字符串需要的参数个数
+pub fn placeholder_count(pieces: &[Piece]) -> usize {
+    pieces.iter().filter(|piece| !matches!(piece, Piece::Literal(_))).count()
+}
+
+/// 用参数填充占位符
+pub fn render(pieces: &[Piece], args: &[Value]) -> Result<String, String> {
+    let expected = placeholder_count(pieces);
+    if expected != args.len() {
+        return Err(format!("format string expects {} arguments but got {}", expected, args.len()));
+    }
+
+    let mut args = args.iter();
+    let mut output = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Literal(text) => output.push_str(text),
+            Piece::Display => output.push_str(&args.next().unwrap().to_string()),
+            Piece::Precision(digits) => match args.next().unwrap() {
+                Value::Float(f) => output.push_str(&format!("{:.*}", digits, f)),
+                Value::Integer(i) => output.push_str(&format!("{:.*}", digits, *i as f64)),
+                other => return Err(format!("'{{:.{}}}' requires a number, got {}", digits, other.type_name())),
+            },
+        }
+    }
+    Ok(output)
+}
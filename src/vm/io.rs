@@ -0,0 +1,50 @@
+// 虚拟机的输入输出流
+//
+// `input()` 从 input 读一行，`print` / `write` 写到 output，`eprint` 写到 error。
+// 默认连接进程的标准输入输出；嵌入和测试时换成内存中的读写目标即可无界面运行
+// （测试使用的内存读写目标见 test_support::captured_io）。
+
+use std::io::{self, BufRead, Write};
+
+/// 虚拟机使用的全部输入输出
+pub struct VmIo {
+    pub input: Box<dyn BufRead>,
+    pub output: Box<dyn Write>,
+    pub error: Box<dyn Write>,
+}
+
+impl VmIo {
+    pub fn new(input: Box<dyn BufRead>, output: Box<dyn Write>, error: Box<dyn Write>) -> Self {
+        VmIo { input, output, error }
+    }
+
+    /// 进程的标准输入、标准输出和标准错误
+    pub fn stdio() -> Self {
+        Self::new(
+            Box::new(io::BufReader::new(io::stdin())),
+            Box::new(io::stdout()),
+            Box::new(io::stderr()),
+        )
+    }
+
+    /// 读一行输入，去掉行尾换行；输入结束时返回空字符串
+    pub fn read_line(&mut self) -> io::Result<String> {
+        // 提示语通常用 write 输出且不带换行，读取前先刷新
+        self.output.flush()?;
+        let mut line = String::new();
+        self.input.read_line(&mut line)?;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(line)
+    }
+}
+
+impl Default for VmIo {
+    fn default() -> Self {
+        Self::stdio()
+    }
+}
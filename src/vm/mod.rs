@@ -1,5 +1,12 @@
+pub mod format;
+pub mod io;
+
 use crate::bytecode::{Chunk, OpCode, Value, Function};
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::io::Write;
+use std::rc::Rc;
+
+pub use io::VmIo;
 
 /// 虚拟机运行时错误
 #[derive(Debug)]
@@ -10,10 +17,22 @@ pub enum VMError {
     UndefinedVariable(String),
     DivisionByZero,
     InvalidOperation(String),
+    GasExhausted,  // 执行的指令数超过了 gas 限制
+    StackImbalance { leftover: usize },  // 严格模式下 Halt 时值栈中残留的值（说明编译器生成的代码不平衡）
+    NotCallable(String),  // 调用的值不是函数（参数为值的类型名）
+    ArityMismatch { function: String, expected: usize, found: usize },  // 调用时的参数个数与函数不符
 }
 
 type VMResult<T> = Result<T, VMError>;
 
+/// print 和 write 的输出目标
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintSink {
+    Stdout,          // 写到 VmIo 的输出流（默认为标准输出）
+    #[cfg(test)]
+    Buffer(String),  // 写入内存缓冲区，供测试读取（宿主程序可以通过 VmIo 提供自己的输出流）
+}
+
 /// 调用帧（用于函数调用）
 #[derive(Debug, Clone)]
 struct CallFrame {
@@ -25,45 +44,142 @@ struct CallFrame {
 /// Zero语言虚拟机
 pub struct VM {
     stack: Vec<Value>,              // 值栈
-    globals: HashMap<String, Value>, // 全局变量
+    globals: Vec<Option<Value>>,     // 全局变量（按编译时分配的槽位索引，None 表示尚未定义）
+    global_names: Vec<String>,       // 全局变量槽位对应的名称
     frames: Vec<CallFrame>,          // 调用栈
     current_frame: usize,            // 当前帧索引
+    instruction_count: u64,          // 已执行的指令数
+    gas_limit: Option<u64>,          // 最多允许执行的指令数（None 表示不限制）
+    print_sink: PrintSink,           // print / write 的输出目标
+    io: VmIo,                        // input / print / write / eprint 使用的输入输出流
+    strict_stack: bool,              // Halt 时检查值栈是否回到执行开始时的深度
+    stack_base: usize,               // 本次 execute 开始时的值栈深度
 }
 
 impl VM {
     pub fn new() -> Self {
         VM {
             stack: Vec::with_capacity(256),
-            globals: HashMap::new(),
+            globals: Vec::new(),
+            global_names: Vec::new(),
             frames: Vec::new(),
             current_frame: 0,
+            instruction_count: 0,
+            gas_limit: None,
+            print_sink: PrintSink::Stdout,
+            io: VmIo::stdio(),
+            strict_stack: false,
+            stack_base: 0,
+        }
+    }
+
+    /// 创建使用给定输入输出流的虚拟机，用于嵌入宿主程序或在测试中无界面运行
+    pub fn with_io(io: VmIo) -> Self {
+        let mut vm = Self::new();
+        vm.io = io;
+        vm
+    }
+
+    /// 设置 print 和 write 的输出目标
+    pub fn set_print_sink(&mut self, sink: PrintSink) {
+        self.print_sink = sink;
+    }
+
+    /// 取出缓冲区中已打印的文本并清空缓冲区（输出到标准输出时返回空字符串）
+    pub fn take_output(&mut self) -> String {
+        match &mut self.print_sink {
+            PrintSink::Stdout => String::new(),
+            #[cfg(test)]
+            PrintSink::Buffer(buffer) => std::mem::take(buffer),
         }
     }
 
+    /// 创建带 gas 限制的虚拟机，执行的指令数超过 limit 时返回 GasExhausted
+    /// 用于运行不受信任的代码，防止死循环阻塞宿主程序
+    pub fn with_gas_limit(limit: u64) -> Self {
+        let mut vm = Self::new();
+        vm.gas_limit = Some(limit);
+        vm
+    }
+
+    /// 严格模式：执行到 Halt 时，值栈必须回到本次 execute 开始时的深度，
+    /// 否则返回 StackImbalance。顶层语句执行完后不应残留任何值，用于发现编译器的栈平衡错误
+    pub fn set_strict_stack(&mut self, strict: bool) {
+        self.strict_stack = strict;
+    }
+
+    /// 已执行的指令数
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// 按名称读取全局变量
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        let slot = self.global_names.iter().position(|n| n == name)?;
+        self.globals.get(slot)?.as_ref()
+    }
+
     /// 执行字节码
+    ///
+    /// 无论正常结束还是出现运行时错误，执行后值栈和调用栈都恢复到执行前的状态，
+    /// 全局变量保留，因此同一个虚拟机可以继续执行后续的 chunk（REPL、嵌入使用）
     pub fn execute(&mut self, chunk: Chunk) -> VMResult<()> {
+        // 全局变量槽位由编译器分配，名称表随顶层 chunk 一起提供
+        self.global_names = chunk.global_names.clone();
+        if self.globals.len() < self.global_names.len() {
+            self.globals.resize(self.global_names.len(), None);
+        }
+
         // 创建主函数帧
         let main_function = Function {
             name: "<script>".to_string(),
             arity: 0,
             chunk,
             locals_count: 0,
+            upvalues: Vec::new(),
         };
 
+        self.stack_base = self.stack.len();
+        let frame_base = self.frames.len();
         self.frames.push(CallFrame {
             function: main_function,
             ip: 0,
-            stack_offset: 0,
+            stack_offset: self.stack_base,
         });
+        self.current_frame = frame_base;
 
-        self.run()
+        let result = self.run();
+
+        self.stack.truncate(self.stack_base);
+        self.frames.truncate(frame_base);
+        self.current_frame = frame_base.saturating_sub(1);
+        result
     }
 
     /// 主执行循环
     fn run(&mut self) -> VMResult<()> {
         loop {
+            // gas 计数
+            if let Some(limit) = self.gas_limit {
+                if self.instruction_count >= limit {
+                    return Err(VMError::GasExhausted);
+                }
+            }
+            self.instruction_count += 1;
+
             let frame = &self.frames[self.current_frame];
-            
+
+            // 取指令（指令指针越界说明字节码被截断或跳转目标损坏）
+            let instruction = match frame.function.chunk.code.get(frame.ip) {
+                Some(op) => op.clone(),
+                None => {
+                    return Err(VMError::InvalidOperation(format!(
+                        "Instruction pointer {} out of bounds in '{}' ({} instructions)",
+                        frame.ip, frame.function.name, frame.function.chunk.code.len()
+                    )));
+                }
+            };
+
             // 调试输出（可选）
             #[cfg(debug_assertions)]
             {
@@ -72,22 +188,14 @@ impl VM {
                     print!("{:?}, ", value);
                 }
                 println!("]");
-                frame.function.chunk.disassemble_instruction(
-                    frame.ip,
-                    &frame.function.chunk.code[frame.ip]
-                );
+                frame.function.chunk.disassemble_instruction(frame.ip, &instruction);
             }
 
-            let instruction = frame.function.chunk.code[frame.ip].clone();
             self.frames[self.current_frame].ip += 1;
 
             match instruction {
                 OpCode::LoadConst(idx) => {
-                    let value = self.frames[self.current_frame]
-                        .function
-                        .chunk
-                        .constants[idx]
-                        .clone();
+                    let value = self.constant(idx)?;
                     self.push(value)?;
                 }
 
@@ -95,48 +203,50 @@ impl VM {
                     self.push(Value::Null)?;
                 }
 
+                OpCode::LoadTrue => self.push(Value::Boolean(true))?,
+                OpCode::LoadFalse => self.push(Value::Boolean(false))?,
+                OpCode::LoadZero => self.push(Value::Integer(0))?,
+                OpCode::LoadOne => self.push(Value::Integer(1))?,
+
                 OpCode::LoadLocal(slot) => {
-                    let offset = self.frames[self.current_frame].stack_offset;
-                    let value = self.stack[offset + slot].clone();
+                    let index = self.local_index(slot)?;
+                    let value = self.stack[index].clone();
                     self.push(value)?;
                 }
 
                 OpCode::StoreLocal(slot) => {
                     let value = self.peek(0)?.clone();
-                    let offset = self.frames[self.current_frame].stack_offset;
-                    self.stack[offset + slot] = value;
-                }
-
-                OpCode::LoadGlobal(idx) => {
-                    let name = match &self.frames[self.current_frame]
-                        .function
-                        .chunk
-                        .constants[idx]
-                    {
-                        Value::String(s) => s.clone(),
-                        _ => return Err(VMError::TypeError("Expected string for variable name".to_string())),
+                    let index = self.local_index(slot)?;
+                    self.stack[index] = value;
+                }
+
+                OpCode::LoadGlobal(slot) => {
+                    let value = match self.globals.get(slot) {
+                        Some(Some(value)) => value.clone(),
+                        _ => return Err(VMError::UndefinedVariable(self.global_name(slot))),
                     };
 
-                    let value = self.globals
-                        .get(&name)
-                        .ok_or_else(|| VMError::UndefinedVariable(name.clone()))?
-                        .clone();
-                    
                     self.push(value)?;
                 }
 
-                OpCode::StoreGlobal(idx) => {
-                    let name = match &self.frames[self.current_frame]
-                        .function
-                        .chunk
-                        .constants[idx]
-                    {
-                        Value::String(s) => s.clone(),
-                        _ => return Err(VMError::TypeError("Expected string for variable name".to_string())),
-                    };
+                OpCode::LoadUpvalue(idx) => {
+                    let upvalues = &self.frames[self.current_frame].function.upvalues;
+                    let value = upvalues.get(idx).cloned().ok_or_else(|| {
+                        VMError::InvalidOperation(format!(
+                            "Upvalue index {} out of bounds ({} upvalues)", idx, upvalues.len()
+                        ))
+                    })?;
+                    self.push(value)?;
+                }
 
+                OpCode::StoreGlobal(slot) => {
                     let value = self.peek(0)?.clone();
-                    self.globals.insert(name, value);
+                    match self.globals.get_mut(slot) {
+                        Some(global) => *global = Some(value),
+                        None => return Err(VMError::InvalidOperation(format!(
+                            "Global slot {} out of bounds ({} globals)", slot, self.globals.len()
+                        ))),
+                    }
                 }
 
                 // 算术运算
@@ -146,6 +256,19 @@ impl VM {
                     (Value::Integer(x), Value::Float(y)) => Ok(Value::Float(x as f64 + y)),
                     (Value::Float(x), Value::Integer(y)) => Ok(Value::Float(x + y as f64)),
                     (Value::String(x), Value::String(y)) => Ok(Value::String(format!("{}{}", x, y))),
+                    // 编译期无法确定类型时的回退：字符串与其他值相加
+                    (Value::String(x), y) => Ok(Value::String(format!("{}{}", x, y.to_string()))),
+                    (x, Value::String(y)) => Ok(Value::String(format!("{}{}", x.to_string(), y))),
+                    (Value::Array(mut x), Value::Array(y)) => {
+                        Rc::make_mut(&mut x).extend(y.iter().cloned());
+                        Ok(Value::Array(x))
+                    }
+                    // 字符范围循环按码点递增
+                    (Value::Char(c), Value::Integer(n)) => u32::try_from(c as i64 + n)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .map(Value::Char)
+                        .ok_or_else(|| VMError::TypeError("Character code point out of range".to_string())),
                     _ => Err(VMError::TypeError("Invalid operands for addition".to_string())),
                 })?,
 
@@ -203,6 +326,43 @@ impl VM {
                     _ => Err(VMError::TypeError("Invalid operands for modulo".to_string())),
                 })?,
 
+                OpCode::Power => self.binary_op(|a, b| match (a, b) {
+                    (Value::Integer(x), Value::Integer(y)) => {
+                        // 整数乘方的结果必须仍是整数，负指数要求显式使用浮点数
+                        if y < 0 {
+                            return Err(VMError::InvalidOperation(format!(
+                                "Negative integer exponent: {} ** {} (use a float base, e.g. {}.0 ** {})",
+                                x, y, x, y
+                            )));
+                        }
+                        u32::try_from(y)
+                            .ok()
+                            .and_then(|y| x.checked_pow(y))
+                            .map(Value::Integer)
+                            .ok_or_else(|| VMError::InvalidOperation(format!("Integer overflow: {} ** {}", x, y)))
+                    }
+                    (Value::Float(x), Value::Float(y)) => Ok(Value::Float(x.powf(y))),
+                    (Value::Integer(x), Value::Float(y)) => Ok(Value::Float((x as f64).powf(y))),
+                    (Value::Float(x), Value::Integer(y)) => Ok(Value::Float(x.powf(y as f64))),
+                    _ => Err(VMError::TypeError("Invalid operands for power".to_string())),
+                })?,
+
+                OpCode::Round => self.binary_op(|value, digits| {
+                    let x = match value {
+                        Value::Float(f) => f,
+                        Value::Integer(i) => i as f64,
+                        _ => return Err(VMError::TypeError("round expects a number".to_string())),
+                    };
+                    let digits = match digits {
+                        Value::Integer(d) => d.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+                        _ => return Err(VMError::TypeError("round expects an integer number of digits".to_string())),
+                    };
+                    // 位数过大时放大后的值不再有限，此时原值已经足够精确
+                    let factor = 10f64.powi(digits);
+                    let rounded = (x * factor).round() / factor;
+                    Ok(Value::Float(if rounded.is_finite() { rounded } else { x }))
+                })?,
+
                 OpCode::Negate => {
                     let value = self.pop()?;
                     let result = match value {
@@ -213,23 +373,149 @@ impl VM {
                     self.push(result)?;
                 }
 
+                // 字符串操作
+                OpCode::Concat => self.binary_op(|a, b| match (a, b) {
+                    (Value::String(x), Value::String(y)) => Ok(Value::String(x + &y)),
+                    _ => Err(VMError::TypeError("Can only concatenate strings".to_string())),
+                })?,
+
+                OpCode::ToString => {
+                    let value = self.pop()?;
+                    self.push(Value::String(value.to_string()))?;
+                }
+
+                OpCode::DeepClone => {
+                    let value = self.pop()?;
+                    self.push(value.deep_clone())?;
+                }
+
+                OpCode::PrettyFormat => {
+                    let value = self.pop()?;
+                    self.push(Value::String(value.pretty_format()))?;
+                }
+
+                OpCode::Format(count) => {
+                    let args = self.stack.split_off(self.stack.len().saturating_sub(count));
+                    if args.len() != count {
+                        return Err(VMError::StackUnderflow);
+                    }
+                    let result = match self.pop()? {
+                        Value::String(template) => format::parse(&template)
+                            .and_then(|pieces| format::render(&pieces, &args))
+                            .map_err(VMError::InvalidOperation)?,
+                        _ => return Err(VMError::TypeError("format string must be a string".to_string())),
+                    };
+                    self.push(Value::String(result))?;
+                }
+
+                OpCode::StrLen => {
+                    let value = self.pop()?;
+                    match value {
+                        Value::String(s) => {
+                            self.push(Value::Integer(s.chars().count() as i64))?;
+                        }
+                        _ => return Err(VMError::TypeError("Can only get length of strings".to_string())),
+                    }
+                }
+
+                OpCode::StrSplit => {
+                    let separator = self.pop()?;
+                    let value = self.pop()?;
+                    let parts: Vec<Value> = match (&value, &separator) {
+                        (Value::String(s), Value::String(sep)) if sep.is_empty() => {
+                            s.chars().map(|c| Value::String(c.to_string())).collect()
+                        }
+                        (Value::String(s), Value::String(sep)) => {
+                            s.split(sep.as_str()).map(|part| Value::String(part.to_string())).collect()
+                        }
+                        _ => return Err(VMError::TypeError("split expects a string and a string separator".to_string())),
+                    };
+                    self.push(Value::Array(Rc::new(parts)))?;
+                }
+
+                OpCode::StrJoin => {
+                    let separator = self.pop()?;
+                    let value = self.pop()?;
+                    let joined = match (&value, &separator) {
+                        (Value::Array(elements), Value::String(sep)) => {
+                            let mut parts = Vec::with_capacity(elements.len());
+                            for element in elements.iter() {
+                                match element {
+                                    Value::String(s) => parts.push(s.as_str()),
+                                    other => {
+                                        return Err(VMError::TypeError(format!(
+                                            "join expects an array of strings, found {} element", other.type_name()
+                                        )))
+                                    }
+                                }
+                            }
+                            parts.join(sep)
+                        }
+                        _ => return Err(VMError::TypeError("join expects an array and a string separator".to_string())),
+                    };
+                    self.push(Value::String(joined))?;
+                }
+
+                OpCode::StrReplace => {
+                    let to = self.pop()?;
+                    let from = self.pop()?;
+                    let value = self.pop()?;
+                    match (&value, &from, &to) {
+                        (Value::String(s), Value::String(from), Value::String(to)) => {
+                            self.push(Value::String(s.replace(from.as_str(), to)))?;
+                        }
+                        _ => return Err(VMError::TypeError("replace expects three strings".to_string())),
+                    }
+                }
+
+                OpCode::StrContains => {
+                    let needle = self.pop()?;
+                    let value = self.pop()?;
+                    match (&value, &needle) {
+                        (Value::String(s), Value::String(needle)) => {
+                            self.push(Value::Boolean(s.contains(needle.as_str())))?;
+                        }
+                        _ => return Err(VMError::TypeError("contains expects two strings".to_string())),
+                    }
+                }
+
+                OpCode::NewStringBuilder => self.push(Value::String(String::new()))?,
+
+                // 字符串构建器的 append：直接修改变量中的字符串，均摊 O(1)
+                OpCode::StrAppendLocal(slot) => {
+                    let value = self.pop()?;
+                    let local = self.local_index(slot)?;
+                    Self::append_to(&mut self.stack[local], value)?;
+                    self.push(Value::Null)?;
+                }
+
+                OpCode::StrAppendGlobal(slot) => {
+                    let value = self.pop()?;
+                    let builder = match self.globals.get_mut(slot) {
+                        Some(Some(builder)) => builder,
+                        _ => return Err(VMError::UndefinedVariable(self.global_name(slot))),
+                    };
+                    Self::append_to(builder, value)?;
+                    self.push(Value::Null)?;
+                }
+
                 // 比较运算
                 OpCode::Equal => {
                     let b = self.pop()?;
                     let a = self.pop()?;
-                    self.push(Value::Boolean(a == b))?;
+                    self.push(Value::Boolean(Self::values_equal(&a, &b)))?;
                 }
 
                 OpCode::NotEqual => {
                     let b = self.pop()?;
                     let a = self.pop()?;
-                    self.push(Value::Boolean(a != b))?;
+                    self.push(Value::Boolean(!Self::values_equal(&a, &b)))?;
                 }
 
-                OpCode::Greater => self.comparison_op(|a, b| a > b)?,
-                OpCode::GreaterEqual => self.comparison_op(|a, b| a >= b)?,
-                OpCode::Less => self.comparison_op(|a, b| a < b)?,
-                OpCode::LessEqual => self.comparison_op(|a, b| a <= b)?,
+                OpCode::Greater => self.comparison_op(|o| o == Ordering::Greater)?,
+                OpCode::GreaterEqual => self.comparison_op(|o| o != Ordering::Less)?,
+                OpCode::Less => self.comparison_op(|o| o == Ordering::Less)?,
+                OpCode::LessEqual => self.comparison_op(|o| o != Ordering::Greater)?,
 
                 // 逻辑运算
                 OpCode::Not => {
@@ -249,27 +535,27 @@ impl VM {
                     self.push(Value::Boolean(a.is_truthy() || b.is_truthy()))?;
                 }
 
-                // 控制流
+                // 控制流（偏移量相对于下一条指令，此时 ip 已经指向下一条指令）
                 OpCode::Jump(offset) => {
-                    self.frames[self.current_frame].ip = offset;
+                    self.jump_by(offset);
                 }
 
                 OpCode::JumpIfFalse(offset) => {
                     let condition = self.peek(0)?;
                     if !condition.is_truthy() {
-                        self.frames[self.current_frame].ip = offset;
+                        self.jump_by(offset);
                     }
                 }
 
                 OpCode::JumpIfTrue(offset) => {
                     let condition = self.peek(0)?;
                     if condition.is_truthy() {
-                        self.frames[self.current_frame].ip = offset;
+                        self.jump_by(offset);
                     }
                 }
 
                 OpCode::Loop(offset) => {
-                    self.frames[self.current_frame].ip = offset;
+                    self.jump_by(offset);
                 }
 
                 // 函数调用
@@ -277,10 +563,13 @@ impl VM {
                     let callee = self.peek(arg_count)?.clone();
                     match callee {
                         Value::Function(func) => {
+                            // 绕过类型检查的动态调用（例如通过无类型注解的参数）也不能读错栈上的值
                             if func.arity != arg_count {
-                                return Err(VMError::InvalidOperation(
-                                    format!("Expected {} arguments but got {}", func.arity, arg_count)
-                                ));
+                                return Err(VMError::ArityMismatch {
+                                    function: func.name.clone(),
+                                    expected: func.arity,
+                                    found: arg_count,
+                                });
                             }
 
                             // 栈布局: [..., function, arg1, arg2, ...]
@@ -297,7 +586,17 @@ impl VM {
                             });
                             self.current_frame += 1;
                         }
-                        _ => return Err(VMError::TypeError("Can only call functions".to_string())),
+                        other => return Err(VMError::NotCallable(other.type_name())),
+                    }
+                }
+
+                OpCode::Closure(count) => {
+                    // 栈布局: [..., function, v1, ..., vn]
+                    self.ensure_stack(count + 1)?;
+                    let upvalues = self.stack.split_off(self.stack.len() - count);
+                    match self.stack.last_mut() {
+                        Some(Value::Function(func)) => func.upvalues = upvalues,
+                        _ => return Err(VMError::TypeError("Can only capture into functions".to_string())),
                     }
                 }
 
@@ -328,8 +627,15 @@ impl VM {
                     self.push(value)?;
                 }
 
+                OpCode::Swap => {
+                    self.ensure_stack(2)?;
+                    let len = self.stack.len();
+                    self.stack.swap(len - 1, len - 2);
+                }
+
                 // 数组操作
                 OpCode::NewArray(size) => {
+                    self.ensure_stack(size)?;
                     let mut elements = Vec::with_capacity(size);
                     // 从栈中弹出元素（注意顺序）
                     for _ in 0..size {
@@ -337,7 +643,13 @@ impl VM {
                     }
                     // 反转以保持正确顺序
                     elements.reverse();
-                    self.push(Value::Array(elements))?;
+                    self.push(Value::Array(Rc::new(elements)))?;
+                }
+
+                OpCode::NewTuple(size) => {
+                    self.ensure_stack(size)?;
+                    let elements = self.stack.split_off(self.stack.len() - size);
+                    self.push(Value::Tuple(Rc::new(elements)))?;
                 }
 
                 OpCode::ArrayGet => {
@@ -350,7 +662,8 @@ impl VM {
                     };
                     
                     match array {
-                        Value::Array(arr) => {
+                        // 元组按下标取元素（下标在编译时已检查）
+                        Value::Array(arr) | Value::Tuple(arr) => {
                             let actual_idx = if idx < 0 {
                                 // 负索引：从末尾访问
                                 let len = arr.len() as i64;
@@ -371,40 +684,79 @@ impl VM {
                     }
                 }
 
-                OpCode::ArraySet => {
-                    let value = self.pop()?;
-                    let index = self.pop()?;
-                    let array = self.pop()?;
+                OpCode::Slice => {
+                    let end = self.pop()?;
+                    let start = self.pop()?;
+                    let object = self.pop()?;
 
-                    let idx = match index {
-                        Value::Integer(i) => i,
-                        _ => return Err(VMError::TypeError("Array index must be an integer".to_string())),
+                    let (start, end) = match (start, end) {
+                        (Value::Integer(start), Value::Integer(end)) => (start, end),
+                        _ => return Err(VMError::TypeError("Slice bounds must be integers".to_string())),
                     };
+                    // 字符串按字符（而不是字节）计算范围
+                    let len = match &object {
+                        Value::Array(arr) => arr.len(),
+                        Value::String(s) => s.chars().count(),
+                        _ => return Err(VMError::TypeError("Can only slice arrays and strings".to_string())),
+                    };
+                    if start < 0 || start > end || end as usize > len {
+                        return Err(VMError::InvalidOperation(
+                            format!("Slice range {}..{} out of bounds (length: {})", start, end, len)
+                        ));
+                    }
+                    let (start, end) = (start as usize, end as usize);
 
-                    // 我们需要可变引用来修改数组
-                    // 但由于所有权问题，这里需要重新构建数组
-                    match array {
-                        Value::Array(mut arr) => {
-                            let actual_idx = if idx < 0 {
-                                let len = arr.len() as i64;
-                                (len + idx) as usize
-                            } else {
-                                idx as usize
-                            };
+                    let result = match object {
+                        Value::Array(arr) => Value::Array(Rc::new(arr[start..end].to_vec())),
+                        Value::String(s) => Value::String(s.chars().skip(start).take(end - start).collect()),
+                        _ => unreachable!(),
+                    };
+                    self.push(result)?;
+                }
 
-                            if actual_idx >= arr.len() {
-                                return Err(VMError::InvalidOperation(
-                                    format!("Array index {} out of bounds (length: {})", idx, arr.len())
-                                ));
-                            }
+                OpCode::ArrayFill => {
+                    let count = self.pop()?;
+                    let value = self.pop()?;
+                    let count = match count {
+                        Value::Integer(n) if n >= 0 => n as usize,
+                        Value::Integer(n) => return Err(VMError::InvalidOperation(
+                            format!("Array fill count must not be negative (got {})", n)
+                        )),
+                        _ => return Err(VMError::TypeError("Array fill count must be an integer".to_string())),
+                    };
+                    self.push(Value::Array(Rc::new(vec![value; count])))?;
+                }
 
-                            arr[actual_idx] = value.clone();
-                            // 只推送修改后的数组，不推送值
-                            // 这样调用者可以决定如何处理结果
-                            self.push(Value::Array(arr))?;
-                        }
-                        _ => return Err(VMError::TypeError("Can only index arrays".to_string())),
-                    }
+                OpCode::ArraySet => {
+                    let value = self.pop()?;
+                    let index = self.pop()?;
+                    let mut array = self.pop()?;
+                    Self::set_element(&mut array, index, value)?;
+                    // 只推送修改后的数组，不推送值
+                    // 这样调用者可以决定如何处理结果
+                    self.push(array)?;
+                }
+
+                // 直接修改变量中的数组：数组没有被共享时不需要复制
+                OpCode::ArraySetLocal(slot) => {
+                    let value = self.pop()?;
+                    let index = self.pop()?;
+                    let local = self.local_index(slot)?;
+                    Self::set_element(&mut self.stack[local], index, value)?;
+                    let array = self.stack[local].clone();
+                    self.push(array)?;
+                }
+
+                OpCode::ArraySetGlobal(slot) => {
+                    let value = self.pop()?;
+                    let index = self.pop()?;
+                    let array = match self.globals.get_mut(slot) {
+                        Some(Some(array)) => array,
+                        _ => return Err(VMError::UndefinedVariable(self.global_name(slot))),
+                    };
+                    Self::set_element(array, index, value)?;
+                    let array = array.clone();
+                    self.push(array)?;
                 }
 
                 OpCode::ArrayLen => {
@@ -417,8 +769,19 @@ impl VM {
                     }
                 }
 
+                OpCode::ArrayConcat => self.binary_op(|a, b| match (a, b) {
+                    (Value::Array(mut x), Value::Array(y)) => {
+                        Rc::make_mut(&mut x).extend(y.iter().cloned());
+                        Ok(Value::Array(x))
+                    }
+                    _ => Err(VMError::TypeError("Can only concatenate arrays".to_string())),
+                })?,
+
                 // 结构体操作
                 OpCode::NewStruct(field_count) => {
+                    // 栈上需要有 field_count 个字段值和结构体名称
+                    self.ensure_stack(field_count + 1)?;
+
                     // 弹出结构体名称（在栈顶）
                     let struct_name = match self.pop()? {
                         Value::String(name) => name,
@@ -480,10 +843,32 @@ impl VM {
                 // 其他
                 OpCode::Print => {
                     let value = self.pop()?;
-                    println!("{}", value.to_string());
+                    self.write_output(&format!("{}\n", value.to_string()))?;
+                }
+
+                OpCode::Write => {
+                    let value = self.pop()?;
+                    self.write_output(&value.to_string())?;
+                }
+
+                OpCode::PrintError => {
+                    let value = self.pop()?;
+                    writeln!(self.io.error, "{}", value.to_string()).map_err(Self::io_error)?;
+                }
+
+                OpCode::Input => {
+                    let line = self.io.read_line().map_err(Self::io_error)?;
+                    self.push(Value::String(line))?;
                 }
 
                 OpCode::Halt => {
+                    if self.strict_stack && self.stack.len() != self.stack_base {
+                        return Err(VMError::StackImbalance {
+                            leftover: self.stack.len().saturating_sub(self.stack_base),
+                        });
+                    }
+                    // write 输出的文本可能还没有换行，结束前刷新
+                    self.io.output.flush().map_err(Self::io_error)?;
                     return Ok(());
                 }
             }
@@ -491,6 +876,22 @@ impl VM {
     }
 
     // 辅助方法
+
+    /// print 和 write 的输出：写到 print_sink 指定的目标，两者的输出保持先后顺序
+    fn write_output(&mut self, text: &str) -> VMResult<()> {
+        match &mut self.print_sink {
+            PrintSink::Stdout => write!(self.io.output, "{}", text).map_err(Self::io_error),
+            #[cfg(test)]
+            PrintSink::Buffer(buffer) => {
+                buffer.push_str(text);
+                Ok(())
+            }
+        }
+    }
+    fn io_error(err: std::io::Error) -> VMError {
+        VMError::InvalidOperation(format!("I/O error: {}", err))
+    }
+
     fn push(&mut self, value: Value) -> VMResult<()> {
         if self.stack.len() >= 1024 {
             return Err(VMError::StackOverflow);
@@ -511,6 +912,79 @@ impl VM {
         Ok(&self.stack[len - 1 - distance])
     }
 
+    /// 检查栈上至少有 count 个值（避免多值指令弹出一半后才发现下溢）
+    fn ensure_stack(&self, count: usize) -> VMResult<()> {
+        if self.stack.len() < count {
+            return Err(VMError::StackUnderflow);
+        }
+        Ok(())
+    }
+
+    /// 把值追加到字符串构建器末尾，非字符串的值按 print 的格式转换
+    fn append_to(builder: &mut Value, value: Value) -> VMResult<()> {
+        match (builder, value) {
+            (Value::String(s), Value::String(text)) => s.push_str(&text),
+            (Value::String(s), value) => s.push_str(&value.to_string()),
+            (builder, _) => {
+                return Err(VMError::TypeError(format!("append expects a StringBuilder, found {}", builder.type_name())))
+            }
+        }
+        Ok(())
+    }
+
+    /// 设置数组元素（负数索引从末尾计算）；数组被共享时先复制一份
+    fn set_element(array: &mut Value, index: Value, value: Value) -> VMResult<()> {
+        let idx = match index {
+            Value::Integer(i) => i,
+            _ => return Err(VMError::TypeError("Array index must be an integer".to_string())),
+        };
+        let arr = array.as_array_mut()
+            .ok_or_else(|| VMError::TypeError("Can only index arrays".to_string()))?;
+
+        let actual_idx = if idx < 0 { arr.len() as i64 + idx } else { idx };
+        if actual_idx < 0 || actual_idx as usize >= arr.len() {
+            return Err(VMError::InvalidOperation(
+                format!("Array index {} out of bounds (length: {})", idx, arr.len())
+            ));
+        }
+        arr[actual_idx as usize] = value;
+        Ok(())
+    }
+
+    /// 全局变量槽位的名称（用于错误信息）
+    #[cold]
+    fn global_name(&self, slot: usize) -> String {
+        self.global_names
+            .get(slot)
+            .cloned()
+            .unwrap_or_else(|| format!("#{}", slot))
+    }
+
+    /// 读取当前帧常量池中的常量
+    fn constant(&self, idx: usize) -> VMResult<Value> {
+        let constants = &self.frames[self.current_frame].function.chunk.constants;
+        constants.get(idx).cloned().ok_or_else(|| {
+            VMError::InvalidOperation(format!(
+                "Constant index {} out of bounds ({} constants)", idx, constants.len()
+            ))
+        })
+    }
+
+    /// 将局部变量槽位转换为栈索引
+    fn local_index(&self, slot: usize) -> VMResult<usize> {
+        let index = self.frames[self.current_frame].stack_offset + slot;
+        if index >= self.stack.len() {
+            return Err(VMError::InvalidOperation(format!("Local slot {} out of bounds", slot)));
+        }
+        Ok(index)
+    }
+
+    /// 按相对偏移移动当前帧的指令指针
+    fn jump_by(&mut self, offset: isize) {
+        let frame = &mut self.frames[self.current_frame];
+        frame.ip = (frame.ip as isize + offset) as usize;
+    }
+
     fn binary_op<F>(&mut self, op: F) -> VMResult<()>
     where
         F: FnOnce(Value, Value) -> VMResult<Value>,
@@ -521,22 +995,38 @@ impl VM {
         self.push(result)
     }
 
-    fn comparison_op<F>(&mut self, op: F) -> VMResult<()>
+    /// 比较大小：整数之间按整数比较（不经过 f64，大整数不会丢失精度），
+    /// 整数与浮点数混合时整数提升为浮点数；与 NaN 比较时结果总是 false
+    fn comparison_op<F>(&mut self, test: F) -> VMResult<()>
     where
-        F: FnOnce(f64, f64) -> bool,
+        F: FnOnce(Ordering) -> bool,
     {
         let b = self.pop()?;
         let a = self.pop()?;
 
-        let result = match (a, b) {
-            (Value::Integer(x), Value::Integer(y)) => op(x as f64, y as f64),
-            (Value::Float(x), Value::Float(y)) => op(x, y),
-            (Value::Integer(x), Value::Float(y)) => op(x as f64, y),
-            (Value::Float(x), Value::Integer(y)) => op(x, y as f64),
-            _ => return Err(VMError::TypeError("Cannot compare non-numeric values".to_string())),
+        let ordering = match (&a, &b) {
+            (Value::Integer(x), Value::Integer(y)) => Some(x.cmp(y)),
+            (Value::Float(x), Value::Float(y)) => x.partial_cmp(y),
+            (Value::Integer(x), Value::Float(y)) => (*x as f64).partial_cmp(y),
+            (Value::Float(x), Value::Integer(y)) => x.partial_cmp(&(*y as f64)),
+            (Value::Char(x), Value::Char(y)) => Some(x.cmp(y)),
+            // 只有数值和字符有顺序；其他值（包括数组和结构体）报错而不是给出任意结果
+            _ => {
+                return Err(VMError::TypeError(format!(
+                    "Cannot compare {} with {}", a.type_name(), b.type_name()
+                )))
+            }
         };
 
-        self.push(Value::Boolean(result))
+        self.push(Value::Boolean(ordering.is_some_and(test)))
+    }
+
+    /// 相等比较：整数与浮点数混合时按数值比较，其他值按结构比较
+    fn values_equal(a: &Value, b: &Value) -> bool {
+        match (a, b) {
+            (Value::Integer(x), Value::Float(y)) | (Value::Float(y), Value::Integer(x)) => *x as f64 == *y,
+            _ => a == b,
+        }
     }
 }
 
@@ -544,4 +1034,1678 @@ impl Default for VM {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::lexer::{Lexer, TokenPreprocessor};
+    use crate::parser::Parser;
+    use crate::type_checker::TypeChecker;
+    use crate::test_support::{captured_io, run_and_capture};
+
+    /// 完整地走一遍 词法 -> 语法 -> 类型检查 -> 编译
+    fn compile_source(source: &str) -> Chunk {
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let mut type_checker = TypeChecker::new();
+        type_checker.check(&program).unwrap();
+
+        let mut compiler = Compiler::new();
+        compiler.set_imported_symbols(type_checker.get_imported_symbols());
+        compiler.compile(program).unwrap()
+    }
+
+    /// 编译并执行
+    fn run_source(source: &str) -> VM {
+        let mut vm = VM::new();
+        vm.set_strict_stack(true);
+        vm.execute(compile_source(source)).unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_heterogeneous_tuple() {
+        let vm = run_source("let pair = (1, \"two\", true); let a = pair.0; let b = pair.1; let c = pair.2;");
+
+        assert_eq!(vm.get_global("a"), Some(&Value::Integer(1)));
+        assert_eq!(vm.get_global("b"), Some(&Value::String("two".to_string())));
+        assert_eq!(vm.get_global("c"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_print_tuple() {
+        // 元组显示为 (a, b)，与数组的 [a, b] 区分
+        let output = run_and_capture("
+            let pair = (1, \"two\");
+            print(pair);
+            print(((1.5, [1, 2]), (true,)));
+            let point: (float, float) = (1, 2);
+            print(point);
+        ");
+        assert_eq!(output, "(1, two)\n((1.5, [1, 2]), (true,))\n(1, 2)\n");
+    }
+
+    #[test]
+    fn test_nested_tuple_destructuring() {
+        let source = "
+            let (a, (b, c)) = (1, (\"two\", 3.5));
+            fn swap(t: (int, int)) -> (int, int) {
+                let (x, (y, z)) = (t.0, (t.1, 0));
+                return (y, x + z);
+            }
+            let swapped = swap((4, 5));
+            let first = swapped.0;
+            let second = swapped.1;
+        ";
+        let vm = run_source(source);
+
+        assert_eq!(vm.get_global("a"), Some(&Value::Integer(1)));
+        assert_eq!(vm.get_global("b"), Some(&Value::String("two".to_string())));
+        assert_eq!(vm.get_global("c"), Some(&Value::Float(3.5)));
+        assert_eq!(vm.get_global("first"), Some(&Value::Integer(5)));
+        assert_eq!(vm.get_global("second"), Some(&Value::Integer(4)));
+    }
+
+    #[test]
+    fn test_imported_struct_alias_field_access() {
+        let source = "
+            mod shapes {
+                pub struct Point { x: int, y: int };
+                pub type Coord = Point;
+            }
+            use shapes::Point;
+            use shapes::Coord as C;
+            let p: C = Point { x: 1, y: 2 };
+            let y = p.y;
+        ";
+        let vm = run_source(source);
+
+        assert_eq!(vm.get_global("y"), Some(&Value::Integer(2)));
+    }
+
+    #[test]
+    fn test_same_alias_name_in_different_modules() {
+        // 两个模块各自声明 Id，别名按模块路径区分，导入的是 a::Id
+        let output = run_and_capture("
+            mod a {
+                pub type Id = float;
+                pub fn as_float() -> float { let v: Id = 3; return v / 2; }
+            }
+            mod b {
+                pub type Id = int;
+                pub fn as_int() -> int { let v: Id = 3; return v / 2; }
+            }
+            use a::Id;
+            let v: Id = 3;
+            print(v / 2);
+            print(a::as_float());
+            print(b::as_int());
+        ");
+        assert_eq!(output, "1.5\n1.5\n1\n");
+    }
+
+    #[test]
+    fn test_module_variable_path_assignment() {
+        let output = run_and_capture("
+            mod config {
+                pub var level = 1;
+                pub fn get() -> int { return level; }
+            }
+            print(config::level);
+            config::level = 5;
+            config::level += 2;
+            print(config::level);
+            print(config::get());
+            var i = 0;
+            while i < 3 { config::level = config::level * 2; i += 1; }
+            print(config::get());
+        ");
+        assert_eq!(output, "1\n7\n7\n56\n");
+    }
+
+    #[test]
+    fn test_module_variables_with_shared_names() {
+        // 不同模块和模块外的同名变量是不同的全局变量
+        let output = run_and_capture("
+            var count = 0;
+            mod a {
+                pub var count = 1;
+                pub fn get() -> int { return count; }
+                pub fn bump() { count += 1; }
+            }
+            mod b {
+                pub var count = 100;
+                pub fn value() -> int { return count; }
+            }
+            a::count = 5;
+            a::bump();
+            b::count += 1;
+            print(count);
+            print(a::get());
+            print(b::value());
+            print(a::count + b::count);
+            use b::count as b_count;
+            print(b_count);
+        ");
+        assert_eq!(output, "0\n6\n101\n107\n101\n");
+    }
+
+    #[test]
+    fn test_gas_limit() {
+        // 死循环在 gas 耗尽时干净地返回错误
+        let mut vm = VM::with_gas_limit(1000);
+        let result = vm.execute(compile_source("while true {}"));
+        assert!(matches!(result, Err(VMError::GasExhausted)));
+        assert_eq!(vm.instruction_count(), 1000);
+
+        // 正常程序在限制内执行完成
+        let mut vm = VM::with_gas_limit(1000);
+        vm.execute(compile_source("var sum = 0; for i in 0..10 { sum = sum + i; }")).unwrap();
+        assert_eq!(vm.get_global("sum"), Some(&Value::Integer(45)));
+        assert!(vm.instruction_count() < 1000);
+    }
+
+    #[test]
+    fn test_relative_jump_control_flow() {
+        let source = "
+            fn classify(n: int) -> int {
+                if n < 0 {
+                    return -1;
+                } else {
+                    if n == 0 {
+                        return 0;
+                    }
+                }
+                return 1;
+            }
+            let signs = [classify(-5), classify(0), classify(7)];
+
+            var evens = 0;
+            var i = 0;
+            while true {
+                i = i + 1;
+                if i > 10 {
+                    break;
+                }
+                if i % 2 == 1 {
+                    continue;
+                }
+                evens = evens + i;
+            }
+
+            var pairs = 0;
+            for a in 0..4 {
+                for b in 0..(a) {
+                    pairs = pairs + 1;
+                }
+            }
+
+            let short = false && (1 / 0 == 0);
+            let either = true || (1 / 0 == 0);
+        ";
+        let vm = run_source(source);
+
+        assert_eq!(
+            vm.get_global("signs"),
+            Some(&Value::Array(Rc::new(vec![Value::Integer(-1), Value::Integer(0), Value::Integer(1)])))
+        );
+        assert_eq!(vm.get_global("evens"), Some(&Value::Integer(30)));
+        assert_eq!(vm.get_global("pairs"), Some(&Value::Integer(6)));
+        assert_eq!(vm.get_global("short"), Some(&Value::Boolean(false)));
+        assert_eq!(vm.get_global("either"), Some(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_jumps_are_relative() {
+        let chunk = compile_source("var i = 0; while i < 3 { i = i + 1; }");
+
+        // 每个跳转目标都在代码范围内，且 Loop 向后跳
+        for (offset, op) in chunk.code.iter().enumerate() {
+            match op {
+                OpCode::Jump(jump) | OpCode::JumpIfFalse(jump) | OpCode::JumpIfTrue(jump) => {
+                    assert!(*jump >= 0);
+                    assert!(Chunk::jump_target(offset, *jump) <= chunk.code.len());
+                }
+                OpCode::Loop(jump) => {
+                    assert!(*jump < 0);
+                    assert!(Chunk::jump_target(offset, *jump) < offset);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupt_chunk_errors_cleanly() {
+        let run_chunk = |code: Vec<OpCode>, constants: Vec<Value>| {
+            let mut chunk = Chunk::new();
+            for op in code {
+                chunk.write(op, 0);
+            }
+            chunk.constants = constants;
+            VM::new().execute(chunk)
+        };
+
+        // NewStruct 需要的字段值多于栈上的值
+        let result = run_chunk(
+            vec![OpCode::LoadConst(0), OpCode::LoadConst(1), OpCode::NewStruct(3), OpCode::Halt],
+            vec![Value::Integer(1), Value::String("Point".to_string())],
+        );
+        assert!(matches!(result, Err(VMError::StackUnderflow)));
+
+        // 空栈上的各种弹栈指令
+        for op in [OpCode::Add, OpCode::Pop, OpCode::Print, OpCode::ArrayGet, OpCode::NewArray(2), OpCode::Return] {
+            assert!(matches!(run_chunk(vec![op, OpCode::Halt], vec![]), Err(VMError::StackUnderflow)));
+        }
+
+        // 被截断的 chunk（没有 Halt）、越界的常量和局部变量
+        assert!(matches!(run_chunk(vec![OpCode::LoadNull], vec![]), Err(VMError::InvalidOperation(_))));
+        assert!(matches!(run_chunk(vec![OpCode::LoadConst(5), OpCode::Halt], vec![]), Err(VMError::InvalidOperation(_))));
+        assert!(matches!(run_chunk(vec![OpCode::LoadLocal(3), OpCode::Halt], vec![]), Err(VMError::InvalidOperation(_))));
+        assert!(matches!(run_chunk(vec![OpCode::Jump(-10), OpCode::Halt], vec![]), Err(VMError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_strict_stack_reports_leftover_values() {
+        // 手工构造的不平衡 chunk：两个值从未被弹出
+        let chunk = || {
+            let mut chunk = Chunk::new();
+            chunk.add_constant(Value::Integer(1));
+            chunk.write(OpCode::LoadConst(0), 1);
+            chunk.write(OpCode::LoadNull, 1);
+            chunk.write(OpCode::Halt, 1);
+            chunk
+        };
+
+        assert!(VM::new().execute(chunk()).is_ok());
+
+        let mut vm = VM::new();
+        vm.set_strict_stack(true);
+        let result = vm.execute(chunk());
+        assert!(matches!(result, Err(VMError::StackImbalance { leftover: 2 })));
+
+        // 正常程序在严格模式下执行完后值栈为空
+        let mut vm = VM::new();
+        vm.set_strict_stack(true);
+        vm.execute(compile_source(
+            "var total = 0;
+             for i in 0..3 { let sq = i * i; total = total + sq; }
+             fn f(x: int) -> int { if x > 1 { return x; } return 0; }
+             print(f(total));",
+        )).unwrap();
+        assert_eq!(vm.get_global("total"), Some(&Value::Integer(5)));
+    }
+
+    #[test]
+    fn test_assignment_statement_keeps_stack_balanced() {
+        // 存储指令保留栈顶的值，表达式语句再把它弹出
+        let chunk = compile_source("var x = 1; x = 5;");
+        assert_eq!(chunk.constants, vec![Value::Integer(5)]);
+        assert_eq!(chunk.code, vec![
+            OpCode::LoadOne, OpCode::StoreGlobal(0), OpCode::Pop,
+            OpCode::LoadConst(0), OpCode::StoreGlobal(0), OpCode::Pop,
+            OpCode::Halt,
+        ]);
+
+        // 各种赋值作为语句（包括循环和函数中的）执行完后都不残留值
+        for source in [
+            "var x = 1; x = 5; x += 2; var y = 0; x = y = 7;",
+            "var a = [1, 2]; a[0] = 5; a[1] += 2;",
+            "struct P { x: int }; var p = P { x: 1 }; p.x = 3; p.x += 1;",
+            "mod m { pub var c = 0; } m::c = 3; m::c += 1;",
+            "for i in 0..3 { var y = 1; y = i; var b = [1]; b[0] = i; b[0] += 1; }",
+            "struct P { x: int }; fn f() { var p = P { x: 1 }; var i = 0; while i < 2000 { i += 1; p.x = i; } } f();",
+        ] {
+            let mut vm = VM::new();
+            vm.set_strict_stack(true);
+            assert!(vm.execute(compile_source(source)).is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_recovers_after_runtime_error() {
+        let mut vm = VM::new();
+        let first = compile_source(
+            "var total = 10;
+             fn ratio(n: int) -> int { let scaled = n * 2; return scaled / 0; }
+             let bad = ratio(total);",
+        );
+        let slot = first.global_names.iter().position(|n| n == "total").unwrap();
+        let global_names = first.global_names.clone();
+
+        // 错误发生在函数调用中，值栈和调用栈上都有残留
+        assert!(matches!(vm.execute(first), Err(VMError::DivisionByZero)));
+        assert!(vm.stack.is_empty() && vm.frames.is_empty());
+
+        // 后续的 chunk 正常执行，并能读写之前定义的全局变量：total = total + 1
+        let mut next = Chunk::new();
+        for op in [OpCode::LoadGlobal(slot), OpCode::LoadOne, OpCode::Add, OpCode::StoreGlobal(slot), OpCode::Pop, OpCode::Halt] {
+            next.write(op, 1);
+        }
+        next.global_names = global_names;
+        vm.execute(next.clone()).unwrap();
+        assert_eq!(vm.get_global("total"), Some(&Value::Integer(11)));
+
+        // 正常结束后同样可以继续执行
+        vm.execute(next).unwrap();
+        assert_eq!(vm.get_global("total"), Some(&Value::Integer(12)));
+    }
+
+    #[test]
+    fn test_local_redeclaration_reuses_slot() {
+        let chunk = compile_source("fn f() -> int { let x = 1; let x = x + 10; let x = x * 2; return x; } let r = f();");
+        let function = match &chunk.constants[0] {
+            Value::Function(function) => function.clone(),
+            other => panic!("expected function constant, got {:?}", other),
+        };
+        // 三次声明共用一个槽位
+        assert!(function.chunk.code.iter().all(|op| !matches!(op, OpCode::LoadLocal(n) | OpCode::StoreLocal(n) if *n != 0)));
+
+        let mut vm = VM::new();
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.get_global("r"), Some(&Value::Integer(22)));
+
+        // 块中重新声明（可以改变类型），内层块中的同名变量仍然是独立的槽位
+        let output = run_and_capture(
+            "{
+                 let s = 1;
+                 let s = \"n=\" + s;
+                 { let s = 5; print(s); }
+                 print(s);
+             }",
+        );
+        assert_eq!(output, "5\nn=1\n");
+    }
+
+    #[test]
+    fn test_global_slots_shadowing_and_redeclaration() {
+        let vm = run_source(
+            "let x = 1;
+             let x = \"two\";
+             fn read_x() -> string { return x; }
+             let from_fn = read_x();
+             var inner = 0;
+             {
+                 let x = 10;
+                 inner = x;
+             }
+             let after = x;
+             var count = 0;
+             fn bump() { count = count + 1; }
+             bump();
+             bump();",
+        );
+
+        // 重新声明复用同一个槽位，函数体与顶层代码看到同一个全局变量
+        assert_eq!(vm.get_global("x"), Some(&Value::String("two".to_string())));
+        assert_eq!(vm.get_global("from_fn"), Some(&Value::String("two".to_string())));
+        // 块内的局部变量遮蔽全局变量，但不会覆盖它
+        assert_eq!(vm.get_global("inner"), Some(&Value::Integer(10)));
+        assert_eq!(vm.get_global("after"), Some(&Value::String("two".to_string())));
+        assert_eq!(vm.get_global("count"), Some(&Value::Integer(2)));
+
+        let chunk = compile_source("let a = 1; let b = a; let a = 2;");
+        assert_eq!(chunk.global_names, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn test_cyclic_alias_without_type_check_compiles() {
+        // 跳过类型检查时编译器展开别名也不能无限递归
+        let source = "type A = B; type B = A; let x: B = 1;";
+        let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        let chunk = Compiler::new().compile(program).unwrap();
+        let mut vm = VM::new();
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.get_global("x"), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_ordering_incomparable_values_is_an_error() {
+        let cases = [
+            ("struct P { x: int }; let r = P { x: 1 } < P { x: 2 };", "Cannot compare P with P"),
+            ("let r = [1, 2] <= [1, 3];", "Cannot compare array with array"),
+            ("let r = \"a\" > 1;", "Cannot compare string with int"),
+            ("let r = true >= false;", "Cannot compare bool with bool"),
+        ];
+        for (source, message) in cases {
+            let result = VM::new().execute(compile_source(source));
+            assert!(
+                matches!(result, Err(VMError::TypeError(ref m)) if m == message),
+                "{}: {:?}", source, result
+            );
+        }
+    }
+
+    #[test]
+    fn test_undefined_global_reports_name() {
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::LoadGlobal(0), 1);
+        chunk.write(OpCode::Halt, 1);
+        chunk.global_names = vec!["missing".to_string()];
+
+        let result = VM::new().execute(chunk);
+        assert!(matches!(result, Err(VMError::UndefinedVariable(ref name)) if name == "missing"));
+
+        // 顶层代码在全局变量定义之前调用读取它的函数（类型检查会拒绝，跳过类型检查时由运行时报告）
+        let source = "fn f() -> int { return later; } let x = f(); let later = 1;";
+        let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        let chunk = Compiler::new().compile(program).unwrap();
+        let result = VM::new().execute(chunk);
+        assert!(matches!(result, Err(VMError::UndefinedVariable(ref name)) if name == "later"));
+    }
+
+    #[test]
+    fn test_swap_exchanges_top_two_values() {
+        let mut chunk = Chunk::new();
+        let one = chunk.add_constant(Value::Integer(1));
+        let ten = chunk.add_constant(Value::Integer(10));
+        chunk.write(OpCode::LoadConst(one), 1);
+        chunk.write(OpCode::LoadConst(ten), 1);
+        chunk.write(OpCode::Swap, 1);
+        chunk.write(OpCode::Subtract, 1);
+        chunk.write(OpCode::StoreGlobal(0), 1);
+        chunk.write(OpCode::Halt, 1);
+        chunk.global_names = vec!["d".to_string()];
+
+        let mut vm = VM::new();
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.get_global("d"), Some(&Value::Integer(9)));
+
+        let mut chunk = Chunk::new();
+        chunk.write(OpCode::LoadTrue, 1);
+        chunk.write(OpCode::Swap, 1);
+        chunk.write(OpCode::Halt, 1);
+        assert!(matches!(VM::new().execute(chunk), Err(VMError::StackUnderflow)));
+    }
+
+    #[test]
+    fn test_compound_assignment_evaluates_target_once() {
+        let source = "struct Counter { hits: int, label: string };
+             var calls = 0;
+             fn next() -> int { calls = calls + 1; return calls; }
+             var a = [1, 2, 3];
+             a[next()] += 10;
+             var names = [\"x\", \"y\"];
+             names[0] += 1;
+             var c = Counter { hits: 1, label: \"c\" };
+             c.hits *= 5;
+             c.label += \"!\";";
+        let chunk = compile_source(source);
+        // 数组元素的复合赋值复制索引后用 Swap 调整操作数顺序
+        assert!(chunk.code.contains(&OpCode::Swap));
+
+        let vm = run_source(source);
+        assert_eq!(vm.get_global("calls"), Some(&Value::Integer(1)));
+        assert_eq!(vm.get_global("a"), Some(&Value::Array(Rc::new(vec![Value::Integer(1), Value::Integer(12), Value::Integer(3)]))));
+        assert_eq!(vm.get_global("names"), Some(&Value::Array(Rc::new(vec![Value::String("x1".to_string()), Value::String("y".to_string())]))));
+        let output = run_and_capture(&format!("{} print(c.hits); print(c.label);", source));
+        assert_eq!(output, "5\nc!\n");
+    }
+
+    #[test]
+    fn test_explicit_self_assignment_evaluates_index_twice() {
+        // 手写的 `a[i] = a[i] + y` 不是复合赋值，两侧的下标各自求值
+        let output = run_and_capture("
+            var calls = 0;
+            fn next() -> int { calls = calls + 1; return calls; }
+            var a = [1, 2, 3, 4];
+            a[next()] = a[next()] + 10;
+            print(\"calls=\" + calls);
+            print(a);
+        ");
+        assert_eq!(output, "calls=2\n[1, 13, 3, 4]\n");
+    }
+
+    #[test]
+    fn test_array_fill() {
+        let output = run_and_capture(
+            "print([0; 5]);
+             var n = 2;
+             var grid = [[0; 3]; n + 1];
+             grid[1] = [7; 2];
+             print(grid); print([\"ab\"; 0].length);
+             let empty: [int] = [1; 0]; print(empty);",
+        );
+        assert_eq!(output, "[0, 0, 0, 0, 0]\n[[0, 0, 0], [7, 7], [0, 0, 0]]\n0\n[]\n");
+
+        // 个数在运行时求值，负数是运行时错误
+        let result = VM::new().execute(compile_source("let n = 0 - 2; let a = [0; n];"));
+        assert!(matches!(result, Err(VMError::InvalidOperation(ref message)) if message.contains("negative")), "{:?}", result);
+
+        // 期望 float 元素时整数字面量按 float 填充
+        let output = run_and_capture("let ys: [float] = [1; 2]; print(ys[0] / 2); let grid: [[float]] = [[3; 2]; 2]; print(grid[1][0] / 2);");
+        assert_eq!(output, "0.5\n1.5\n");
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let output = run_and_capture(
+            "let a = 3, b = 7;
+             let m = if a > b { a } else { b };
+             print(m);
+             fn sign(n: int) -> string {
+                 return if n < 0 { \"-\" } else { if n == 0 { \"0\" } else { \"+\" } };
+             }
+             print(sign(0 - 5) + sign(0) + sign(5));
+             var calls = 0;
+             let total = 10 + if a < b { calls += 1; print(calls); a * 2 } else { b };
+             print(total);
+             for i in 0..3 { print(if i % 2 == 0 { i } else { 0 - i }); }",
+        );
+        assert_eq!(output, "7\n-0+\n1\n16\n0\n-1\n2\n");
+
+        // 分支的值留在栈上，分支里不能声明局部变量或跳出循环
+        let compile = |source: &str| {
+            let mut lexer = Lexer::new(source.to_string());
+            let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            Compiler::new().compile(program)
+        };
+        assert!(matches!(
+            compile("let m = if true { let t = 1; t } else { 2 };"),
+            Err(crate::compiler::CompileError::StatementInIfExpression(_))
+        ));
+        assert!(matches!(
+            compile("while (true) { let m = if true { break; 1 } else { 2 }; }"),
+            Err(crate::compiler::CompileError::StatementInIfExpression(_))
+        ));
+        assert!(compile("let m = if true { var i = 0; 1 } else { 2 };").is_err());
+        assert!(compile("var i = 0; let m = if true { while (i < 3) { i += 1; if (i == 2) { break; } } i } else { 0 };").is_ok());
+    }
+
+    #[test]
+    fn test_multiple_bindings_in_one_declaration() {
+        let output = run_and_capture(
+            "let a = 1, b = 2, c = a + b;
+             print(c);
+             fn f() -> int { var x = 10, y = x * 2; y += x; return y; }
+             print(f());
+             let s: string = \"n\", n: int = c * 2;
+             print(s + n);",
+        );
+        assert_eq!(output, "3\n30\nn6\n");
+    }
+
+    #[test]
+    fn test_recursive_frames_keep_their_own_locals() {
+        // 局部变量按帧基址访问：递归调用返回后，参数和局部变量仍是本帧的值
+        let output = run_and_capture(
+            "fn factorial(n: int) -> int {
+                 if (n <= 1) { return 1; }
+                 let rest = factorial(n - 1);
+                 return n * rest;
+             }
+             fn depth(n: int) -> int {
+                 let before = n * 10;
+                 if (n == 0) { return 0; }
+                 let inner = depth(n - 1);
+                 if (before != n * 10) { return -1; }
+                 return inner + n;
+             }
+             print(factorial(5)); print(depth(200));",
+        );
+        assert_eq!(output, "120\n20100\n");
+    }
+
+    #[test]
+    fn test_nested_function_captures_parameter() {
+        let vm = run_source(
+            "fn outer(base: int) -> int {
+                 fn add_base(x: int) -> int {
+                     return x + base;
+                 }
+                 return add_base(1) + add_base(2);
+             }
+             fn scale_all(factor: int, offset: int) -> int {
+                 let bias = offset * 2;
+                 fn apply(x: int) -> int {
+                     fn adjust(y: int) -> int {
+                         return y + bias;
+                     }
+                     return adjust(x * factor);
+                 }
+                 return apply(3);
+             }
+             let base = 1000;
+             let a = outer(10);
+             let b = scale_all(4, 5);",
+        );
+
+        assert_eq!(vm.get_global("a"), Some(&Value::Integer(23)));
+        // 两层嵌套：apply 需要转发 adjust 捕获的 bias
+        assert_eq!(vm.get_global("b"), Some(&Value::Integer(22)));
+    }
+
+    #[test]
+    fn test_assign_to_captured_variable_is_rejected() {
+        let source = "fn counter(start: int) -> int {
+                          var n = start;
+                          fn bump() { n = n + 1; }
+                          bump();
+                          return n;
+                      }";
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let result = Compiler::new().compile(program);
+        assert!(matches!(result, Err(crate::compiler::CompileError::AssignToCaptured(ref name, span)) if name == "n" && span.line == 3));
+    }
+
+    #[test]
+    fn test_undefined_field_error_has_location_and_suggestion() {
+        // 跳过类型检查，直接交给编译器
+        let source = "struct Point { x: int, y: int };\nlet p = Point { x: 1, z: 2 };";
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let err = Compiler::new().compile(program).err().unwrap();
+        match &err {
+            crate::compiler::CompileError::UndefinedField { struct_name, field, available, .. } => {
+                assert_eq!(struct_name, "Point");
+                assert_eq!(field, "z");
+                assert_eq!(available, &vec!["x".to_string(), "y".to_string()]);
+            }
+            other => panic!("Expected UndefinedField, got {:?}", other),
+        }
+        assert_eq!(err.span().map(|s| s.line), Some(2));
+        assert_eq!(err.suggestion().as_deref(), Some("valid fields of `Point` are: `x`, `y`"));
+
+        let displayed = crate::error::ErrorDisplayer::new(crate::error::ErrorMode::Simple)
+            .format_error(&err.to_compiler_error(), Some(source));
+        assert!(displayed.contains("2:9"), "{}", displayed);
+        assert!(displayed.contains("struct `Point` has no field `z`"), "{}", displayed);
+    }
+
+    #[test]
+    fn test_undefined_method_error_has_location() {
+        // 跳过类型检查：位置指向方法名
+        let source = "struct Point { x: int };\nlet p = Point { x: 1 };\nprint(p.norm());";
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let err = Compiler::new().compile(program).err().unwrap();
+        assert!(matches!(err, crate::compiler::CompileError::UndefinedVariable(ref message, _) if message.contains("norm")), "{:?}", err);
+        assert_eq!(err.span(), Some(crate::ast::Span::new(3, 9)));
+    }
+
+    #[test]
+    fn test_main_entry_point() {
+        let vm = run_source(
+            "let greeting = \"hi\";
+             var calls = 0;
+             var answer = 0;
+             fn helper(x: int) -> int { return x * 2; }
+             fn main() {
+                 calls = calls + 1;
+                 let result = helper(21);
+                 answer = result;
+             }",
+        );
+
+        // main 在所有声明之后被调用一次
+        assert_eq!(vm.get_global("calls"), Some(&Value::Integer(1)));
+        assert_eq!(vm.get_global("answer"), Some(&Value::Integer(42)));
+    }
+
+    #[test]
+    fn test_main_entry_point_errors() {
+        let compile = |source: &str| {
+            let mut lexer = Lexer::new(source.to_string());
+            let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            Compiler::new().compile(program)
+        };
+
+        assert!(matches!(
+            compile("fn main() { print(1); } main();"),
+            Err(crate::compiler::CompileError::MainWithTopLevelStatements)
+        ));
+        assert!(matches!(
+            compile("fn main(x: int) { print(x); }"),
+            Err(crate::compiler::CompileError::MainWithParameters)
+        ));
+        // 没有 main 时按顺序执行顶层语句
+        assert!(compile("fn helper() { print(1); } helper();").is_ok());
+    }
+
+    #[test]
+    fn test_string_concat_with_non_string() {
+        let vm = run_source(
+            "let a = \"x=\" + 5;
+             let b = 5 + \"!\";
+             let c = \"ok: \" + true + \", \" + 1.5;
+             let n = 2 + 3;
+             let is_five = 2 + 3 == 5;",
+        );
+
+        assert_eq!(vm.get_global("a"), Some(&Value::String("x=5".to_string())));
+        assert_eq!(vm.get_global("b"), Some(&Value::String("5!".to_string())));
+        assert_eq!(vm.get_global("c"), Some(&Value::String("ok: true, 1.5".to_string())));
+        assert_eq!(vm.get_global("n"), Some(&Value::Integer(5)));
+        assert_eq!(vm.get_global("is_five"), Some(&Value::Boolean(true)));
+
+        // 数字相加不会生成字符串转换指令
+        let chunk = compile_source("let n = 2 + 3;");
+        assert!(chunk.code.contains(&OpCode::Add));
+        assert!(!chunk.code.iter().any(|op| matches!(op, OpCode::Concat | OpCode::ToString)));
+    }
+
+    #[test]
+    fn test_struct_update_syntax() {
+        let vm = run_source(
+            "struct Point { x: int, y: int, label: string };
+             let p = Point { x: 1, y: 2, label: \"p\" };
+             let moved = Point { x: 10, ..p };
+             let copy = Point { ..p };
+             let relabeled = Point { label: \"q\", y: 20, ..moved };
+             let mx = moved.x;
+             let my = moved.y;
+             let px = p.x;",
+        );
+
+        let point = |x: i64, y: i64, label: &str| Value::Struct(crate::bytecode::StructValue {
+            struct_name: "Point".to_string(),
+            fields: vec![Value::Integer(x), Value::Integer(y), Value::String(label.to_string())],
+        });
+        assert_eq!(vm.get_global("moved"), Some(&point(10, 2, "p")));
+        // ..base 提供所有字段
+        assert_eq!(vm.get_global("copy"), Some(&point(1, 2, "p")));
+        assert_eq!(vm.get_global("relabeled"), Some(&point(10, 20, "q")));
+        assert_eq!(vm.get_global("mx"), Some(&Value::Integer(10)));
+        assert_eq!(vm.get_global("my"), Some(&Value::Integer(2)));
+        // base 本身不受影响
+        assert_eq!(vm.get_global("px"), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_char_range_for_loop() {
+        let vm = run_source(
+            "var alphabet = \"\";
+             for c in 'a'..'{' {
+                 alphabet = alphabet + c;
+             }
+             var empty = 0;
+             for c in 'z'..'a' {
+                 empty = empty + 1;
+             }
+             for c in 'c'..'c' {
+                 empty = empty + 1;
+             }
+             var last = 'x';
+             for c in 'A'..'D' {
+                 last = c;
+             }",
+        );
+
+        assert_eq!(
+            vm.get_global("alphabet"),
+            Some(&Value::String("abcdefghijklmnopqrstuvwxyz".to_string()))
+        );
+        // start >= end 时不执行循环体
+        assert_eq!(vm.get_global("empty"), Some(&Value::Integer(0)));
+        assert_eq!(vm.get_global("last"), Some(&Value::Char('C')));
+    }
+
+    #[test]
+    fn test_array_concat() {
+        let vm = run_source(
+            "let a = [1, 2] + [3, 4];
+             let b = [] + [5];
+             let c = [6, 7] + [];
+             let base = [1];
+             let d = base + base + [2];
+             let n = 1 + 2;",
+        );
+
+        let ints = |values: &[i64]| Value::Array(Rc::new(values.iter().map(|&v| Value::Integer(v)).collect()));
+        assert_eq!(vm.get_global("a"), Some(&ints(&[1, 2, 3, 4])));
+        assert_eq!(vm.get_global("b"), Some(&ints(&[5])));
+        assert_eq!(vm.get_global("c"), Some(&ints(&[6, 7])));
+        assert_eq!(vm.get_global("d"), Some(&ints(&[1, 1, 2])));
+        // 数字加法不受影响
+        assert_eq!(vm.get_global("n"), Some(&Value::Integer(3)));
+
+        let chunk = compile_source("let a = [1] + [2];");
+        assert!(chunk.code.contains(&OpCode::ArrayConcat));
+        assert!(!chunk.code.contains(&OpCode::Add));
+    }
+
+    #[test]
+    fn test_hoist_invariant_global_load() {
+        let source = "let limit = 5; var i = 0; var total = 0; \
+                      while (i < limit) { total = total + limit; i = i + 1; }";
+        let compile = |hoist: bool| {
+            let mut lexer = Lexer::new(source.to_string());
+            let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            let mut compiler = Compiler::new();
+            compiler.set_hoist_loop_invariants(hoist);
+            compiler.compile(program).unwrap()
+        };
+
+        let chunk = compile(true);
+        let limit = chunk.global_names.iter().position(|n| n == "limit").unwrap();
+        let loads: Vec<usize> = chunk.code.iter().enumerate()
+            .filter(|(_, op)| matches!(op, OpCode::LoadGlobal(slot) if *slot == limit))
+            .map(|(i, _)| i)
+            .collect();
+        let loop_exit = chunk.code.iter().position(|op| matches!(op, OpCode::JumpIfFalse(_))).unwrap();
+        // 只在循环开始之前读取一次
+        assert_eq!(loads.len(), 1);
+        assert!(loads[0] < loop_exit);
+        // 循环中被赋值的全局变量不外提
+        let i = chunk.global_names.iter().position(|n| n == "i").unwrap();
+        assert!(chunk.code[loads[0]..].iter().any(|op| matches!(op, OpCode::LoadGlobal(slot) if *slot == i)));
+
+        // 关闭优化时每次迭代都读取全局变量，结果相同
+        assert_eq!(compile(false).code.iter().filter(|op| matches!(op, OpCode::LoadGlobal(slot) if *slot == limit)).count(), 2);
+        for hoist in [true, false] {
+            let mut vm = VM::new();
+            vm.execute(compile(hoist)).unwrap();
+            assert_eq!(vm.get_global("total"), Some(&Value::Integer(25)));
+        }
+    }
+
+    #[test]
+    fn test_no_hoist_across_calls() {
+        let source = "var n = 0; fn bump() { n = n + 1; } while (n < 3) { bump(); }";
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = TokenPreprocessor::preprocess(lexer.tokenize().unwrap());
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut compiler = Compiler::new();
+        compiler.set_hoist_loop_invariants(true);
+        let mut vm = VM::new();
+        // 循环中有函数调用时不外提，否则会死循环
+        vm.execute(compiler.compile(program).unwrap()).unwrap();
+        assert_eq!(vm.get_global("n"), Some(&Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_immediate_literals() {
+        let chunk = compile_source("let b = true; let f = false; let z = 0; let o = 1; let n = 2;");
+        assert_eq!(chunk.code[0], OpCode::LoadTrue);
+        assert!(chunk.code.contains(&OpCode::LoadFalse));
+        assert!(chunk.code.contains(&OpCode::LoadZero));
+        assert!(chunk.code.contains(&OpCode::LoadOne));
+        // 只有 2 需要进入常量池
+        assert_eq!(chunk.constants, vec![Value::Integer(2)]);
+
+        let vm = run_source("let b = true; let f = false; let z = 0; let o = 1; var s = 0; for i in 0..3 { s = s + i; }");
+        assert_eq!(vm.get_global("b"), Some(&Value::Boolean(true)));
+        assert_eq!(vm.get_global("f"), Some(&Value::Boolean(false)));
+        assert_eq!(vm.get_global("z"), Some(&Value::Integer(0)));
+        assert_eq!(vm.get_global("o"), Some(&Value::Integer(1)));
+        assert_eq!(vm.get_global("s"), Some(&Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_print_sink_captures_output() {
+        let output = run_and_capture("print(1 + 2); print(\"a\" + 'b'); print([1, 2] + [3]); print(2.5);");
+        assert_eq!(output, "3\nab\n[1, 2, 3]\n2.5\n");
+
+        // 取出后缓冲区被清空
+        let mut vm = VM::new();
+        vm.set_print_sink(PrintSink::Buffer(String::new()));
+        vm.execute(compile_source("print(true);")).unwrap();
+        assert_eq!(vm.take_output(), "true\n");
+        assert_eq!(vm.take_output(), "");
+    }
+
+    #[test]
+    fn test_vm_io_drives_input_and_captures_streams() {
+        let (io, stdout, stderr) = captured_io("Ada\n3\r\n");
+        let mut vm = VM::with_io(io);
+        vm.execute(compile_source(
+            "write(\"name? \");
+             let name = input();
+             let count = input();
+             print(\"hello \" + name);
+             eprint(\"count was \" + count);
+             write(1); write(2);
+             let rest = input();
+             print(rest.length);",
+        ))
+        .unwrap();
+        assert_eq!(stdout.take(), "name? hello Ada\n120\n");
+        assert_eq!(stderr.take(), "count was 3\n");
+
+        // 缓冲区 sink 优先于 VmIo 的输出流，print 和 write 按顺序写入同一个缓冲区，eprint 仍写到错误流
+        let (io, stdout, stderr) = captured_io("");
+        let mut vm = VM::with_io(io);
+        vm.set_print_sink(PrintSink::Buffer(String::new()));
+        vm.execute(compile_source("print(\"a\"); write(\"b\"); eprint(2); write(\"c\"); print(1);")).unwrap();
+        assert_eq!(vm.take_output(), "a\nbc1\n");
+        assert_eq!(stdout.take(), "");
+        assert_eq!(stderr.take(), "2\n");
+    }
+
+    #[test]
+    fn test_empty_programs() {
+        // 空文件、只有注释、只有换行或空白的文件都是合法程序，编译后只剩 Halt
+        for source in ["", "// only a comment\n/* block */", "\n", "  \t\r\n\n"] {
+            let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            assert!(program.statements.is_empty(), "source {:?}", source);
+            TypeChecker::new().check(&program).unwrap();
+
+            let chunk = compile_source(source);
+            assert_eq!(chunk.code, vec![OpCode::Halt], "source {:?}", source);
+            assert_eq!(run_and_capture(source), "", "source {:?}", source);
+        }
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let output = run_and_capture(
+            "let fields = \"name,age,,city\".split(\",\");
+             print(fields.length); print(fields[0]); print(fields[2] == \"\"); print(fields[3]);
+             print(fields.join(\" | \"));
+             let words: [string] = [\"a\", \"b\", \"c\"];
+             print(words.join(\"\"));
+             print(\"héllo\".split(\"\").length);
+             print(\"a-b-c\".replace(\"-\", \"+\"));
+             let line = \"key=value\";
+             print(line.contains(\"=\")); print(line.contains(\"==\")); print(line.contains(\"\"));",
+        );
+        assert_eq!(output, "4\nname\ntrue\ncity\nname | age |  | city\nabc\n5\na+b+c\ntrue\nfalse\ntrue\n");
+
+        // impl 块中的同名方法优先于内置方法
+        let output = run_and_capture(
+            "impl string { fn contains(self, sub: string) -> string { return \"custom\"; } }
+             print(\"abc\".contains(\"b\"));",
+        );
+        assert_eq!(output, "custom\n");
+    }
+
+    #[test]
+    fn test_string_methods_on_call_results() {
+        // 接收者是用户函数的返回值时，按声明的返回类型选择内置方法
+        let output = run_and_capture(
+            "fn name() -> string { return \"alice\"; }
+             fn up(s: string) -> string { return s + \"!\"; }
+             fn parts() -> [string] { return \"x,y\".split(\",\"); }
+             let n = name();
+             print(n.contains(\"a\"));
+             print(name().contains(\"z\"));
+             print(up(\"a-b\").replace(\"-\", \"+\"));
+             print(up(\"p,q\").split(\",\").length);
+             print(parts().join(\"+\"));",
+        );
+        assert_eq!(output, "true\nfalse\na+b!\n2\nx+y\n");
+    }
+
+    #[test]
+    fn test_string_builder() {
+        let output = run_and_capture(
+            "var sb = string_builder();
+             for i in 0..4 { sb.append(i); sb.append(\",\"); }
+             sb.append('!'); sb.append([1, 2]);
+             print(sb.build());
+             fn repeat(s: string, n: int) -> string {
+                 var b: StringBuilder = string_builder();
+                 var i = 0;
+                 while i < n { b.append(s); i += 1; }
+                 let before = b;
+                 b.append(\".\");
+                 return b.build() + \" \" + before.build();
+             }
+             print(repeat(\"ab\", 3));
+             print(string_builder().build() == \"\");",
+        );
+        // 复制构建器得到独立的副本
+        assert_eq!(output, "0,1,2,3,![1, 2]\nababab. ababab\ntrue\n");
+
+        // append 直接修改变量，不经过 Load/Store；表达式语句执行后值栈平衡
+        let chunk = compile_source("var sb = string_builder(); sb.append(\"x\"); fn f() { var b = string_builder(); b.append(1); } f();");
+        assert!(chunk.code.contains(&OpCode::StrAppendGlobal(0)));
+        let function = chunk.constants.iter().find_map(|c| match c {
+            Value::Function(function) => Some(function.clone()),
+            _ => None,
+        }).unwrap();
+        assert!(function.chunk.code.iter().any(|op| matches!(op, OpCode::StrAppendLocal(_))));
+        let mut vm = VM::new();
+        vm.set_strict_stack(true);
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.get_global("sb"), Some(&Value::String("x".to_string())));
+    }
+
+    #[test]
+    fn test_string_builder_from_function() {
+        // 函数返回的构建器按声明的返回类型识别
+        let output = run_and_capture(
+            "fn make() -> StringBuilder { var b = string_builder(); b.append(\"hi\"); return b; }
+             var sb = make();
+             sb.append(\"!\");
+             print(sb.build());
+             print(make().build());",
+        );
+        assert_eq!(output, "hi!\nhi\n");
+    }
+
+    #[test]
+    fn test_int_literal_in_float_context() {
+        // 声明为 float 的变量在运行时也是浮点数
+        let output = run_and_capture("let x: float = 3; print(x / 2); let xs: [float] = [1, 2.5]; print(xs[0] / 4);");
+        assert_eq!(output, "1.5\n0.25\n");
+    }
+
+    #[test]
+    fn test_int_literal_as_float_argument_and_return() {
+        let output = run_and_capture("
+            fn half(x: float) -> float { return x / 2; }
+            fn one() -> float { return 1; }
+            struct Scale { factor: float };
+            impl Scale { fn apply(self, x: float) -> float { return x * self.factor; } }
+            print(half(3));
+            print(one() / 2);
+            let s = Scale { factor: 0.5 };
+            print(s.apply(3));
+        ");
+        assert_eq!(output, "1.5\n0.5\n1.5\n");
+    }
+
+    #[test]
+    fn test_int_literal_assigned_to_float_target() {
+        let output = run_and_capture("
+            var x: float = 1.5;
+            x = 3;
+            print(x / 2);
+            struct P { v: float };
+            var p = P { v: 0.5 };
+            p.v = 5;
+            print(p.v / 2);
+            var xs: [float] = [0.5, 1.5];
+            xs[1] = 7;
+            print(xs[1] / 2);
+            fn local() -> float { var y: float = 0.5; y = 1; return y / 4; }
+            print(local());
+        ");
+        assert_eq!(output, "1.5\n2.5\n3.5\n0.25\n");
+    }
+
+    #[test]
+    fn test_int_literal_in_nested_float_position() {
+        // 期望类型传入元组元素、结构体字段和 if 表达式的分支
+        let output = run_and_capture("
+            let t: (float, int) = (1, 2);
+            print(t.0 / 2);
+            print(t.1 / 2);
+            struct P { x: float };
+            let p = P { x: 3 };
+            print(p.x / 2);
+            let q = P { x: 5, ..p };
+            print(q.x / 2);
+            let x: float = if true { 3 } else { 4 };
+            print(x / 2);
+            let y: float = if false { 3 } else { -5 };
+            print(y / 2);
+        ");
+        assert_eq!(output, "0.5\n1\n1.5\n2.5\n1.5\n-2.5\n");
+    }
+
+    #[test]
+    fn test_length_property() {
+        let output = run_and_capture(
+            "print([1, 2, 3].length == 3); print(\"abc\".length == 3);
+             let xs: [int] = []; let s = \"héllo\"; print(xs.length); print(s.length);",
+        );
+        assert_eq!(output, "true\ntrue\n0\n5\n");
+    }
+
+    #[test]
+    fn test_power_operator() {
+        let output = run_and_capture(
+            "print(2 ** 10 == 1024); print(2.0 ** 0.5); print(2 ** 3 ** 2); print(-2 ** 2);
+             let base = 3; print(base ** 2 * 2); print(2 ** -1.0); print(4 ** 0.5);",
+        );
+        assert_eq!(output, "true\n1.4142135623730951\n512\n-4\n18\n0.5\n2\n");
+
+        // 负的整数指数和溢出都是运行时错误，而不是静默截断
+        for source in ["let e = -1; print(2 ** e);", "let e = 64; print(2 ** e);"] {
+            let result = VM::new().execute(compile_source(source));
+            assert!(matches!(result, Err(VMError::InvalidOperation(_))), "{}: {:?}", source, result);
+        }
+    }
+
+    #[test]
+    fn test_inline_small_functions() {
+        let compile = |source: &str, inline: bool| {
+            let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            let mut compiler = Compiler::new();
+            compiler.set_inline_small_functions(inline);
+            compiler.compile(program).unwrap()
+        };
+        let run = |chunk: Chunk| {
+            let mut vm = VM::new();
+            vm.set_strict_stack(true);
+            vm.set_print_sink(PrintSink::Buffer(String::new()));
+            vm.execute(chunk).unwrap();
+            vm.take_output()
+        };
+        let calls = |chunk: &Chunk| chunk.code.iter().filter(|op| matches!(op, OpCode::Call(_))).count();
+
+        let source = "struct Point { x: int, y: int };
+             fn area(w: int, h: int) -> int { return w * h; }
+             fn get_x(p: Point) -> int { return p.x; }
+             var total = 0;
+             let p = Point { x: 3, y: 4 };
+             for i in 0..4 { total = total + area(i, i + 1) + get_x(p); }
+             print(total);";
+        let inlined = compile(source, true);
+        // 函数体直接展开在循环中，不再有 Call
+        assert_eq!(calls(&inlined), 0);
+        assert!(inlined.code.windows(3).any(|w| matches!(w, [OpCode::LoadGlobal(_), OpCode::FieldGet(0), _])));
+        assert_eq!(calls(&compile(source, false)), 2);
+        assert_eq!(run(inlined), run(compile(source, false)));
+
+        // 不内联：递归、有副作用或类型未知的实参、多条语句的函数体、读取全局变量的函数体、被局部变量遮蔽
+        for source in [
+            "fn fact(n: int) -> int { return n * fact(n - 1); } print(fact(0));",
+            "fn twice(x: int) -> int { return x + x; } fn next() -> int { return 1; } print(twice(next()));",
+            "fn twice(x: int) -> int { return x + x; } fn f(y) { print(twice(y)); }",
+            "fn twice(x: int) -> int { let y = x; return y + y; } print(twice(2));",
+            "let base = 10; fn add(x: int) -> int { return x + base; } print(add(2));",
+            "fn sq(x: int) -> int { return x * x; } fn f(sq) -> int { return sq(3); }",
+        ] {
+            let chunk = compile(source, true);
+            let nested = chunk.constants.iter()
+                .filter_map(|c| match c { Value::Function(f) => Some(calls(&f.chunk)), _ => None })
+                .sum::<usize>();
+            assert!(calls(&chunk) + nested > 0, "unexpectedly inlined: {}", source);
+        }
+
+        // 模块函数与顶层函数共享全局命名空间，后声明的同名函数覆盖先声明的，不能内联
+        let source = "fn area(w: int, h: int) -> int { return w * h; }
+             mod util {
+                 pub fn area(w: int, h: int) -> int { return w + h; }
+                 pub fn f() -> int { return area(2, 3); }
+             }
+             print(area(2, 3));
+             print(util::f());";
+        assert_eq!(run(compile(source, true)), run(compile(source, false)));
+        assert_eq!(calls(&compile(source, true)), 2);
+    }
+
+    #[test]
+    fn test_strength_reduction() {
+        let compile = |source: &str, optimize: bool| {
+            let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+            let program = Parser::new(tokens).parse().unwrap();
+            let mut compiler = Compiler::new();
+            compiler.set_strength_reduction(optimize);
+            compiler.compile(program).unwrap()
+        };
+        // 函数体的指令（去掉结尾的 LoadNull, Return）
+        let body = |source: &str| {
+            let chunk = compile(source, true);
+            match &chunk.constants[0] {
+                Value::Function(function) => function.chunk.code[..function.chunk.code.len() - 2].to_vec(),
+                other => panic!("expected function constant, got {:?}", other),
+            }
+        };
+        use OpCode::*;
+
+        assert_eq!(body("fn f(x: int) -> int { return x * 2; }"), vec![LoadLocal(0), LoadLocal(0), Add, Return]);
+        assert_eq!(body("fn f(x: float) -> float { return 2 * x; }"), vec![LoadLocal(0), LoadLocal(0), Add, Return]);
+        assert_eq!(body("fn f(x: int) -> int { return x * 1; }"), vec![LoadLocal(0), Return]);
+        assert_eq!(body("fn f(x: int) -> int { return 0 * x; }"), vec![LoadZero, Return]);
+        assert_eq!(body("fn f(x: int) -> int { return x + 0; }"), vec![LoadLocal(0), Return]);
+        assert_eq!(body("fn f(x: int) -> int { return 0 + x; }"), vec![LoadLocal(0), Return]);
+        assert_eq!(body("fn f(x: int) -> int { return x - 0; }"), vec![LoadLocal(0), Return]);
+
+        // 取负的数值字面量折叠为常量
+        let chunk = compile("let a = -5; let b = -2.5;", true);
+        assert!(!chunk.code.contains(&Negate));
+        assert!(chunk.constants.contains(&Value::Integer(-5)) && chunk.constants.contains(&Value::Float(-2.5)));
+
+        // 浮点数乘 0、加 0 不化简（NaN、-0.0）；0 - x 不是恒等式；没有类型信息的参数不化简
+        assert_eq!(body("fn f(x: float) -> float { return x * 0; }"), vec![LoadLocal(0), LoadZero, Multiply, Return]);
+        assert_eq!(body("fn f(x: float) -> float { return x + 0; }"), vec![LoadLocal(0), LoadZero, Add, Return]);
+        assert_eq!(body("fn f(x: int) -> int { return 0 - x; }"), vec![LoadZero, LoadLocal(0), Subtract, Return]);
+        assert_eq!(body("fn f(x) { return x * 2; }"), vec![LoadLocal(0), LoadConst(0), Multiply, Return]);
+
+        // 化简前后结果相同
+        let source = "fn f(x: int) -> int { return x * 2 + x * 0 + (x - 0) * 1; } let r = f(7) + -3;";
+        for optimize in [true, false] {
+            let mut vm = VM::new();
+            vm.execute(compile(source, optimize)).unwrap();
+            assert_eq!(vm.get_global("r"), Some(&Value::Integer(18)));
+        }
+    }
+
+    #[test]
+    fn test_nested_glob_import() {
+        let output = run_and_capture(
+            "mod a { pub mod b { pub fn inc(x: int) -> int { return x + 1; } pub fn dbl(x: int) -> int { return x * 2; } } }
+             use a::b::*;
+             print(inc(dbl(3)));",
+        );
+        assert_eq!(output, "7\n");
+    }
+
+    #[test]
+    fn test_method_returning_self_type() {
+        let output = run_and_capture(
+            "struct P { x: int, y: int };
+             impl P {
+                 fn clone(self) -> Self { return self; }
+                 fn sum(self) -> int { return self.x + self.y; }
+             }
+             let p = P { x: 3, y: 4 };
+             let q: P = p.clone();
+             print(q.sum());",
+        );
+        assert_eq!(output, "7\n");
+    }
+
+    #[test]
+    fn test_print_function_value() {
+        let output = run_and_capture(
+            "fn add(a: int, b: int) -> int { return a + b; }
+             let op = add;
+             print(op);
+             print(\"op is \" + op);",
+        );
+        assert_eq!(output, "<fn add/2>\nop is <fn add/2>\n");
+    }
+
+    #[test]
+    fn test_break_continue_pop_block_locals() {
+        // run_and_capture 使用严格栈检查：顶层代码结束时值栈必须为空
+        let output = run_and_capture(
+            "var total = 0;
+             for i in 0..10 { let a = i; { let b = a * 2; let c = b + 1; if (i == 3) { break; } total = total + c; } }
+             var j = 0;
+             while (j < 5) { j = j + 1; let k = j; { let m = k; if (m == 2) { continue; } total = total + m; } }
+             for i in 0..4 { let skip = i % 2; if (skip == 1) { continue; } total = total + 100; }
+             fn f() -> int {
+                 var s = 0;
+                 for i in 0..5 { let x = i; { let y = x; if (y == 3) { break; } s = s + y; } }
+                 let after = 100;
+                 return s + after;
+             }
+             print(total); print(f());",
+        );
+        assert_eq!(output, "222\n103\n");
+    }
+
+    #[test]
+    fn test_methods_on_primitive_types() {
+        let output = run_and_capture(
+            "impl int {
+                 fn is_even(self) -> bool { return self % 2 == 0; }
+                 fn plus(self, other: Self) -> Self { return self + other; }
+             }
+             impl string {
+                 fn shout(self) -> string { return self + \"!\"; }
+             }
+             print(5.is_even()); print(4.is_even());
+             let n = 7; print(n.plus(3));
+             let s: string = \"hi\"; print(s.shout()); print(\"ok\".shout());",
+        );
+        assert_eq!(output, "false\ntrue\n10\nhi!\nok!\n");
+    }
+
+    #[test]
+    fn test_merge_links_separately_compiled_chunks() {
+        // 库 chunk 定义全局变量和函数；主 chunk 单独编译（不做类型检查，引用的名称按全局变量分配槽位）
+        let mut linked = compile_source(
+            "var total = 10; let greeting = \"hi\";
+             fn add(n: int) -> int { total = total + n; return total; }",
+        );
+        let tokens = TokenPreprocessor::preprocess(Lexer::new(
+            "let extra = 2.5; var i = 0; while (i < 3) { add(i); i = i + 1; } let doubled = add(total); let s = greeting + \"hi\";".to_string(),
+        ).tokenize().unwrap());
+        let main = Compiler::new().compile(Parser::new(tokens).parse().unwrap()).unwrap();
+        assert_ne!(main.global_names, linked.global_names);
+
+        let library_constants = linked.constants.len();
+        let start = linked.merge(main);
+        assert_eq!(linked.code[start - 1..].iter().filter(|op| **op == OpCode::Halt).count(), 1);
+        // 重复的字符串常量被合并
+        assert_eq!(linked.constants.iter().filter(|c| **c == Value::String("hi".to_string())).count(), 1);
+        assert!(linked.constants.len() > library_constants);
+
+        let mut vm = VM::new();
+        vm.set_strict_stack(true);
+        vm.execute(linked).unwrap();
+        assert_eq!(vm.get_global("total"), Some(&Value::Integer(26)));
+        assert_eq!(vm.get_global("doubled"), Some(&Value::Integer(26)));
+        assert_eq!(vm.get_global("extra"), Some(&Value::Float(2.5)));
+        assert_eq!(vm.get_global("s"), Some(&Value::String("hihi".to_string())));
+    }
+
+    #[test]
+    fn test_merge_keeps_signed_zero_constants_apart() {
+        // 源码中的 -0.0 编译为 0.0 取负，这里直接构造带 -0.0 常量的 chunk
+        let chunk_with = |values: &[f64]| {
+            let mut chunk = Chunk::new();
+            for value in values {
+                let idx = chunk.add_constant(Value::Float(*value));
+                chunk.write(OpCode::LoadConst(idx), 1);
+                chunk.write(OpCode::Print, 1);
+            }
+            chunk.write(OpCode::Halt, 1);
+            chunk
+        };
+
+        let mut linked = chunk_with(&[0.0, 2.5]);
+        linked.merge(chunk_with(&[-0.0, 2.5, f64::NAN]));
+        linked.merge(chunk_with(&[f64::NAN]));
+        let merged: Vec<u64> = linked.constants.iter()
+            .map(|c| match c { Value::Float(f) => f.to_bits(), other => panic!("unexpected constant {:?}", other) })
+            .collect();
+        // 0.0 和 -0.0 各占一个常量；相同的 2.5 和相同位模式的 NaN 只保留一个
+        assert_eq!(merged, vec![0.0f64.to_bits(), 2.5f64.to_bits(), (-0.0f64).to_bits(), f64::NAN.to_bits()]);
+        assert_eq!(1.0 / f64::from_bits(merged[0]), f64::INFINITY);
+        assert_eq!(1.0 / f64::from_bits(merged[2]), f64::NEG_INFINITY);
+
+        let mut vm = VM::new();
+        vm.set_print_sink(PrintSink::Buffer(String::new()));
+        vm.execute(linked).unwrap();
+        assert_eq!(vm.take_output(), "0\n2.5\n-0\n2.5\nNaN\nNaN\n");
+
+        // 函数常量中嵌套的浮点数也按位比较：只差零的符号的函数不会被合并
+        let function_with = |value: f64| Value::Function(Function {
+            name: "g".to_string(),
+            arity: 0,
+            chunk: chunk_with(&[value]),
+            locals_count: 0,
+            upvalues: Vec::new(),
+        });
+        assert_eq!(function_with(0.0), function_with(-0.0));
+        assert!(!function_with(0.0).identical(&function_with(-0.0)));
+        assert!(function_with(-0.0).identical(&function_with(-0.0)));
+        let mut linked = Chunk::new();
+        linked.add_constant(function_with(0.0));
+        let mut other = Chunk::new();
+        other.add_constant(function_with(-0.0));
+        linked.merge(other);
+        assert_eq!(linked.constants.len(), 2);
+    }
+
+    #[test]
+    fn test_calling_non_function_is_runtime_error() {
+        // 不经过类型检查直接编译，让调用到达虚拟机
+        let compile_unchecked = |source: &str| {
+            let tokens = TokenPreprocessor::preprocess(Lexer::new(source.to_string()).tokenize().unwrap());
+            Compiler::new().compile(Parser::new(tokens).parse().unwrap()).unwrap()
+        };
+
+        let result = VM::new().execute(compile_unchecked("let x = 5; x();"));
+        assert!(matches!(result, Err(VMError::NotCallable(ref found)) if found == "int"), "{:?}", result);
+
+        let result = VM::new().execute(compile_unchecked("struct P { x: int }; let p = P { x: 1 }; p(2);"));
+        assert!(matches!(result, Err(VMError::NotCallable(ref found)) if found == "P"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_to_string_on_all_values() {
+        let output = run_and_capture(
+            "struct P { x: int };
+             impl P { fn to_string(self) -> string { return \"P!\"; } }
+             print((42).to_string());
+             print(true.to_string());
+             print([1, 2].to_string());
+             print((1.5).to_string().length);
+             print(\"s\".to_string() + 'c'.to_string());
+             print(P { x: 1 }.to_string());",
+        );
+        // impl 块中自定义的 to_string 优先
+        assert_eq!(output, "42\ntrue\n[1, 2]\n3\nsc\nP!\n");
+    }
+
+    #[test]
+    fn test_call_with_wrong_argument_count_is_runtime_error() {
+        // 通过无类型注解的参数调用，类型检查器无法得知参数个数
+        let result = VM::new().execute(compile_source(
+            "fn add(a: int, b: int) -> int { return a + b; }
+             fn apply(f) { return f(1); }
+             let f = add;
+             print(apply(f));",
+        ));
+        assert!(matches!(
+            result,
+            Err(VMError::ArityMismatch { ref function, expected: 2, found: 1 }) if function == "add"
+        ), "{:?}", result);
+    }
+
+    #[test]
+    fn test_method_chaining_through_returned_structs() {
+        let output = run_and_capture(
+            "struct Rect { w: int, h: int };
+             struct Size { area: int };
+             impl Rect {
+                 fn scale(self, k: int) -> Rect { return Rect { w: self.w * k, h: self.h * k }; }
+                 fn grow(self) -> Self { return Rect { w: self.w + 1, h: self.h + 1 }; }
+                 fn size(self) -> Size { return Size { area: self.w * self.h }; }
+             }
+             impl Size {
+                 fn double(self) -> int { return self.area * 2; }
+             }
+             impl string {
+                 fn shout(self) -> string { return self + \"!\"; }
+             }
+             let r = Rect { w: 2, h: 3 };
+             print(r.scale(2).size().area);
+             print(r.grow().scale(3).size().double());
+             fn chained(rect: Rect) -> int { return rect.grow().size().area; }
+             print(chained(r));
+             print(\"hi\".shout().shout().length);",
+        );
+        assert_eq!(output, "24\n216\n12\n4\n");
+    }
+
+    #[test]
+    fn test_optional_field_access() {
+        let output = run_and_capture(
+            "struct P { x: int, name: string };
+             let p = P { x: 7, name: \"seven\" };
+             print(p?.x); print(p?.name.length);
+             print(null?.x);
+             var missing: P;
+             print(missing?.x);
+             print(null?.x?.y);",
+        );
+        assert_eq!(output, "7\n5\nnull\nnull\nnull\n");
+    }
+
+    #[test]
+    fn test_slice_strings_and_arrays() {
+        let output = run_and_capture(
+            "let s = \"hello\";
+             print(s[1..3] == \"el\");
+             print(\"héllo wörld\"[1..8]);
+             print(\"日本語\"[1..3].length);
+             let arr = [1, 2, 3, 4];
+             let middle = arr[1..3];
+             print(middle.length); print(middle[0]);
+             print(s[2..2] == \"\");",
+        );
+        assert_eq!(output, "true\néllo wö\n2\n2\n2\ntrue\n");
+
+        for source in ["let s = \"héllo\"[2..6];", "let a = [1, 2][1..0];"] {
+            let result = VM::new().execute(compile_source(source));
+            assert!(matches!(result, Err(VMError::InvalidOperation(ref message)) if message.contains("out of bounds")), "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn test_round_and_format_builtins() {
+        let output = run_and_capture(
+            "print(round(3.14159, 2) == 3.14);
+             let pi = 3.14159265358979;
+             print(format(\"{:.3}\", pi));
+             print(format(\"pi = {}, n = {:.1}, {{ok}}\", pi, 2));
+             print(round(2.5, 0)); print(round(1234.5, -2));
+             print(format(\"{}\", round(1.0 / 3.0, 4)).length);",
+        );
+        assert_eq!(output, "true\n3.142\npi = 3.14159265358979, n = 2.0, {ok}\n3\n1200\n6\n");
+
+        // 用户定义的同名函数优先于内置函数
+        let output = run_and_capture("fn round(x: float, d: int) -> string { return \"mine\"; } print(round(1.5, 0));");
+        assert_eq!(output, "mine\n");
+
+        // 非字面量格式字符串的参数数量在运行时检查
+        let result = VM::new().execute(compile_source("let f = \"{} and {}\"; let s = format(f, 1);"));
+        assert!(matches!(result, Err(VMError::InvalidOperation(ref message)) if message.contains("expects 2")), "{:?}", result);
+    }
+
+    #[test]
+    fn test_unknown_builtin_name_is_a_normal_call() {
+        // 不在内置函数表中的名字按普通函数解析：加载函数后用 Call 调用
+        assert!(crate::builtins::lookup("len").is_none());
+        let chunk = compile_source("fn len(s: string) -> int { return s.length; } let n = len(\"abc\");");
+        assert!(chunk.code.contains(&OpCode::Call(1)));
+        let mut vm = VM::new();
+        vm.execute(chunk).unwrap();
+        assert_eq!(vm.get_global("n"), Some(&Value::Integer(3)));
+
+        // 内置函数直接生成表中的指令，不经过 Call
+        let chunk = compile_source("let s = clone(\"abc\"); eprint(s);");
+        assert!(chunk.code.contains(&OpCode::DeepClone) && chunk.code.contains(&OpCode::PrintError));
+        assert!(!chunk.code.iter().any(|op| matches!(op, OpCode::Call(_))));
+
+        // 既不是内置函数也没有定义的名字由类型检查器报告
+        let tokens = Lexer::new("let n = len(\"abc\");".to_string()).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let result = TypeChecker::new().check(&program);
+        assert!(matches!(result, Err(crate::type_checker::TypeError::UndefinedFunction(ref name)) if name == "len"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_array_element_assignment_keeps_value_semantics() {
+        let output = run_and_capture(
+            "var a = [1, 2, 3];
+             var b = a;
+             b[0] = 9; b[2] += 10;
+             print(a); print(b);
+             fn fill(n: int) -> [int] {
+                 var xs = [0, 0, 0];
+                 let copy = xs;
+                 for i in 0..(n) { xs[i] = i * i; }
+                 print(copy);
+                 return xs;
+             }
+             let filled = fill(3);
+             print(filled);",
+        );
+        assert_eq!(output, "[1, 2, 3]\n[9, 2, 13]\n[0, 0, 0]\n[0, 1, 4]\n");
+
+        // 变量中的数组直接原地修改，不再先加载整个数组
+        let chunk = compile_source("var g = [1, 2]; g[0] = 5; fn f() -> int { var l = [1]; l[0] = 2; return l[0]; }");
+        assert!(chunk.code.iter().any(|op| matches!(op, OpCode::ArraySetGlobal(_))));
+        assert!(!chunk.code.contains(&OpCode::ArraySet));
+        let function = chunk.constants.iter().find_map(|c| match c {
+            Value::Function(function) if function.name == "f" => Some(function),
+            _ => None,
+        }).unwrap();
+        assert!(function.chunk.code.iter().any(|op| matches!(op, OpCode::ArraySetLocal(_))));
+    }
+
+    #[test]
+    fn test_clone_builtin_copies_nested_values() {
+        let output = run_and_capture(
+            "struct Team { name: string, scores: [int] };
+             var grid = [[1, 2], [3, 4]];
+             var copy = clone(grid);
+             var row = copy[0]; row[0] = 9; copy[0] = row; copy[1][1] = 0;
+             print(grid); print(copy);
+             let team = Team { name: \"a\", scores: [1, 2] };
+             var other = clone(team);
+             other.name = \"b\"; other.scores = other.scores + [3];
+             print(team.name); print(team.scores); print(other.name); print(other.scores);",
+        );
+        assert_eq!(output, "[[1, 2], [3, 4]]\n[[9, 2], [3, 4]]\na\n[1, 2]\nb\n[1, 2, 3]\n");
+
+        // 深拷贝不与原值共享任何数组
+        let original = Value::Array(Rc::new(vec![Value::Array(Rc::new(vec![Value::Integer(1)]))]));
+        let copy = original.deep_clone();
+        assert_eq!(copy, original);
+        match (&original, &copy) {
+            (Value::Array(a), Value::Array(b)) => {
+                assert!(!Rc::ptr_eq(a, b));
+                match (&a[0], &b[0]) {
+                    (Value::Array(x), Value::Array(y)) => assert!(!Rc::ptr_eq(x, y)),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_pretty_print_nested_values() {
+        let output = run_and_capture(
+            "struct Point { x: int, y: int };
+             struct Shape { name: string, points: [Point], tags: [string] };
+             let shape = Shape { name: \"tri\", points: [Point { x: 0, y: 1 }, Point { x: 2, y: 3 }], tags: [] };
+             pretty_print(shape);
+             pretty_print(42);",
+        );
+        assert_eq!(
+            output,
+            "Shape {\n    \"tri\",\n    [\n        Point {\n            0,\n            1,\n        },\n        \
+             Point {\n            2,\n            3,\n        },\n    ],\n    [],\n}\n42\n"
+        );
+    }
+
+    #[test]
+    fn test_discard_binding() {
+        let output = run_and_capture(
+            "var calls = 0;
+             fn tick() -> int { calls = calls + 1; return calls; }
+             let _ = tick(); let _ = tick(); let _ = \"again\";
+             fn inner() -> int {
+                 let _ = tick(); let _ = 1.5; let (a, _) = (10, tick());
+                 var n = 0;
+                 for _ in 0..3 { for _ in 0..2 { n = n + 1; } }
+                 return a + n;
+             }
+             print(inner()); print(calls);",
+        );
+        assert_eq!(output, "16\n4\n");
+    }
+
+    #[test]
+    fn test_debug_format_quotes_strings() {
+        let text = Value::String("a\nb\tc".to_string());
+        assert_eq!(text.to_string(), "a\nb\tc");
+        assert_eq!(text.debug_format(), "\"a\\nb\\tc\"");
+
+        assert_eq!(Value::Char('\t').to_string(), "\t");
+        assert_eq!(Value::Char('\'').debug_format(), "'\\''");
+
+        // 集合中的元素也使用调试格式，数字等其他值与 to_string 相同
+        let array = Value::Array(Rc::new(vec![Value::Integer(1), Value::String("x\"y".to_string()), Value::Char('c')]));
+        assert_eq!(array.to_string(), "[1, x\"y, c]");
+        assert_eq!(array.debug_format(), "[1, \"x\\\"y\", 'c']");
+        let point = Value::Struct(crate::bytecode::StructValue {
+            struct_name: "P".to_string(),
+            fields: vec![Value::Integer(1), Value::String("".to_string())],
+        });
+        assert_eq!(point.debug_format(), "P { 1, \"\" }");
+    }
+
+    #[test]
+    fn test_char_print_and_debug_format() {
+        // print 输出字符本身，调试格式与字符串使用相同的转义规则
+        let output = run_and_capture("print('a'); print('\\n'); print(['\\t', 'b']); pretty_print('\\t');");
+        assert_eq!(output, "a\n\n\n[\t, b]\n'\\t'\n");
+
+        assert_eq!(Value::Char('\t').debug_format(), "'\\t'");
+        assert_eq!(Value::Char('\n').debug_format(), "'\\n'");
+        assert_eq!(Value::Char('\u{7}').debug_format(), "'\\x07'");
+        assert_eq!(Value::Char('"').debug_format(), "'\"'");
+        assert_eq!(Value::String("'".to_string()).debug_format(), "\"'\"");
+    }
+}